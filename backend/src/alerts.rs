@@ -0,0 +1,106 @@
+use crate::config::AlertsConfig;
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying the hex HMAC-SHA256 of the body, when a secret is set.
+const SIGNATURE_HEADER: &str = "X-Gridwalk-Signature";
+
+/// Something operators should look at, sent to the configured webhooks and
+/// published as an event.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Alert {
+    /// The layer's data went longer than its expected update frequency
+    /// without an update.
+    LayerStale {
+        layer_id: String,
+        /// When the layer's rows last changed.
+        updated_at: i64,
+        /// Seconds the data was expected to be updated within.
+        max_age: u64,
+    },
+}
+
+impl Alert {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Alert::LayerStale { .. } => "layer_stale",
+        }
+    }
+}
+
+/// Delivers alerts to webhooks. Without webhooks alerts are only published
+/// as events.
+pub struct Alerts {
+    client: reqwest::Client,
+    webhooks: Vec<String>,
+    secret: Option<Vec<u8>>,
+}
+
+impl Alerts {
+    pub fn from_config(config: &AlertsConfig) -> Result<Self> {
+        Ok(Alerts {
+            client: reqwest::Client::builder()
+                .user_agent(concat!("gridwalk/", env!("CARGO_PKG_VERSION")))
+                .timeout(Duration::from_secs(config.timeout))
+                .build()?,
+            webhooks: config.webhooks.clone(),
+            secret: config
+                .secret
+                .as_ref()
+                .map(|secret| secret.expose().as_bytes().to_vec()),
+        })
+    }
+
+    /// POST the alert as JSON to every webhook, once each. Failed
+    /// deliveries are logged rather than retried.
+    pub async fn send(&self, alert: &Alert) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_vec(alert) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode {} alert: {e}", alert.name());
+                return;
+            }
+        };
+        let signature = self.secret.as_ref().map(|secret| {
+            let mut mac =
+                HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+            mac.update(&body);
+            hex::encode(mac.finalize().into_bytes())
+        });
+        for webhook in &self.webhooks {
+            let mut request = self
+                .client
+                .post(webhook)
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            let result = match request.send().await {
+                Ok(response) => response.error_for_status().map(|_| ()),
+                Err(e) => Err(e),
+            };
+            let outcome = match result {
+                Ok(()) => "ok",
+                Err(e) => {
+                    warn!("Failed to deliver {} alert: {e}", alert.name());
+                    "error"
+                }
+            };
+            counter!("alerts_sent_total", "alert" => alert.name(), "outcome" => outcome)
+                .increment(1);
+        }
+    }
+}
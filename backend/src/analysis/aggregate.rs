@@ -0,0 +1,166 @@
+use crate::postgis::{create_derived_table, quote_ident, transform_sql, LayerTable};
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Grid {
+    /// Uber H3 cells. Needs the `h3_postgis` extension.
+    H3,
+    Geohash,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatisticOp {
+    Sum,
+    Avg,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Statistic {
+    pub op: StatisticOp,
+    pub column: String,
+}
+
+/// Bin features into grid cells, counting them and optionally summarising
+/// numeric columns. Non-point features are binned by a point on their surface.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Aggregation {
+    pub grid: Grid,
+    pub resolution: u8,
+    #[serde(default)]
+    pub statistics: Vec<Statistic>,
+}
+
+impl Aggregation {
+    pub fn validate(&self) -> Result<()> {
+        let max = match self.grid {
+            Grid::H3 => 15,
+            Grid::Geohash => 12,
+        };
+        let min = match self.grid {
+            Grid::H3 => 0,
+            Grid::Geohash => 1,
+        };
+        if !(min..=max).contains(&self.resolution) {
+            return Err(anyhow!("resolution must be between {min} and {max}"));
+        }
+        Ok(())
+    }
+
+    /// SQL grouping the layer into cells, yielding `cell`, `count`, each
+    /// statistic and the cell polygon in WGS84 as `geom`. `filter` is
+    /// appended as the WHERE clause when given. Also returns the names of the
+    /// non-geometry output columns.
+    async fn cells_sql(
+        &self,
+        pool: &Pool,
+        source: &LayerTable,
+        filter: &str,
+    ) -> Result<(String, Vec<String>)> {
+        let columns = source.attribute_columns(pool).await?;
+        let point = transform_sql(
+            &format!(
+                "ST_PointOnSurface(t.{})",
+                quote_ident(&source.geometry_column)
+            ),
+            source.srid,
+            4326,
+        );
+        let (cell, boundary) = match self.grid {
+            Grid::H3 => (
+                format!("h3_lat_lng_to_cell({point}, {})", self.resolution),
+                "h3_cell_to_boundary_geometry(cell)",
+            ),
+            Grid::Geohash => (
+                format!("ST_GeoHash({point}, {})", self.resolution),
+                "ST_SetSRID(ST_GeomFromGeoHash(cell), 4326)",
+            ),
+        };
+
+        let mut statistics = String::new();
+        let mut outputs = String::new();
+        let mut output_columns = vec!["cell".to_string(), "count".to_string()];
+        for statistic in &self.statistics {
+            if !columns.contains(&statistic.column) {
+                return Err(anyhow!("unknown column {}", statistic.column));
+            }
+            let (function, prefix) = match statistic.op {
+                StatisticOp::Sum => ("sum", "sum"),
+                StatisticOp::Avg => ("avg", "avg"),
+            };
+            let alias = quote_ident(&format!("{prefix}_{}", statistic.column));
+            statistics.push_str(&format!(
+                ", {function}(t.{})::float8 AS {alias}",
+                quote_ident(&statistic.column)
+            ));
+            outputs.push_str(&format!(", {alias}"));
+            output_columns.push(alias);
+        }
+
+        let sql = format!(
+            "SELECT cell::text AS cell, count{outputs}, {boundary} AS geom FROM (
+                 SELECT {cell} AS cell, count(*) AS count{statistics}
+                 FROM {} t {filter}
+                 GROUP BY 1
+             ) cells",
+            source.qualified_name()
+        );
+        Ok((sql, output_columns))
+    }
+}
+
+/// Materialise the aggregation as a polygon layer.
+#[instrument(skip(pool, aggregation))]
+pub async fn aggregate_layer(
+    pool: &Pool,
+    source_id: &str,
+    aggregation: &Aggregation,
+    output: &str,
+) -> Result<()> {
+    let source = LayerTable::from_source_id(pool, source_id).await?;
+    let (select_sql, _) = aggregation.cells_sql(pool, &source, "").await?;
+    create_derived_table(pool, output, &select_sql, 4326).await
+}
+
+/// Render the aggregation for one tile as MVT, for heatmap-style display
+/// without materialising a layer.
+#[instrument(skip(pool, aggregation))]
+pub async fn aggregate_tile(
+    pool: &Pool,
+    source_id: &str,
+    aggregation: &Aggregation,
+    z: u8,
+    x: u32,
+    y: u32,
+) -> Result<Vec<u8>> {
+    let source = LayerTable::from_source_id(pool, source_id).await?;
+    // Take points from the surrounding tiles too, so cells on the tile edge
+    // are counted in full before being clipped.
+    let envelope = transform_sql(
+        &format!("ST_TileEnvelope({z}, {x}, {y}, margin => 1.0)"),
+        3857,
+        source.srid,
+    );
+    let filter = format!(
+        "WHERE t.{} && {envelope}",
+        quote_ident(&source.geometry_column)
+    );
+    let (cells, columns) = aggregation.cells_sql(pool, &source, &filter).await?;
+    let attributes: String = columns.iter().map(|c| format!(", c.{c}")).collect();
+    let sql = format!(
+        "SELECT ST_AsMVT(tile, 'aggregate', 4096, 'geom') FROM (
+             SELECT ST_AsMVTGeom(ST_Transform(c.geom, 3857), ST_TileEnvelope({z}, {x}, {y})) AS geom
+                    {attributes}
+             FROM ({cells}) c
+         ) tile
+         WHERE geom IS NOT NULL"
+    );
+    let client = pool.get().await?;
+    let row = client.query_one(&sql, &[]).await?;
+    Ok(row.get::<_, Option<Vec<u8>>>(0).unwrap_or_default())
+}
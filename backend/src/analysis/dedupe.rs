@@ -0,0 +1,177 @@
+use crate::postgis::{create_derived_table, quote_ident, transform_sql, LayerTable};
+use crate::validation::{Validate, Validator};
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Farthest apart, in metres, features may be and still be duplicates.
+const MAX_DISTANCE: f64 = 1000.0;
+const MAX_ATTRIBUTES: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupeMode {
+    /// A layer of the duplicates only, each with `duplicate_of`, the key of
+    /// the feature it duplicates.
+    #[default]
+    Report,
+    /// A copy of the layer without the duplicates, each kept feature with
+    /// `duplicate_count`, the number of duplicates merged into it.
+    Merge,
+}
+
+/// Find features that duplicate another feature of the layer. Features are
+/// taken in primary key order, and each is a duplicate of the first earlier
+/// feature it matches; that feature's attributes are the ones kept.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Dedupe {
+    /// Features whose geometries are no farther apart than this, in metres,
+    /// are candidates. At 0, the default, geometries must be identical,
+    /// vertex for vertex.
+    #[serde(default)]
+    pub distance: f64,
+    /// Attributes compared between candidates. Text is compared ignoring
+    /// case and surrounding whitespace. Empty compares geometry only.
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    /// Share of `attributes` that must be equal, from 0 to 1. Defaults to
+    /// all of them.
+    #[serde(default = "all_attributes")]
+    pub similarity: f64,
+    #[serde(default)]
+    pub mode: DedupeMode,
+}
+
+fn all_attributes() -> f64 {
+    1.0
+}
+
+impl Validate for Dedupe {
+    fn check(&self, v: &mut Validator) {
+        v.check(
+            "distance",
+            (0.0..=MAX_DISTANCE).contains(&self.distance),
+            "out_of_range",
+            format!("must be between 0 and {MAX_DISTANCE} metres"),
+        );
+        v.count("attributes", self.attributes.len(), 0, MAX_ATTRIBUTES);
+        v.check(
+            "similarity",
+            (0.0..=1.0).contains(&self.similarity),
+            "out_of_range",
+            "must be between 0 and 1",
+        );
+    }
+}
+
+impl Dedupe {
+    /// SQL condition true when `o`, an earlier feature, matches `t`.
+    fn match_sql(&self, source: &LayerTable) -> String {
+        let geom = quote_ident(&source.geometry_column);
+        let mut conditions = if self.distance == 0.0 {
+            // `~=` narrows candidates by bounding box through the index
+            vec![format!(
+                "o.{geom} ~= t.{geom}
+                 AND md5(ST_AsEWKB(o.{geom})) = md5(ST_AsEWKB(t.{geom}))"
+            )]
+        } else {
+            // Measured in Web Mercator, corrected for latitude
+            let window = transform_sql(
+                &format!("ST_Expand(near.g, {} * near.stretch)", self.distance),
+                3857,
+                source.srid,
+            );
+            let mercator = transform_sql(&format!("o.{geom}"), source.srid, 3857);
+            vec![format!(
+                "o.{geom} && {window}
+                 AND ST_DWithin({mercator}, near.g, {} * near.stretch)",
+                self.distance
+            )]
+        };
+        if !self.attributes.is_empty() {
+            let equal: Vec<String> = self
+                .attributes
+                .iter()
+                .map(|attribute| {
+                    let column = quote_ident(attribute);
+                    format!(
+                        "(lower(trim(o.{column}::text))
+                          IS NOT DISTINCT FROM lower(trim(t.{column}::text)))::int"
+                    )
+                })
+                .collect();
+            let required = (self.similarity * self.attributes.len() as f64).ceil() as usize;
+            conditions.push(format!("{} >= {required}", equal.join(" + ")));
+        }
+        conditions.join(" AND ")
+    }
+}
+
+/// Write the duplicates of the layer's features, or the layer without
+/// them, to `output`. `key` is the layer's primary key.
+#[instrument(skip(pool, dedupe))]
+pub async fn dedupe(
+    pool: &Pool,
+    source_id: &str,
+    key: &str,
+    dedupe: &Dedupe,
+    output: &str,
+) -> Result<()> {
+    let source = LayerTable::from_source_id(pool, source_id).await?;
+    let columns = source.attribute_columns(pool).await?;
+    if let Some(unknown) = dedupe.attributes.iter().find(|a| !columns.contains(a)) {
+        return Err(anyhow!("unknown attribute {unknown}"));
+    }
+    let attributes: String = columns
+        .iter()
+        .map(|column| format!("t.{}, ", quote_ident(column)))
+        .collect();
+    let geom = quote_ident(&source.geometry_column);
+    let key = quote_ident(key);
+    let table = source.qualified_name();
+    let mercator = transform_sql(&format!("t.{geom}"), source.srid, 3857);
+    let wgs84 = transform_sql(&format!("ST_Centroid(t.{geom})"), source.srid, 4326);
+
+    // The key of the first earlier feature each feature matches
+    let first_match = format!(
+        "SELECT t.{key} AS id, m.first_id
+         FROM {table} t
+         CROSS JOIN LATERAL (
+             SELECT {mercator} AS g, 1 / cos(radians(ST_Y({wgs84}))) AS stretch
+         ) near
+         LEFT JOIN LATERAL (
+             SELECT o.{key} AS first_id FROM {table} o
+             WHERE o.{key} < t.{key} AND {}
+             ORDER BY o.{key}
+             LIMIT 1
+         ) m ON true
+         WHERE t.{geom} IS NOT NULL",
+        dedupe.match_sql(&source)
+    );
+    let select_sql = match dedupe.mode {
+        DedupeMode::Report => format!(
+            "WITH matches AS ({first_match})
+             SELECT {attributes}f.first_id AS duplicate_of, t.{geom} AS geom
+             FROM {table} t
+             JOIN matches f ON f.id = t.{key}
+             WHERE f.first_id IS NOT NULL"
+        ),
+        DedupeMode::Merge => format!(
+            "WITH matches AS ({first_match}),
+             merged AS (
+                 SELECT first_id, count(*) AS duplicate_count
+                 FROM matches WHERE first_id IS NOT NULL GROUP BY first_id
+             )
+             SELECT {attributes}coalesce(d.duplicate_count, 0) AS duplicate_count,
+                    t.{geom} AS geom
+             FROM {table} t
+             LEFT JOIN matches f ON f.id = t.{key}
+             LEFT JOIN merged d ON d.first_id = t.{key}
+             WHERE f.first_id IS NULL"
+        ),
+    };
+
+    create_derived_table(pool, output, &select_sql, source.srid).await
+}
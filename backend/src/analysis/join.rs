@@ -0,0 +1,106 @@
+use crate::postgis::{create_derived_table, quote_ident, transform_sql, LayerTable};
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinPredicate {
+    /// Target features that touch or overlap a join feature.
+    Intersects,
+    /// Target features lying entirely inside a join feature.
+    Within,
+    /// The closest join feature, with its distance.
+    Nearest,
+}
+
+/// Copy attributes from `join` onto each feature of `target`. Each target
+/// feature is kept once, taking the first matching join feature.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SpatialJoin {
+    pub target: String,
+    pub join: String,
+    pub predicate: JoinPredicate,
+    /// Join layer columns to copy. Defaults to all of them.
+    pub columns: Option<Vec<String>>,
+    /// Prefix for copied columns, keeping them apart from target columns.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+fn default_prefix() -> String {
+    "join_".to_string()
+}
+
+#[instrument(skip(pool, spec))]
+pub async fn spatial_join(pool: &Pool, spec: &SpatialJoin, output: &str) -> Result<()> {
+    let target = LayerTable::from_source_id(pool, &spec.target).await?;
+    let join = LayerTable::from_source_id(pool, &spec.join).await?;
+    let target_columns = target.attribute_columns(pool).await?;
+    let join_columns = join.attribute_columns(pool).await?;
+
+    let copied = match &spec.columns {
+        Some(columns) => {
+            if let Some(unknown) = columns.iter().find(|c| !join_columns.contains(c)) {
+                return Err(anyhow!("unknown join column {unknown}"));
+            }
+            columns.clone()
+        }
+        None => join_columns,
+    };
+
+    let target_geom = format!("t.{}", quote_ident(&target.geometry_column));
+    let join_geom = transform_sql(
+        &format!("j.{}", quote_ident(&join.geometry_column)),
+        join.srid,
+        target.srid,
+    );
+
+    let mut select: Vec<String> = target_columns
+        .iter()
+        .map(|column| format!("t.{}", quote_ident(column)))
+        .collect();
+    select.extend(copied.iter().map(|column| {
+        format!(
+            "m.{} AS {}",
+            quote_ident(column),
+            quote_ident(&format!("{}{column}", spec.prefix))
+        )
+    }));
+    let mut lateral: Vec<String> = copied
+        .iter()
+        .map(|column| format!("j.{}", quote_ident(column)))
+        .collect();
+
+    let condition = match spec.predicate {
+        JoinPredicate::Intersects => format!("WHERE ST_Intersects({target_geom}, {join_geom})"),
+        JoinPredicate::Within => format!("WHERE ST_Within({target_geom}, {join_geom})"),
+        JoinPredicate::Nearest => {
+            lateral.push(format!(
+                "ST_Distance({target_geom}, {join_geom}) AS distance"
+            ));
+            select.push(format!(
+                "m.distance AS {}",
+                quote_ident(&format!("{}distance", spec.prefix))
+            ));
+            format!("ORDER BY {target_geom} <-> {join_geom}")
+        }
+    };
+    select.push(format!("{target_geom} AS geom"));
+    if lateral.is_empty() {
+        lateral.push("1".to_string());
+    }
+
+    let select_sql = format!(
+        "SELECT {} FROM {} t
+         LEFT JOIN LATERAL (SELECT {} FROM {} j {condition} LIMIT 1) m ON true",
+        select.join(", "),
+        target.qualified_name(),
+        lateral.join(", "),
+        join.qualified_name(),
+    );
+
+    create_derived_table(pool, output, &select_sql, target.srid).await
+}
@@ -0,0 +1,133 @@
+mod aggregate;
+mod dedupe;
+mod join;
+pub mod routing;
+
+pub use aggregate::*;
+pub use dedupe::*;
+pub use join::*;
+
+use crate::postgis::{create_derived_table, quote_ident, transform_sql, LayerTable};
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Geoprocessing operations run against a layer's table in PostGIS.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum Operation {
+    /// Buffer each feature by `distance` metres.
+    Buffer {
+        distance: f64,
+    },
+    Centroid,
+    /// A single hull around every feature in the layer.
+    ConvexHull,
+    /// Merge features sharing a value of `attribute`.
+    Dissolve {
+        attribute: String,
+    },
+    /// Cut features to the area covered by another layer.
+    Clip {
+        layer: String,
+    },
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Buffer { .. } => "buffer",
+            Operation::Centroid => "centroid",
+            Operation::ConvexHull => "convex_hull",
+            Operation::Dissolve { .. } => "dissolve",
+            Operation::Clip { .. } => "clip",
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Operation::Buffer { distance } if !distance.is_finite() || *distance <= 0.0 => {
+                Err(anyhow!("buffer distance must be a positive number"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Default name for a layer derived from `source_id`.
+pub fn output_name(source_id: &str, suffix: &str) -> String {
+    let base: String = source_id
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(40)
+        .collect();
+    let id = Uuid::new_v4().simple().to_string();
+    format!("{base}_{suffix}_{}", &id[..8])
+}
+
+/// Buffer in metres, going through geography when the SRID allows it.
+fn buffer_sql(geom: &str, srid: i32, distance: f64) -> String {
+    match srid {
+        0 => format!("ST_Buffer({geom}, {distance})"),
+        4326 => format!("ST_Buffer({geom}::geography, {distance})::geometry"),
+        srid => format!(
+            "ST_Transform(ST_Buffer(ST_Transform({geom}, 4326)::geography, {distance})::geometry, {srid})"
+        ),
+    }
+}
+
+#[instrument(skip(pool, operation), fields(operation = operation.name()))]
+pub async fn run(pool: &Pool, source_id: &str, operation: &Operation, output: &str) -> Result<()> {
+    let source = LayerTable::from_source_id(pool, source_id).await?;
+    let columns = source.attribute_columns(pool).await?;
+    let attributes: String = columns
+        .iter()
+        .map(|column| format!("t.{}, ", quote_ident(column)))
+        .collect();
+    let geom = format!("t.{}", quote_ident(&source.geometry_column));
+    let from = format!("{} t", source.qualified_name());
+
+    let select_sql = match operation {
+        Operation::Buffer { distance } => format!(
+            "SELECT {attributes}{} AS geom FROM {from}",
+            buffer_sql(&geom, source.srid, *distance)
+        ),
+        Operation::Centroid => {
+            format!("SELECT {attributes}ST_Centroid({geom}) AS geom FROM {from}")
+        }
+        Operation::ConvexHull => format!(
+            "SELECT count(*) AS feature_count, ST_ConvexHull(ST_Collect({geom})) AS geom FROM {from}"
+        ),
+        Operation::Dissolve { attribute } => {
+            if !columns.contains(attribute) {
+                return Err(anyhow!("unknown attribute {attribute}"));
+            }
+            let attribute = quote_ident(attribute);
+            format!(
+                "SELECT t.{attribute}, count(*) AS feature_count, ST_Union({geom}) AS geom
+                 FROM {from} GROUP BY t.{attribute}"
+            )
+        }
+        Operation::Clip { layer } => {
+            let clip = LayerTable::from_source_id(pool, layer).await?;
+            let clip_geom = transform_sql(
+                &format!("c.{}", quote_ident(&clip.geometry_column)),
+                clip.srid,
+                source.srid,
+            );
+            format!(
+                "SELECT {attributes}ST_Intersection({geom}, clip.geom) AS geom
+                 FROM {from}
+                 JOIN (SELECT ST_Union({clip_geom}) AS geom FROM {} c) clip
+                   ON ST_Intersects({geom}, clip.geom)",
+                clip.qualified_name()
+            )
+        }
+    };
+
+    create_derived_table(pool, output, &select_sql, source.srid).await
+}
@@ -0,0 +1,365 @@
+use crate::config::RoutingConfig;
+use crate::postgis::{quote_ident, valid_table_name};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Driving,
+    Cycling,
+    Walking,
+}
+
+/// A route as a GeoJSON LineString, in metres and seconds.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Route {
+    pub geometry: Value,
+    pub distance: f64,
+    pub duration: f64,
+}
+
+/// The area reachable within `minutes`, as a GeoJSON geometry.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Contour {
+    pub minutes: u32,
+    pub geometry: Value,
+}
+
+#[async_trait]
+pub trait RoutingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn route(
+        &self,
+        client: &reqwest::Client,
+        profile: Profile,
+        locations: &[[f64; 2]],
+    ) -> Result<Route>;
+    async fn isochrone(
+        &self,
+        client: &reqwest::Client,
+        profile: Profile,
+        location: [f64; 2],
+        minutes: &[u32],
+    ) -> Result<Vec<Contour>>;
+}
+
+pub struct RoutingService {
+    provider: Box<dyn RoutingProvider>,
+    client: reqwest::Client,
+}
+
+impl RoutingService {
+    /// Build from configuration. Routing is disabled when no URL is
+    /// configured.
+    pub fn from_config(config: &RoutingConfig) -> Result<Option<Self>> {
+        let Some(base_url) = &config.url else {
+            return Ok(None);
+        };
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let provider: Box<dyn RoutingProvider> = match config.provider.as_str() {
+            "osrm" => Box::new(Osrm { base_url }),
+            "valhalla" => Box::new(Valhalla { base_url }),
+            other => return Err(anyhow!("Unknown routing provider: {other}")),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Some(RoutingService { provider, client }))
+    }
+
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    pub async fn route(&self, profile: Profile, locations: &[[f64; 2]]) -> Result<Route> {
+        if locations.len() < 2 {
+            return Err(anyhow!("a route needs at least two locations"));
+        }
+        self.provider.route(&self.client, profile, locations).await
+    }
+
+    pub async fn isochrone(
+        &self,
+        profile: Profile,
+        location: [f64; 2],
+        minutes: &[u32],
+    ) -> Result<Vec<Contour>> {
+        if minutes.is_empty() || minutes.iter().any(|m| *m == 0 || *m > 120) {
+            return Err(anyhow!("contours must be between 1 and 120 minutes"));
+        }
+        self.provider
+            .isochrone(&self.client, profile, location, minutes)
+            .await
+    }
+}
+
+struct Osrm {
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct OsrmResponse {
+    code: String,
+    #[serde(default)]
+    routes: Vec<OsrmRoute>,
+}
+
+#[derive(Deserialize)]
+struct OsrmRoute {
+    geometry: Value,
+    distance: f64,
+    duration: f64,
+}
+
+#[async_trait]
+impl RoutingProvider for Osrm {
+    fn name(&self) -> &'static str {
+        "osrm"
+    }
+
+    async fn route(
+        &self,
+        client: &reqwest::Client,
+        profile: Profile,
+        locations: &[[f64; 2]],
+    ) -> Result<Route> {
+        let profile = match profile {
+            Profile::Driving => "driving",
+            Profile::Cycling => "cycling",
+            Profile::Walking => "foot",
+        };
+        let coordinates: Vec<String> = locations
+            .iter()
+            .map(|[lon, lat]| format!("{lon},{lat}"))
+            .collect();
+        let response: OsrmResponse = client
+            .get(format!(
+                "{}/route/v1/{profile}/{}",
+                self.base_url,
+                coordinates.join(";")
+            ))
+            .query(&[("overview", "full"), ("geometries", "geojson")])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let route = response
+            .routes
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("OSRM found no route: {}", response.code))?;
+        Ok(Route {
+            geometry: route.geometry,
+            distance: route.distance,
+            duration: route.duration,
+        })
+    }
+
+    async fn isochrone(
+        &self,
+        _client: &reqwest::Client,
+        _profile: Profile,
+        _location: [f64; 2],
+        _minutes: &[u32],
+    ) -> Result<Vec<Contour>> {
+        Err(anyhow!("OSRM does not support isochrones"))
+    }
+}
+
+struct Valhalla {
+    base_url: String,
+}
+
+fn costing(profile: Profile) -> &'static str {
+    match profile {
+        Profile::Driving => "auto",
+        Profile::Cycling => "bicycle",
+        Profile::Walking => "pedestrian",
+    }
+}
+
+/// Decode a Google-style encoded polyline into `[lon, lat]` pairs.
+fn decode_polyline(encoded: &str, precision: i32) -> Result<Vec<[f64; 2]>> {
+    let factor = 10f64.powi(precision);
+    let bytes = encoded.as_bytes();
+    let mut index = 0;
+    let (mut lat, mut lon) = (0i64, 0i64);
+    let mut coordinates = Vec::new();
+    while index < bytes.len() {
+        for value in [&mut lat, &mut lon] {
+            let mut shift = 0;
+            let mut result = 0i64;
+            loop {
+                let byte = *bytes
+                    .get(index)
+                    .ok_or_else(|| anyhow!("truncated polyline"))?
+                    as i64
+                    - 63;
+                index += 1;
+                result |= (byte & 0x1f) << shift;
+                shift += 5;
+                if byte < 0x20 {
+                    break;
+                }
+            }
+            *value += if result & 1 != 0 {
+                !(result >> 1)
+            } else {
+                result >> 1
+            };
+        }
+        coordinates.push([lon as f64 / factor, lat as f64 / factor]);
+    }
+    Ok(coordinates)
+}
+
+#[async_trait]
+impl RoutingProvider for Valhalla {
+    fn name(&self) -> &'static str {
+        "valhalla"
+    }
+
+    async fn route(
+        &self,
+        client: &reqwest::Client,
+        profile: Profile,
+        locations: &[[f64; 2]],
+    ) -> Result<Route> {
+        let locations: Vec<Value> = locations
+            .iter()
+            .map(|[lon, lat]| json!({ "lon": lon, "lat": lat }))
+            .collect();
+        let response: Value = client
+            .post(format!("{}/route", self.base_url))
+            .json(&json!({ "locations": locations, "costing": costing(profile) }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let trip = &response["trip"];
+        let mut coordinates = Vec::new();
+        for leg in trip["legs"].as_array().into_iter().flatten() {
+            let shape = leg["shape"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Valhalla leg has no shape"))?;
+            coordinates.extend(decode_polyline(shape, 6)?);
+        }
+        let summary = &trip["summary"];
+        Ok(Route {
+            geometry: json!({ "type": "LineString", "coordinates": coordinates }),
+            // Valhalla reports kilometres
+            distance: summary["length"].as_f64().unwrap_or_default() * 1000.0,
+            duration: summary["time"].as_f64().unwrap_or_default(),
+        })
+    }
+
+    async fn isochrone(
+        &self,
+        client: &reqwest::Client,
+        profile: Profile,
+        location: [f64; 2],
+        minutes: &[u32],
+    ) -> Result<Vec<Contour>> {
+        let contours: Vec<Value> = minutes.iter().map(|m| json!({ "time": m })).collect();
+        let response: Value = client
+            .post(format!("{}/isochrone", self.base_url))
+            .json(&json!({
+                "locations": [{ "lon": location[0], "lat": location[1] }],
+                "costing": costing(profile),
+                "contours": contours,
+                "polygons": true,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        response["features"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Valhalla returned no isochrones"))?
+            .iter()
+            .map(|feature| {
+                Ok(Contour {
+                    minutes: feature["properties"]["contour"]
+                        .as_f64()
+                        .ok_or_else(|| anyhow!("isochrone has no contour"))?
+                        as u32,
+                    geometry: feature["geometry"].clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Materialise a route as a single-feature layer.
+pub async fn save_route(pool: &Pool, name: &str, route: &Route) -> Result<()> {
+    let rows = vec![(
+        vec![route.distance, route.duration],
+        route.geometry.to_string(),
+    )];
+    save_layer(pool, name, &["distance_m", "duration_s"], rows).await
+}
+
+/// Materialise isochrone contours as a polygon layer.
+pub async fn save_isochrones(pool: &Pool, name: &str, contours: &[Contour]) -> Result<()> {
+    let rows = contours
+        .iter()
+        .map(|contour| (vec![contour.minutes as f64], contour.geometry.to_string()))
+        .collect();
+    save_layer(pool, name, &["minutes"], rows).await
+}
+
+async fn save_layer(
+    pool: &Pool,
+    name: &str,
+    columns: &[&str],
+    rows: Vec<(Vec<f64>, String)>,
+) -> Result<()> {
+    if !valid_table_name(name) {
+        return Err(anyhow!("invalid table name {name}"));
+    }
+    let table = format!("public.{}", quote_ident(name));
+    let definitions: String = columns
+        .iter()
+        .map(|column| format!("{} double precision, ", quote_ident(column)))
+        .collect();
+    let names: String = columns
+        .iter()
+        .map(|column| format!("{}, ", quote_ident(column)))
+        .collect();
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    transaction
+        .batch_execute(&format!(
+            "CREATE TABLE {table} ({definitions}geom geometry(Geometry, 4326));
+             CREATE INDEX ON {table} USING gist (geom);"
+        ))
+        .await?;
+    for (values, geometry) in rows {
+        let placeholders: String = (1..=values.len())
+            .map(|i| format!("($1::float8[])[{i}], "))
+            .collect();
+        transaction
+            .execute(
+                &format!(
+                    "INSERT INTO {table} ({names}geom)
+                     VALUES ({placeholders}ST_SetSRID(ST_GeomFromGeoJSON($2::text), 4326))"
+                ),
+                &[&values, &geometry],
+            )
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
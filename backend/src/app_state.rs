@@ -1,7 +1,57 @@
-use martin::Source;
-use std::collections::HashMap;
+use crate::alerts::Alerts;
+use crate::analysis::routing::RoutingService;
+use crate::cdn::Cdn;
+use crate::config::{BackupsConfig, LabelsConfig, LimitsConfig, SeedingConfig, SqlLayersConfig};
+use crate::core::{Basemap, EventBus, JobRunner};
+use crate::data::Database;
+use crate::geocoding::Geocoder;
+use crate::graphql::GraphqlSchema;
+use crate::query_cache::QueryCache;
+use crate::rate_limit::RateLimiter;
+use crate::remote::Fetcher;
+use crate::scanning::Scanning;
+use crate::security_headers::SecurityHeaders;
+use crate::signing::UrlSigner;
+use crate::sources::SourceRegistry;
+use crate::storage::Storage;
+use crate::tiling::TilingRegistry;
+use deadpool_postgres::Pool;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub sources: HashMap<String, Box<dyn Source>>,
+    pub app_data: Arc<dyn Database>,
+    pub geocoder: Arc<Geocoder>,
+    pub pg_pool: Pool,
+    /// Read-only connections with the `[postgres]` limits, for tiles,
+    /// search and lookups.
+    pub read_pool: Pool,
+    /// Sources queried for point-in-polygon lookups, e.g. admin areas.
+    pub boundary_layers: Vec<String>,
+    /// Routing engine proxy, when one is configured.
+    pub routing: Option<Arc<RoutingService>>,
+    /// Externally reachable base URL, used when emitting links to tiles.
+    pub public_url: String,
+    pub sources: Arc<SourceRegistry>,
+    pub tiling: Arc<TilingRegistry>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub security_headers: Arc<SecurityHeaders>,
+    pub metrics: PrometheusHandle,
+    pub jobs: JobRunner,
+    pub events: EventBus,
+    pub alerts: Arc<Alerts>,
+    pub signer: Arc<UrlSigner>,
+    pub cdn: Arc<Cdn>,
+    pub query_cache: Arc<QueryCache>,
+    pub storage: Arc<Storage>,
+    pub scanning: Arc<Scanning>,
+    pub backups: BackupsConfig,
+    pub fetcher: Arc<Fetcher>,
+    pub basemaps: Arc<Vec<Basemap>>,
+    pub labels: LabelsConfig,
+    pub limits: LimitsConfig,
+    pub seeding: SeedingConfig,
+    pub sql_layers: SqlLayersConfig,
+    pub graphql: GraphqlSchema,
 }
@@ -0,0 +1,293 @@
+use crate::data::Database;
+use crate::storage::BlobStore;
+use crate::validation::{Validate, Validator};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+const FORMAT: &str = "gridwalk-backup";
+/// Bumped when items can no longer be restored as they are.
+const VERSION: u32 = 1;
+
+/// Kinds of item in the table, by the prefix of their keys.
+const KINDS: [&str; 8] = [
+    "attachment",
+    "comment",
+    "connector",
+    "feature",
+    "job",
+    "layer",
+    "map",
+    "share",
+];
+
+/// First line of a backup.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    created_at: i64,
+    items: usize,
+}
+
+/// Each following line, as in DynamoDB's own exports to S3.
+#[derive(Debug, Serialize, Deserialize)]
+struct Line {
+    #[serde(rename = "Item")]
+    item: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackupSummary {
+    /// Name to restore the backup by.
+    pub name: String,
+    /// Items written to or restored from it.
+    pub items: usize,
+}
+
+/// Which items of a backup to restore. Items are matched by the kind and id
+/// in either of their keys, so a map comes back with its comments and share
+/// links.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RestoreFilter {
+    /// Kinds to restore, e.g. `map` or `layer`; every kind when empty.
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    /// Ids to restore, of whichever kinds are restored; every id when empty.
+    #[serde(default)]
+    pub ids: Vec<String>,
+}
+
+impl Validate for RestoreFilter {
+    fn check(&self, v: &mut Validator) {
+        for (index, kind) in self.kinds.iter().enumerate() {
+            v.check(
+                &format!("kinds[{index}]"),
+                KINDS.contains(&kind.as_str()),
+                "unknown_kind",
+                format!("must be one of {}", KINDS.join(", ")),
+            );
+        }
+    }
+}
+
+impl RestoreFilter {
+    fn matches_key(&self, key: &str) -> bool {
+        let Some((kind, id)) = key.split_once('#') else {
+            return false;
+        };
+        let kind = kind.to_lowercase();
+        (self.kinds.is_empty() || self.kinds.contains(&kind))
+            && (self.ids.is_empty() || self.ids.iter().any(|i| i == id))
+    }
+
+    fn matches(&self, item: &Value) -> bool {
+        ["PK", "SK"]
+            .iter()
+            .filter_map(|key| item.get(key)?.get("S")?.as_str())
+            .any(|key| self.matches_key(key))
+    }
+}
+
+/// Backups are named by when they were taken, so they sort by age, with a
+/// random suffix so two taken in the same second don't overwrite each other.
+fn new_name() -> String {
+    let suffix = rand::thread_rng().next_u32();
+    format!("{}{suffix:08x}", Utc::now().format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Names are used in object keys, so only those `new_name` makes are
+/// accepted.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn key(prefix: &str, name: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    if prefix.is_empty() {
+        format!("{name}.ndjson.gz")
+    } else {
+        format!("{prefix}/{name}.ndjson.gz")
+    }
+}
+
+fn encode(items: &[Value]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let header = Header {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        created_at: Utc::now().timestamp(),
+        items: items.len(),
+    };
+    serde_json::to_writer(&mut encoder, &header)?;
+    encoder.write_all(b"\n")?;
+    for item in items {
+        serde_json::to_writer(&mut encoder, &Line { item: item.clone() })?;
+        encoder.write_all(b"\n")?;
+    }
+    Ok(encoder.finish()?)
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<Value>> {
+    let mut lines = BufReader::new(GzDecoder::new(bytes)).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(anyhow!("Backup is empty")),
+    };
+    if header.format != FORMAT {
+        return Err(anyhow!("Not a backup: {}", header.format));
+    }
+    if header.version != VERSION {
+        return Err(anyhow!(
+            "Backup is version {}, this build restores version {VERSION}",
+            header.version
+        ));
+    }
+
+    let mut items = Vec::with_capacity(header.items);
+    for line in lines {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let line: Line = serde_json::from_str(&line)?;
+        items.push(line.item);
+    }
+    if items.len() != header.items {
+        return Err(anyhow!(
+            "Backup is truncated: {} of {} items",
+            items.len(),
+            header.items
+        ));
+    }
+    Ok(items)
+}
+
+/// Export every item in the store to object storage under `prefix`.
+pub async fn create(
+    database: &Arc<dyn Database>,
+    store: &dyn BlobStore,
+    prefix: &str,
+) -> Result<BackupSummary> {
+    let items = database.export_items().await?;
+    let body = encode(&items)?;
+    let name = new_name();
+    store
+        .put(&key(prefix, &name), body, "application/gzip")
+        .await?;
+    Ok(BackupSummary {
+        name,
+        items: items.len(),
+    })
+}
+
+/// The items of the named backup, or `None` if there is no such backup.
+pub async fn load(store: &dyn BlobStore, prefix: &str, name: &str) -> Result<Option<Vec<Value>>> {
+    if !valid_name(name) {
+        return Ok(None);
+    }
+    match store.get(&key(prefix, name)).await? {
+        Some(body) => Ok(Some(decode(&body)?)),
+        None => Ok(None),
+    }
+}
+
+/// Write the items `filter` matches back to the store, over whatever is
+/// there now. Servers cache some of them, so restart them afterwards.
+pub async fn restore(
+    database: &Arc<dyn Database>,
+    items: &[Value],
+    filter: &RestoreFilter,
+) -> Result<usize> {
+    filter.validate()?;
+    let items: Vec<Value> = items
+        .iter()
+        .filter(|item| filter.matches(item))
+        .cloned()
+        .collect();
+    Ok(database.import_items(&items).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn map_item(id: &str) -> Value {
+        json!({"PK": {"S": format!("MAP#{id}")}, "SK": {"S": format!("MAP#{id}")}})
+    }
+
+    #[test]
+    fn names_are_unique_and_usable_as_keys() {
+        let first = new_name();
+        let second = new_name();
+        assert_ne!(first, second);
+        assert!(valid_name(&first));
+        assert!(!valid_name("../etc/passwd"));
+        assert!(!valid_name(""));
+    }
+
+    #[test]
+    fn keys_are_under_the_prefix() {
+        assert_eq!(key("backups/", "x"), "backups/x.ndjson.gz");
+        assert_eq!(key("", "x"), "x.ndjson.gz");
+    }
+
+    #[test]
+    fn backups_round_trip() {
+        let items = vec![map_item("a"), map_item("b")];
+        assert_eq!(decode(&encode(&items).unwrap()).unwrap(), items);
+    }
+
+    #[test]
+    fn truncated_backups_are_rejected() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let header = Header {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            created_at: 0,
+            items: 2,
+        };
+        serde_json::to_writer(&mut encoder, &header).unwrap();
+        encoder.write_all(b"\n").unwrap();
+        serde_json::to_writer(
+            &mut encoder,
+            &Line {
+                item: map_item("a"),
+            },
+        )
+        .unwrap();
+        let error = decode(&encoder.finish().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{error}");
+    }
+
+    #[test]
+    fn restores_match_kind_and_id_in_either_key() {
+        let comment = json!({"PK": {"S": "MAP#a"}, "SK": {"S": "COMMENT#c"}});
+        let filter = RestoreFilter {
+            kinds: vec!["map".to_string()],
+            ids: vec!["a".to_string()],
+        };
+        assert!(filter.matches(&map_item("a")));
+        assert!(filter.matches(&comment));
+        assert!(!filter.matches(&map_item("b")));
+        assert!(RestoreFilter::default().matches(&map_item("b")));
+    }
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        let filter = RestoreFilter {
+            kinds: vec!["organisation".to_string()],
+            ids: Vec::new(),
+        };
+        assert!(filter.validate().is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use tracing::{error, info};
+
+use gridwalk_backend::{
+    backup::{self, RestoreFilter},
+    changes,
+    config::{self, Cli, Config},
+    core::Layer,
+    data::Dynamodb,
+    redact,
+    storage::{BlobStore, Storage},
+    telemetry,
+};
+
+/// Operational tasks against a deployment's DynamoDB table and PostGIS
+/// database, using the same configuration as the server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct AdminCli {
+    #[command(flatten)]
+    config: Cli,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create the DynamoDB table if it does not exist yet, and the change
+    /// log in PostGIS.
+    Migrate,
+    /// Recompute cached layer metadata, keeping catalog metadata. Reindexes
+    /// every PostGIS source when no ids are given.
+    Reindex { source_ids: Vec<String> },
+    /// Export every item in the DynamoDB table to object storage.
+    Backup,
+    /// Write the items of a backup over what is stored now; all of them
+    /// unless narrowed down. Restart the servers afterwards.
+    Restore {
+        /// Name printed when the backup was taken.
+        name: String,
+        /// Restore only items of this kind, e.g. `map` or `layer`.
+        #[arg(long = "kind")]
+        kinds: Vec<String>,
+        /// Restore only items with this id, e.g. a map id.
+        #[arg(long = "id")]
+        ids: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = AdminCli::parse();
+    let config = Config::load(&cli.config)?;
+    redact::set_enabled(config.redact_logs);
+    telemetry::init_tracing(None)?;
+
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .unwrap();
+
+    match cli.command {
+        Command::Migrate => {
+            Dynamodb::migrate(&config.dynamodb).await?;
+            info!("DynamoDB table {} is ready", config.dynamodb.table);
+            let pool = config::initialize_pg_pool(config.database_url.expose())?;
+            changes::migrate(&pool).await?;
+            info!("PostGIS change log is ready");
+        }
+        Command::Reindex { source_ids } => reindex(&config, source_ids).await?,
+        Command::Backup => {
+            let database = Dynamodb::new(&config.dynamodb).await?;
+            let storage = backup_storage(&config).await?;
+            let summary =
+                backup::create(&database, storage.as_ref(), &config.backups.prefix).await?;
+            info!("Backed up {} items to {}", summary.items, summary.name);
+        }
+        Command::Restore { name, kinds, ids } => {
+            let database = Dynamodb::new(&config.dynamodb).await?;
+            let storage = backup_storage(&config).await?;
+            let items = backup::load(storage.as_ref(), &config.backups.prefix, &name)
+                .await?
+                .ok_or_else(|| anyhow!("No backup named {name}"))?;
+            let restored =
+                backup::restore(&database, &items, &RestoreFilter { kinds, ids }).await?;
+            info!("Restored {restored} of {} items from {name}", items.len());
+        }
+    }
+    Ok(())
+}
+
+async fn backup_storage(config: &Config) -> Result<Box<dyn BlobStore>> {
+    Storage::from_config(&config.storage)
+        .await?
+        .into_store()
+        .ok_or_else(|| anyhow!("Backups need storage; storage.provider is none"))
+}
+
+async fn reindex(config: &Config, source_ids: Vec<String>) -> Result<()> {
+    let database = Dynamodb::new(&config.dynamodb).await?;
+    let pool = config::initialize_pg_pool(config.database_url.expose())?;
+    // SQL layers' views run with the read-only connections' limits
+    let sandboxed_url = config.postgres.sandboxed_url(config.database_url.expose());
+    let read_pool = config::initialize_pg_pool(&sandboxed_url)?;
+    let source_ids = if source_ids.is_empty() {
+        let mut ids: Vec<String> = config::initialize_pg_config(config.database_url.expose())
+            .await?
+            .iter()
+            .map(|source| source.get_id().to_string())
+            .collect();
+        ids.sort();
+        ids
+    } else {
+        source_ids
+    };
+
+    let mut failed = 0;
+    for source_id in &source_ids {
+        match Layer::reindex(&database, &pool, &read_pool, source_id).await {
+            Ok(layer) => info!("Reindexed {source_id}: {} features", layer.feature_count),
+            Err(e) => {
+                error!("Failed to reindex {source_id}: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} layers failed to reindex",
+            source_ids.len()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,431 @@
+use crate::postgis::{self, quote_ident, quote_literal, LayerTable};
+use anyhow::Result;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use tokio_postgres::Row;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Branches of layers, and for each the features of its parent as they
+/// were when it was made, as row hashes, to tell which side changed what.
+const BRANCHES_SQL: &str = "
+CREATE SCHEMA IF NOT EXISTS gridwalk;
+CREATE TABLE IF NOT EXISTS gridwalk.layer_branches (
+    source_id text PRIMARY KEY,
+    parent_id text NOT NULL,
+    name text NOT NULL,
+    status text NOT NULL DEFAULT 'open',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    merged_at timestamptz
+);
+CREATE INDEX IF NOT EXISTS layer_branches_parent_idx ON gridwalk.layer_branches (parent_id);
+CREATE TABLE IF NOT EXISTS gridwalk.branch_bases (
+    source_id text NOT NULL REFERENCES gridwalk.layer_branches ON DELETE CASCADE,
+    feature_id jsonb NOT NULL,
+    row_hash text NOT NULL,
+    PRIMARY KEY (source_id, feature_id)
+);
+";
+
+const BRANCH_COLUMNS: &str = "source_id, parent_id, name, status,
+    extract(epoch FROM created_at)::bigint, extract(epoch FROM merged_at)::bigint";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchStatus {
+    Open,
+    Merged,
+}
+
+/// A copy of a layer edited on its own and merged back into it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Branch {
+    /// The branch's own layer.
+    pub source_id: String,
+    /// The layer it was made from and merges into.
+    pub parent_id: String,
+    pub name: String,
+    pub status: BranchStatus,
+    pub created_at: i64,
+    pub merged_at: Option<i64>,
+}
+
+impl Branch {
+    fn from_row(row: &Row) -> Self {
+        Branch {
+            source_id: row.get(0),
+            parent_id: row.get(1),
+            name: row.get(2),
+            status: if row.get::<_, &str>(3) == "merged" {
+                BranchStatus::Merged
+            } else {
+                BranchStatus::Open
+            },
+            created_at: row.get(4),
+            merged_at: row.get(5),
+        }
+    }
+}
+
+/// What a branch changed since it was made. Features are WGS84 GeoJSON from
+/// the branch.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BranchDiff {
+    pub added: Vec<Value>,
+    pub modified: Vec<Value>,
+    /// Primary keys of features the branch deleted.
+    pub deleted: Vec<Value>,
+    /// Primary keys of features the parent also changed, differently.
+    /// These must be resolved before the branch can merge.
+    pub conflicts: Vec<Value>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct MergeReport {
+    pub added: u64,
+    pub modified: u64,
+    pub deleted: u64,
+}
+
+/// Why a merge failed.
+#[derive(Debug)]
+pub enum BranchError {
+    NotFound(&'static str),
+    /// The branch was merged already.
+    Merged,
+    /// Primary keys of features changed on both sides.
+    Conflicts(Vec<Value>),
+    /// The tables no longer line up, e.g. a column was dropped from one.
+    Invalid(String),
+    Failed(anyhow::Error),
+}
+
+impl fmt::Display for BranchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchError::NotFound(kind) => write!(f, "{kind} not found"),
+            BranchError::Merged => write!(f, "branch was merged already"),
+            BranchError::Conflicts(ids) => {
+                write!(f, "{} features changed on both sides", ids.len())
+            }
+            BranchError::Invalid(message) => write!(f, "invalid: {message}"),
+            BranchError::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for BranchError {}
+
+impl From<tokio_postgres::Error> for BranchError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        match e.as_db_error() {
+            Some(db) => BranchError::Invalid(db.message().to_string()),
+            None => BranchError::Failed(e.into()),
+        }
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for BranchError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        BranchError::Failed(e.into())
+    }
+}
+
+impl From<anyhow::Error> for BranchError {
+    fn from(e: anyhow::Error) -> Self {
+        BranchError::Failed(e)
+    }
+}
+
+/// SQL hashing each row of `table` by key. The geometry is hashed as EWKB
+/// under a fixed name, since a branch's geometry column may be named
+/// differently from its parent's.
+fn row_hashes_sql(table: &LayerTable, key: &str) -> String {
+    let geometry = quote_ident(&table.geometry_column);
+    format!(
+        "SELECT to_jsonb(t.{}) AS id,
+                md5(((to_jsonb(t) - {}) || jsonb_build_object('', t.{geometry}::text))::text) AS hash
+         FROM {} t",
+        quote_ident(key),
+        quote_literal(&table.geometry_column),
+        table.qualified_name(),
+    )
+}
+
+/// Copy `parent` into a new table `source_id` and record it as a branch. The
+/// branch's key takes its defaults from the parent's, so keys of features
+/// added on either side don't collide.
+#[instrument(skip(pool, parent), fields(parent = %parent.table))]
+pub async fn create(
+    pool: &Pool,
+    parent: &LayerTable,
+    parent_id: &str,
+    key: &str,
+    source_id: &str,
+    name: &str,
+) -> Result<()> {
+    postgis::copy_table(pool, parent, source_id).await?;
+    let branch = LayerTable::from_source_id(pool, source_id).await?;
+    let mut client = pool.get().await?;
+    let default: Option<String> = client
+        .query_one(
+            "SELECT column_default::text FROM information_schema.columns
+             WHERE table_schema = $1 AND table_name = $2 AND column_name = $3",
+            &[&parent.schema, &parent.table, &key],
+        )
+        .await?
+        .get(0);
+    let transaction = client.transaction().await?;
+    transaction.batch_execute(BRANCHES_SQL).await?;
+    if let Some(default) = default {
+        transaction
+            .batch_execute(&format!(
+                "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {default}",
+                branch.qualified_name(),
+                quote_ident(key)
+            ))
+            .await?;
+    }
+    transaction
+        .execute(
+            "INSERT INTO gridwalk.layer_branches (source_id, parent_id, name)
+             VALUES ($1, $2, $3)",
+            &[&source_id, &parent_id, &name],
+        )
+        .await?;
+    transaction
+        .execute(
+            &format!(
+                "INSERT INTO gridwalk.branch_bases (source_id, feature_id, row_hash)
+                 SELECT $1, id, hash FROM ({}) b",
+                row_hashes_sql(&branch, key)
+            ),
+            &[&source_id],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// The branch whose layer is `source_id`.
+#[instrument(skip(pool))]
+pub async fn get(pool: &Pool, source_id: &str) -> Result<Option<Branch>> {
+    let client = pool.get().await?;
+    client.batch_execute(BRANCHES_SQL).await?;
+    let row = client
+        .query_opt(
+            &format!("SELECT {BRANCH_COLUMNS} FROM gridwalk.layer_branches WHERE source_id = $1"),
+            &[&source_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(Branch::from_row))
+}
+
+/// Branches made from the layer, newest first.
+#[instrument(skip(pool))]
+pub async fn list(pool: &Pool, parent_id: &str) -> Result<Vec<Branch>> {
+    let client = pool.get().await?;
+    client.batch_execute(BRANCHES_SQL).await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {BRANCH_COLUMNS} FROM gridwalk.layer_branches
+                 WHERE parent_id = $1 ORDER BY created_at DESC"
+            ),
+            &[&parent_id],
+        )
+        .await?;
+    Ok(rows.iter().map(Branch::from_row).collect())
+}
+
+/// A feature the branch changed, by its row hash at the branch point, on
+/// the branch and on the parent; `None` where the feature is absent.
+struct Change {
+    id: Value,
+    base: Option<String>,
+    branch: Option<String>,
+    parent: Option<String>,
+}
+
+impl Change {
+    /// The parent changed the feature too, and not in the same way.
+    fn conflicts(&self) -> bool {
+        self.parent != self.base && self.parent != self.branch
+    }
+}
+
+async fn changes(
+    transaction: &Transaction<'_>,
+    source_id: &str,
+    branch: &LayerTable,
+    parent: &LayerTable,
+    key: &str,
+) -> Result<Vec<Change>, BranchError> {
+    let sql = format!(
+        "SELECT coalesce(base.feature_id, b.id), base.row_hash, b.hash, p.hash
+         FROM (SELECT feature_id, row_hash FROM gridwalk.branch_bases WHERE source_id = $1) base
+         FULL JOIN ({}) b ON b.id = base.feature_id
+         LEFT JOIN ({}) p ON p.id = coalesce(base.feature_id, b.id)
+         WHERE base.row_hash IS DISTINCT FROM b.hash",
+        row_hashes_sql(branch, key),
+        row_hashes_sql(parent, key),
+    );
+    let rows = transaction.query(&sql, &[&source_id]).await?;
+    Ok(rows
+        .iter()
+        .map(|row| Change {
+            id: row.get(0),
+            base: row.get(1),
+            branch: row.get(2),
+            parent: row.get(3),
+        })
+        .collect())
+}
+
+/// What the branch changed, and which of its changes conflict with the
+/// parent's since it was made.
+#[instrument(skip_all, fields(branch = %branch.table))]
+pub async fn diff(
+    pool: &Pool,
+    source_id: &str,
+    branch: &LayerTable,
+    parent: &LayerTable,
+    key: &str,
+) -> Result<BranchDiff, BranchError> {
+    let changes = {
+        let mut client = pool.get().await?;
+        let transaction = client.transaction().await?;
+        changes(&transaction, source_id, branch, parent, key).await?
+    };
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    let mut diff = BranchDiff::default();
+    for change in changes {
+        if change.conflicts() {
+            diff.conflicts.push(change.id.clone());
+        }
+        match (&change.base, &change.branch) {
+            (_, None) => diff.deleted.push(change.id),
+            (None, Some(_)) => added.push(change.id),
+            (Some(_), Some(_)) => modified.push(change.id),
+        }
+    }
+    diff.added = branch.features(pool, key, &added).await?;
+    diff.modified = branch.features(pool, key, &modified).await?;
+    Ok(diff)
+}
+
+/// Apply the branch's changes to its parent in one transaction, and mark
+/// it merged. Fails, changing nothing, if any of them conflict. Changes the
+/// parent already made identically are skipped.
+#[instrument(skip_all, fields(branch = %branch.table))]
+pub async fn merge(
+    pool: &Pool,
+    source_id: &str,
+    branch: &LayerTable,
+    parent: &LayerTable,
+    key: &str,
+) -> Result<MergeReport, BranchError> {
+    let columns = parent.attribute_columns(pool).await?;
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let status = transaction
+        .query_opt(
+            "SELECT status FROM gridwalk.layer_branches WHERE source_id = $1 FOR UPDATE",
+            &[&source_id],
+        )
+        .await?;
+    match status.as_ref().map(|row| row.get::<_, &str>(0)) {
+        None => return Err(BranchError::NotFound("Branch")),
+        Some("merged") => return Err(BranchError::Merged),
+        Some(_) => {}
+    }
+    // Hold off other writers to the parent until the merge is in
+    transaction
+        .batch_execute(&format!(
+            "LOCK TABLE {} IN SHARE ROW EXCLUSIVE MODE",
+            parent.qualified_name()
+        ))
+        .await?;
+
+    let changes = changes(&transaction, source_id, branch, parent, key).await?;
+    let conflicts: Vec<Value> = changes
+        .iter()
+        .filter(|change| change.conflicts())
+        .map(|change| change.id.clone())
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(BranchError::Conflicts(conflicts));
+    }
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    let mut deleted = Vec::new();
+    for change in changes.into_iter().filter(|c| c.parent != c.branch) {
+        match (&change.parent, &change.branch) {
+            (_, None) => deleted.push(change.id),
+            (None, Some(_)) => added.push(change.id),
+            (Some(_), Some(_)) => modified.push(change.id),
+        }
+    }
+
+    let key_column = quote_ident(key);
+    let mut targets: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+    targets.push(quote_ident(&parent.geometry_column));
+    let mut values: Vec<String> = columns
+        .iter()
+        .map(|c| format!("b.{}", quote_ident(c)))
+        .collect();
+    values.push(format!("b.{}", quote_ident(&branch.geometry_column)));
+
+    let deleted_count = transaction
+        .execute(
+            &format!(
+                "DELETE FROM {} p WHERE to_jsonb(p.{key_column}) = ANY($1)",
+                parent.qualified_name()
+            ),
+            &[&deleted],
+        )
+        .await?;
+    let modified_count = transaction
+        .execute(
+            &format!(
+                "UPDATE {} p SET ({}) = ({})
+                 FROM {} b
+                 WHERE b.{key_column} = p.{key_column} AND to_jsonb(b.{key_column}) = ANY($1)",
+                parent.qualified_name(),
+                targets.join(", "),
+                values.join(", "),
+                branch.qualified_name(),
+            ),
+            &[&modified],
+        )
+        .await?;
+    let added_count = transaction
+        .execute(
+            &format!(
+                "INSERT INTO {} ({}) OVERRIDING SYSTEM VALUE
+                 SELECT {} FROM {} b WHERE to_jsonb(b.{key_column}) = ANY($1)",
+                parent.qualified_name(),
+                targets.join(", "),
+                values.join(", "),
+                branch.qualified_name(),
+            ),
+            &[&added],
+        )
+        .await?;
+    transaction
+        .execute(
+            "UPDATE gridwalk.layer_branches SET status = 'merged', merged_at = now()
+             WHERE source_id = $1",
+            &[&source_id],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(MergeReport {
+        added: added_count,
+        modified: modified_count,
+        deleted: deleted_count,
+    })
+}
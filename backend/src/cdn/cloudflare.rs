@@ -0,0 +1,45 @@
+use super::{CacheKey, Purger};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::time::Duration;
+
+pub struct Cloudflare {
+    client: reqwest::Client,
+    zone_id: String,
+    api_token: String,
+}
+
+impl Cloudflare {
+    pub fn new(zone_id: String, api_token: String) -> Result<Self> {
+        Ok(Cloudflare {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            zone_id,
+            api_token,
+        })
+    }
+}
+
+#[async_trait]
+impl Purger for Cloudflare {
+    fn name(&self) -> &'static str {
+        "cloudflare"
+    }
+
+    async fn purge(&self, keys: &[CacheKey]) -> Result<()> {
+        let tags: Vec<String> = keys.iter().map(CacheKey::tag).collect();
+        self.client
+            .post(format!(
+                "https://api.cloudflare.com/client/v4/zones/{}/purge_cache",
+                self.zone_id
+            ))
+            .bearer_auth(&self.api_token)
+            .json(&json!({ "tags": tags }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use super::{CacheKey, Purger};
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_cloudfront::types::{InvalidationBatch, Paths};
+use aws_sdk_cloudfront::Client;
+use uuid::Uuid;
+
+/// CloudFront has no tag purging, so keys are invalidated by path. Uses the
+/// ambient AWS credentials.
+pub struct CloudFront {
+    client: Client,
+    distribution_id: String,
+}
+
+impl CloudFront {
+    pub async fn new(distribution_id: String) -> Self {
+        let sdk_config = aws_config::defaults(BehaviorVersion::latest()).load().await;
+        CloudFront {
+            client: Client::new(&sdk_config),
+            distribution_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Purger for CloudFront {
+    fn name(&self) -> &'static str {
+        "cloudfront"
+    }
+
+    async fn purge(&self, keys: &[CacheKey]) -> Result<()> {
+        let mut paths: Vec<String> = keys.iter().flat_map(CacheKey::paths).collect();
+        paths.sort();
+        paths.dedup();
+        let batch = InvalidationBatch::builder()
+            .paths(
+                Paths::builder()
+                    .quantity(paths.len() as i32)
+                    .set_items(Some(paths))
+                    .build()?,
+            )
+            .caller_reference(Uuid::new_v4().to_string())
+            .build()?;
+        self.client
+            .create_invalidation()
+            .distribution_id(&self.distribution_id)
+            .invalidation_batch(batch)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
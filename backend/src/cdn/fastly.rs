@@ -0,0 +1,44 @@
+use super::{CacheKey, Purger};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+pub struct Fastly {
+    client: reqwest::Client,
+    service_id: String,
+    api_token: String,
+}
+
+impl Fastly {
+    pub fn new(service_id: String, api_token: String) -> Result<Self> {
+        Ok(Fastly {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            service_id,
+            api_token,
+        })
+    }
+}
+
+#[async_trait]
+impl Purger for Fastly {
+    fn name(&self) -> &'static str {
+        "fastly"
+    }
+
+    async fn purge(&self, keys: &[CacheKey]) -> Result<()> {
+        let tags: Vec<String> = keys.iter().map(CacheKey::tag).collect();
+        self.client
+            .post(format!(
+                "https://api.fastly.com/service/{}/purge",
+                self.service_id
+            ))
+            .header("Fastly-Key", &self.api_token)
+            .header("Surrogate-Key", tags.join(" "))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
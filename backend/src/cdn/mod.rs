@@ -0,0 +1,251 @@
+mod cloudflare;
+mod cloudfront;
+mod fastly;
+
+pub use cloudflare::Cloudflare;
+pub use cloudfront::CloudFront;
+pub use fastly::Fastly;
+
+use crate::config::CdnConfig;
+use crate::core::{Event, EventBus};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use flate2::read::GzDecoder;
+use metrics::counter;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// What a cached response depends on, so it can be invalidated when that
+/// changes. Sent as surrogate keys; CDNs without tag purging invalidate by
+/// path instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheKey {
+    Layer(String),
+    Map(String),
+    Share(String),
+}
+
+impl CacheKey {
+    pub fn tag(&self) -> String {
+        match self {
+            CacheKey::Layer(id) => format!("layer-{id}"),
+            CacheKey::Map(id) => format!("map-{id}"),
+            CacheKey::Share(token) => format!("share-{token}"),
+        }
+    }
+
+    /// Paths to invalidate for CDNs that purge by path. Wildcards may only
+    /// end a path, so shared responses are invalidated wholesale.
+    pub fn paths(&self) -> Vec<String> {
+        match self {
+            CacheKey::Layer(id) => vec![
+                format!("/tiles/{id}/*"),
+                format!("/layers/{id}/aggregate/*"),
+                "/shared/*".to_string(),
+            ],
+            CacheKey::Map(id) => vec![format!("/maps/{id}/*"), "/shared/*".to_string()],
+            CacheKey::Share(token) => vec![format!("/shared/{token}/*")],
+        }
+    }
+}
+
+#[async_trait]
+pub trait Purger: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn purge(&self, keys: &[CacheKey]) -> Result<()>;
+}
+
+/// Cache headers for responses a CDN may store, and invalidation when the
+/// data behind them changes.
+pub struct Cdn {
+    purger: Option<Box<dyn Purger>>,
+    tile_max_age: u64,
+    style_max_age: u64,
+}
+
+impl Cdn {
+    pub async fn from_config(config: &CdnConfig) -> Result<Self> {
+        let purger: Option<Box<dyn Purger>> = match config.provider.as_str() {
+            "none" => None,
+            "fastly" => Some(Box::new(Fastly::new(
+                config.target()?,
+                config.api_token()?,
+            )?)),
+            "cloudflare" => Some(Box::new(Cloudflare::new(
+                config.target()?,
+                config.api_token()?,
+            )?)),
+            "cloudfront" => Some(Box::new(CloudFront::new(config.target()?).await)),
+            other => return Err(anyhow!("Unknown CDN provider: {other}")),
+        };
+        Ok(Cdn {
+            purger,
+            tile_max_age: config.tile_max_age,
+            style_max_age: config.style_max_age,
+        })
+    }
+
+    /// Tiles some sources store gzipped are passed through as-is to clients
+    /// that accept gzip and inflated for the rest. Raw tiles are left to the
+    /// compression layer.
+    pub fn tile_response(&self, headers: &HeaderMap, tile: Vec<u8>, keys: &[CacheKey]) -> Response {
+        let gzipped = tile.starts_with(&GZIP_MAGIC);
+        let (tile, encoded) = if gzipped && !accepts_gzip(headers) {
+            match inflate(&tile) {
+                Ok(tile) => (tile, false),
+                Err(e) => {
+                    warn!("Failed to inflate gzipped tile: {e}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+        } else {
+            (tile, gzipped)
+        };
+
+        let mut response = cached_response(
+            headers,
+            "application/vnd.mapbox-vector-tile",
+            tile,
+            self.tile_max_age,
+            keys,
+        );
+        let response_headers = response.headers_mut();
+        response_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+        if gzipped {
+            response_headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+        if encoded {
+            response_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        response
+    }
+
+    pub fn style_response(
+        &self,
+        headers: &HeaderMap,
+        style: &serde_json::Value,
+        keys: &[CacheKey],
+    ) -> Response {
+        let body = serde_json::to_vec(style).unwrap_or_default();
+        cached_response(headers, "application/json", body, self.style_max_age, keys)
+    }
+
+    /// Invalidate cached responses for `keys`. Failures are logged; cached
+    /// copies then expire on their own.
+    pub async fn purge(&self, keys: &[CacheKey]) {
+        let Some(purger) = &self.purger else {
+            return;
+        };
+        let outcome = match purger.purge(keys).await {
+            Ok(()) => {
+                info!("Purged {} CDN cache keys via {}", keys.len(), purger.name());
+                "ok"
+            }
+            Err(e) => {
+                warn!("CDN purge via {} failed: {e:#}", purger.name());
+                "error"
+            }
+        };
+        counter!("cdn_purges_total", "provider" => purger.name(), "outcome" => outcome)
+            .increment(1);
+    }
+
+    /// Purge layers and maps whenever they change.
+    pub fn purge_on_changes(self: Arc<Self>, events: &EventBus) {
+        if self.purger.is_none() {
+            return;
+        }
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let key = match receiver.recv().await {
+                    Ok(Event::LayerUpdated { layer }) => CacheKey::Layer(layer.id),
+                    Ok(Event::LayerDeleted { layer_id }) => CacheKey::Layer(layer_id),
+                    Ok(Event::MapUpdated { map }) => CacheKey::Map(map.id),
+                    Ok(Event::MapDeleted { map_id }) => CacheKey::Map(map_id),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Missed {missed} events; some CDN entries may be stale until they expire");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                self.purge(&[key]).await;
+            }
+        });
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|encoding| {
+                let mut parts = encoding.split(';');
+                let name = parts.next().unwrap_or_default().trim();
+                let refused = parts.any(|param| param.trim().replace(' ', "") == "q=0");
+                (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+            })
+        })
+}
+
+fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut inflated)?;
+    Ok(inflated)
+}
+
+/// Attach an ETag, Cache-Control and surrogate keys, answering with 304
+/// when the client already holds this version.
+fn cached_response(
+    headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+    max_age: u64,
+    keys: &[CacheKey],
+) -> Response {
+    let digest = Sha256::digest(&body);
+    // Weak, as the compression layer may serve encoded variants of the body
+    let etag = format!("W/\"{}\"", hex::encode(&digest[..16]));
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, content_type)], body).into_response()
+    };
+
+    let tags: Vec<String> = keys.iter().map(CacheKey::tag).collect();
+    let response_headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={max_age}")) {
+        response_headers.insert(header::CACHE_CONTROL, value);
+    }
+    // Fastly reads space-separated Surrogate-Key, Cloudflare comma-separated
+    // Cache-Tag
+    if let Ok(value) = HeaderValue::from_str(&tags.join(" ")) {
+        response_headers.insert("surrogate-key", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&tags.join(",")) {
+        response_headers.insert("cache-tag", value);
+    }
+    response
+}
@@ -0,0 +1,413 @@
+use crate::postgis::{transform_sql, LayerTable};
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Log of row changes written by a trigger on each tracked layer table.
+/// Rows are stored whole, geometry included, in the table's own SRID. Edits
+/// applied through sync are remembered by client id so retries are not
+/// applied twice.
+///
+/// Ids are taken from a sequence before commit, so a transaction committing
+/// after one that started later would land behind readers' cursors. The
+/// trigger holds a per-table lock until commit, so each table's ids are in
+/// commit order and a cursor never passes a change yet to be committed.
+const CHANGE_LOG_SQL: &str = "
+CREATE SCHEMA IF NOT EXISTS gridwalk;
+CREATE TABLE IF NOT EXISTS gridwalk.feature_changes (
+    id bigserial PRIMARY KEY,
+    table_schema text NOT NULL,
+    table_name text NOT NULL,
+    operation text NOT NULL,
+    old_row jsonb,
+    new_row jsonb,
+    changed_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS feature_changes_table_idx
+    ON gridwalk.feature_changes (table_schema, table_name, id);
+CREATE TABLE IF NOT EXISTS gridwalk.sync_edits (
+    table_schema text NOT NULL,
+    table_name text NOT NULL,
+    client_id text NOT NULL,
+    feature_id jsonb NOT NULL,
+    applied_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (table_schema, table_name, client_id)
+);
+CREATE OR REPLACE FUNCTION gridwalk.record_feature_change() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    PERFORM pg_advisory_xact_lock(hashtext('gridwalk.feature_changes'),
+                                  hashtext(TG_TABLE_SCHEMA || '.' || TG_TABLE_NAME));
+    INSERT INTO gridwalk.feature_changes (table_schema, table_name, operation, old_row, new_row)
+    VALUES (
+        TG_TABLE_SCHEMA,
+        TG_TABLE_NAME,
+        lower(TG_OP),
+        CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END,
+        CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END
+    );
+    RETURN NULL;
+END
+$$;
+";
+
+/// The position the change log was at when each version of a tracked
+/// layer was written, so versions can be compared.
+const LAYER_VERSIONS_SQL: &str = "
+CREATE TABLE IF NOT EXISTS gridwalk.layer_versions (
+    table_schema text NOT NULL,
+    table_name text NOT NULL,
+    version bigint NOT NULL,
+    change_id bigint NOT NULL,
+    PRIMARY KEY (table_schema, table_name, version)
+);
+";
+
+const TRIGGER_NAME: &str = "gridwalk_feature_changes";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl ChangeOperation {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "insert" => Some(ChangeOperation::Insert),
+            "update" => Some(ChangeOperation::Update),
+            "delete" => Some(ChangeOperation::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// One feature-level change. Features are GeoJSON in WGS84; `old` is absent
+/// for inserts and `new` for deletes.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeatureChange {
+    /// Position in the feed, usable as the next `since` cursor.
+    pub id: i64,
+    pub operation: ChangeOperation,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+    pub changed_at: i64,
+}
+
+/// Create the change log, and update its trigger function. Run once at
+/// startup.
+pub async fn migrate(pool: &Pool) -> Result<()> {
+    let client = pool.get().await?;
+    client.batch_execute(CHANGE_LOG_SQL).await?;
+    client.batch_execute(LAYER_VERSIONS_SQL).await?;
+    Ok(())
+}
+
+/// Start recording changes to the table. Idempotent.
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn track(pool: &Pool, table: &LayerTable) -> Result<()> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let table = table.qualified_name();
+    transaction
+        .batch_execute(&format!(
+            "DROP TRIGGER IF EXISTS {TRIGGER_NAME} ON {table};
+             CREATE TRIGGER {TRIGGER_NAME}
+             AFTER INSERT OR UPDATE OR DELETE ON {table}
+             FOR EACH ROW EXECUTE PROCEDURE gridwalk.record_feature_change();"
+        ))
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn is_tracked(pool: &Pool, table: &LayerTable) -> Result<bool> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "SELECT EXISTS (
+                 SELECT 1 FROM pg_trigger t
+                 JOIN pg_class c ON c.oid = t.tgrelid
+                 JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE n.nspname = $1 AND c.relname = $2 AND t.tgname = $3
+             )",
+            &[&table.schema, &table.table, &TRIGGER_NAME],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Values of `key` for features changed after the `since` cursor, as JSON
+/// text.
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn touched_since(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    since: i64,
+) -> Result<HashSet<String>> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            "SELECT DISTINCT coalesce(new_row->$4::text, old_row->$4::text)::text
+             FROM gridwalk.feature_changes
+             WHERE table_schema = $1 AND table_name = $2 AND id > $3",
+            &[&table.schema, &table.table, &since, &key],
+        )
+        .await?;
+    Ok(rows.iter().filter_map(|row| row.get(0)).collect())
+}
+
+/// SQL for a logged row as a WGS84 GeoJSON feature, or null. The geometry
+/// column's name is in parameter `$param`.
+fn feature_sql(table: &LayerTable, row: &str, param: usize) -> String {
+    let geometry = transform_sql(
+        &format!(
+            "ST_SetSRID(ST_GeomFromGeoJSON({row}->>${param}::text), {})",
+            table.srid
+        ),
+        table.srid,
+        4326,
+    );
+    format!(
+        "CASE WHEN {row} IS NOT NULL THEN jsonb_build_object(
+             'type', 'Feature',
+             'properties', {row} - ${param}::text,
+             'geometry', ST_AsGeoJSON({geometry})::jsonb
+         ) END"
+    )
+}
+
+/// Changes to the table after the `since` cursor, oldest first.
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn since(
+    pool: &Pool,
+    table: &LayerTable,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<FeatureChange>> {
+    let sql = format!(
+        "SELECT id, operation, {}, {}, extract(epoch FROM changed_at)::bigint
+         FROM gridwalk.feature_changes
+         WHERE table_schema = $1 AND table_name = $2 AND id > $3
+         ORDER BY id
+         LIMIT $4",
+        feature_sql(table, "old_row", 5),
+        feature_sql(table, "new_row", 5),
+    );
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &table.schema,
+                &table.table,
+                &since,
+                &limit,
+                &table.geometry_column,
+            ],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            Some(FeatureChange {
+                id: row.get(0),
+                operation: ChangeOperation::parse(row.get(1))?,
+                old: row.get(2),
+                new: row.get(3),
+                changed_at: row.get(4),
+            })
+        })
+        .collect())
+}
+
+/// The newest position in the table's change log, 0 when it is empty.
+async fn head(client: &deadpool_postgres::Client, table: &LayerTable) -> Result<i64> {
+    let row = client
+        .query_one(
+            "SELECT coalesce(max(id), 0) FROM gridwalk.feature_changes
+             WHERE table_schema = $1 AND table_name = $2",
+            &[&table.schema, &table.table],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Record that `version` of the layer covers its changes so far. Does
+/// nothing for tables whose changes aren't tracked.
+#[instrument(skip(pool))]
+pub async fn mark_version(pool: &Pool, source_id: &str, version: u64) -> Result<()> {
+    let table = LayerTable::from_source_id(pool, source_id).await?;
+    if !is_tracked(pool, &table).await? {
+        return Ok(());
+    }
+    let client = pool.get().await?;
+    let change_id = head(&client, &table).await?;
+    client
+        .execute(
+            "INSERT INTO gridwalk.layer_versions (table_schema, table_name, version, change_id)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (table_schema, table_name, version)
+             DO UPDATE SET change_id = EXCLUDED.change_id",
+            &[&table.schema, &table.table, &(version as i64), &change_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The change log position of `version`: that of the newest recorded
+/// version at or before it, as versions that only changed settings are not
+/// recorded. `None` for versions from before changes were tracked.
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn version_position(
+    pool: &Pool,
+    table: &LayerTable,
+    version: u64,
+) -> Result<Option<i64>> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            "SELECT change_id FROM gridwalk.layer_versions
+             WHERE table_schema = $1 AND table_name = $2 AND version <= $3
+             ORDER BY version DESC LIMIT 1",
+            &[&table.schema, &table.table, &(version as i64)],
+        )
+        .await?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+/// A value before and after.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ValueDelta {
+    pub from: Value,
+    pub to: Value,
+}
+
+/// A feature that differs between two versions.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FeatureDelta {
+    pub id: Value,
+    /// The feature at the later version, as WGS84 GeoJSON.
+    pub feature: Value,
+    /// Properties that changed, by name; absent ones are null.
+    pub attributes: BTreeMap<String, ValueDelta>,
+    /// The GeoJSON geometry before and after, when it moved.
+    pub geometry: Option<ValueDelta>,
+}
+
+/// How the layer's features differ between two versions.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct LayerDiff {
+    /// Features as at the later version, as WGS84 GeoJSON.
+    pub added: Vec<Value>,
+    /// Features as at the earlier version, as WGS84 GeoJSON.
+    pub removed: Vec<Value>,
+    pub modified: Vec<FeatureDelta>,
+    /// More features changed than were read; compare closer versions.
+    pub truncated: bool,
+}
+
+fn delta(id: Value, old: &Value, new: Value) -> Option<FeatureDelta> {
+    let properties = |feature: &Value| {
+        feature["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default()
+    };
+    let old_properties = properties(old);
+    let new_properties = properties(&new);
+    let mut attributes = BTreeMap::new();
+    for name in old_properties.keys().chain(new_properties.keys()) {
+        let from = old_properties.get(name).cloned().unwrap_or(Value::Null);
+        let to = new_properties.get(name).cloned().unwrap_or(Value::Null);
+        if from != to {
+            attributes.insert(name.clone(), ValueDelta { from, to });
+        }
+    }
+    let geometry = (old["geometry"] != new["geometry"]).then(|| ValueDelta {
+        from: old["geometry"].clone(),
+        to: new["geometry"].clone(),
+    });
+    if attributes.is_empty() && geometry.is_none() {
+        return None;
+    }
+    Some(FeatureDelta {
+        id,
+        feature: new,
+        attributes,
+        geometry,
+    })
+}
+
+/// The net difference between two change log positions: each feature
+/// changed in between, compared as it was at `from` and at `to`. At most
+/// `limit` features are read.
+#[instrument(skip_all, fields(table = %table.table))]
+pub async fn diff(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    from: i64,
+    to: Option<i64>,
+    limit: i64,
+) -> Result<LayerDiff> {
+    let client = pool.get().await?;
+    let to = match to {
+        Some(to) => to,
+        None => head(&client, table).await?,
+    };
+    let sql = format!(
+        "SELECT feature_id, {}, {}
+         FROM (
+             SELECT coalesce(new_row->$4::text, old_row->$4::text) AS feature_id,
+                    (array_agg(old_row ORDER BY id))[1] AS old_row,
+                    (array_agg(new_row ORDER BY id DESC))[1] AS new_row
+             FROM gridwalk.feature_changes
+             WHERE table_schema = $1 AND table_name = $2 AND id > $3 AND id <= $5
+             GROUP BY 1
+         ) c
+         ORDER BY feature_id
+         LIMIT $7",
+        feature_sql(table, "old_row", 6),
+        feature_sql(table, "new_row", 6),
+    );
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &table.schema,
+                &table.table,
+                &from,
+                &key,
+                &to,
+                &table.geometry_column,
+                &(limit + 1),
+            ],
+        )
+        .await?;
+    let mut diff = LayerDiff {
+        truncated: rows.len() as i64 > limit,
+        ..LayerDiff::default()
+    };
+    for row in rows.iter().take(limit as usize) {
+        let id: Value = row.get(0);
+        let old: Option<Value> = row.get(1);
+        let new: Option<Value> = row.get(2);
+        match (old, new) {
+            (None, Some(new)) => diff.added.push(new),
+            (Some(old), None) => diff.removed.push(old),
+            (Some(old), Some(new)) => diff.modified.extend(delta(id, &old, new)),
+            // Added and removed again in between
+            (None, None) => {}
+        }
+    }
+    Ok(diff)
+}
@@ -0,0 +1,79 @@
+use crate::data::{DataResult, Database};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A file, such as an inspection photo, attached to one feature of a layer.
+/// The bytes live in object storage; this is the record pointing at them.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Attachment {
+    pub id: String,
+    pub layer_id: String,
+    /// Primary key of the feature, as text.
+    pub feature_id: String,
+    pub filename: String,
+    pub content_type: String,
+    /// Size in bytes.
+    pub size: i64,
+    /// Whether a JPEG thumbnail was generated, for images.
+    pub has_thumbnail: bool,
+    pub created_at: i64,
+}
+
+impl Attachment {
+    pub fn new(
+        layer_id: &str,
+        feature_id: &str,
+        filename: &str,
+        content_type: &str,
+        size: i64,
+    ) -> Self {
+        Attachment {
+            id: Uuid::new_v4().to_string(),
+            layer_id: layer_id.to_string(),
+            feature_id: feature_id.to_string(),
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            size,
+            has_thumbnail: false,
+            created_at: Utc::now().timestamp(),
+        }
+    }
+
+    /// Object key of the file, relative to the storage prefix. Feature ids
+    /// are left out as they may contain any character.
+    pub fn object_key(&self) -> String {
+        format!("{}/{}", self.layer_id, self.id)
+    }
+
+    pub fn thumbnail_key(&self) -> String {
+        format!("{}/{}.thumb.jpg", self.layer_id, self.id)
+    }
+
+    pub async fn from_id(
+        database: &Arc<dyn Database>,
+        layer_id: &str,
+        feature_id: &str,
+        id: &str,
+    ) -> DataResult<Self> {
+        database.get_attachment(layer_id, feature_id, id).await
+    }
+
+    pub async fn for_feature(
+        database: &Arc<dyn Database>,
+        layer_id: &str,
+        feature_id: &str,
+    ) -> DataResult<Vec<Self>> {
+        database.get_attachments(layer_id, feature_id).await
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.create_attachment(self).await
+    }
+
+    pub async fn delete(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.delete_attachment(self).await
+    }
+}
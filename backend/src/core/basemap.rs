@@ -0,0 +1,46 @@
+use crate::config::BasemapConfig;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A background map the frontend can draw layers over, configured for the
+/// deployment.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Basemap {
+    pub id: String,
+    pub name: String,
+    /// MapLibre style URL, with any provider API key filled in. Keys reach
+    /// browsers this way, so use ones restricted to the site's origin.
+    pub style_url: String,
+    pub attribution: Option<String>,
+    /// Used for maps that don't pick a basemap.
+    pub default: bool,
+}
+
+impl Basemap {
+    /// The configured basemaps, the first being the default unless another
+    /// is marked as such.
+    pub fn from_config(configs: &[BasemapConfig]) -> Vec<Basemap> {
+        let default = configs.iter().position(|c| c.default).unwrap_or(0);
+        configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| Basemap {
+                id: config.id.clone(),
+                name: config.name.clone(),
+                style_url: match &config.api_key {
+                    Some(key) => config.style_url.replace("{key}", key.expose()),
+                    None => config.style_url.clone(),
+                },
+                attribution: config.attribution.clone(),
+                default: index == default,
+            })
+            .collect()
+    }
+
+    /// The basemap for a map: its own choice, or the default when it has
+    /// none or its choice has since been removed from the config.
+    pub fn for_map<'a>(basemaps: &'a [Basemap], id: Option<&str>) -> Option<&'a Basemap> {
+        id.and_then(|id| basemaps.iter().find(|basemap| basemap.id == id))
+            .or_else(|| basemaps.iter().find(|basemap| basemap.default))
+    }
+}
@@ -0,0 +1,160 @@
+use crate::data::{DataResult, Database};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Where on a map a comment thread is pinned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommentAnchor {
+    /// A feature, by the primary key of its layer's table.
+    Feature {
+        layer_id: String,
+        feature_id: String,
+    },
+    /// A WGS84 coordinate.
+    Point { lon: f64, lat: f64 },
+}
+
+impl Validate for CommentAnchor {
+    fn check(&self, v: &mut Validator) {
+        match self {
+            CommentAnchor::Feature { feature_id, .. } => {
+                v.length("feature_id", feature_id, 1, usize::MAX)
+            }
+            CommentAnchor::Point { lon, lat } => v.lon_lat("", [*lon, *lat]),
+        }
+    }
+}
+
+/// A comment on a map, for review workflows. Comments without a parent start
+/// a thread, optionally pinned to an anchor, and only threads are resolved.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Comment {
+    pub id: String,
+    pub map_id: String,
+    /// The comment starting the thread; `None` for that comment itself.
+    pub parent_id: Option<String>,
+    pub author: String,
+    pub body: String,
+    pub anchor: Option<CommentAnchor>,
+    /// Names mentioned in the body as `@name`.
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    #[serde(default)]
+    pub resolved: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A thread: its first comment and the replies to it, oldest first.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CommentThread {
+    pub comment: Comment,
+    pub replies: Vec<Comment>,
+}
+
+const MAX_AUTHOR_LENGTH: usize = 100;
+const MAX_BODY_LENGTH: usize = 10_000;
+
+/// Names mentioned as `@name`, in order of first mention.
+fn parse_mentions(body: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    for word in body.split(|c: char| c.is_whitespace() || c == ',' || c == ';') {
+        let Some(name) = word.strip_prefix('@') else {
+            continue;
+        };
+        let name = name.trim_end_matches(['.', ':', '!', '?', ')']);
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if valid && !mentions.iter().any(|m| m == name) {
+            mentions.push(name.to_string());
+        }
+    }
+    mentions
+}
+
+impl Comment {
+    pub fn new(
+        map_id: &str,
+        parent_id: Option<String>,
+        author: &str,
+        body: &str,
+        anchor: Option<CommentAnchor>,
+    ) -> Result<Self, ValidationErrors> {
+        let author = author.trim();
+        let body = body.trim();
+        let mut v = Validator::default();
+        v.length("author", author, 1, MAX_AUTHOR_LENGTH);
+        v.length("body", body, 1, MAX_BODY_LENGTH);
+        if let Some(anchor) = &anchor {
+            v.check(
+                "anchor",
+                parent_id.is_none(),
+                "not_allowed",
+                "replies take their thread's anchor",
+            );
+            v.nested("anchor", anchor);
+        }
+        v.finish()?;
+        let now = Utc::now().timestamp();
+        Ok(Comment {
+            id: Uuid::new_v4().to_string(),
+            map_id: map_id.to_string(),
+            parent_id,
+            author: author.to_string(),
+            body: body.to_string(),
+            anchor,
+            mentions: parse_mentions(body),
+            resolved: false,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.create_comment(self).await
+    }
+
+    pub async fn from_id(database: &Arc<dyn Database>, map_id: &str, id: &str) -> DataResult<Self> {
+        database.get_comment(map_id, id).await
+    }
+
+    /// The map's comments grouped into threads, oldest thread first.
+    pub async fn threads(
+        database: &Arc<dyn Database>,
+        map_id: &str,
+    ) -> DataResult<Vec<CommentThread>> {
+        let mut comments = database.get_comments(map_id).await?;
+        comments.sort_by_key(|comment| comment.created_at);
+        let (roots, replies): (Vec<_>, Vec<_>) = comments
+            .into_iter()
+            .partition(|comment| comment.parent_id.is_none());
+        Ok(roots
+            .into_iter()
+            .map(|comment| CommentThread {
+                replies: replies
+                    .iter()
+                    .filter(|reply| reply.parent_id.as_deref() == Some(&comment.id))
+                    .cloned()
+                    .collect(),
+                comment,
+            })
+            .collect())
+    }
+
+    pub async fn set_resolved(
+        &mut self,
+        database: &Arc<dyn Database>,
+        resolved: bool,
+    ) -> DataResult<()> {
+        self.resolved = resolved;
+        self.updated_at = Utc::now().timestamp();
+        database.update_comment(self).await
+    }
+}
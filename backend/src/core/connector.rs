@@ -0,0 +1,156 @@
+use crate::data::{DataResult, Database};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// An external feature service harvested by a connector.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConnectorSource {
+    /// A feature type of a WFS 2.0 service that can return GeoJSON.
+    Wfs { url: String, type_name: String },
+    /// A layer of an ArcGIS FeatureServer or MapServer, e.g.
+    /// `https://example.com/arcgis/rest/services/Trees/FeatureServer/0`.
+    Arcgis { url: String },
+}
+
+impl ConnectorSource {
+    /// URL of the page of features starting at `offset`, as WGS84 GeoJSON.
+    pub fn page_url(&self, offset: usize, limit: usize) -> Result<Url> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        match self {
+            ConnectorSource::Wfs { url, type_name } => {
+                let mut url = Url::parse(url)?;
+                url.query_pairs_mut().extend_pairs([
+                    ("service", "WFS"),
+                    ("version", "2.0.0"),
+                    ("request", "GetFeature"),
+                    ("typeNames", type_name),
+                    ("outputFormat", "application/json"),
+                    ("srsName", "EPSG:4326"),
+                    ("startIndex", &offset),
+                    ("count", &limit),
+                ]);
+                Ok(url)
+            }
+            ConnectorSource::Arcgis { url } => {
+                let mut url = Url::parse(&format!("{}/query", url.trim_end_matches('/')))?;
+                url.query_pairs_mut().extend_pairs([
+                    ("where", "1=1"),
+                    ("outFields", "*"),
+                    ("outSR", "4326"),
+                    ("f", "geojson"),
+                    ("resultOffset", &offset),
+                    ("resultRecordCount", &limit),
+                ]);
+                Ok(url)
+            }
+        }
+    }
+}
+
+impl Validate for ConnectorSource {
+    fn check(&self, v: &mut Validator) {
+        match self {
+            ConnectorSource::Wfs { url, type_name } => {
+                v.http_url("url", url);
+                v.length("type_name", type_name, 1, usize::MAX);
+            }
+            ConnectorSource::Arcgis { url } => v.http_url("url", url),
+        }
+    }
+}
+
+/// Keeps a layer in step with an external feature service, such as a
+/// council's open-data server. Each harvest replaces the layer's table with
+/// a fresh copy.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Connector {
+    pub id: String,
+    pub source: ConnectorSource,
+    /// Table the features are written to, published as the layer of the
+    /// same name.
+    pub layer_id: String,
+    /// Seconds between scheduled harvests; `None` only harvests on request.
+    pub refresh_interval: Option<u64>,
+    pub last_harvested_at: Option<i64>,
+    /// Why the last harvest failed, if it did.
+    pub last_error: Option<String>,
+    pub created_at: i64,
+}
+
+/// Harvests page through whole services, so they are not scheduled more
+/// often than this.
+const MIN_REFRESH_INTERVAL: u64 = 300;
+
+impl Connector {
+    pub fn new(
+        source: ConnectorSource,
+        layer_id: &str,
+        refresh_interval: Option<u64>,
+    ) -> Result<Self, ValidationErrors> {
+        let mut v = Validator::default();
+        v.nested("source", &source);
+        v.table_name("layer_id", layer_id);
+        if let Some(interval) = refresh_interval {
+            v.check(
+                "refresh_interval",
+                interval >= MIN_REFRESH_INTERVAL,
+                "out_of_range",
+                format!("must be at least {MIN_REFRESH_INTERVAL} seconds"),
+            );
+        }
+        v.finish()?;
+        Ok(Connector {
+            id: Uuid::new_v4().to_string(),
+            source,
+            layer_id: layer_id.to_string(),
+            refresh_interval,
+            last_harvested_at: None,
+            last_error: None,
+            created_at: Utc::now().timestamp(),
+        })
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.put_connector(self).await
+    }
+
+    pub async fn from_id(database: &Arc<dyn Database>, id: &str) -> DataResult<Self> {
+        database.get_connector(id).await
+    }
+
+    pub async fn get_all(database: &Arc<dyn Database>) -> DataResult<Vec<Self>> {
+        database.get_connectors().await
+    }
+
+    /// Stop harvesting. The layer is left as last harvested.
+    pub async fn delete(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.delete_connector(&self.id).await
+    }
+
+    /// Whether a scheduled harvest is due at `now`.
+    pub fn is_due(&self, now: i64) -> bool {
+        match (self.refresh_interval, self.last_harvested_at) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last)) => now - last >= interval as i64,
+        }
+    }
+
+    /// Record the outcome of a harvest.
+    pub async fn finish(
+        &mut self,
+        database: &Arc<dyn Database>,
+        error: Option<String>,
+    ) -> DataResult<()> {
+        self.last_harvested_at = Some(Utc::now().timestamp());
+        self.last_error = error;
+        database.put_connector(self).await
+    }
+}
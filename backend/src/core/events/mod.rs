@@ -0,0 +1,196 @@
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "redis")]
+mod redis;
+
+use crate::alerts::Alert;
+use crate::config::EventsConfig;
+use crate::core::{Comment, Job, Layer, Map, ShareResource};
+use crate::schedules::ScheduleRun;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Events buffered per subscriber; slower subscribers miss older events.
+const CAPACITY: usize = 256;
+
+/// A change clients may want to react to without polling. Published by the
+/// stores whenever they write, see `data::PublishingStore`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Layer metadata was computed or its catalog edited.
+    LayerUpdated {
+        layer: Layer,
+    },
+    /// A layer was purged from the trash, and its table dropped.
+    LayerDeleted {
+        layer_id: String,
+    },
+    /// A job was queued or changed status.
+    JobUpdated {
+        job: Job,
+    },
+    /// A map was created or edited.
+    MapUpdated {
+        map: Map,
+    },
+    MapDeleted {
+        map_id: String,
+    },
+    /// A share link was created or revoked. Tokens are not included; list
+    /// the resource's shares to see them.
+    SharesChanged {
+        resource: ShareResource,
+        resource_id: String,
+    },
+    /// A comment was posted, or its thread resolved or reopened. Carries
+    /// the comment's mentions for anyone notifying the people named.
+    CommentUpdated {
+        comment: Comment,
+    },
+    /// A file was attached to or removed from a feature.
+    AttachmentsChanged {
+        layer_id: String,
+        feature_id: String,
+    },
+    /// A refresh from the layer's source added or removed features.
+    /// Refreshes that find nothing new are not published.
+    LayerDataChanged {
+        run: ScheduleRun,
+    },
+    /// Something needs attention, e.g. a layer missed its expected update.
+    /// The same alert is sent to the configured webhooks.
+    Alert {
+        alert: Alert,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::LayerUpdated { .. } => "layer_updated",
+            Event::LayerDeleted { .. } => "layer_deleted",
+            Event::JobUpdated { .. } => "job_updated",
+            Event::MapUpdated { .. } => "map_updated",
+            Event::MapDeleted { .. } => "map_deleted",
+            Event::SharesChanged { .. } => "shares_changed",
+            Event::CommentUpdated { .. } => "comment_updated",
+            Event::AttachmentsChanged { .. } => "attachments_changed",
+            Event::LayerDataChanged { .. } => "layer_data_changed",
+            Event::Alert { .. } => "alert",
+        }
+    }
+}
+
+/// Carries events between replicas, so subscribers see changes made on any
+/// of them.
+#[async_trait]
+pub trait Transport: Send + Sync + 'static {
+    async fn publish(&self, payload: Vec<u8>) -> Result<()>;
+    /// Hand every payload published by any replica, including this one, to
+    /// `deliver` until the connection fails.
+    async fn listen(&self, deliver: &(dyn Fn(&[u8]) + Send + Sync)) -> Result<()>;
+}
+
+/// Broadcasts events to everyone subscribed at the time they are published.
+/// Without a transport events stay within the process.
+#[derive(Clone)]
+pub struct EventBus {
+    local: broadcast::Sender<Event>,
+    outbox: Option<mpsc::UnboundedSender<Event>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (local, _) = broadcast::channel(CAPACITY);
+        EventBus {
+            local,
+            outbox: None,
+        }
+    }
+}
+
+impl EventBus {
+    pub async fn from_config(config: &EventsConfig) -> Result<Self> {
+        let transport: Arc<dyn Transport> = match config.backend.as_str() {
+            "memory" => return Ok(EventBus::default()),
+            #[cfg(feature = "redis")]
+            "redis" => Arc::new(redis::RedisTransport::connect(config.url()?).await?),
+            #[cfg(feature = "nats")]
+            "nats" => Arc::new(nats::NatsTransport::connect(config.url()?).await?),
+            other => {
+                return Err(anyhow!(
+                    "Event backend {other} is not available in this build"
+                ))
+            }
+        };
+        Ok(EventBus::with_transport(transport))
+    }
+
+    /// Send events through `transport` and deliver whatever arrives on it
+    /// to local subscribers. Events are published in order from a single
+    /// task; while the transport is down they are dropped.
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        let (local, _) = broadcast::channel(CAPACITY);
+        let (outbox, mut pending) = mpsc::unbounded_channel::<Event>();
+
+        let publisher = transport.clone();
+        tokio::spawn(async move {
+            while let Some(event) = pending.recv().await {
+                let payload = match serde_json::to_vec(&event) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Failed to encode {} event: {e}", event.name());
+                        continue;
+                    }
+                };
+                if let Err(e) = publisher.publish(payload).await {
+                    warn!("Failed to publish {} event: {e:#}", event.name());
+                }
+            }
+        });
+
+        let subscribers = local.clone();
+        tokio::spawn(async move {
+            let deliver = move |payload: &[u8]| match serde_json::from_slice::<Event>(payload) {
+                Ok(event) => {
+                    let _ = subscribers.send(event);
+                }
+                Err(e) => warn!("Ignoring unreadable event: {e}"),
+            };
+            loop {
+                if let Err(e) = transport.listen(&deliver).await {
+                    warn!("Event subscription failed, reconnecting: {e:#}");
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+
+        EventBus {
+            local,
+            outbox: Some(outbox),
+        }
+    }
+
+    pub fn publish(&self, event: Event) {
+        match &self.outbox {
+            Some(outbox) => {
+                let _ = outbox.send(event);
+            }
+            // Sending only fails when nobody is listening
+            None => {
+                let _ = self.local.send(event);
+            }
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.local.subscribe()
+    }
+}
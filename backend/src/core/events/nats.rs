@@ -0,0 +1,35 @@
+use super::Transport;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio_stream::StreamExt;
+
+const SUBJECT: &str = "gridwalk.events";
+
+/// Core NATS publish/subscribe, without JetStream persistence.
+pub struct NatsTransport {
+    client: async_nats::Client,
+}
+
+impl NatsTransport {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Ok(NatsTransport {
+            client: async_nats::connect(url).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for NatsTransport {
+    async fn publish(&self, payload: Vec<u8>) -> Result<()> {
+        self.client.publish(SUBJECT, payload.into()).await?;
+        Ok(())
+    }
+
+    async fn listen(&self, deliver: &(dyn Fn(&[u8]) + Send + Sync)) -> Result<()> {
+        let mut subscriber = self.client.subscribe(SUBJECT).await?;
+        while let Some(message) = subscriber.next().await {
+            deliver(&message.payload);
+        }
+        Err(anyhow!("NATS subscription closed"))
+    }
+}
@@ -0,0 +1,44 @@
+use super::Transport;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Client};
+use tokio_stream::StreamExt;
+
+const CHANNEL: &str = "gridwalk:events";
+
+/// Redis pub/sub. Messages are not persisted, so replicas only see events
+/// published while they are subscribed.
+pub struct RedisTransport {
+    client: Client,
+    connection: MultiplexedConnection,
+}
+
+impl RedisTransport {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(RedisTransport { client, connection })
+    }
+}
+
+#[async_trait]
+impl Transport for RedisTransport {
+    async fn publish(&self, payload: Vec<u8>) -> Result<()> {
+        self.connection
+            .clone()
+            .publish::<_, _, ()>(CHANNEL, payload)
+            .await?;
+        Ok(())
+    }
+
+    async fn listen(&self, deliver: &(dyn Fn(&[u8]) + Send + Sync)) -> Result<()> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(CHANNEL).await?;
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            deliver(message.get_payload_bytes());
+        }
+        Err(anyhow!("Redis subscription closed"))
+    }
+}
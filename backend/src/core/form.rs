@@ -0,0 +1,184 @@
+use super::Attribute;
+use crate::topology::TopologyRules;
+use crate::validation::{ValidationErrors, Validator};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Number,
+    Integer,
+    Boolean,
+    /// `YYYY-MM-DD`.
+    Date,
+    /// One of `choices`.
+    Choice,
+    /// Any number of `choices`, as an array.
+    MultiChoice,
+    /// Attachment ids of photos, as an array.
+    Photo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FormField {
+    /// The attribute column the field fills.
+    pub name: String,
+    pub label: Option<String>,
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// Allowed values of choice fields.
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+/// How features of an editable layer are collected in the field. Submissions
+/// are checked against it before they are written.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Form {
+    pub fields: Vec<FormField>,
+    /// Rules edited geometries are snapped to and checked against.
+    #[serde(default)]
+    pub topology: Option<TopologyRules>,
+}
+
+const MAX_FIELDS: usize = 200;
+
+impl Form {
+    /// Reject forms that do not fit the layer's attributes.
+    pub fn validate(&self, attributes: &[Attribute]) -> Result<(), ValidationErrors> {
+        let mut v = Validator::default();
+        v.count("fields", self.fields.len(), 0, MAX_FIELDS);
+        let mut seen = HashSet::new();
+        for (index, field) in self.fields.iter().enumerate() {
+            v.within(&format!("fields[{index}]"), |v| {
+                v.check(
+                    "name",
+                    attributes.iter().any(|a| a.name == field.name),
+                    "unknown_attribute",
+                    format!("{} is not an attribute of the layer", field.name),
+                );
+                v.check(
+                    "name",
+                    seen.insert(&field.name),
+                    "duplicate",
+                    "appears more than once",
+                );
+                let has_choices =
+                    matches!(field.field_type, FieldType::Choice | FieldType::MultiChoice);
+                v.check(
+                    "choices",
+                    !has_choices || !field.choices.is_empty(),
+                    "required",
+                    "choice fields need choices",
+                );
+                v.check(
+                    "choices",
+                    has_choices || field.choices.is_empty(),
+                    "unexpected",
+                    "only choice fields take choices",
+                );
+            });
+        }
+        if let Some(topology) = &self.topology {
+            v.nested("topology", topology);
+        }
+        v.finish()
+    }
+
+    /// Check the properties of a submitted GeoJSON feature. Partial
+    /// submissions, such as updates, only check the fields they include.
+    pub fn check(&self, feature: &Value, partial: bool) -> Result<(), String> {
+        let empty = JsonMap::new();
+        let properties = feature
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let mut errors = Vec::new();
+        for field in &self.fields {
+            match properties.get(&field.name) {
+                None | Some(Value::Null) => {
+                    if field.required && !partial {
+                        errors.push(format!("{} is required", field.name));
+                    } else if field.required && properties.contains_key(&field.name) {
+                        errors.push(format!("{} cannot be cleared", field.name));
+                    }
+                }
+                Some(value) => {
+                    if let Err(e) = field.check(value) {
+                        errors.push(format!("{}: {e}", field.name));
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+impl FormField {
+    fn check(&self, value: &Value) -> Result<(), String> {
+        let valid = match self.field_type {
+            FieldType::Text => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Date => value
+                .as_str()
+                .is_some_and(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()),
+            FieldType::Choice => {
+                return match value.as_str() {
+                    Some(choice) if self.choices.iter().any(|c| c == choice) => Ok(()),
+                    _ => Err(format!("must be one of {}", self.choices.join(", "))),
+                }
+            }
+            FieldType::MultiChoice => {
+                return match value.as_array() {
+                    Some(values)
+                        if values.iter().all(|v| {
+                            v.as_str()
+                                .is_some_and(|choice| self.choices.iter().any(|c| c == choice))
+                        }) =>
+                    {
+                        Ok(())
+                    }
+                    _ => Err(format!(
+                        "must be a list drawn from {}",
+                        self.choices.join(", ")
+                    )),
+                }
+            }
+            FieldType::Photo => value
+                .as_array()
+                .is_some_and(|ids| ids.iter().all(Value::is_string)),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("expected {}", self.field_type.describe()))
+        }
+    }
+}
+
+impl FieldType {
+    fn describe(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "a number",
+            FieldType::Integer => "an integer",
+            FieldType::Boolean => "true or false",
+            FieldType::Date => "a YYYY-MM-DD date",
+            FieldType::Choice => "a choice",
+            FieldType::MultiChoice => "a list of choices",
+            FieldType::Photo => "a list of attachment ids",
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::data::{DataResult, Database};
+use crate::scanning::ScanResult;
+use anyhow::Result;
+use chrono::Utc;
+use metrics::gauge;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tokio::time::timeout;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "succeeded" => Some(JobStatus::Succeeded),
+            "failed" => Some(JobStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Units of work a job has completed out of its total, e.g. tiles seeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct JobProgress {
+    pub completed: u64,
+    pub total: u64,
+}
+
+/// A background task, such as an analysis run, tracked so clients can poll it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub status: JobStatus,
+    /// Id of the layer the job produced or worked on, once it has succeeded.
+    pub output_layer: Option<String>,
+    pub error: Option<String>,
+    /// How far the job has got, for jobs that report it.
+    pub progress: Option<JobProgress>,
+    /// Verdict on the file the job imported, when scanning is enabled.
+    pub scan: Option<ScanResult>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Job {
+    pub fn new(kind: &str) -> Self {
+        let now = Utc::now().timestamp();
+        Job {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            status: JobStatus::Queued,
+            output_layer: None,
+            error: None,
+            progress: None,
+            scan: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.create_job(self).await
+    }
+
+    pub async fn from_id(database: &Arc<dyn Database>, id: &str) -> DataResult<Self> {
+        database.get_job(id).await
+    }
+
+    pub async fn save(&mut self, database: &Arc<dyn Database>) -> DataResult<()> {
+        self.updated_at = Utc::now().timestamp();
+        database.update_job(self).await
+    }
+}
+
+/// Lets a running job record its progress, and what it found on the way,
+/// on the job.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    job: Arc<Mutex<Job>>,
+    database: Arc<dyn Database>,
+}
+
+impl ProgressReporter {
+    pub fn job_id(&self) -> String {
+        self.job.lock().unwrap().id.clone()
+    }
+
+    /// Record progress. Failures are logged, as progress is only for display.
+    pub async fn report(&self, completed: u64, total: u64) {
+        self.update(|job| job.progress = Some(JobProgress { completed, total }))
+            .await;
+    }
+
+    /// Record the verdict on the job's input. It is kept when the job
+    /// finishes, even if saving it now fails.
+    pub async fn record_scan(&self, scan: ScanResult) {
+        self.update(|job| job.scan = Some(scan)).await;
+    }
+
+    async fn update(&self, change: impl FnOnce(&mut Job)) {
+        let mut job = {
+            let mut job = self.job.lock().unwrap();
+            change(&mut job);
+            job.clone()
+        };
+        if let Err(e) = job.save(&self.database).await {
+            warn!("Failed to update job {}: {e}", job.id);
+        }
+    }
+}
+
+/// Runs jobs in the background and keeps track of them, so shutdown can
+/// wait for them to finish.
+#[derive(Clone, Default)]
+pub struct JobRunner {
+    tracker: TaskTracker,
+    /// Ids of running jobs, with a handle to abort each by once it has
+    /// been spawned.
+    running: Arc<Mutex<HashMap<String, Option<AbortHandle>>>>,
+}
+
+/// How long aborted jobs get to stop before they are recorded as failed.
+const ABORT_GRACE: Duration = Duration::from_secs(5);
+
+/// Counts a job in `jobs_in_flight` for as long as its task is alive,
+/// including tasks that are aborted or panic.
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        gauge!("jobs_in_flight").increment(1.0);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        gauge!("jobs_in_flight").decrement(1.0);
+    }
+}
+
+impl JobRunner {
+    /// Run `task` on the runtime, recording progress on the job. The task
+    /// resolves to the id of the layer it produced.
+    pub fn spawn<F>(&self, job: Job, database: Arc<dyn Database>, task: F)
+    where
+        F: Future<Output = Result<String>> + Send + 'static,
+    {
+        self.spawn_with_progress(job, database, |_| task);
+    }
+
+    /// Like `spawn`, handing the task a reporter for its progress.
+    pub fn spawn_with_progress<T, F>(&self, job: Job, database: Arc<dyn Database>, task: T)
+    where
+        T: FnOnce(ProgressReporter) -> F,
+        F: Future<Output = Result<String>> + Send + 'static,
+    {
+        let in_flight = InFlight::start();
+        let id = job.id.clone();
+        self.running.lock().unwrap().insert(id.clone(), None);
+        let running = self.running.clone();
+        let job = Arc::new(Mutex::new(job));
+        let task = task(ProgressReporter {
+            job: job.clone(),
+            database: database.clone(),
+        });
+        let handle = self.tracker.spawn(async move {
+            let _in_flight = in_flight;
+            let mut snapshot = {
+                let mut job = job.lock().unwrap();
+                job.status = JobStatus::Running;
+                job.clone()
+            };
+            if let Err(e) = snapshot.save(&database).await {
+                error!("Failed to mark job {} running: {e}", snapshot.id);
+            }
+
+            let result = task.await;
+            let mut job = job.lock().unwrap().clone();
+            match result {
+                Ok(output_layer) => {
+                    job.status = JobStatus::Succeeded;
+                    job.output_layer = Some(output_layer);
+                }
+                Err(e) => {
+                    error!("Job {} failed: {e:#}", job.id);
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+
+            if let Err(e) = job.save(&database).await {
+                error!("Failed to record outcome of job {}: {e}", job.id);
+            }
+            running.lock().unwrap().remove(&job.id);
+        });
+        // Unless the job has already finished and removed itself
+        if let Some(abort) = self.running.lock().unwrap().get_mut(&id) {
+            *abort = Some(handle.abort_handle());
+        }
+    }
+
+    /// Wait up to `deadline` for running jobs. Jobs still running after that
+    /// are aborted, so they can't record an outcome of their own, and then
+    /// recorded as failed. An aborted job may leave part of its output
+    /// behind, e.g. a table with only some of its rows, to be dropped or
+    /// overwritten when the request is resubmitted.
+    pub async fn drain(&self, database: &Arc<dyn Database>, deadline: Duration) {
+        self.tracker.close();
+        let running = self.running.lock().unwrap().len();
+        if running == 0 {
+            return;
+        }
+        info!("Waiting for {running} background jobs to finish");
+        if timeout(deadline, self.tracker.wait()).await.is_ok() {
+            return;
+        }
+
+        for abort in self.running.lock().unwrap().values().flatten() {
+            abort.abort();
+        }
+        if timeout(ABORT_GRACE, self.tracker.wait()).await.is_err() {
+            warn!("Some background jobs did not stop when aborted");
+        }
+
+        // Aborted jobs never got to remove themselves
+        let interrupted: Vec<String> = self.running.lock().unwrap().keys().cloned().collect();
+        for id in interrupted {
+            warn!("Job {id} interrupted by shutdown");
+            let mut job = match Job::from_id(database, &id).await {
+                Ok(job) => job,
+                Err(e) => {
+                    error!("Failed to load interrupted job {id}: {e}");
+                    continue;
+                }
+            };
+            job.status = JobStatus::Failed;
+            job.error = Some("Interrupted by server shutdown; resubmit the request".to_string());
+            if let Err(e) = job.save(database).await {
+                error!("Failed to record interruption of job {id}: {e}");
+            }
+        }
+    }
+}
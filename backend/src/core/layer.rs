@@ -0,0 +1,1030 @@
+use super::Form;
+use crate::changes;
+use crate::data::{DataError, DataResult, Database};
+use crate::expression;
+use crate::postgis::{quote_ident, quote_literal, transform_sql, LayerTable};
+use crate::quality::QualityRules;
+use crate::validation::{Validate, ValidationErrors, Validator};
+use anyhow::Result;
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Attribute {
+    pub name: String,
+    pub data_type: String,
+}
+
+/// Numeric column types, whose statistics include a range and mean.
+const NUMERIC_TYPES: [&str; 6] = [
+    "smallint",
+    "integer",
+    "bigint",
+    "numeric",
+    "real",
+    "double precision",
+];
+/// Most common values reported per attribute.
+const TOP_VALUES: i64 = 10;
+/// Writes a refresh attempts before giving up on concurrent edits.
+const REFRESH_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// A summary of an attribute's values, e.g. for choosing ramp breaks.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AttributeStats {
+    pub name: String,
+    pub data_type: String,
+    pub count: i64,
+    pub null_count: i64,
+    pub distinct_count: i64,
+    /// Range, mean and standard deviation of numeric attributes.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub stddev: Option<f64>,
+    /// The most common values as text, most frequent first.
+    pub top_values: Vec<ValueCount>,
+}
+
+impl AttributeStats {
+    /// Scan the attribute's values; `None` if the layer has no such
+    /// attribute.
+    #[instrument(skip(pool))]
+    pub async fn compute(pool: &Pool, source_id: &str, name: &str) -> Result<Option<Self>> {
+        let table = LayerTable::from_source_id(pool, source_id).await?;
+        let client = pool.get().await?;
+        let Some(column) = client
+            .query_opt(
+                "SELECT data_type::text FROM information_schema.columns
+                 WHERE table_schema = $1 AND table_name = $2 AND column_name = $3
+                   AND column_name <> $4",
+                &[&table.schema, &table.table, &name, &table.geometry_column],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        let data_type: String = column.get(0);
+        let value = format!("t.{}", quote_ident(name));
+        let numeric = if NUMERIC_TYPES.contains(&data_type.as_str()) {
+            format!(
+                "min({value})::float8, max({value})::float8,
+                 avg({value})::float8, stddev_pop({value})::float8"
+            )
+        } else {
+            "NULL::float8, NULL::float8, NULL::float8, NULL::float8".to_string()
+        };
+        let qualified_name = table.qualified_name();
+        let row = client
+            .query_one(
+                &format!(
+                    "SELECT count({value}), count(*) - count({value}),
+                            count(DISTINCT {value}::text), {numeric}
+                     FROM {qualified_name} t"
+                ),
+                &[],
+            )
+            .await?;
+        let top_values = client
+            .query(
+                &format!(
+                    "SELECT {value}::text AS value, count(*) FROM {qualified_name} t
+                     WHERE {value} IS NOT NULL
+                     GROUP BY 1 ORDER BY 2 DESC, 1 LIMIT {TOP_VALUES}"
+                ),
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| ValueCount {
+                value: row.get(0),
+                count: row.get(1),
+            })
+            .collect();
+        Ok(Some(AttributeStats {
+            name: name.to_string(),
+            data_type,
+            count: row.get(0),
+            null_count: row.get(1),
+            distinct_count: row.get(2),
+            min: row.get(3),
+            max: row.get(4),
+            mean: row.get(5),
+            stddev: row.get(6),
+            top_values,
+        }))
+    }
+}
+
+/// Descriptive metadata maintained by people rather than computed from the
+/// data, used to find and govern layers in the catalog.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Catalog {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub license: Option<String>,
+    /// Credit for the data's source, shown alongside the layer on maps.
+    pub attribution: Option<String>,
+}
+
+const MAX_TAGS: usize = 32;
+const MAX_TAG_LENGTH: usize = 64;
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+const MAX_LICENSE_LENGTH: usize = 200;
+const MAX_ATTRIBUTION_LENGTH: usize = 500;
+
+impl Validate for Catalog {
+    fn check(&self, v: &mut Validator) {
+        v.count("tags", self.tags.len(), 0, MAX_TAGS);
+        for (index, tag) in self.tags.iter().enumerate() {
+            v.length(&format!("tags[{index}]"), tag, 1, MAX_TAG_LENGTH);
+        }
+        let text = [
+            ("description", &self.description, MAX_DESCRIPTION_LENGTH),
+            ("license", &self.license, MAX_LICENSE_LENGTH),
+            ("attribution", &self.attribution, MAX_ATTRIBUTION_LENGTH),
+        ];
+        for (name, value, max) in text {
+            if let Some(value) = value {
+                v.length(name, value, 0, max);
+            }
+        }
+    }
+}
+
+impl Catalog {
+    /// Lowercase, trim and deduplicate tags, and reject unusable values.
+    pub fn normalize(mut self) -> Result<Self, ValidationErrors> {
+        let mut tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        self.tags = tags;
+        self.validate()?;
+        Ok(self)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == &tag.trim().to_lowercase())
+    }
+}
+
+/// Zooms an attribute is kept in tiles at. Tiles outside the range leave it
+/// out, keeping low-zoom tiles small.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AttributeZoom {
+    pub name: String,
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+}
+
+/// Simplification tolerance at a zoom, in tile units (a tile is 4096 wide).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ToleranceStop {
+    pub zoom: u8,
+    pub tolerance: f64,
+}
+
+/// An attribute added to the layer's tiles, computed by a SQL expression
+/// over its columns, e.g. `ST_Area(geom::geography) / 10000` for hectares
+/// or `name || ' ' || ref` for a label. The source data is left alone.
+/// Expressions are limited to what `expression::columns` allows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ComputedAttribute {
+    pub name: String,
+    pub expression: String,
+}
+
+/// Longest expression a computed attribute may have.
+const MAX_EXPRESSION_LENGTH: usize = 1000;
+/// Most decimal places numbers in tiles may keep.
+const MAX_PRECISION: u8 = 12;
+const MAX_INCLUDED_ATTRIBUTES: usize = 500;
+
+/// How features are written into the layer's tiles.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TileEncoding {
+    /// Columns written to tiles; all of them when unset. Computed attributes
+    /// are always written.
+    pub include: Option<Vec<String>>,
+    /// Decimal places fractional numbers are rounded to, which keeps tiles
+    /// small. Integers are written as they are.
+    pub precision: Option<u8>,
+    /// Column written as each feature's MVT id. Non-negative integers are
+    /// used as they are; other values are hashed to one.
+    pub id_field: Option<String>,
+    /// Without `id_field`, use the layer's primary key as the feature id, so
+    /// a feature keeps its id across versions of the layer and client-side
+    /// feature state such as hover or selection follows it.
+    #[serde(default)]
+    pub stable_ids: bool,
+}
+
+impl TileEncoding {
+    fn is_set(&self) -> bool {
+        self != &TileEncoding::default()
+    }
+
+    /// Whether tiles carry the column, zoom ranges aside.
+    pub fn includes(&self, column: &str) -> bool {
+        self.include
+            .as_ref()
+            .map_or(true, |include| include.iter().any(|name| name == column))
+    }
+}
+
+/// How a layer's tiles are served.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Tiling {
+    /// Deepest zoom tiles are generated at; defaults to the source's own.
+    pub max_zoom: Option<u8>,
+    /// Serve tiles deeper than `max_zoom` from the tile at `max_zoom`,
+    /// clipped and scaled up, rather than answering 404.
+    #[serde(default = "default_overzoom")]
+    pub overzoom: bool,
+    /// Attributes only kept at some zooms; others are kept at every zoom.
+    #[serde(default)]
+    pub attributes: Vec<AttributeZoom>,
+    /// Simplification tolerance by zoom, in ascending zoom order. Tolerances
+    /// are interpolated between stops and held beyond the first and last.
+    #[serde(default)]
+    pub simplify: Vec<ToleranceStop>,
+    /// Leave out polygons smaller than this, in square tile units.
+    pub min_area: Option<f64>,
+    /// Leave out lines shorter than this, in tile units.
+    pub min_length: Option<f64>,
+    /// Attributes computed for each feature as its tile is rendered. Zoom
+    /// ranges in `attributes` apply to them too.
+    #[serde(default)]
+    pub computed: Vec<ComputedAttribute>,
+    #[serde(default)]
+    pub encoding: TileEncoding,
+}
+
+fn default_overzoom() -> bool {
+    true
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Tiling {
+            max_zoom: None,
+            overzoom: default_overzoom(),
+            attributes: Vec::new(),
+            simplify: Vec::new(),
+            min_area: None,
+            min_length: None,
+            computed: Vec::new(),
+            encoding: TileEncoding::default(),
+        }
+    }
+}
+
+/// Longest query a SQL layer may be defined by.
+const MAX_SQL_LENGTH: usize = 20_000;
+
+/// The query behind a layer published as a view. `{name}` in the query is
+/// replaced with the named parameter, as a literal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SqlView {
+    /// A single SELECT, or WITH query, with a geometry column typed with an
+    /// SRID, e.g. `geom::geometry(Geometry, 4326)`.
+    pub sql: String,
+    /// Strings, numbers, booleans or nulls, by name.
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub parameters: BTreeMap<String, Value>,
+}
+
+fn valid_parameter_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl SqlView {
+    /// The query with its parameters substituted.
+    pub fn render(&self) -> String {
+        let mut sql = self.sql.trim().trim_end_matches(';').to_string();
+        for (name, value) in &self.parameters {
+            let literal = match value {
+                Value::String(text) => quote_literal(text),
+                Value::Null => "NULL".to_string(),
+                other => other.to_string(),
+            };
+            sql = sql.replace(&format!("{{{name}}}"), &literal);
+        }
+        sql
+    }
+}
+
+impl Validate for SqlView {
+    fn check(&self, v: &mut Validator) {
+        v.length("sql", &self.sql, 1, MAX_SQL_LENGTH);
+        let keyword = self
+            .sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        v.check(
+            "sql",
+            keyword == "select" || keyword == "with",
+            "not_select",
+            "must be a SELECT or WITH query",
+        );
+        v.within("parameters", |v| {
+            for (name, value) in &self.parameters {
+                v.check(
+                    name,
+                    valid_parameter_name(name),
+                    "invalid_name",
+                    "names must be lowercase letters, digits and underscores",
+                );
+                v.check(
+                    name,
+                    !value.is_array() && !value.is_object(),
+                    "invalid_value",
+                    "must be a string, number, boolean or null",
+                );
+            }
+        });
+    }
+}
+
+/// Deepest zoom a tile can be requested at.
+pub const MAX_TILE_ZOOM: u8 = 30;
+
+fn non_negative(value: f64) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+fn check_zoom(v: &mut Validator, name: &str, zoom: Option<u8>) {
+    if let Some(zoom) = zoom {
+        v.range(name, zoom, 0, MAX_TILE_ZOOM);
+    }
+}
+
+fn check_non_negative(v: &mut Validator, name: &str, value: Option<f64>) {
+    if let Some(value) = value {
+        v.check(
+            name,
+            non_negative(value),
+            "negative",
+            "must be a non-negative number",
+        );
+    }
+}
+
+impl Validate for Tiling {
+    fn check(&self, v: &mut Validator) {
+        check_zoom(v, "max_zoom", self.max_zoom);
+        for (index, attribute) in self.attributes.iter().enumerate() {
+            v.within(&format!("attributes[{index}]"), |v| {
+                let repeated = self.attributes[..index]
+                    .iter()
+                    .any(|other| other.name == attribute.name);
+                v.check("name", !repeated, "duplicate", "is listed twice");
+                check_zoom(v, "min_zoom", attribute.min_zoom);
+                check_zoom(v, "max_zoom", attribute.max_zoom);
+                if let (Some(min), Some(max)) = (attribute.min_zoom, attribute.max_zoom) {
+                    v.check(
+                        "min_zoom",
+                        min <= max,
+                        "invalid_range",
+                        "must not be above max_zoom",
+                    );
+                }
+            });
+        }
+        for (index, stop) in self.simplify.iter().enumerate() {
+            v.within(&format!("simplify[{index}]"), |v| {
+                check_zoom(v, "zoom", Some(stop.zoom));
+                check_non_negative(v, "tolerance", Some(stop.tolerance));
+                v.check(
+                    "zoom",
+                    index == 0 || self.simplify[index - 1].zoom < stop.zoom,
+                    "out_of_order",
+                    "stops must be in ascending zoom order",
+                );
+            });
+        }
+        check_non_negative(v, "min_area", self.min_area);
+        check_non_negative(v, "min_length", self.min_length);
+        for (index, attribute) in self.computed.iter().enumerate() {
+            v.within(&format!("computed[{index}]"), |v| {
+                v.check(
+                    "name",
+                    valid_parameter_name(&attribute.name) && attribute.name.len() <= 63,
+                    "invalid_name",
+                    "must be lowercase letters, digits and underscores",
+                );
+                let repeated = self.computed[..index]
+                    .iter()
+                    .any(|other| other.name == attribute.name);
+                v.check("name", !repeated, "duplicate", "is listed twice");
+                v.length(
+                    "expression",
+                    &attribute.expression,
+                    1,
+                    MAX_EXPRESSION_LENGTH,
+                );
+                if let Err(message) = expression::columns(&attribute.expression) {
+                    v.error("expression", "invalid_expression", message);
+                }
+            });
+        }
+        v.within("encoding", |v| {
+            let encoding = &self.encoding;
+            if let Some(include) = &encoding.include {
+                v.count("include", include.len(), 0, MAX_INCLUDED_ATTRIBUTES);
+                for (index, name) in include.iter().enumerate() {
+                    v.length(&format!("include[{index}]"), name, 1, 63);
+                }
+            }
+            if let Some(precision) = encoding.precision {
+                v.check(
+                    "precision",
+                    precision <= MAX_PRECISION,
+                    "out_of_range",
+                    format!("must be at most {MAX_PRECISION}"),
+                );
+            }
+            if let Some(id_field) = &encoding.id_field {
+                v.length("id_field", id_field, 1, 63);
+            }
+        });
+    }
+}
+
+impl Tiling {
+    /// Whether tiles need more than the source's own tiling.
+    pub fn has_rules(&self) -> bool {
+        !self.attributes.is_empty()
+            || !self.simplify.is_empty()
+            || self.min_area.is_some()
+            || self.min_length.is_some()
+            || !self.computed.is_empty()
+            || self.encoding.is_set()
+    }
+
+    /// Whether tiles at `zoom` carry the attribute.
+    pub fn keeps_attribute(&self, name: &str, zoom: u8) -> bool {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map_or(true, |attribute| {
+                attribute.min_zoom.map_or(true, |min| zoom >= min)
+                    && attribute.max_zoom.map_or(true, |max| zoom <= max)
+            })
+    }
+
+    /// Simplification tolerance at `zoom`, in tile units.
+    pub fn tolerance(&self, zoom: u8) -> Option<f64> {
+        let after = self.simplify.iter().position(|stop| stop.zoom >= zoom);
+        match after {
+            None => self.simplify.last().map(|stop| stop.tolerance),
+            Some(0) => Some(self.simplify[0].tolerance),
+            Some(index) => {
+                let (low, high) = (self.simplify[index - 1], self.simplify[index]);
+                let t = f64::from(zoom - low.zoom) / f64::from(high.zoom - low.zoom);
+                Some(low.tolerance + (high.tolerance - low.tolerance) * t)
+            }
+        }
+    }
+}
+
+/// How often a layer's data is expected to change. The scheduler flags the
+/// layer once its data goes longer than `max_age` without an update, and
+/// sends an alert. Any write to the data clears the flag; recomputing the
+/// metadata only does if it finds the data changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Freshness {
+    /// Seconds the data may go without an update, e.g. 86400 for a daily
+    /// feed.
+    pub max_age: u64,
+    /// When the data was found overdue; `None` while it is fresh.
+    #[serde(default)]
+    pub stale_since: Option<i64>,
+}
+
+impl Freshness {
+    pub fn new(max_age: u64) -> Self {
+        Freshness {
+            max_age,
+            stale_since: None,
+        }
+    }
+
+    /// Whether data last updated at `updated_at` is overdue at `now`.
+    pub fn is_overdue(&self, updated_at: i64, now: i64) -> bool {
+        now - updated_at > self.max_age as i64
+    }
+}
+
+/// Cached facts about a layer's data, so clients need not scan features to
+/// find extents or schema. Recomputed whenever the layer's data changes.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Layer {
+    pub id: String,
+    /// `[west, south, east, north]` in WGS84; `None` for an empty layer.
+    pub bbox: Option<[f64; 4]>,
+    pub feature_count: i64,
+    pub geometry_types: Vec<String>,
+    pub attributes: Vec<Attribute>,
+    pub srid: i32,
+    pub updated_at: i64,
+    /// When the layer's rows last changed. Freshness is measured from this
+    /// rather than `updated_at`, which moves whenever metadata is
+    /// recomputed.
+    pub data_updated_at: i64,
+    #[serde(flatten)]
+    pub catalog: Catalog,
+    /// Data-collection form, for layers edited in the field.
+    #[serde(default)]
+    pub form: Option<Form>,
+    #[serde(default)]
+    pub tiling: Tiling,
+    /// Incremented on every write. Updates name the version they were
+    /// based on, and fail if the layer has moved on.
+    #[serde(default)]
+    pub version: u64,
+    /// The query a SQL layer is a view of; `None` for layers backed by a
+    /// table.
+    #[serde(default)]
+    pub sql: Option<SqlView>,
+    /// Expected update frequency, and whether the data is overdue.
+    #[serde(default)]
+    pub freshness: Option<Freshness>,
+    /// Rules features are checked against on import and edit.
+    #[serde(default)]
+    pub quality: Option<QualityRules>,
+    /// When the layer was moved to the trash. Trashed layers are hidden,
+    /// tiles and all, until restored, or purged along with their table.
+    #[serde(default)]
+    pub deleted_at: Option<i64>,
+}
+
+impl Layer {
+    /// Scan the layer's table and compute fresh metadata. The table may be
+    /// a SQL layer's view, so `pool` should be the read-only connections.
+    #[instrument(skip(pool))]
+    pub async fn compute(pool: &Pool, source_id: &str) -> Result<Self> {
+        let table = LayerTable::from_source_id(pool, source_id).await?;
+        let geom = quote_ident(&table.geometry_column);
+        let wgs84 = transform_sql(&format!("ST_Envelope({geom})"), table.srid, 4326);
+        let client = pool.get().await?;
+
+        let row = client
+            .query_one(
+                &format!(
+                    "WITH stats AS (
+                         SELECT count(*) AS feature_count,
+                                ST_Extent({wgs84}) AS extent,
+                                array_remove(array_agg(DISTINCT GeometryType({geom})), NULL) AS types
+                         FROM {}
+                     )
+                     SELECT feature_count,
+                            ST_XMin(extent), ST_YMin(extent), ST_XMax(extent), ST_YMax(extent),
+                            types
+                     FROM stats",
+                    table.qualified_name()
+                ),
+                &[],
+            )
+            .await?;
+        let bbox = match (
+            row.get::<_, Option<f64>>(1),
+            row.get::<_, Option<f64>>(2),
+            row.get::<_, Option<f64>>(3),
+            row.get::<_, Option<f64>>(4),
+        ) {
+            (Some(west), Some(south), Some(east), Some(north)) => Some([west, south, east, north]),
+            _ => None,
+        };
+        let feature_count: i64 = row.get(0);
+        let geometry_types: Option<Vec<String>> = row.get(5);
+
+        let attributes = client
+            .query(
+                "SELECT column_name::text, data_type::text FROM information_schema.columns
+                 WHERE table_schema = $1 AND table_name = $2 AND column_name <> $3
+                 ORDER BY ordinal_position",
+                &[&table.schema, &table.table, &table.geometry_column],
+            )
+            .await?
+            .iter()
+            .map(|row| Attribute {
+                name: row.get(0),
+                data_type: row.get(1),
+            })
+            .collect();
+
+        let now = Utc::now().timestamp();
+        Ok(Layer {
+            id: source_id.to_string(),
+            bbox,
+            feature_count,
+            geometry_types: geometry_types.unwrap_or_default(),
+            attributes,
+            srid: table.srid,
+            updated_at: now,
+            data_updated_at: now,
+            catalog: Catalog::default(),
+            form: None,
+            tiling: Tiling::default(),
+            version: 0,
+            sql: None,
+            freshness: None,
+            quality: None,
+            deleted_at: None,
+        })
+    }
+
+    /// Recompute and store metadata after the layer's data changed. Catalog
+    /// metadata, the form, tiling settings, expected freshness and quality
+    /// rules are kept as they were; the data counts as fresh again. The
+    /// metadata is computed over `read_pool`, and the version recorded
+    /// through `pool`.
+    pub async fn refresh(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+    ) -> Result<Self> {
+        Layer::recompute(database, pool, read_pool, source_id, true).await
+    }
+
+    /// Recompute and store metadata without the layer's data having been
+    /// written through Gridwalk, e.g. on a schedule. The data only counts
+    /// as fresh again if its feature count or extent changed, as they do
+    /// after edits made directly in PostGIS.
+    pub async fn reindex(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+    ) -> Result<Self> {
+        Layer::recompute(database, pool, read_pool, source_id, false).await
+    }
+
+    async fn recompute(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        written: bool,
+    ) -> Result<Self> {
+        let computed = Layer::compute(read_pool, source_id).await?;
+        let mut attempt = 1;
+        loop {
+            let mut layer = computed.clone();
+            match database.get_layer(source_id).await {
+                Ok(existing) => {
+                    layer.catalog = existing.catalog;
+                    layer.form = existing.form;
+                    layer.tiling = existing.tiling;
+                    layer.version = existing.version;
+                    layer.sql = existing.sql;
+                    if written
+                        || layer.feature_count != existing.feature_count
+                        || layer.bbox != existing.bbox
+                    {
+                        layer.freshness = existing.freshness.map(|f| Freshness::new(f.max_age));
+                    } else {
+                        layer.data_updated_at = existing.data_updated_at;
+                        layer.freshness = existing.freshness;
+                    }
+                    layer.quality = existing.quality;
+                    layer.deleted_at = existing.deleted_at;
+                }
+                Err(DataError::NotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+            layer.version += 1;
+            match database.put_layer(&layer).await {
+                // Settings were edited meanwhile; carry over the new ones
+                Err(DataError::Conflict(_)) if attempt < REFRESH_ATTEMPTS => attempt += 1,
+                Err(e) => return Err(e.into()),
+                Ok(_) => {
+                    if let Err(e) = changes::mark_version(pool, source_id, layer.version).await {
+                        warn!(
+                            "Failed to record version {} of {source_id}: {e:#}",
+                            layer.version
+                        );
+                    }
+                    return Ok(layer);
+                }
+            }
+        }
+    }
+
+    /// Record metadata for `output`, a copy of the layer's table, carrying
+    /// over the layer's catalog metadata, form, tiling settings, expected
+    /// freshness and quality rules.
+    pub async fn copy(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        output: &str,
+    ) -> Result<Self> {
+        let source = Layer::from_id(database, pool, read_pool, source_id).await?;
+        let mut layer = Layer::compute(read_pool, output).await?;
+        layer.catalog = source.catalog;
+        layer.form = source.form;
+        layer.tiling = source.tiling;
+        layer.freshness = source.freshness.map(|f| Freshness::new(f.max_age));
+        layer.quality = source.quality;
+        layer.version = 1;
+        database.put_layer(&layer).await?;
+        Ok(layer)
+    }
+
+    /// Record metadata for `output`, a view just created from `view`. The
+    /// view's query is the user's, so `pool` should be the read-only
+    /// connections, with their statement timeout.
+    pub async fn create_sql(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        output: &str,
+        view: SqlView,
+    ) -> Result<Self> {
+        let mut layer = Layer::compute(pool, output).await?;
+        layer.sql = Some(view);
+        layer.version = 1;
+        database.put_layer(&layer).await?;
+        Ok(layer)
+    }
+
+    /// Apply `change` to the stored layer and store the result. With
+    /// `version`, the write fails with a conflict unless the layer is still
+    /// at that version; without, it only fails on a concurrent write.
+    async fn modify(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        change: impl FnOnce(&mut Layer) -> Result<()>,
+    ) -> Result<Self> {
+        let mut layer = Layer::from_id(database, pool, read_pool, source_id).await?;
+        change(&mut layer)?;
+        layer.version = version.unwrap_or(layer.version) + 1;
+        database.put_layer(&layer).await?;
+        Ok(layer)
+    }
+
+    pub async fn update_catalog(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        catalog: Catalog,
+    ) -> Result<Self> {
+        let catalog = catalog.normalize()?;
+        Layer::modify(database, pool, read_pool, source_id, version, |layer| {
+            layer.catalog = catalog;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn update_tiling(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        tiling: Tiling,
+    ) -> Result<Self> {
+        tiling.validate()?;
+        Layer::modify(database, pool, read_pool, source_id, version, |layer| {
+            layer.tiling = tiling;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace or, with `None`, remove the layer's form.
+    pub async fn update_form(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        form: Option<Form>,
+    ) -> Result<Self> {
+        Layer::modify(database, pool, read_pool, source_id, version, |layer| {
+            if let Some(form) = &form {
+                form.validate(&layer.attributes)?;
+            }
+            layer.form = form;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace or, with `None`, remove the layer's expected freshness.
+    pub async fn update_freshness(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        freshness: Option<Freshness>,
+    ) -> Result<Self> {
+        Layer::modify(database, pool, read_pool, source_id, version, |layer| {
+            layer.freshness = freshness;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace or, with `None`, remove the layer's quality rules.
+    pub async fn update_quality(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: Option<u64>,
+        quality: Option<QualityRules>,
+    ) -> Result<Self> {
+        Layer::modify(database, pool, read_pool, source_id, version, |layer| {
+            if let Some(quality) = &quality {
+                quality.validate(&layer.attributes)?;
+            }
+            layer.quality = quality;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Flag the layer's data as overdue since `now`, unless the layer has
+    /// moved on from `version`, e.g. because its data was just updated.
+    /// Returns the flagged layer.
+    pub async fn mark_stale(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+        version: u64,
+        now: i64,
+    ) -> Result<Option<Self>> {
+        let result = Layer::modify(
+            database,
+            pool,
+            read_pool,
+            source_id,
+            Some(version),
+            |layer| {
+                if let Some(freshness) = &mut layer.freshness {
+                    freshness.stale_since = Some(now);
+                }
+                Ok(())
+            },
+        )
+        .await;
+        match result {
+            Ok(layer) => Ok(Some(layer)),
+            Err(e) => match e.downcast::<DataError>() {
+                Ok(DataError::Conflict(_)) => Ok(None),
+                Ok(e) => Err(e.into()),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Cached metadata, computed on first request.
+    pub async fn from_id(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+    ) -> Result<Self> {
+        match database.get_layer(source_id).await {
+            Ok(layer) if layer.deleted_at.is_some() => Err(DataError::NotFound("Layer").into()),
+            Ok(layer) => Ok(layer),
+            Err(DataError::NotFound(_)) => {
+                Layer::refresh(database, pool, read_pool, source_id).await
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// A layer in the trash.
+    pub async fn from_trash(database: &Arc<dyn Database>, source_id: &str) -> DataResult<Self> {
+        match database.get_layer(source_id).await? {
+            layer if layer.deleted_at.is_none() => Err(DataError::NotFound("Layer")),
+            layer => Ok(layer),
+        }
+    }
+
+    pub async fn get_all(database: &Arc<dyn Database>) -> DataResult<Vec<Self>> {
+        let mut layers = database.get_layers().await?;
+        layers.retain(|layer| layer.deleted_at.is_none());
+        Ok(layers)
+    }
+
+    pub async fn get_trash(database: &Arc<dyn Database>) -> DataResult<Vec<Self>> {
+        let mut layers = database.get_layers().await?;
+        layers.retain(|layer| layer.deleted_at.is_some());
+        Ok(layers)
+    }
+
+    /// Cached metadata of those of `ids` that have it, without computing
+    /// the rest. Trashed layers are left out.
+    pub async fn get_many(database: &Arc<dyn Database>, ids: &[String]) -> DataResult<Vec<Self>> {
+        let mut layers = database.get_layers_by_id(ids).await?;
+        layers.retain(|layer| layer.deleted_at.is_none());
+        Ok(layers)
+    }
+
+    pub async fn trash(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        read_pool: &Pool,
+        source_id: &str,
+    ) -> Result<Self> {
+        Layer::modify(database, pool, read_pool, source_id, None, |layer| {
+            layer.deleted_at = Some(Utc::now().timestamp());
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn restore(database: &Arc<dyn Database>, source_id: &str) -> DataResult<Self> {
+        let mut layer = Layer::from_trash(database, source_id).await?;
+        layer.deleted_at = None;
+        layer.version += 1;
+        database.put_layer(&layer).await?;
+        Ok(layer)
+    }
+
+    /// Permanently delete layers that have been in the trash for longer
+    /// than `retention` seconds, dropping their tables or views, and return
+    /// how many were removed. Layers restored meanwhile are left alone, and
+    /// ones that fail to drop stay in the trash for the next run.
+    pub async fn purge_trash(
+        database: &Arc<dyn Database>,
+        pool: &Pool,
+        retention: i64,
+    ) -> Result<usize> {
+        let cutoff = Utc::now().timestamp() - retention;
+        let mut purged = 0;
+        for layer in Layer::get_trash(database).await? {
+            if !layer
+                .deleted_at
+                .is_some_and(|deleted_at| deleted_at < cutoff)
+            {
+                continue;
+            }
+            match layer.purge(database, pool).await {
+                Ok(true) => purged += 1,
+                Ok(false) => {}
+                Err(e) => warn!("Failed to purge {} from the trash: {e:#}", layer.id),
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Drop a trashed layer's table or view and delete its metadata. False
+    /// if the layer was restored since it was listed.
+    async fn purge(&self, database: &Arc<dyn Database>, pool: &Pool) -> Result<bool> {
+        let table = LayerTable::from_source_id(pool, &self.id).await?;
+        let kind = if self.sql.is_some() { "VIEW" } else { "TABLE" };
+        // The drop only commits once the metadata is gone
+        let mut client = pool.get().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .batch_execute(&format!("DROP {kind} {}", table.qualified_name()))
+            .await?;
+        match database.delete_layer(self).await {
+            Ok(()) => {
+                transaction.commit().await?;
+                Ok(true)
+            }
+            Err(DataError::Conflict(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::core::{Fill, LayerStyle, Paint, Ramp, RampKind, Stroke};
+use anyhow::Result;
+use image::codecs::png::PngEncoder;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Edge of a legend swatch, in pixels.
+const SWATCH_SIZE: u32 = 20;
+/// Space between swatches in the PNG legend.
+const SWATCH_GAP: u32 = 4;
+/// MapLibre's circle colour when a layer has no fill.
+const DEFAULT_CIRCLE_COLOR: &str = "#000000";
+
+/// How a layer's features are drawn, from the geometry it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwatchShape {
+    Polygon,
+    Line,
+    Point,
+}
+
+impl SwatchShape {
+    /// From PostGIS geometry type names, preferring polygons then lines for
+    /// layers of mixed geometry.
+    pub fn for_geometry_types(types: &[String]) -> Self {
+        let has = |kind: &str| types.iter().any(|t| t.to_uppercase().contains(kind));
+        if has("POLYGON") {
+            SwatchShape::Polygon
+        } else if has("LINE") {
+            SwatchShape::Line
+        } else {
+            SwatchShape::Point
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LegendEntry {
+    pub label: String,
+    /// Attribute the entry is a class of; `None` for a single symbol.
+    pub property: Option<String>,
+    /// Kind of ramp the entry comes from. Interpolated entries are stops of
+    /// a continuous gradient rather than classes.
+    pub kind: Option<RampKind>,
+    pub fill: Option<String>,
+    pub stroke: Option<String>,
+}
+
+/// The symbology of a layer as legend entries, in the order they are drawn
+/// in the PNG legend.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Legend {
+    pub layer_id: String,
+    pub shape: SwatchShape,
+    pub fill_opacity: f64,
+    pub stroke_opacity: f64,
+    pub stroke_width: f64,
+    pub point_radius: f64,
+    pub entries: Vec<LegendEntry>,
+}
+
+fn constant(paint: Option<&Paint>) -> Option<String> {
+    match paint? {
+        Paint::Constant(color) => Some(color.clone()),
+        Paint::Ramp(_) => None,
+    }
+}
+
+fn label(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Label and colour of each class of a ramp, matching how `to_expression`
+/// assigns colours.
+fn classes(ramp: &Ramp) -> Vec<(String, String)> {
+    let stops = &ramp.stops;
+    match ramp.kind {
+        RampKind::Interpolate => stops
+            .iter()
+            .map(|stop| (label(&stop.value), stop.color.clone()))
+            .collect(),
+        RampKind::Categorical => stops
+            .iter()
+            .map(|stop| (label(&stop.value), stop.color.clone()))
+            .chain([("Other".to_string(), ramp.default.clone())])
+            .collect(),
+        RampKind::Step => {
+            let mut classes = Vec::new();
+            if let Some(first) = stops.first() {
+                classes.push((format!("< {}", label(&first.value)), ramp.default.clone()));
+            }
+            for (index, stop) in stops.iter().enumerate() {
+                let label = match stops.get(index + 1) {
+                    Some(next) => format!("{} – {}", label(&stop.value), label(&next.value)),
+                    None => format!("≥ {}", label(&stop.value)),
+                };
+                classes.push((label, stop.color.clone()));
+            }
+            classes
+        }
+    }
+}
+
+/// Entries for a symbol: one per ramp class, or a single one labelled
+/// `name`. Ramp classes are prefixed with `prefix`.
+fn symbol_entries(
+    name: &str,
+    prefix: Option<&str>,
+    fill: Option<&Fill>,
+    stroke: Option<&Stroke>,
+) -> Vec<LegendEntry> {
+    let fill = fill.map(|fill| &fill.color);
+    let stroke = stroke.map(|stroke| &stroke.color);
+    let class_label = |label: String| match prefix {
+        Some(prefix) => format!("{prefix}: {label}"),
+        None => label,
+    };
+    let mut entries = Vec::new();
+    if let Some(Paint::Ramp(ramp)) = fill {
+        for (label, color) in classes(ramp) {
+            entries.push(LegendEntry {
+                label: class_label(label),
+                property: Some(ramp.property.clone()),
+                kind: Some(ramp.kind),
+                fill: Some(color),
+                stroke: constant(stroke),
+            });
+        }
+    }
+    if let Some(Paint::Ramp(ramp)) = stroke {
+        for (label, color) in classes(ramp) {
+            entries.push(LegendEntry {
+                label: class_label(label),
+                property: Some(ramp.property.clone()),
+                kind: Some(ramp.kind),
+                fill: constant(fill),
+                stroke: Some(color),
+            });
+        }
+    }
+    if entries.is_empty() {
+        entries.push(LegendEntry {
+            label: name.to_string(),
+            property: None,
+            kind: None,
+            fill: constant(fill),
+            stroke: constant(stroke),
+        });
+    }
+    entries
+}
+
+impl Legend {
+    /// Entries for each rule in order, then for features matching none.
+    pub fn new(layer_id: &str, shape: SwatchShape, style: &LayerStyle) -> Self {
+        let mut entries = Vec::new();
+        for (index, rule) in style.rules.iter().enumerate() {
+            let name = rule
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("Rule {}", index + 1));
+            entries.extend(symbol_entries(
+                &name,
+                Some(&name),
+                rule.fill.as_ref(),
+                rule.stroke.as_ref(),
+            ));
+        }
+        let (name, prefix) = match style.rules.is_empty() {
+            true => (layer_id, None),
+            false => ("Other", Some("Other")),
+        };
+        entries.extend(symbol_entries(
+            name,
+            prefix,
+            style.fill.as_ref(),
+            style.stroke.as_ref(),
+        ));
+        Legend {
+            layer_id: layer_id.to_string(),
+            shape,
+            fill_opacity: style.fill.as_ref().map_or(1.0, |fill| fill.opacity),
+            stroke_opacity: style.stroke.as_ref().map_or(1.0, |stroke| stroke.opacity),
+            stroke_width: style.stroke.as_ref().map_or(0.0, |stroke| stroke.width),
+            point_radius: style.point_radius,
+            entries,
+        }
+    }
+
+    /// The swatches stacked top to bottom, one per entry. Only hex colours
+    /// are drawn; others leave their part of the swatch transparent.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let rows = self.entries.len() as u32;
+        let height = rows * (SWATCH_SIZE + SWATCH_GAP) - SWATCH_GAP;
+        let mut image = RgbaImage::new(SWATCH_SIZE, height);
+        for (index, entry) in self.entries.iter().enumerate() {
+            self.draw(&mut image, index as u32 * (SWATCH_SIZE + SWATCH_GAP), entry);
+        }
+        let mut encoded = Vec::new();
+        image.write_with_encoder(PngEncoder::new(&mut encoded))?;
+        Ok(encoded)
+    }
+
+    fn draw(&self, image: &mut RgbaImage, top: u32, entry: &LegendEntry) {
+        let fill = match (&entry.fill, self.shape) {
+            (Some(color), _) => parse_color(color, self.fill_opacity),
+            (None, SwatchShape::Point) => parse_color(DEFAULT_CIRCLE_COLOR, 1.0),
+            (None, _) => None,
+        };
+        let stroke = entry
+            .stroke
+            .as_deref()
+            .and_then(|color| parse_color(color, self.stroke_opacity));
+        let size = SWATCH_SIZE as f64;
+        let half = size / 2.0;
+        let width = self.stroke_width.clamp(0.0, half / 2.0);
+        for y in 0..SWATCH_SIZE {
+            for x in 0..SWATCH_SIZE {
+                let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                let color = match self.shape {
+                    SwatchShape::Polygon => {
+                        let edge = px.min(py).min(size - px).min(size - py) - 1.0;
+                        if edge < 0.0 {
+                            None
+                        } else if edge < width && stroke.is_some() {
+                            stroke
+                        } else {
+                            fill
+                        }
+                    }
+                    // Line layers only use the stroke
+                    SwatchShape::Line if (py - half).abs() <= width.max(1.0) / 2.0 => stroke,
+                    SwatchShape::Line => None,
+                    SwatchShape::Point => {
+                        let radius = self.point_radius.clamp(1.0, half - width - 1.0);
+                        let distance = ((px - half).powi(2) + (py - half).powi(2)).sqrt();
+                        if distance <= radius {
+                            fill
+                        } else if distance <= radius + width {
+                            stroke
+                        } else {
+                            None
+                        }
+                    }
+                };
+                if let Some(color) = color {
+                    image.put_pixel(x, top + y, color);
+                }
+            }
+        }
+    }
+}
+
+/// An RGBA colour from `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+fn parse_color(color: &str, opacity: f64) -> Option<Rgba<u8>> {
+    let hex = color.strip_prefix('#')?;
+    let channels: Vec<u8> = match hex.len() {
+        3 | 4 => hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u8 * 17))
+            .collect::<Option<_>>()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    let alpha = channels.get(3).copied().unwrap_or(255) as f64 * opacity.clamp(0.0, 1.0);
+    Some(Rgba([
+        channels[0],
+        channels[1],
+        channels[2],
+        alpha.round() as u8,
+    ]))
+}
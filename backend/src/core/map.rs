@@ -0,0 +1,182 @@
+use crate::core::{LayerStyle, MAX_TILE_ZOOM};
+use crate::data::{DataError, DataResult, Database};
+use crate::validation::{Validate, Validator};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A composed map: an ordered stack of layers plus the view it opens on.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Map {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Layers in draw order, bottom first.
+    pub layers: Vec<MapLayer>,
+    pub viewport: Viewport,
+    /// Id of the configured basemap to draw under the layers; `None` for
+    /// the deployment's default.
+    #[serde(default)]
+    pub basemap: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// When the map was moved to the trash. Trashed maps are hidden, and
+    /// their shares stop working, until restored or purged.
+    #[serde(default)]
+    pub deleted_at: Option<i64>,
+    /// Incremented on every write, starting at 1. Updates must name the
+    /// version they were based on, and fail if the map has moved on.
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MapLayer {
+    /// Id of the tile source backing this layer.
+    pub source_id: String,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub style: LayerStyle,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl Validate for MapLayer {
+    fn check(&self, v: &mut Validator) {
+        v.length("source_id", &self.source_id, 1, 255);
+        v.result("style", self.style.validate());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Viewport {
+    /// `[longitude, latitude]`
+    pub center: [f64; 2],
+    pub zoom: f64,
+    #[serde(default)]
+    pub bearing: f64,
+    #[serde(default)]
+    pub pitch: f64,
+}
+
+impl Validate for Viewport {
+    fn check(&self, v: &mut Validator) {
+        v.lon_lat("center", self.center);
+        v.range("zoom", self.zoom, 0.0, f64::from(MAX_TILE_ZOOM));
+        v.range("bearing", self.bearing, -360.0, 360.0);
+        v.range("pitch", self.pitch, 0.0, 85.0);
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            center: [0.0, 0.0],
+            zoom: 1.0,
+            bearing: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl Map {
+    pub fn new(
+        name: String,
+        description: Option<String>,
+        layers: Vec<MapLayer>,
+        viewport: Viewport,
+        basemap: Option<String>,
+    ) -> Self {
+        let now = Utc::now().timestamp();
+        Map {
+            id: Uuid::new_v4().to_string(),
+            name,
+            description,
+            layers,
+            viewport,
+            basemap,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            version: 1,
+        }
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.create_map(self).await
+    }
+
+    pub async fn from_id(database: &Arc<dyn Database>, id: &str) -> DataResult<Self> {
+        match database.get_map(id).await? {
+            map if map.deleted_at.is_some() => Err(DataError::NotFound("Map")),
+            map => Ok(map),
+        }
+    }
+
+    /// A map in the trash.
+    pub async fn from_trash(database: &Arc<dyn Database>, id: &str) -> DataResult<Self> {
+        match database.get_map(id).await? {
+            map if map.deleted_at.is_none() => Err(DataError::NotFound("Map")),
+            map => Ok(map),
+        }
+    }
+
+    pub async fn get_all(database: &Arc<dyn Database>) -> DataResult<Vec<Self>> {
+        let mut maps = database.get_maps().await?;
+        maps.retain(|map| map.deleted_at.is_none());
+        Ok(maps)
+    }
+
+    pub async fn get_trash(database: &Arc<dyn Database>) -> DataResult<Vec<Self>> {
+        let mut maps = database.get_maps().await?;
+        maps.retain(|map| map.deleted_at.is_some());
+        Ok(maps)
+    }
+
+    /// Store the map as the version after `self.version`, failing with a
+    /// conflict if another write got there first.
+    pub async fn update(&mut self, database: &Arc<dyn Database>) -> DataResult<()> {
+        self.updated_at = Utc::now().timestamp();
+        self.version += 1;
+        database.update_map(self).await
+    }
+
+    pub async fn trash(&mut self, database: &Arc<dyn Database>) -> DataResult<()> {
+        self.deleted_at = Some(Utc::now().timestamp());
+        self.update(database).await
+    }
+
+    pub async fn restore(&mut self, database: &Arc<dyn Database>) -> DataResult<()> {
+        self.deleted_at = None;
+        self.update(database).await
+    }
+
+    /// Delete the map for good, unless it has left the trash since it was
+    /// read.
+    pub async fn delete(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.delete_map(self).await
+    }
+
+    /// Permanently delete maps that have been in the trash for longer than
+    /// `retention` seconds, returning how many were removed. Maps restored
+    /// meanwhile are left alone.
+    pub async fn purge_trash(database: &Arc<dyn Database>, retention: i64) -> DataResult<usize> {
+        let cutoff = Utc::now().timestamp() - retention;
+        let mut purged = 0;
+        for map in Map::get_trash(database).await? {
+            if map.deleted_at.is_some_and(|deleted_at| deleted_at < cutoff) {
+                match map.delete(database).await {
+                    Ok(()) => purged += 1,
+                    Err(DataError::Conflict(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(purged)
+    }
+}
@@ -0,0 +1,27 @@
+pub mod attachment;
+pub mod basemap;
+pub mod comment;
+pub mod connector;
+pub mod events;
+pub mod form;
+pub mod job;
+pub mod layer;
+pub mod legend;
+pub mod map;
+pub mod search;
+pub mod share;
+pub mod style;
+
+pub use attachment::*;
+pub use basemap::*;
+pub use comment::*;
+pub use connector::*;
+pub use events::*;
+pub use form::*;
+pub use job::*;
+pub use layer::*;
+pub use legend::*;
+pub use map::*;
+pub use search::*;
+pub use share::*;
+pub use style::*;
@@ -0,0 +1,112 @@
+use crate::cdn::CacheKey;
+use crate::core::{Layer, Map};
+use crate::data::Database;
+use crate::postgis::LayerTable;
+use crate::query_cache::QueryCache;
+use crate::sources::SourceRegistry;
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+use utoipa::ToSchema;
+
+const FEATURES_PER_LAYER: i64 = 20;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchResult {
+    Layer {
+        id: String,
+        bbox: Option<[f64; 4]>,
+    },
+    Map {
+        id: String,
+        name: String,
+        description: Option<String>,
+    },
+    Feature {
+        layer: String,
+        properties: Value,
+        /// `[longitude, latitude]` of a point on the feature.
+        location: [f64; 2],
+    },
+}
+
+fn matches(term: &str, value: &str) -> bool {
+    value.to_lowercase().contains(term)
+}
+
+/// Search layer and map names, and optionally the text attributes of
+/// features in `feature_layers`. Feature matches are cached per layer.
+pub async fn search(
+    database: &Arc<dyn Database>,
+    sources: &SourceRegistry,
+    pool: &Pool,
+    cache: &QueryCache,
+    query: &str,
+    feature_layers: &[String],
+) -> Result<Vec<SearchResult>> {
+    let term = query.trim().to_lowercase();
+    let mut results = Vec::new();
+
+    let layers = Layer::get_all(database).await?;
+    for id in sources.ids() {
+        let cached = layers.iter().find(|l| l.id == id);
+        let catalog_match = cached.is_some_and(|layer| {
+            layer.catalog.has_tag(&term)
+                || layer
+                    .catalog
+                    .description
+                    .as_deref()
+                    .is_some_and(|description| matches(&term, description))
+        });
+        if matches(&term, &id) || catalog_match {
+            let bbox = cached.and_then(|l| l.bbox);
+            results.push(SearchResult::Layer { id, bbox });
+        }
+    }
+
+    for map in Map::get_all(database).await? {
+        let description_match = map
+            .description
+            .as_deref()
+            .is_some_and(|description| matches(&term, description));
+        if matches(&term, &map.name) || description_match {
+            results.push(SearchResult::Map {
+                id: map.id,
+                name: map.name,
+                description: map.description,
+            });
+        }
+    }
+
+    for source_id in feature_layers {
+        let features = cache
+            .get_or_compute(
+                "feature_search",
+                &(source_id, &term),
+                &[CacheKey::Layer(source_id.clone())],
+                || async {
+                    let table = LayerTable::from_source_id(pool, source_id).await?;
+                    table
+                        .search_features(pool, query.trim(), FEATURES_PER_LAYER)
+                        .await
+                },
+            )
+            .await;
+        match features {
+            Ok(features) => results.extend(features.into_iter().map(|(properties, location)| {
+                SearchResult::Feature {
+                    layer: source_id.clone(),
+                    properties,
+                    location,
+                }
+            })),
+            Err(e) => warn!("Feature search on {source_id} failed: {e}"),
+        }
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,205 @@
+use crate::data::{DataResult, Database};
+use crate::validation::ValidationErrors;
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareResource {
+    Map,
+    Layer,
+}
+
+impl ShareResource {
+    pub fn key_prefix(&self) -> &'static str {
+        match self {
+            ShareResource::Map => "MAP",
+            ShareResource::Layer => "LAYER",
+        }
+    }
+}
+
+/// A view-only link to a map or layer that works without a session.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Share {
+    pub token: String,
+    pub resource: ShareResource,
+    pub resource_id: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    /// Origins allowed to embed or fetch the share, e.g. `https://example.com`.
+    /// An empty list places no restriction.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+/// Longest lifetime of a share link, in seconds: one year.
+pub const MAX_EXPIRES_IN: i64 = 365 * 24 * 60 * 60;
+
+/// Most origins one share can be restricted to.
+pub const MAX_ALLOWED_ORIGINS: usize = 32;
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl Share {
+    /// `expires_in` is in seconds; `None` creates a link that never expires.
+    /// Repeated origins are dropped, as they are stored as a set.
+    pub fn new(
+        resource: ShareResource,
+        resource_id: &str,
+        expires_in: Option<i64>,
+        mut allowed_origins: Vec<String>,
+    ) -> Result<Self, ValidationErrors> {
+        let mut seen = HashSet::new();
+        allowed_origins.retain(|origin| seen.insert(origin.clone()));
+        let now = Utc::now().timestamp();
+        let expires_at = match expires_in {
+            Some(seconds) => Some(now.checked_add(seconds).ok_or_else(|| {
+                ValidationErrors::single("expires_in", "out_of_range", "is too far in the future")
+            })?),
+            None => None,
+        };
+        Ok(Share {
+            token: generate_token(),
+            resource,
+            resource_id: resource_id.to_string(),
+            created_at: now,
+            expires_at,
+            allowed_origins,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.expires_at
+            .map_or(true, |expires_at| expires_at > Utc::now().timestamp())
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.is_empty() || self.allowed_origins.iter().any(|o| o == origin)
+    }
+
+    /// Value for the `frame-ancestors` CSP directive.
+    pub fn frame_ancestors(&self) -> String {
+        if self.allowed_origins.is_empty() {
+            "*".to_string()
+        } else {
+            self.allowed_origins.join(" ")
+        }
+    }
+
+    pub async fn create(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.create_share(self).await
+    }
+
+    pub async fn from_token(database: &Arc<dyn Database>, token: &str) -> DataResult<Self> {
+        database.get_share(token).await
+    }
+
+    /// Active shares for a resource. Expired links are left for revocation.
+    pub async fn get_active(
+        database: &Arc<dyn Database>,
+        resource: ShareResource,
+        resource_id: &str,
+    ) -> DataResult<Vec<Self>> {
+        let shares = database.get_shares(resource, resource_id).await?;
+        Ok(shares.into_iter().filter(Share::is_active).collect())
+    }
+
+    pub async fn revoke(&self, database: &Arc<dyn Database>) -> DataResult<()> {
+        database.delete_share(self).await
+    }
+}
+
+/// Accept only bare `http(s)://host[:port]` origins.
+pub fn valid_origin(origin: &str) -> bool {
+    let Some(host) = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    !host.is_empty() && !host.contains(['/', ' ', ';', '\'', '"'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share_with_origins(origins: &[&str]) -> Share {
+        let origins = origins.iter().map(|o| o.to_string()).collect();
+        Share::new(ShareResource::Map, "map", None, origins).unwrap()
+    }
+
+    #[test]
+    fn expiry_is_counted_from_creation() {
+        let share = Share::new(ShareResource::Layer, "layer", Some(60), Vec::new()).unwrap();
+        assert_eq!(share.expires_at, Some(share.created_at + 60));
+        assert!(share.is_active());
+    }
+
+    #[test]
+    fn shares_without_expiry_stay_active() {
+        let share = share_with_origins(&[]);
+        assert_eq!(share.expires_at, None);
+        assert!(share.is_active());
+    }
+
+    #[test]
+    fn expired_shares_are_inactive() {
+        let mut share = share_with_origins(&[]);
+        share.expires_at = Some(Utc::now().timestamp() - 1);
+        assert!(!share.is_active());
+    }
+
+    #[test]
+    fn overflowing_expiry_is_rejected() {
+        let errors = Share::new(ShareResource::Map, "map", Some(i64::MAX), Vec::new()).unwrap_err();
+        assert_eq!(errors.errors[0].field, "expires_in");
+        assert_eq!(errors.errors[0].code, "out_of_range");
+    }
+
+    #[test]
+    fn unrestricted_shares_allow_any_origin() {
+        let share = share_with_origins(&[]);
+        assert!(share.allows_origin("https://anywhere.example"));
+        assert_eq!(share.frame_ancestors(), "*");
+    }
+
+    #[test]
+    fn restricted_shares_allow_only_listed_origins() {
+        let share = share_with_origins(&["https://a.example", "https://b.example:8443"]);
+        assert!(share.allows_origin("https://a.example"));
+        assert!(share.allows_origin("https://b.example:8443"));
+        assert!(!share.allows_origin("https://b.example"));
+        assert!(!share.allows_origin("http://a.example"));
+        assert_eq!(
+            share.frame_ancestors(),
+            "https://a.example https://b.example:8443"
+        );
+    }
+
+    #[test]
+    fn repeated_origins_are_dropped() {
+        let share = share_with_origins(&["https://a.example", "https://a.example"]);
+        assert_eq!(share.allowed_origins, vec!["https://a.example"]);
+    }
+
+    #[test]
+    fn origins_are_bare_scheme_and_host() {
+        assert!(valid_origin("https://example.com"));
+        assert!(valid_origin("http://localhost:3000"));
+        assert!(!valid_origin("example.com"));
+        assert!(!valid_origin("https://"));
+        assert!(!valid_origin("https://example.com/path"));
+        assert!(!valid_origin("https://example.com; script-src *"));
+        assert!(!valid_origin("ftp://example.com"));
+    }
+}
@@ -0,0 +1,650 @@
+use crate::config::LabelsConfig;
+use crate::core::Map;
+use crate::sources::{source_layer, SourceRegistry};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+/// Styling for a single map layer. Polygons use `fill`, lines and polygon
+/// outlines use `stroke`, points are drawn as circles using both.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LayerStyle {
+    pub fill: Option<Fill>,
+    pub stroke: Option<Stroke>,
+    #[serde(default = "default_point_radius")]
+    pub point_radius: f64,
+    /// Symbology for features matching a filter, drawn in order. Features
+    /// matching no rule's filter get the style above.
+    #[serde(default)]
+    pub rules: Vec<StyleRule>,
+    pub label: Option<LabelStyle>,
+}
+
+/// Symbology for the features matching every condition of `filter`, shown
+/// between `min_zoom` and `max_zoom`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StyleRule {
+    /// Shown in legends.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub filter: Vec<Condition>,
+    pub min_zoom: Option<f64>,
+    pub max_zoom: Option<f64>,
+    pub fill: Option<Fill>,
+    pub stroke: Option<Stroke>,
+    /// Defaults to the style's `point_radius`.
+    pub point_radius: Option<f64>,
+}
+
+/// A comparison of a feature attribute with a value.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Condition {
+    pub property: String,
+    pub op: ConditionOp,
+    /// A string, number or boolean; an array of them for `in`.
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    In,
+}
+
+/// Text drawn for each feature.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LabelStyle {
+    /// Template with attributes in braces, e.g. `{name} ({population})`.
+    pub text: String,
+    #[serde(default)]
+    pub placement: LabelPlacement,
+    /// Font stack; defaults to the configured `labels.font`.
+    pub font: Option<Vec<String>>,
+    #[serde(default = "default_text_size")]
+    pub size: f64,
+    #[serde(default = "default_text_color")]
+    pub color: String,
+    pub halo_color: Option<String>,
+    #[serde(default = "default_halo_width")]
+    pub halo_width: f64,
+    /// Offset from the anchor in ems, `[right, down]`.
+    #[serde(default)]
+    pub offset: [f64; 2],
+    /// Draw labels even where they collide with others.
+    #[serde(default)]
+    pub allow_overlap: bool,
+    pub min_zoom: Option<f64>,
+    pub max_zoom: Option<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelPlacement {
+    /// At the point, or the centre of lines and polygons.
+    #[default]
+    Point,
+    /// Repeated along lines and polygon outlines.
+    Line,
+    /// Once, at the middle of lines.
+    LineCenter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Fill {
+    pub color: Paint,
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Stroke {
+    pub color: Paint,
+    #[serde(default = "default_stroke_width")]
+    pub width: f64,
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+}
+
+/// A colour that is either constant or driven by a feature attribute.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Paint {
+    Constant(String),
+    Ramp(Ramp),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Ramp {
+    pub property: String,
+    #[serde(rename = "type")]
+    pub kind: RampKind,
+    pub stops: Vec<Stop>,
+    /// Colour for features that fall outside the stops.
+    #[serde(default = "default_color")]
+    pub default: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RampKind {
+    /// Continuous gradient between numeric stops.
+    Interpolate,
+    /// Graduated classes starting at each numeric stop.
+    Step,
+    /// Exact matches on attribute values.
+    Categorical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Stop {
+    pub value: Value,
+    pub color: String,
+}
+
+fn default_point_radius() -> f64 {
+    4.0
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+fn default_stroke_width() -> f64 {
+    1.0
+}
+
+fn default_color() -> String {
+    "#888888".to_string()
+}
+
+fn default_text_size() -> f64 {
+    12.0
+}
+
+fn default_text_color() -> String {
+    "#333333".to_string()
+}
+
+fn default_halo_width() -> f64 {
+    1.0
+}
+
+/// Beyond MapLibre's deepest zoom.
+const MAX_ZOOM: f64 = 24.0;
+const MAX_RULES: usize = 50;
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        LayerStyle {
+            fill: None,
+            stroke: None,
+            point_radius: default_point_radius(),
+            rules: Vec::new(),
+            label: None,
+        }
+    }
+}
+
+impl LayerStyle {
+    pub fn validate(&self) -> Result<()> {
+        validate_symbol(
+            self.fill.as_ref(),
+            self.stroke.as_ref(),
+            Some(self.point_radius),
+        )?;
+        if self.rules.len() > MAX_RULES {
+            return Err(anyhow!("a style can have at most {MAX_RULES} rules"));
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate()
+                .map_err(|e| anyhow!("rule {}: {e}", index + 1))?;
+        }
+        if let Some(label) = &self.label {
+            label.validate().map_err(|e| anyhow!("label: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_symbol(
+    fill: Option<&Fill>,
+    stroke: Option<&Stroke>,
+    point_radius: Option<f64>,
+) -> Result<()> {
+    if let Some(fill) = fill {
+        fill.color.validate()?;
+        validate_opacity(fill.opacity)?;
+    }
+    if let Some(stroke) = stroke {
+        stroke.color.validate()?;
+        validate_opacity(stroke.opacity)?;
+        if stroke.width < 0.0 {
+            return Err(anyhow!("stroke width must not be negative"));
+        }
+    }
+    if point_radius.is_some_and(|radius| radius < 0.0) {
+        return Err(anyhow!("point radius must not be negative"));
+    }
+    Ok(())
+}
+
+fn validate_zoom(min_zoom: Option<f64>, max_zoom: Option<f64>) -> Result<()> {
+    for zoom in min_zoom.into_iter().chain(max_zoom) {
+        if !(0.0..=MAX_ZOOM).contains(&zoom) {
+            return Err(anyhow!("zoom must be between 0 and {MAX_ZOOM}"));
+        }
+    }
+    if let (Some(min), Some(max)) = (min_zoom, max_zoom) {
+        if min >= max {
+            return Err(anyhow!("min_zoom must be below max_zoom"));
+        }
+    }
+    Ok(())
+}
+
+impl StyleRule {
+    fn validate(&self) -> Result<()> {
+        validate_symbol(self.fill.as_ref(), self.stroke.as_ref(), self.point_radius)?;
+        validate_zoom(self.min_zoom, self.max_zoom)?;
+        for condition in &self.filter {
+            condition.validate()?;
+        }
+        Ok(())
+    }
+
+    /// The rule's filter as a MapLibre expression.
+    fn to_expression(&self) -> Value {
+        let mut all = vec![json!("all")];
+        all.extend(self.filter.iter().map(Condition::to_expression));
+        Value::Array(all)
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+}
+
+impl Condition {
+    fn validate(&self) -> Result<()> {
+        if self.property.is_empty() {
+            return Err(anyhow!("condition property must not be empty"));
+        }
+        let valid = match self.op {
+            ConditionOp::Eq | ConditionOp::Ne => is_scalar(&self.value),
+            ConditionOp::Lt | ConditionOp::Lte | ConditionOp::Gt | ConditionOp::Gte => {
+                self.value.is_number()
+            }
+            ConditionOp::In => self
+                .value
+                .as_array()
+                .is_some_and(|values| !values.is_empty() && values.iter().all(is_scalar)),
+        };
+        if !valid {
+            return Err(anyhow!(
+                "invalid value for {:?} on {}",
+                self.op,
+                self.property
+            ));
+        }
+        Ok(())
+    }
+
+    fn to_expression(&self) -> Value {
+        let input = json!(["get", self.property]);
+        let op = match self.op {
+            ConditionOp::Eq => "==",
+            ConditionOp::Ne => "!=",
+            ConditionOp::Lt => "<",
+            ConditionOp::Lte => "<=",
+            ConditionOp::Gt => ">",
+            ConditionOp::Gte => ">=",
+            ConditionOp::In => return json!(["in", input, ["literal", self.value]]),
+        };
+        json!([op, input, self.value])
+    }
+}
+
+/// A label template split into literal text and attribute names.
+fn parse_template(template: &str) -> Result<Vec<(bool, String)>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| anyhow!("unclosed {{ in label text"))?;
+        let property = &rest[start + 1..end];
+        if property.is_empty() || property.contains('{') {
+            return Err(anyhow!("invalid attribute in label text"));
+        }
+        if start > 0 {
+            parts.push((false, rest[..start].to_string()));
+        }
+        parts.push((true, property.to_string()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push((false, rest.to_string()));
+    }
+    Ok(parts)
+}
+
+impl LabelStyle {
+    fn validate(&self) -> Result<()> {
+        if parse_template(&self.text)?.is_empty() {
+            return Err(anyhow!("text must not be empty"));
+        }
+        if !(self.size > 0.0 && self.size <= 96.0) {
+            return Err(anyhow!("size must be between 0 and 96"));
+        }
+        if self.halo_width < 0.0 {
+            return Err(anyhow!("halo width must not be negative"));
+        }
+        if self.font.as_ref().is_some_and(|font| font.is_empty()) {
+            return Err(anyhow!("font must name at least one font"));
+        }
+        validate_zoom(self.min_zoom, self.max_zoom)
+    }
+
+    /// The text as a MapLibre expression, missing attributes left blank.
+    fn text_expression(&self) -> Value {
+        let mut parts: Vec<Value> = parse_template(&self.text)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(attribute, part)| match attribute {
+                true => json!(["to-string", ["coalesce", ["get", part], ""]]),
+                false => json!(part),
+            })
+            .collect();
+        match parts.len() {
+            1 => parts.remove(0),
+            _ => {
+                parts.insert(0, json!("concat"));
+                Value::Array(parts)
+            }
+        }
+    }
+}
+
+fn validate_opacity(opacity: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err(anyhow!("opacity must be between 0 and 1"));
+    }
+    Ok(())
+}
+
+impl Paint {
+    fn validate(&self) -> Result<()> {
+        let Paint::Ramp(ramp) = self else {
+            return Ok(());
+        };
+        if ramp.stops.is_empty() {
+            return Err(anyhow!("ramp on {} has no stops", ramp.property));
+        }
+        if matches!(ramp.kind, RampKind::Interpolate | RampKind::Step) {
+            let mut previous = f64::NEG_INFINITY;
+            for stop in &ramp.stops {
+                let value = stop
+                    .value
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("ramp on {} needs numeric stops", ramp.property))?;
+                if value <= previous {
+                    return Err(anyhow!("ramp on {} stops must ascend", ramp.property));
+                }
+                previous = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Render as a MapLibre paint value or expression.
+    pub fn to_expression(&self) -> Value {
+        let ramp = match self {
+            Paint::Constant(color) => return json!(color),
+            Paint::Ramp(ramp) => ramp,
+        };
+        let input = json!(["get", ramp.property]);
+        let mut expression = match ramp.kind {
+            RampKind::Interpolate => vec![json!("interpolate"), json!(["linear"]), input],
+            RampKind::Step => vec![json!("step"), input, json!(ramp.default)],
+            RampKind::Categorical => vec![json!("match"), input],
+        };
+        for stop in &ramp.stops {
+            expression.push(stop.value.clone());
+            expression.push(json!(stop.color));
+        }
+        if let RampKind::Categorical = ramp.kind {
+            expression.push(json!(ramp.default));
+        }
+        Value::Array(expression)
+    }
+}
+
+/// Match features by geometry, including multi-part geometries.
+fn geometry_filter(types: &[&str]) -> Value {
+    let mut all_types = Vec::new();
+    for kind in types {
+        all_types.push(json!(kind));
+        all_types.push(json!(format!("Multi{kind}")));
+    }
+    json!(["match", ["geometry-type"], all_types, true, false])
+}
+
+/// Fill, line and circle layers drawing a symbol, as `(suffix, type, filter,
+/// paint)`, restricted to features matching `condition`.
+fn symbol_layers(
+    fill: Option<&Fill>,
+    stroke: Option<&Stroke>,
+    point_radius: f64,
+    condition: Option<&Value>,
+) -> Vec<(&'static str, &'static str, Value, Value)> {
+    let filter = |types: &[&str]| match condition {
+        Some(condition) => json!(["all", geometry_filter(types), condition]),
+        None => geometry_filter(types),
+    };
+    let mut layers = Vec::new();
+
+    if let Some(fill) = fill {
+        layers.push((
+            "fill",
+            "fill",
+            filter(&["Polygon"]),
+            json!({
+                "fill-color": fill.color.to_expression(),
+                "fill-opacity": fill.opacity,
+            }),
+        ));
+    }
+
+    if let Some(stroke) = stroke {
+        layers.push((
+            "line",
+            "line",
+            filter(&["LineString", "Polygon"]),
+            json!({
+                "line-color": stroke.color.to_expression(),
+                "line-width": stroke.width,
+                "line-opacity": stroke.opacity,
+            }),
+        ));
+    }
+
+    let mut paint = serde_json::Map::new();
+    paint.insert("circle-radius".into(), json!(point_radius));
+    if let Some(fill) = fill {
+        paint.insert("circle-color".into(), fill.color.to_expression());
+        paint.insert("circle-opacity".into(), json!(fill.opacity));
+    }
+    if let Some(stroke) = stroke {
+        paint.insert("circle-stroke-color".into(), stroke.color.to_expression());
+        paint.insert("circle-stroke-width".into(), json!(stroke.width));
+    }
+    layers.push(("circle", "circle", filter(&["Point"]), Value::Object(paint)));
+    layers
+}
+
+fn set_zoom_range(layer: &mut Value, min_zoom: Option<f64>, max_zoom: Option<f64>) {
+    if let Some(min_zoom) = min_zoom {
+        layer["minzoom"] = json!(min_zoom);
+    }
+    if let Some(max_zoom) = max_zoom {
+        layer["maxzoom"] = json!(max_zoom);
+    }
+}
+
+/// Build a complete MapLibre style document for a map. `tile_url` gives the
+/// tile URL template for a source id.
+pub fn style_document(
+    map: &Map,
+    sources: &SourceRegistry,
+    labels: &LabelsConfig,
+    tile_url: impl Fn(&str) -> String,
+) -> Value {
+    let mut style_sources = serde_json::Map::new();
+    let mut style_layers = Vec::new();
+
+    for (index, layer) in map.layers.iter().enumerate() {
+        let Some(source) = sources.get(&layer.source_id) else {
+            continue;
+        };
+        let tilejson = source.get_tilejson();
+        style_sources.insert(
+            layer.source_id.clone(),
+            json!({
+                "type": "vector",
+                "tiles": [tile_url(&layer.source_id)],
+                "minzoom": tilejson.minzoom.unwrap_or(0),
+                "maxzoom": tilejson.maxzoom.unwrap_or(22),
+            }),
+        );
+
+        let source_layer = source_layer(source.as_ref());
+        let visibility = if layer.visible { "visible" } else { "none" };
+        let style = &layer.style;
+        let base = |suffix: &str, kind: &str, filter: Value, paint: Value| {
+            json!({
+                "id": format!("{index}-{}-{suffix}", layer.source_id),
+                "type": kind,
+                "source": layer.source_id,
+                "source-layer": source_layer,
+                "filter": filter,
+                "layout": { "visibility": visibility },
+                "paint": paint,
+            })
+        };
+
+        // The base style draws what no rule matches
+        let unmatched = (!style.rules.is_empty()).then(|| {
+            let mut any = vec![json!("any")];
+            any.extend(style.rules.iter().map(StyleRule::to_expression));
+            json!(["!", any])
+        });
+        for (suffix, kind, filter, paint) in symbol_layers(
+            style.fill.as_ref(),
+            style.stroke.as_ref(),
+            style.point_radius,
+            unmatched.as_ref(),
+        ) {
+            style_layers.push(base(suffix, kind, filter, paint));
+        }
+
+        for (number, rule) in style.rules.iter().enumerate() {
+            let condition = rule.to_expression();
+            for (suffix, kind, filter, paint) in symbol_layers(
+                rule.fill.as_ref(),
+                rule.stroke.as_ref(),
+                rule.point_radius.unwrap_or(style.point_radius),
+                Some(&condition),
+            ) {
+                let mut rule_layer = base(&format!("rule{number}-{suffix}"), kind, filter, paint);
+                set_zoom_range(&mut rule_layer, rule.min_zoom, rule.max_zoom);
+                style_layers.push(rule_layer);
+            }
+        }
+
+        if let Some(label) = &style.label {
+            let font = label
+                .font
+                .clone()
+                .unwrap_or_else(|| vec![labels.font.clone()]);
+            let placement = match label.placement {
+                LabelPlacement::Point => "point",
+                LabelPlacement::Line => "line",
+                LabelPlacement::LineCenter => "line-center",
+            };
+            let mut label_layer = json!({
+                "id": format!("{index}-{}-label", layer.source_id),
+                "type": "symbol",
+                "source": layer.source_id,
+                "source-layer": source_layer,
+                "layout": {
+                    "visibility": visibility,
+                    "text-field": label.text_expression(),
+                    "text-font": font,
+                    "text-size": label.size,
+                    "text-offset": label.offset,
+                    "text-allow-overlap": label.allow_overlap,
+                    "symbol-placement": placement,
+                },
+                "paint": { "text-color": label.color },
+            });
+            if let Some(halo_color) = &label.halo_color {
+                label_layer["paint"]["text-halo-color"] = json!(halo_color);
+                label_layer["paint"]["text-halo-width"] = json!(label.halo_width);
+            }
+            set_zoom_range(&mut label_layer, label.min_zoom, label.max_zoom);
+            style_layers.push(label_layer);
+        }
+    }
+
+    let mut document = json!({
+        "version": 8,
+        "name": map.name,
+        "center": map.viewport.center,
+        "zoom": map.viewport.zoom,
+        "bearing": map.viewport.bearing,
+        "pitch": map.viewport.pitch,
+        "sources": style_sources,
+        "layers": style_layers,
+    });
+    if let Some(glyphs) = &labels.glyphs_url {
+        document["glyphs"] = json!(glyphs);
+    }
+    document
+}
+
+/// Point every layer of a style document at a single source serving the
+/// map's composite tiles, replacing the per-layer sources. Layers keep their
+/// `source-layer`, since composite tiles hold each layer under its own name.
+pub fn use_composite_source(document: &mut Value, tile_url: String) {
+    let zooms = |key: &str| {
+        document["sources"]
+            .as_object()
+            .into_iter()
+            .flat_map(|sources| sources.values())
+            .filter_map(|source| source[key].as_u64())
+            .collect::<Vec<_>>()
+    };
+    let min_zoom = zooms("minzoom").into_iter().min().unwrap_or(0);
+    let max_zoom = zooms("maxzoom").into_iter().max().unwrap_or(22);
+    document["sources"] = json!({
+        "composite": {
+            "type": "vector",
+            "tiles": [tile_url],
+            "minzoom": min_zoom,
+            "maxzoom": max_zoom,
+        }
+    });
+    if let Some(layers) = document["layers"].as_array_mut() {
+        for layer in layers {
+            layer["source"] = json!("composite");
+        }
+    }
+}
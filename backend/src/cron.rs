@@ -0,0 +1,161 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// Days searched for the next match. Long enough to reach the next
+/// 29 February across a skipped leap year.
+const MAX_DAYS: u32 = 366 * 8;
+
+/// A five-field cron expression, `minute hour day-of-month month
+/// day-of-week`, evaluated in UTC. Fields take `*`, numbers, ranges `a-b`,
+/// steps `*/n` or `a-b/n` and comma-separated lists of these; days of the
+/// week run from 0 (Sunday) to 7 (Sunday again). When both day fields are
+/// restricted a day matching either is enough, as in classic cron. The
+/// shorthands `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are
+/// accepted too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// The first minute strictly after `after` the schedule fires at.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date_naive();
+        for _ in 0..MAX_DAYS {
+            if self.matches_day(date) {
+                let first = date == start.date_naive();
+                let from_hour = if first { start.hour() } else { 0 };
+                for hour in (from_hour..24).filter(|h| bit(self.hours, *h)) {
+                    let from_minute = if first && hour == from_hour {
+                        start.minute()
+                    } else {
+                        0
+                    };
+                    if let Some(minute) = (from_minute..60).find(|m| bit(self.minutes, *m)) {
+                        let time = date.and_hms_opt(hour, minute, 0)?;
+                        return Some(Utc.from_utc_datetime(&time));
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !bit(self.months, date.month()) {
+            return false;
+        }
+        let day = bit(self.days, date.day());
+        let weekday = bit(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// The values a field matches, as a bit set, and whether it starts with `*`
+/// and so leaves the other day field to decide.
+fn parse_field(field: &str, name: &str, min: u32, max: u32) -> Result<(u64, bool), String> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step in {name} field: {part}"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let number = |value: &str| -> Result<u32, String> {
+            value
+                .parse()
+                .ok()
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| format!("{name} must be between {min} and {max}, got {value}"))
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (number(from)?, number(to)?),
+                // `5/15` means from 5 to the end in steps of 15
+                None if step > 1 => (number(range)?, max),
+                None => {
+                    let value = number(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if from > to {
+            return Err(format!("{name} range {range} runs backwards"));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok((set, field.starts_with('*')))
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+        let (weekdays, any_weekday) = parse_field(weekday, "day of week", 0, 7)?;
+        let (days, any_day) = parse_field(day, "day of month", 1, 31)?;
+        let schedule = CronSchedule {
+            expression: expression.trim().to_string(),
+            minutes: parse_field(minute, "minute", 0, 59)?.0,
+            hours: parse_field(hour, "hour", 0, 23)?.0,
+            days,
+            months: parse_field(month, "month", 1, 12)?.0,
+            // 7 is another name for Sunday
+            weekdays: weekdays | ((weekdays >> 7) & 1),
+            any_day,
+            any_weekday,
+        };
+        if schedule.next_after(Utc::now()).is_none() {
+            return Err("never fires, e.g. 30 February".to_string());
+        }
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
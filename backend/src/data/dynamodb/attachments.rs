@@ -0,0 +1,138 @@
+use super::conversions::{get_n, get_opt_bool, get_s, Item};
+use super::Dynamodb;
+use crate::core::Attachment;
+use crate::data::{AttachmentStore, DataError, DataResult};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use metrics::counter;
+use tracing::instrument;
+
+fn feature_key(layer_id: &str, feature_id: &str) -> AV {
+    AV::S(format!("FEATURE#{layer_id}#{feature_id}"))
+}
+
+fn attachment_key(id: &str) -> AV {
+    AV::S(format!("ATTACHMENT#{id}"))
+}
+
+fn attachment_to_item(attachment: &Attachment) -> Item {
+    let mut item = Item::new();
+    item.insert(
+        "PK".to_string(),
+        feature_key(&attachment.layer_id, &attachment.feature_id),
+    );
+    item.insert("SK".to_string(), attachment_key(&attachment.id));
+    item.insert("layer_id".to_string(), AV::S(attachment.layer_id.clone()));
+    item.insert(
+        "feature_id".to_string(),
+        AV::S(attachment.feature_id.clone()),
+    );
+    item.insert("filename".to_string(), AV::S(attachment.filename.clone()));
+    item.insert(
+        "content_type".to_string(),
+        AV::S(attachment.content_type.clone()),
+    );
+    item.insert("size".to_string(), AV::N(attachment.size.to_string()));
+    item.insert(
+        "has_thumbnail".to_string(),
+        AV::Bool(attachment.has_thumbnail),
+    );
+    item.insert(
+        "created_at".to_string(),
+        AV::N(attachment.created_at.to_string()),
+    );
+    item
+}
+
+impl TryFrom<&Item> for Attachment {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        Ok(Attachment {
+            id: get_s(item, "SK")?
+                .trim_start_matches("ATTACHMENT#")
+                .to_string(),
+            layer_id: get_s(item, "layer_id")?,
+            feature_id: get_s(item, "feature_id")?,
+            filename: get_s(item, "filename")?,
+            content_type: get_s(item, "content_type")?,
+            size: get_n(item, "size")?,
+            has_thumbnail: get_opt_bool(item, "has_thumbnail")?.unwrap_or(false),
+            created_at: get_n(item, "created_at")?,
+        })
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn create_attachment(&self, attachment: &Attachment) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "create_attachment").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(attachment_to_item(attachment)))
+            .condition_expression("attribute_not_exists(PK)")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_attachment(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+        id: &str,
+    ) -> DataResult<Attachment> {
+        counter!("dynamodb_calls_total", "operation" => "get_attachment").increment(1);
+        let response = self
+            .reader
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", feature_key(layer_id, feature_id))
+            .key("SK", attachment_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Attachment"))?;
+        Attachment::try_from(&item)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_attachments(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+    ) -> DataResult<Vec<Attachment>> {
+        counter!("dynamodb_calls_total", "operation" => "get_attachments").increment(1);
+        let items = self
+            .reader
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("PK = :pk AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":pk", feature_key(layer_id, feature_id))
+            .expression_attribute_values(":prefix", AV::S("ATTACHMENT#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Attachment::try_from).collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_attachment(&self, attachment: &Attachment) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "delete_attachment").increment(1);
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(
+                "PK",
+                feature_key(&attachment.layer_id, &attachment.feature_id),
+            )
+            .key("SK", attachment_key(&attachment.id))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
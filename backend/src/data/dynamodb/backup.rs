@@ -0,0 +1,136 @@
+use super::conversions::Item;
+use super::Dynamodb;
+use crate::data::{BackupStore, DataResult};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{AttributeValue as AV, PutRequest, WriteRequest};
+use metrics::counter;
+use serde_json::{json, Map, Value};
+use tracing::instrument;
+
+/// Writes per BatchWriteItem request, DynamoDB's limit.
+const BATCH_WRITE_SIZE: usize = 25;
+
+fn value_to_json(value: &AV) -> Result<Value> {
+    Ok(match value {
+        AV::S(s) => json!({ "S": s }),
+        AV::N(n) => json!({ "N": n }),
+        AV::Bool(b) => json!({ "BOOL": b }),
+        AV::Null(null) => json!({ "NULL": null }),
+        AV::Ss(strings) => json!({ "SS": strings }),
+        AV::Ns(numbers) => json!({ "NS": numbers }),
+        AV::L(values) => {
+            json!({ "L": values.iter().map(value_to_json).collect::<Result<Vec<_>>>()? })
+        }
+        AV::M(values) => json!({ "M": item_to_json(values)? }),
+        other => return Err(anyhow!("unsupported attribute value {other:?}")),
+    })
+}
+
+fn item_to_json(item: &Item) -> Result<Value> {
+    let mut object = Map::new();
+    for (name, value) in item {
+        object.insert(name.clone(), value_to_json(value)?);
+    }
+    Ok(Value::Object(object))
+}
+
+fn strings(values: &[Value]) -> Result<Vec<String>> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("expected a string, got {value}"))
+        })
+        .collect()
+}
+
+fn value_from_json(value: &Value) -> Result<AV> {
+    let typed = value
+        .as_object()
+        .filter(|object| object.len() == 1)
+        .and_then(|object| object.iter().next());
+    let Some((kind, value)) = typed else {
+        return Err(anyhow!("expected a typed attribute value, got {value}"));
+    };
+    Ok(match (kind.as_str(), value) {
+        ("S", Value::String(s)) => AV::S(s.clone()),
+        ("N", Value::String(n)) => AV::N(n.clone()),
+        ("BOOL", Value::Bool(b)) => AV::Bool(*b),
+        ("NULL", Value::Bool(null)) => AV::Null(*null),
+        ("SS", Value::Array(values)) => AV::Ss(strings(values)?),
+        ("NS", Value::Array(values)) => AV::Ns(strings(values)?),
+        ("L", Value::Array(values)) => {
+            AV::L(values.iter().map(value_from_json).collect::<Result<_>>()?)
+        }
+        ("M", values) => AV::M(item_from_json(values)?),
+        (kind, value) => return Err(anyhow!("unsupported attribute value {kind}: {value}")),
+    })
+}
+
+fn item_from_json(value: &Value) -> Result<Item> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow!("expected an object of attributes, got {value}"))?;
+    object
+        .iter()
+        .map(|(name, value)| Ok((name.clone(), value_from_json(value)?)))
+        .collect()
+}
+
+#[async_trait]
+impl BackupStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn export_items(&self) -> DataResult<Vec<Value>> {
+        counter!("dynamodb_calls_total", "operation" => "export_items").increment(1);
+        let items = self
+            .reader
+            .scan()
+            .table_name(&self.table_name)
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        Ok(items.iter().map(item_to_json).collect::<Result<Vec<_>>>()?)
+    }
+
+    #[instrument(skip_all, fields(items = items.len()))]
+    async fn import_items(&self, items: &[Value]) -> DataResult<usize> {
+        // Parse everything first, so a bad item fails the restore before
+        // anything is written
+        let items: Vec<Item> = items.iter().map(item_from_json).collect::<Result<_>>()?;
+        for item in &items {
+            if !item.contains_key("PK") || !item.contains_key("SK") {
+                return Err(anyhow!("item without a PK and SK").into());
+            }
+        }
+
+        for chunk in items.chunks(BATCH_WRITE_SIZE) {
+            let mut writes = chunk
+                .iter()
+                .map(|item| {
+                    let put = PutRequest::builder().set_item(Some(item.clone())).build()?;
+                    Ok(WriteRequest::builder().put_request(put).build())
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Throttled writes come back unprocessed, to be sent again
+            while !writes.is_empty() {
+                counter!("dynamodb_calls_total", "operation" => "import_items").increment(1);
+                let response = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.table_name, writes)
+                    .send()
+                    .await?;
+                writes = response
+                    .unprocessed_items
+                    .and_then(|mut unprocessed| unprocessed.remove(&self.table_name))
+                    .unwrap_or_default();
+            }
+        }
+        Ok(items.len())
+    }
+}
@@ -0,0 +1,126 @@
+use super::conversions::{get_json, get_n, get_opt_bool, get_opt_json, get_opt_s, get_s, Item};
+use super::Dynamodb;
+use crate::core::Comment;
+use crate::data::{CommentStore, DataError, DataResult};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use metrics::counter;
+use tracing::instrument;
+
+fn map_key(map_id: &str) -> AV {
+    AV::S(format!("MAP#{map_id}"))
+}
+
+fn comment_key(id: &str) -> AV {
+    AV::S(format!("COMMENT#{id}"))
+}
+
+fn comment_to_item(comment: &Comment) -> DataResult<Item> {
+    let mut item = Item::new();
+    item.insert("PK".to_string(), map_key(&comment.map_id));
+    item.insert("SK".to_string(), comment_key(&comment.id));
+    if let Some(parent_id) = &comment.parent_id {
+        item.insert("parent_id".to_string(), AV::S(parent_id.clone()));
+    }
+    item.insert("author".to_string(), AV::S(comment.author.clone()));
+    item.insert("body".to_string(), AV::S(comment.body.clone()));
+    if let Some(anchor) = &comment.anchor {
+        item.insert("anchor".to_string(), AV::S(serde_json::to_string(anchor)?));
+    }
+    item.insert(
+        "mentions".to_string(),
+        AV::S(serde_json::to_string(&comment.mentions)?),
+    );
+    item.insert("resolved".to_string(), AV::Bool(comment.resolved));
+    item.insert(
+        "created_at".to_string(),
+        AV::N(comment.created_at.to_string()),
+    );
+    item.insert(
+        "updated_at".to_string(),
+        AV::N(comment.updated_at.to_string()),
+    );
+    Ok(item)
+}
+
+impl TryFrom<&Item> for Comment {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        Ok(Comment {
+            id: get_s(item, "SK")?
+                .trim_start_matches("COMMENT#")
+                .to_string(),
+            map_id: get_s(item, "PK")?.trim_start_matches("MAP#").to_string(),
+            parent_id: get_opt_s(item, "parent_id")?,
+            author: get_s(item, "author")?,
+            body: get_s(item, "body")?,
+            anchor: get_opt_json(item, "anchor")?,
+            mentions: get_json(item, "mentions")?,
+            resolved: get_opt_bool(item, "resolved")?.unwrap_or(false),
+            created_at: get_n(item, "created_at")?,
+            updated_at: get_n(item, "updated_at")?,
+        })
+    }
+}
+
+#[async_trait]
+impl CommentStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn create_comment(&self, comment: &Comment) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "create_comment").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(comment_to_item(comment)?))
+            .condition_expression("attribute_not_exists(SK)")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_comment(&self, map_id: &str, id: &str) -> DataResult<Comment> {
+        counter!("dynamodb_calls_total", "operation" => "get_comment").increment(1);
+        let response = self
+            .reader
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", map_key(map_id))
+            .key("SK", comment_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Comment"))?;
+        Comment::try_from(&item)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_comments(&self, map_id: &str) -> DataResult<Vec<Comment>> {
+        counter!("dynamodb_calls_total", "operation" => "get_comments").increment(1);
+        let items = self
+            .reader
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("PK = :pk AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":pk", map_key(map_id))
+            .expression_attribute_values(":prefix", AV::S("COMMENT#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Comment::try_from).collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn update_comment(&self, comment: &Comment) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "update_comment").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(comment_to_item(comment)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use super::conversions::{get_json, get_n, get_opt_n, get_opt_s, get_s, Item};
+use super::Dynamodb;
+use crate::core::Connector;
+use crate::data::{ConnectorStore, DataError, DataResult};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use metrics::counter;
+use tracing::instrument;
+
+fn connector_key(id: &str) -> AV {
+    AV::S(format!("CONNECTOR#{id}"))
+}
+
+fn connector_to_item(connector: &Connector) -> DataResult<Item> {
+    let mut item = Item::new();
+    item.insert("PK".to_string(), connector_key(&connector.id));
+    item.insert("SK".to_string(), connector_key(&connector.id));
+    item.insert(
+        "source".to_string(),
+        AV::S(serde_json::to_string(&connector.source)?),
+    );
+    item.insert("layer_id".to_string(), AV::S(connector.layer_id.clone()));
+    if let Some(interval) = connector.refresh_interval {
+        item.insert("refresh_interval".to_string(), AV::N(interval.to_string()));
+    }
+    if let Some(harvested_at) = connector.last_harvested_at {
+        item.insert(
+            "last_harvested_at".to_string(),
+            AV::N(harvested_at.to_string()),
+        );
+    }
+    if let Some(error) = &connector.last_error {
+        item.insert("last_error".to_string(), AV::S(error.clone()));
+    }
+    item.insert(
+        "created_at".to_string(),
+        AV::N(connector.created_at.to_string()),
+    );
+    Ok(item)
+}
+
+impl TryFrom<&Item> for Connector {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        Ok(Connector {
+            id: get_s(item, "PK")?
+                .trim_start_matches("CONNECTOR#")
+                .to_string(),
+            source: get_json(item, "source")?,
+            layer_id: get_s(item, "layer_id")?,
+            refresh_interval: get_opt_n(item, "refresh_interval")?,
+            last_harvested_at: get_opt_n(item, "last_harvested_at")?,
+            last_error: get_opt_s(item, "last_error")?,
+            created_at: get_n(item, "created_at")?,
+        })
+    }
+}
+
+#[async_trait]
+impl ConnectorStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn put_connector(&self, connector: &Connector) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "put_connector").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(connector_to_item(connector)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_connector(&self, id: &str) -> DataResult<Connector> {
+        counter!("dynamodb_calls_total", "operation" => "get_connector").increment(1);
+        let response = self
+            .reader
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", connector_key(id))
+            .key("SK", connector_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Connector"))?;
+        Connector::try_from(&item)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_connectors(&self) -> DataResult<Vec<Connector>> {
+        counter!("dynamodb_calls_total", "operation" => "get_connectors").increment(1);
+        let items = self
+            .reader
+            .scan()
+            .table_name(&self.table_name)
+            .filter_expression("begins_with(PK, :prefix) AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":prefix", AV::S("CONNECTOR#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Connector::try_from).collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_connector(&self, id: &str) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "delete_connector").increment(1);
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", connector_key(id))
+            .key("SK", connector_key(id))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
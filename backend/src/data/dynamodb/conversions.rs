@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::de::DeserializeOwned;
+use std::any::type_name;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+/// The attribute, unless it is absent or null.
+fn present<'a>(item: &'a Item, key: &str) -> Option<&'a AttributeValue> {
+    item.get(key)
+        .filter(|value| !matches!(value, AttributeValue::Null(_)))
+}
+
+pub fn get_s(item: &Item, key: &str) -> Result<String> {
+    get_opt_s(item, key)?.ok_or_else(|| anyhow!("missing string attribute {key}"))
+}
+
+/// A string attribute that may be absent. One of another type is an error
+/// rather than absent, so a corrupt item is not read as a partial one.
+pub fn get_opt_s(item: &Item, key: &str) -> Result<Option<String>> {
+    present(item, key)
+        .map(|value| {
+            value
+                .as_s()
+                .cloned()
+                .map_err(|_| anyhow!("attribute {key} is not a string"))
+        })
+        .transpose()
+}
+
+pub fn get_n<T: FromStr>(item: &Item, key: &str) -> Result<T> {
+    get_opt_n(item, key)?.ok_or_else(|| anyhow!("missing number attribute {key}"))
+}
+
+pub fn get_opt_n<T: FromStr>(item: &Item, key: &str) -> Result<Option<T>> {
+    present(item, key)
+        .map(|value| {
+            let n = value
+                .as_n()
+                .map_err(|_| anyhow!("attribute {key} is not a number"))?;
+            n.parse()
+                .map_err(|_| anyhow!("attribute {key} is not a valid {}: {n}", type_name::<T>()))
+        })
+        .transpose()
+}
+
+pub fn get_opt_bool(item: &Item, key: &str) -> Result<Option<bool>> {
+    present(item, key)
+        .map(|value| {
+            value
+                .as_bool()
+                .copied()
+                .map_err(|_| anyhow!("attribute {key} is not a boolean"))
+        })
+        .transpose()
+}
+
+/// A string set attribute; empty sets can't be stored, so absent is empty.
+pub fn get_ss(item: &Item, key: &str) -> Result<Vec<String>> {
+    present(item, key)
+        .map(|value| {
+            value
+                .as_ss()
+                .cloned()
+                .map_err(|_| anyhow!("attribute {key} is not a string set"))
+        })
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Nested structures are stored as JSON strings.
+pub fn get_json<T: DeserializeOwned>(item: &Item, key: &str) -> Result<T> {
+    get_opt_json(item, key)?.ok_or_else(|| anyhow!("missing string attribute {key}"))
+}
+
+pub fn get_opt_json<T: DeserializeOwned>(item: &Item, key: &str) -> Result<Option<T>> {
+    get_opt_s(item, key)?
+        .map(|raw| {
+            serde_json::from_str(&raw).map_err(|e| anyhow!("invalid JSON in attribute {key}: {e}"))
+        })
+        .transpose()
+}
+
+/// Condition matching an item stored at version `previous`, bound as
+/// `:previous`. Items written before versioning count as version 0.
+pub fn version_condition(previous: u64) -> &'static str {
+    if previous == 0 {
+        "(attribute_not_exists(version) OR version = :previous)"
+    } else {
+        "version = :previous"
+    }
+}
+
+/// Condition matching an item whose `deleted_at` is still `deleted_at`,
+/// with the value to bind for it, so an item restored from the trash isn't
+/// purged on a stale read.
+pub fn deleted_at_condition(deleted_at: Option<i64>) -> (&'static str, Option<Item>) {
+    match deleted_at {
+        Some(deleted_at) => (
+            "deleted_at = :deleted_at",
+            Some(HashMap::from([(
+                ":deleted_at".to_string(),
+                AttributeValue::N(deleted_at.to_string()),
+            )])),
+        ),
+        None => ("attribute_not_exists(deleted_at)", None),
+    }
+}
@@ -0,0 +1,105 @@
+use super::conversions::{get_n, get_opt_json, get_opt_s, get_s, Item};
+use super::Dynamodb;
+use crate::core::{Job, JobStatus};
+use crate::data::{DataError, DataResult, JobStore};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use metrics::counter;
+use tracing::instrument;
+
+fn job_key(id: &str) -> AV {
+    AV::S(format!("JOB#{id}"))
+}
+
+fn job_to_item(job: &Job) -> DataResult<Item> {
+    let mut item = Item::new();
+    item.insert("PK".to_string(), job_key(&job.id));
+    item.insert("SK".to_string(), job_key(&job.id));
+    item.insert("kind".to_string(), AV::S(job.kind.clone()));
+    item.insert(
+        "job_status".to_string(),
+        AV::S(job.status.as_str().to_string()),
+    );
+    if let Some(output_layer) = &job.output_layer {
+        item.insert("output_layer".to_string(), AV::S(output_layer.clone()));
+    }
+    if let Some(error) = &job.error {
+        item.insert("error".to_string(), AV::S(error.clone()));
+    }
+    if let Some(progress) = &job.progress {
+        item.insert(
+            "progress".to_string(),
+            AV::S(serde_json::to_string(progress)?),
+        );
+    }
+    if let Some(scan) = &job.scan {
+        item.insert("scan".to_string(), AV::S(serde_json::to_string(scan)?));
+    }
+    item.insert("created_at".to_string(), AV::N(job.created_at.to_string()));
+    item.insert("updated_at".to_string(), AV::N(job.updated_at.to_string()));
+    Ok(item)
+}
+
+impl TryFrom<&Item> for Job {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        let status = get_s(item, "job_status")?;
+        Ok(Job {
+            id: get_s(item, "PK")?.trim_start_matches("JOB#").to_string(),
+            kind: get_s(item, "kind")?,
+            status: JobStatus::parse(&status)
+                .ok_or_else(|| anyhow!("unknown job status {status}"))?,
+            output_layer: get_opt_s(item, "output_layer")?,
+            error: get_opt_s(item, "error")?,
+            progress: get_opt_json(item, "progress")?,
+            scan: get_opt_json(item, "scan")?,
+            created_at: get_n(item, "created_at")?,
+            updated_at: get_n(item, "updated_at")?,
+        })
+    }
+}
+
+#[async_trait]
+impl JobStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn create_job(&self, job: &Job) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "create_job").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(job_to_item(job)?))
+            .condition_expression("attribute_not_exists(PK)")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_job(&self, id: &str) -> DataResult<Job> {
+        counter!("dynamodb_calls_total", "operation" => "get_job").increment(1);
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", job_key(id))
+            .key("SK", job_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Job"))?;
+        Job::try_from(&item)
+    }
+
+    #[instrument(skip_all)]
+    async fn update_job(&self, job: &Job) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "update_job").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(job_to_item(job)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
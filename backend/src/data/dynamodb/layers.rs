@@ -0,0 +1,238 @@
+use super::conversions::{
+    deleted_at_condition, get_json, get_n, get_opt_json, get_opt_n, get_s, version_condition, Item,
+};
+use super::Dynamodb;
+use crate::core::Layer;
+use crate::data::{DataError, DataResult, LayerStore};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{AttributeValue as AV, KeysAndAttributes};
+use aws_sdk_dynamodb::Client;
+use metrics::counter;
+use std::collections::HashMap;
+use tracing::instrument;
+
+/// Keys per BatchGetItem request, DynamoDB's limit.
+const BATCH_GET_SIZE: usize = 100;
+
+fn layer_key(id: &str) -> AV {
+    AV::S(format!("LAYER#{id}"))
+}
+
+fn layer_to_item(layer: &Layer) -> DataResult<Item> {
+    let mut item = Item::new();
+    item.insert("PK".to_string(), layer_key(&layer.id));
+    item.insert("SK".to_string(), layer_key(&layer.id));
+    item.insert(
+        "bbox".to_string(),
+        AV::S(serde_json::to_string(&layer.bbox)?),
+    );
+    item.insert(
+        "feature_count".to_string(),
+        AV::N(layer.feature_count.to_string()),
+    );
+    item.insert(
+        "geometry_types".to_string(),
+        AV::S(serde_json::to_string(&layer.geometry_types)?),
+    );
+    item.insert(
+        "attributes".to_string(),
+        AV::S(serde_json::to_string(&layer.attributes)?),
+    );
+    item.insert("srid".to_string(), AV::N(layer.srid.to_string()));
+    item.insert(
+        "updated_at".to_string(),
+        AV::N(layer.updated_at.to_string()),
+    );
+    item.insert(
+        "data_updated_at".to_string(),
+        AV::N(layer.data_updated_at.to_string()),
+    );
+    item.insert(
+        "catalog".to_string(),
+        AV::S(serde_json::to_string(&layer.catalog)?),
+    );
+    if let Some(form) = &layer.form {
+        item.insert("form".to_string(), AV::S(serde_json::to_string(form)?));
+    }
+    item.insert(
+        "tiling".to_string(),
+        AV::S(serde_json::to_string(&layer.tiling)?),
+    );
+    item.insert("version".to_string(), AV::N(layer.version.to_string()));
+    if let Some(sql) = &layer.sql {
+        item.insert("sql".to_string(), AV::S(serde_json::to_string(sql)?));
+    }
+    if let Some(freshness) = &layer.freshness {
+        item.insert(
+            "freshness".to_string(),
+            AV::S(serde_json::to_string(freshness)?),
+        );
+    }
+    if let Some(quality) = &layer.quality {
+        item.insert(
+            "quality".to_string(),
+            AV::S(serde_json::to_string(quality)?),
+        );
+    }
+    if let Some(deleted_at) = layer.deleted_at {
+        item.insert("deleted_at".to_string(), AV::N(deleted_at.to_string()));
+    }
+    Ok(item)
+}
+
+impl TryFrom<&Item> for Layer {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        Ok(Layer {
+            id: get_s(item, "PK")?.trim_start_matches("LAYER#").to_string(),
+            bbox: get_json(item, "bbox")?,
+            feature_count: get_n(item, "feature_count")?,
+            geometry_types: get_json(item, "geometry_types")?,
+            attributes: get_json(item, "attributes")?,
+            srid: get_n(item, "srid")?,
+            updated_at: get_n(item, "updated_at")?,
+            // Items written before rows were tracked separately only have
+            // updated_at, the closest there is.
+            data_updated_at: match get_opt_n(item, "data_updated_at")? {
+                Some(data_updated_at) => data_updated_at,
+                None => get_n(item, "updated_at")?,
+            },
+            // Items written before catalog metadata existed have none.
+            catalog: get_opt_json(item, "catalog")?.unwrap_or_default(),
+            form: get_opt_json(item, "form")?,
+            tiling: get_opt_json(item, "tiling")?.unwrap_or_default(),
+            version: get_opt_n(item, "version")?.unwrap_or(0),
+            sql: get_opt_json(item, "sql")?,
+            freshness: get_opt_json(item, "freshness")?,
+            quality: get_opt_json(item, "quality")?,
+            deleted_at: get_opt_n(item, "deleted_at")?,
+        })
+    }
+}
+
+impl Dynamodb {
+    /// From the region `client` is for; writes check against the home
+    /// region, so conflicts are explained from there.
+    async fn read_layer(&self, client: &Client, id: &str) -> DataResult<Layer> {
+        counter!("dynamodb_calls_total", "operation" => "get_layer").increment(1);
+        let response = client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", layer_key(id))
+            .key("SK", layer_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Layer"))?;
+        Layer::try_from(&item)
+    }
+}
+
+#[async_trait]
+impl LayerStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn put_layer(&self, layer: &Layer) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "put_layer").increment(1);
+        let previous = layer.version.saturating_sub(1);
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(layer_to_item(layer)?))
+            .condition_expression(version_condition(previous))
+            .expression_attribute_values(":previous", AV::N(previous.to_string()))
+            .send()
+            .await;
+        match result.map_err(DataError::from) {
+            Ok(_) => Ok(()),
+            Err(DataError::Conflict(_)) => {
+                let current = self.read_layer(&self.client, &layer.id).await?;
+                Err(DataError::Conflict(format!(
+                    "layer is at version {}, not {previous}",
+                    current.version
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn get_layer(&self, id: &str) -> DataResult<Layer> {
+        self.read_layer(&self.reader, id).await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_layers(&self) -> DataResult<Vec<Layer>> {
+        counter!("dynamodb_calls_total", "operation" => "get_layers").increment(1);
+        let items = self
+            .reader
+            .scan()
+            .table_name(&self.table_name)
+            .filter_expression("begins_with(PK, :prefix) AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":prefix", AV::S("LAYER#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Layer::try_from).collect()
+    }
+
+    #[instrument(skip_all, fields(ids = ids.len()))]
+    async fn get_layers_by_id(&self, ids: &[String]) -> DataResult<Vec<Layer>> {
+        let mut layers = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(BATCH_GET_SIZE) {
+            let keys: Vec<HashMap<String, AV>> = chunk
+                .iter()
+                .map(|id| {
+                    HashMap::from([
+                        ("PK".to_string(), layer_key(id)),
+                        ("SK".to_string(), layer_key(id)),
+                    ])
+                })
+                .collect();
+            let mut request = Some(
+                KeysAndAttributes::builder()
+                    .set_keys(Some(keys))
+                    .build()
+                    .map_err(anyhow::Error::from)?,
+            );
+            // Throttled keys come back unprocessed, to be asked for again
+            while let Some(keys) = request.take() {
+                counter!("dynamodb_calls_total", "operation" => "get_layers_by_id").increment(1);
+                let response = self
+                    .reader
+                    .batch_get_item()
+                    .request_items(&self.table_name, keys)
+                    .send()
+                    .await?;
+                if let Some(mut responses) = response.responses {
+                    for item in responses.remove(&self.table_name).unwrap_or_default() {
+                        layers.push(Layer::try_from(&item)?);
+                    }
+                }
+                request = response
+                    .unprocessed_keys
+                    .and_then(|mut unprocessed| unprocessed.remove(&self.table_name))
+                    .filter(|keys| !keys.keys().is_empty());
+            }
+        }
+        Ok(layers)
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_layer(&self, layer: &Layer) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "delete_layer").increment(1);
+        let (condition, values) = deleted_at_condition(layer.deleted_at);
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", layer_key(&layer.id))
+            .key("SK", layer_key(&layer.id))
+            .condition_expression(format!("attribute_exists(PK) AND {condition}"))
+            .set_expression_attribute_values(values)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
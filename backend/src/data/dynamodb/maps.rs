@@ -0,0 +1,164 @@
+use super::conversions::{
+    deleted_at_condition, get_json, get_n, get_opt_n, get_opt_s, get_s, version_condition, Item,
+};
+use super::Dynamodb;
+use crate::core::Map;
+use crate::data::{DataError, DataResult, MapStore};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use aws_sdk_dynamodb::Client;
+use metrics::counter;
+use tracing::instrument;
+
+fn map_key(id: &str) -> AV {
+    AV::S(format!("MAP#{id}"))
+}
+
+fn map_to_item(map: &Map) -> DataResult<Item> {
+    let mut item = Item::new();
+    item.insert("PK".to_string(), map_key(&map.id));
+    item.insert("SK".to_string(), map_key(&map.id));
+    item.insert("map_name".to_string(), AV::S(map.name.clone()));
+    if let Some(description) = &map.description {
+        item.insert("description".to_string(), AV::S(description.clone()));
+    }
+    item.insert(
+        "layers".to_string(),
+        AV::S(serde_json::to_string(&map.layers)?),
+    );
+    item.insert(
+        "viewport".to_string(),
+        AV::S(serde_json::to_string(&map.viewport)?),
+    );
+    if let Some(basemap) = &map.basemap {
+        item.insert("basemap".to_string(), AV::S(basemap.clone()));
+    }
+    item.insert("created_at".to_string(), AV::N(map.created_at.to_string()));
+    item.insert("updated_at".to_string(), AV::N(map.updated_at.to_string()));
+    if let Some(deleted_at) = map.deleted_at {
+        item.insert("deleted_at".to_string(), AV::N(deleted_at.to_string()));
+    }
+    item.insert("version".to_string(), AV::N(map.version.to_string()));
+    Ok(item)
+}
+
+impl TryFrom<&Item> for Map {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        let pk = get_s(item, "PK")?;
+        Ok(Map {
+            id: pk.trim_start_matches("MAP#").to_string(),
+            name: get_s(item, "map_name")?,
+            description: get_opt_s(item, "description")?,
+            layers: get_json(item, "layers")?,
+            viewport: get_json(item, "viewport")?,
+            basemap: get_opt_s(item, "basemap")?,
+            created_at: get_n(item, "created_at")?,
+            updated_at: get_n(item, "updated_at")?,
+            deleted_at: get_opt_n(item, "deleted_at")?,
+            version: get_opt_n(item, "version")?.unwrap_or(0),
+        })
+    }
+}
+
+impl Dynamodb {
+    /// From the region `client` is for; writes check against the home
+    /// region, so conflicts are explained from there.
+    async fn read_map(&self, client: &Client, id: &str) -> DataResult<Map> {
+        counter!("dynamodb_calls_total", "operation" => "get_map").increment(1);
+        let response = client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", map_key(id))
+            .key("SK", map_key(id))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Map"))?;
+        Map::try_from(&item)
+    }
+}
+
+#[async_trait]
+impl MapStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn create_map(&self, map: &Map) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "create_map").increment(1);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(map_to_item(map)?))
+            .condition_expression("attribute_not_exists(PK)")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_map(&self, id: &str) -> DataResult<Map> {
+        self.read_map(&self.reader, id).await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_maps(&self) -> DataResult<Vec<Map>> {
+        counter!("dynamodb_calls_total", "operation" => "get_maps").increment(1);
+        let items = self
+            .reader
+            .scan()
+            .table_name(&self.table_name)
+            .filter_expression("begins_with(PK, :prefix) AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":prefix", AV::S("MAP#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Map::try_from).collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn update_map(&self, map: &Map) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "update_map").increment(1);
+        let previous = map.version.saturating_sub(1);
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(map_to_item(map)?))
+            .condition_expression(format!(
+                "attribute_exists(PK) AND {}",
+                version_condition(previous)
+            ))
+            .expression_attribute_values(":previous", AV::N(previous.to_string()))
+            .send()
+            .await;
+        match result.map_err(DataError::from) {
+            Ok(_) => Ok(()),
+            // Either the map is gone or it has moved past `previous`
+            Err(DataError::Conflict(_)) => {
+                let current = self.read_map(&self.client, &map.id).await?;
+                Err(DataError::Conflict(format!(
+                    "map is at version {}, not {previous}",
+                    current.version
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_map(&self, map: &Map) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "delete_map").increment(1);
+        let (condition, values) = deleted_at_condition(map.deleted_at);
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", map_key(&map.id))
+            .key("SK", map_key(&map.id))
+            .condition_expression(format!("attribute_exists(PK) AND {condition}"))
+            .set_expression_attribute_values(values)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+mod attachments;
+mod backup;
+mod comments;
+mod connectors;
+mod conversions;
+mod jobs;
+mod layers;
+mod maps;
+mod shares;
+
+use crate::config::DynamodbConfig;
+use crate::data::{DataResult, Database, HealthCheck};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+};
+use aws_sdk_dynamodb::Client;
+use metrics::counter;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+/// The single table. Writes, and reads the auth path or a following write
+/// depends on, go to the home region; other reads go to `reader`, which may
+/// be a replica a second or so behind it.
+#[derive(Clone)]
+pub struct Dynamodb {
+    client: Client,
+    reader: Client,
+    table_name: String,
+}
+
+impl Dynamodb {
+    /// Connect to DynamoDB. With an endpoint override, e.g. DynamoDB Local,
+    /// the client uses test credentials and the table is created if it does
+    /// not exist yet.
+    pub async fn new(config: &DynamodbConfig) -> Result<Arc<dyn Database>> {
+        let db = Dynamodb::connect(config).await?;
+        if config.endpoint.is_some() {
+            db.ensure_table().await?;
+        }
+        Ok(Arc::new(db))
+    }
+
+    /// Create the table if it does not exist yet.
+    pub async fn migrate(config: &DynamodbConfig) -> Result<()> {
+        Dynamodb::connect(config).await?.ensure_table().await
+    }
+
+    async fn connect(config: &DynamodbConfig) -> Result<Self> {
+        let read_region = read_region(config)?;
+        let client = Dynamodb::client(config, &config.region).await;
+        let reader = if read_region == config.region {
+            client.clone()
+        } else {
+            info!("Reading from DynamoDB in {read_region}");
+            Dynamodb::client(config, &read_region).await
+        };
+        Ok(Dynamodb {
+            client,
+            reader,
+            table_name: config.table.clone(),
+        })
+    }
+
+    async fn client(config: &DynamodbConfig, region: &str) -> Client {
+        let loader =
+            aws_config::defaults(BehaviorVersion::latest()).region(Region::new(region.to_string()));
+        let sdk_config = match &config.endpoint {
+            Some(endpoint) => {
+                loader
+                    .endpoint_url(endpoint)
+                    .test_credentials()
+                    .load()
+                    .await
+            }
+            None => loader.load().await,
+        };
+        Client::new(&sdk_config)
+    }
+
+    async fn ensure_table(&self) -> Result<()> {
+        if self
+            .client
+            .describe_table()
+            .table_name(&self.table_name)
+            .send()
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        info!("Creating DynamoDB table {}", self.table_name);
+        self.client
+            .create_table()
+            .table_name(&self.table_name)
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name("PK")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?,
+            )
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name("SK")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name("PK")
+                    .key_type(KeyType::Hash)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name("SK")
+                    .key_type(KeyType::Range)
+                    .build()?,
+            )
+            .billing_mode(BillingMode::PayPerRequest)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+/// The configured read region, or the local one when the table is there.
+fn read_region(config: &DynamodbConfig) -> Result<String> {
+    let holds_table = |region: &str| {
+        region == config.region || config.replica_regions.iter().any(|r| r == region)
+    };
+    match &config.read_region {
+        Some(region) if holds_table(region) => Ok(region.clone()),
+        Some(region) => Err(anyhow!(
+            "dynamodb.read_region {region} is neither the region nor a replica"
+        )),
+        None => Ok(std::env::var("AWS_REGION")
+            .ok()
+            .filter(|region| holds_table(region))
+            .unwrap_or_else(|| config.region.clone())),
+    }
+}
+
+#[async_trait]
+impl HealthCheck for Dynamodb {
+    #[instrument(skip_all)]
+    async fn ping(&self) -> DataResult<()> {
+        for client in [&self.client, &self.reader] {
+            counter!("dynamodb_calls_total", "operation" => "ping").increment(1);
+            client
+                .describe_table()
+                .table_name(&self.table_name)
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        AttachmentStore, CommentStore, ConnectorStore, DataError, DataResult, JobStore, LayerStore,
+        MapStore, ShareStore,
+    };
+    use aws_sdk_dynamodb::config::Credentials;
+    use axum::http::header::CONTENT_TYPE;
+    use axum::Router;
+
+    /// A stand-in for DynamoDB answering every request with `body`.
+    async fn table_answering(body: &'static str) -> Dynamodb {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().fallback(move || async move {
+            ([(CONTENT_TYPE, "application/x-amz-json-1.0")], body)
+        });
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-2"))
+            .endpoint_url(format!("http://{address}"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .build();
+        let client = Client::from_conf(config);
+        Dynamodb {
+            client: client.clone(),
+            reader: client,
+            table_name: "gridwalk-test".to_string(),
+        }
+    }
+
+    /// An empty object is how GetItem reports that there is no such item.
+    async fn empty_table() -> Dynamodb {
+        table_answering("{}").await
+    }
+
+    fn backend_error<T>(result: DataResult<T>) -> String {
+        match result {
+            Err(DataError::Backend(e)) => e.to_string(),
+            Err(e) => panic!("expected a backend error, got {e}"),
+            Ok(_) => panic!("expected a backend error, got an item"),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_map_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_map("missing").await,
+            Err(DataError::NotFound("Map"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_share_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_share("missing").await,
+            Err(DataError::NotFound("Share"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_job_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_job("missing").await,
+            Err(DataError::NotFound("Job"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_layer_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_layer("missing").await,
+            Err(DataError::NotFound("Layer"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_layers_are_left_out_of_batch_gets() {
+        let db = empty_table().await;
+        let layers = db.get_layers_by_id(&["missing".to_string()]).await.unwrap();
+        assert!(layers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_attachment_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_attachment("layer", "1", "missing").await,
+            Err(DataError::NotFound("Attachment"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_comment_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_comment("map", "missing").await,
+            Err(DataError::NotFound("Comment"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_connector_is_not_found() {
+        let db = empty_table().await;
+        assert!(matches!(
+            db.get_connector("missing").await,
+            Err(DataError::NotFound("Connector"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn partial_map_is_an_error() {
+        let db = table_answering(r#"{"Item": {"PK": {"S": "MAP#m"}, "SK": {"S": "MAP#m"}}}"#).await;
+        let message = backend_error(db.get_map("m").await);
+        assert!(message.contains("map_name"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn malformed_map_is_an_error() {
+        let db = table_answering(
+            r#"{"Item": {
+                "PK": {"S": "MAP#m"}, "SK": {"S": "MAP#m"},
+                "map_name": {"S": "Map"}, "layers": {"S": "not json"},
+                "viewport": {"S": "{}"},
+                "created_at": {"N": "1"}, "updated_at": {"N": "1"}
+            }}"#,
+        )
+        .await;
+        let message = backend_error(db.get_map("m").await);
+        assert!(
+            message.contains("invalid JSON in attribute layers"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn layers_without_data_updated_at_fall_back_to_updated_at() {
+        let db = table_answering(
+            r#"{"Item": {
+                "PK": {"S": "LAYER#l"}, "SK": {"S": "LAYER#l"},
+                "bbox": {"S": "null"}, "feature_count": {"N": "0"},
+                "geometry_types": {"S": "[]"}, "attributes": {"S": "[]"},
+                "srid": {"N": "4326"}, "updated_at": {"N": "42"}
+            }}"#,
+        )
+        .await;
+        let layer = db.get_layer("l").await.unwrap();
+        assert_eq!(layer.data_updated_at, 42);
+    }
+
+    #[tokio::test]
+    async fn mistyped_optional_attribute_is_an_error() {
+        let db = table_answering(
+            r#"{"Item": {
+                "PK": {"S": "MAP#m"}, "SK": {"S": "SHARE#t"},
+                "resource": {"S": "map"}, "resource_id": {"S": "m"},
+                "created_at": {"N": "1"}, "expires_at": {"S": "soon"}
+            }}"#,
+        )
+        .await;
+        let message = backend_error(db.get_share("t").await);
+        assert!(
+            message.contains("attribute expires_at is not a number"),
+            "{message}"
+        );
+    }
+}
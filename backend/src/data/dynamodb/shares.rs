@@ -0,0 +1,148 @@
+use super::conversions::{get_n, get_opt_n, get_s, get_ss, Item};
+use super::Dynamodb;
+use crate::core::{Share, ShareResource};
+use crate::data::{DataError, DataResult, ShareStore};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue as AV;
+use metrics::counter;
+use tracing::instrument;
+
+fn share_key(token: &str) -> AV {
+    AV::S(format!("SHARE#{token}"))
+}
+
+fn resource_key(resource: ShareResource, resource_id: &str) -> AV {
+    AV::S(format!("{}#{resource_id}", resource.key_prefix()))
+}
+
+fn share_attributes(share: &Share, item: &mut Item) {
+    let resource = match share.resource {
+        ShareResource::Map => "map",
+        ShareResource::Layer => "layer",
+    };
+    item.insert("resource".to_string(), AV::S(resource.to_string()));
+    item.insert("resource_id".to_string(), AV::S(share.resource_id.clone()));
+    item.insert(
+        "created_at".to_string(),
+        AV::N(share.created_at.to_string()),
+    );
+    if let Some(expires_at) = share.expires_at {
+        item.insert("expires_at".to_string(), AV::N(expires_at.to_string()));
+    }
+    if !share.allowed_origins.is_empty() {
+        item.insert(
+            "allowed_origins".to_string(),
+            AV::Ss(share.allowed_origins.clone()),
+        );
+    }
+}
+
+impl TryFrom<&Item> for Share {
+    type Error = DataError;
+
+    fn try_from(item: &Item) -> DataResult<Self> {
+        let resource = match get_s(item, "resource")?.as_str() {
+            "map" => ShareResource::Map,
+            "layer" => ShareResource::Layer,
+            other => return Err(anyhow!("unknown share resource {other}").into()),
+        };
+        let token = get_s(item, "SK")?.trim_start_matches("SHARE#").to_string();
+        Ok(Share {
+            token,
+            resource,
+            resource_id: get_s(item, "resource_id")?,
+            created_at: get_n(item, "created_at")?,
+            expires_at: get_opt_n(item, "expires_at")?,
+            allowed_origins: get_ss(item, "allowed_origins")?,
+        })
+    }
+}
+
+#[async_trait]
+impl ShareStore for Dynamodb {
+    #[instrument(skip_all)]
+    async fn create_share(&self, share: &Share) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "create_share").increment(1);
+        // The share itself, looked up by token
+        let mut share_item = Item::new();
+        share_item.insert("PK".to_string(), share_key(&share.token));
+        share_item.insert("SK".to_string(), share_key(&share.token));
+        share_attributes(share, &mut share_item);
+
+        // The resource -> share link, used to list shares of a resource
+        let mut link_item = Item::new();
+        link_item.insert(
+            "PK".to_string(),
+            resource_key(share.resource, &share.resource_id),
+        );
+        link_item.insert("SK".to_string(), share_key(&share.token));
+        share_attributes(share, &mut link_item);
+
+        for item in [share_item, link_item] {
+            self.client
+                .put_item()
+                .table_name(&self.table_name)
+                .set_item(Some(item))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_share(&self, token: &str) -> DataResult<Share> {
+        counter!("dynamodb_calls_total", "operation" => "get_share").increment(1);
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", share_key(token))
+            .key("SK", share_key(token))
+            .send()
+            .await?;
+        let item = response.item.ok_or(DataError::NotFound("Share"))?;
+        Share::try_from(&item)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_shares(
+        &self,
+        resource: ShareResource,
+        resource_id: &str,
+    ) -> DataResult<Vec<Share>> {
+        counter!("dynamodb_calls_total", "operation" => "get_shares").increment(1);
+        let items = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("PK = :pk AND begins_with(SK, :prefix)")
+            .expression_attribute_values(":pk", resource_key(resource, resource_id))
+            .expression_attribute_values(":prefix", AV::S("SHARE#".to_string()))
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+        items.iter().map(Share::try_from).collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_share(&self, share: &Share) -> DataResult<()> {
+        counter!("dynamodb_calls_total", "operation" => "delete_share").increment(1);
+        let keys = [
+            share_key(&share.token),
+            resource_key(share.resource, &share.resource_id),
+        ];
+        for pk in keys {
+            self.client
+                .delete_item()
+                .table_name(&self.table_name)
+                .key("PK", pk)
+                .key("SK", share_key(&share.token))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use aws_sdk_dynamodb::error::{ProvideErrorMetadata, SdkError};
+use metrics::counter;
+use std::fmt;
+
+/// Failures from the application stores, distinguished so callers can tell
+/// a missing item from a broken backend.
+#[derive(Debug)]
+pub enum DataError {
+    /// The named kind of item does not exist, e.g. `Map`.
+    NotFound(&'static str),
+    /// A write conflicted with an existing item.
+    Conflict(String),
+    Unauthorized(String),
+    /// The backend failed or returned an unreadable item.
+    Backend(anyhow::Error),
+}
+
+pub type DataResult<T> = Result<T, DataError>;
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::NotFound(kind) => write!(f, "{kind} not found"),
+            DataError::Conflict(message) => write!(f, "conflict: {message}"),
+            DataError::Unauthorized(message) => write!(f, "unauthorized: {message}"),
+            DataError::Backend(e) => write!(f, "backend error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl<E, R> From<SdkError<E, R>> for DataError
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: fmt::Debug + Send + Sync + 'static,
+{
+    fn from(e: SdkError<E, R>) -> Self {
+        counter!("dynamodb_errors_total").increment(1);
+        match e.code() {
+            Some("ConditionalCheckFailedException") => {
+                DataError::Conflict("condition check failed".to_string())
+            }
+            _ => DataError::Backend(e.into()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for DataError {
+    fn from(e: anyhow::Error) -> Self {
+        DataError::Backend(e)
+    }
+}
+
+impl From<serde_json::Error> for DataError {
+    fn from(e: serde_json::Error) -> Self {
+        DataError::Backend(e.into())
+    }
+}
@@ -0,0 +1,131 @@
+mod dynamodb;
+mod error;
+mod publishing;
+
+use crate::core::{Attachment, Comment, Connector, Job, Layer, Map, Share, ShareResource};
+use async_trait::async_trait;
+
+pub use dynamodb::Dynamodb;
+pub use error::{DataError, DataResult};
+pub use publishing::PublishingStore;
+
+#[async_trait]
+pub trait MapStore: Send + Sync + 'static {
+    async fn create_map(&self, map: &Map) -> DataResult<()>;
+    async fn get_map(&self, id: &str) -> DataResult<Map>;
+    async fn get_maps(&self) -> DataResult<Vec<Map>>;
+    /// Fails with a conflict unless the stored map is at `map.version - 1`.
+    async fn update_map(&self, map: &Map) -> DataResult<()>;
+    /// Fails with a conflict unless the stored map has the same
+    /// `deleted_at`, i.e. it has not been restored or trashed again since.
+    async fn delete_map(&self, map: &Map) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait ShareStore: Send + Sync + 'static {
+    async fn create_share(&self, share: &Share) -> DataResult<()>;
+    async fn get_share(&self, token: &str) -> DataResult<Share>;
+    async fn get_shares(
+        &self,
+        resource: ShareResource,
+        resource_id: &str,
+    ) -> DataResult<Vec<Share>>;
+    async fn delete_share(&self, share: &Share) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait JobStore: Send + Sync + 'static {
+    async fn create_job(&self, job: &Job) -> DataResult<()>;
+    async fn get_job(&self, id: &str) -> DataResult<Job>;
+    async fn update_job(&self, job: &Job) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait LayerStore: Send + Sync + 'static {
+    /// Fails with a conflict unless the stored layer is at
+    /// `layer.version - 1`, or absent when that is 0.
+    async fn put_layer(&self, layer: &Layer) -> DataResult<()>;
+    async fn get_layer(&self, id: &str) -> DataResult<Layer>;
+    async fn get_layers(&self) -> DataResult<Vec<Layer>>;
+    /// Those of `ids` that have metadata, in no particular order.
+    async fn get_layers_by_id(&self, ids: &[String]) -> DataResult<Vec<Layer>>;
+    /// Fails with a conflict unless the stored layer has the same
+    /// `deleted_at`, like `delete_map`.
+    async fn delete_layer(&self, layer: &Layer) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait AttachmentStore: Send + Sync + 'static {
+    async fn create_attachment(&self, attachment: &Attachment) -> DataResult<()>;
+    async fn get_attachment(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+        id: &str,
+    ) -> DataResult<Attachment>;
+    async fn get_attachments(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+    ) -> DataResult<Vec<Attachment>>;
+    async fn delete_attachment(&self, attachment: &Attachment) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait CommentStore: Send + Sync + 'static {
+    async fn create_comment(&self, comment: &Comment) -> DataResult<()>;
+    async fn get_comment(&self, map_id: &str, id: &str) -> DataResult<Comment>;
+    async fn get_comments(&self, map_id: &str) -> DataResult<Vec<Comment>>;
+    async fn update_comment(&self, comment: &Comment) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait ConnectorStore: Send + Sync + 'static {
+    async fn put_connector(&self, connector: &Connector) -> DataResult<()>;
+    async fn get_connector(&self, id: &str) -> DataResult<Connector>;
+    async fn get_connectors(&self) -> DataResult<Vec<Connector>>;
+    async fn delete_connector(&self, id: &str) -> DataResult<()>;
+}
+
+#[async_trait]
+pub trait BackupStore: Send + Sync + 'static {
+    /// Every item in the store, in DynamoDB JSON, e.g.
+    /// `{"PK": {"S": "MAP#..."}}`.
+    async fn export_items(&self) -> DataResult<Vec<serde_json::Value>>;
+    /// Write exported items over whatever is stored at their keys, returning
+    /// how many were written. Items are written as they are, unchecked.
+    async fn import_items(&self, items: &[serde_json::Value]) -> DataResult<usize>;
+}
+
+#[async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    /// Verify the store is reachable, for readiness probes.
+    async fn ping(&self) -> DataResult<()>;
+}
+
+pub trait Database:
+    MapStore
+    + ShareStore
+    + JobStore
+    + LayerStore
+    + AttachmentStore
+    + CommentStore
+    + ConnectorStore
+    + BackupStore
+    + HealthCheck
+{
+}
+
+impl<
+        T: MapStore
+            + ShareStore
+            + JobStore
+            + LayerStore
+            + AttachmentStore
+            + CommentStore
+            + ConnectorStore
+            + BackupStore
+            + HealthCheck,
+    > Database for T
+{
+}
@@ -0,0 +1,240 @@
+use crate::core::{
+    Attachment, Comment, Connector, Event, EventBus, Job, Layer, Map, Share, ShareResource,
+};
+use crate::data::{
+    AttachmentStore, BackupStore, CommentStore, ConnectorStore, DataResult, Database, HealthCheck,
+    JobStore, LayerStore, MapStore, ShareStore,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Wraps a database and publishes an event after every successful write, so
+/// no code path can change data without subscribers hearing about it.
+pub struct PublishingStore {
+    inner: Arc<dyn Database>,
+    events: EventBus,
+}
+
+impl PublishingStore {
+    pub fn wrap(inner: Arc<dyn Database>, events: EventBus) -> Arc<dyn Database> {
+        Arc::new(PublishingStore { inner, events })
+    }
+}
+
+#[async_trait]
+impl MapStore for PublishingStore {
+    async fn create_map(&self, map: &Map) -> DataResult<()> {
+        self.inner.create_map(map).await?;
+        self.events.publish(Event::MapUpdated { map: map.clone() });
+        Ok(())
+    }
+
+    async fn get_map(&self, id: &str) -> DataResult<Map> {
+        self.inner.get_map(id).await
+    }
+
+    async fn get_maps(&self) -> DataResult<Vec<Map>> {
+        self.inner.get_maps().await
+    }
+
+    async fn update_map(&self, map: &Map) -> DataResult<()> {
+        self.inner.update_map(map).await?;
+        self.events.publish(Event::MapUpdated { map: map.clone() });
+        Ok(())
+    }
+
+    async fn delete_map(&self, map: &Map) -> DataResult<()> {
+        self.inner.delete_map(map).await?;
+        self.events.publish(Event::MapDeleted {
+            map_id: map.id.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShareStore for PublishingStore {
+    async fn create_share(&self, share: &Share) -> DataResult<()> {
+        self.inner.create_share(share).await?;
+        self.events.publish(Event::SharesChanged {
+            resource: share.resource,
+            resource_id: share.resource_id.clone(),
+        });
+        Ok(())
+    }
+
+    async fn get_share(&self, token: &str) -> DataResult<Share> {
+        self.inner.get_share(token).await
+    }
+
+    async fn get_shares(
+        &self,
+        resource: ShareResource,
+        resource_id: &str,
+    ) -> DataResult<Vec<Share>> {
+        self.inner.get_shares(resource, resource_id).await
+    }
+
+    async fn delete_share(&self, share: &Share) -> DataResult<()> {
+        self.inner.delete_share(share).await?;
+        self.events.publish(Event::SharesChanged {
+            resource: share.resource,
+            resource_id: share.resource_id.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobStore for PublishingStore {
+    async fn create_job(&self, job: &Job) -> DataResult<()> {
+        self.inner.create_job(job).await?;
+        self.events.publish(Event::JobUpdated { job: job.clone() });
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> DataResult<Job> {
+        self.inner.get_job(id).await
+    }
+
+    async fn update_job(&self, job: &Job) -> DataResult<()> {
+        self.inner.update_job(job).await?;
+        self.events.publish(Event::JobUpdated { job: job.clone() });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LayerStore for PublishingStore {
+    async fn put_layer(&self, layer: &Layer) -> DataResult<()> {
+        self.inner.put_layer(layer).await?;
+        self.events.publish(Event::LayerUpdated {
+            layer: layer.clone(),
+        });
+        Ok(())
+    }
+
+    async fn get_layer(&self, id: &str) -> DataResult<Layer> {
+        self.inner.get_layer(id).await
+    }
+
+    async fn get_layers(&self) -> DataResult<Vec<Layer>> {
+        self.inner.get_layers().await
+    }
+
+    async fn get_layers_by_id(&self, ids: &[String]) -> DataResult<Vec<Layer>> {
+        self.inner.get_layers_by_id(ids).await
+    }
+
+    async fn delete_layer(&self, layer: &Layer) -> DataResult<()> {
+        self.inner.delete_layer(layer).await?;
+        self.events.publish(Event::LayerDeleted {
+            layer_id: layer.id.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for PublishingStore {
+    async fn create_attachment(&self, attachment: &Attachment) -> DataResult<()> {
+        self.inner.create_attachment(attachment).await?;
+        self.events.publish(Event::AttachmentsChanged {
+            layer_id: attachment.layer_id.clone(),
+            feature_id: attachment.feature_id.clone(),
+        });
+        Ok(())
+    }
+
+    async fn get_attachment(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+        id: &str,
+    ) -> DataResult<Attachment> {
+        self.inner.get_attachment(layer_id, feature_id, id).await
+    }
+
+    async fn get_attachments(
+        &self,
+        layer_id: &str,
+        feature_id: &str,
+    ) -> DataResult<Vec<Attachment>> {
+        self.inner.get_attachments(layer_id, feature_id).await
+    }
+
+    async fn delete_attachment(&self, attachment: &Attachment) -> DataResult<()> {
+        self.inner.delete_attachment(attachment).await?;
+        self.events.publish(Event::AttachmentsChanged {
+            layer_id: attachment.layer_id.clone(),
+            feature_id: attachment.feature_id.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CommentStore for PublishingStore {
+    async fn create_comment(&self, comment: &Comment) -> DataResult<()> {
+        self.inner.create_comment(comment).await?;
+        self.events.publish(Event::CommentUpdated {
+            comment: comment.clone(),
+        });
+        Ok(())
+    }
+
+    async fn get_comment(&self, map_id: &str, id: &str) -> DataResult<Comment> {
+        self.inner.get_comment(map_id, id).await
+    }
+
+    async fn get_comments(&self, map_id: &str) -> DataResult<Vec<Comment>> {
+        self.inner.get_comments(map_id).await
+    }
+
+    async fn update_comment(&self, comment: &Comment) -> DataResult<()> {
+        self.inner.update_comment(comment).await?;
+        self.events.publish(Event::CommentUpdated {
+            comment: comment.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConnectorStore for PublishingStore {
+    async fn put_connector(&self, connector: &Connector) -> DataResult<()> {
+        self.inner.put_connector(connector).await
+    }
+
+    async fn get_connector(&self, id: &str) -> DataResult<Connector> {
+        self.inner.get_connector(id).await
+    }
+
+    async fn get_connectors(&self) -> DataResult<Vec<Connector>> {
+        self.inner.get_connectors().await
+    }
+
+    async fn delete_connector(&self, id: &str) -> DataResult<()> {
+        self.inner.delete_connector(id).await
+    }
+}
+
+/// Restores write past the stores above, so they publish nothing; servers
+/// pick restored items up when they restart.
+#[async_trait]
+impl BackupStore for PublishingStore {
+    async fn export_items(&self) -> DataResult<Vec<serde_json::Value>> {
+        self.inner.export_items().await
+    }
+
+    async fn import_items(&self, items: &[serde_json::Value]) -> DataResult<usize> {
+        self.inner.import_items(items).await
+    }
+}
+
+#[async_trait]
+impl HealthCheck for PublishingStore {
+    async fn ping(&self) -> DataResult<()> {
+        self.inner.ping().await
+    }
+}
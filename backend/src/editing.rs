@@ -0,0 +1,501 @@
+use crate::changes::ChangeOperation;
+use crate::postgis::{feature_columns, quote_ident, LayerTable};
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use tokio_postgres::Row;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Edit sessions and the steps taken in them. Each step keeps the feature's
+/// row before and after, geometry as EWKB hex, so it can be reverted and
+/// reapplied exactly.
+const EDIT_SESSIONS_SQL: &str = "
+CREATE SCHEMA IF NOT EXISTS gridwalk;
+CREATE TABLE IF NOT EXISTS gridwalk.edit_sessions (
+    id text PRIMARY KEY,
+    table_schema text NOT NULL,
+    table_name text NOT NULL,
+    name text NOT NULL,
+    status text NOT NULL DEFAULT 'open',
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS gridwalk.edit_steps (
+    session_id text NOT NULL REFERENCES gridwalk.edit_sessions ON DELETE CASCADE,
+    seq integer NOT NULL,
+    feature_id jsonb NOT NULL,
+    old_row jsonb,
+    new_row jsonb,
+    undone boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (session_id, seq)
+);
+";
+
+const SESSION_SQL: &str = "
+SELECT s.id, s.name, s.status, extract(epoch FROM s.created_at)::bigint,
+       count(e.seq) FILTER (WHERE NOT e.undone),
+       count(e.seq) FILTER (WHERE e.undone)
+FROM gridwalk.edit_sessions s
+LEFT JOIN gridwalk.edit_steps e ON e.session_id = s.id
+WHERE s.id = $1 AND s.table_schema = $2 AND s.table_name = $3
+GROUP BY s.id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EditSessionStatus {
+    Open,
+    Committed,
+    Discarded,
+}
+
+impl EditSessionStatus {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "open" => Some(EditSessionStatus::Open),
+            "committed" => Some(EditSessionStatus::Committed),
+            "discarded" => Some(EditSessionStatus::Discarded),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            EditSessionStatus::Open => "open",
+            EditSessionStatus::Committed => "committed",
+            EditSessionStatus::Discarded => "discarded",
+        }
+    }
+}
+
+/// A named group of edits to one layer with its own undo and redo stacks.
+/// Edits are written to the table as they are made; committing closes the
+/// session and bumps the layer version, discarding reverts them all.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EditSession {
+    pub id: String,
+    pub name: String,
+    pub status: EditSessionStatus,
+    /// Edits undo can revert.
+    pub undo_depth: i64,
+    /// Undone edits redo can reapply.
+    pub redo_depth: i64,
+    pub created_at: i64,
+}
+
+impl EditSession {
+    fn from_row(row: &Row) -> Option<Self> {
+        Some(EditSession {
+            id: row.get(0),
+            name: row.get(1),
+            status: EditSessionStatus::parse(row.get(2))?,
+            created_at: row.get(3),
+            undo_depth: row.get(4),
+            redo_depth: row.get(5),
+        })
+    }
+}
+
+/// One edit made in a session.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SessionEdit {
+    pub operation: ChangeOperation,
+    /// Primary key of the feature, for updates and deletes.
+    pub id: Option<Value>,
+    /// WGS84 GeoJSON feature, for inserts and updates. Properties not given
+    /// are left unchanged by updates.
+    pub feature: Option<Value>,
+}
+
+/// Why a session operation failed.
+#[derive(Debug)]
+pub enum EditError {
+    /// The named kind of item does not exist, e.g. `Edit session`.
+    NotFound(&'static str),
+    /// The session is closed, has nothing to undo or redo, or a feature was
+    /// changed outside it.
+    Conflict(String),
+    /// The edit is at fault, e.g. a value does not fit its column.
+    Invalid(String),
+    Failed(anyhow::Error),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NotFound(kind) => write!(f, "{kind} not found"),
+            EditError::Conflict(message) => write!(f, "conflict: {message}"),
+            EditError::Invalid(message) => write!(f, "invalid: {message}"),
+            EditError::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl From<tokio_postgres::Error> for EditError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        match e.as_db_error() {
+            Some(db) => EditError::Invalid(db.message().to_string()),
+            None => EditError::Failed(e.into()),
+        }
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for EditError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        EditError::Failed(e.into())
+    }
+}
+
+/// Open a session on the table.
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn start(pool: &Pool, table: &LayerTable, name: &str) -> Result<EditSession, EditError> {
+    let client = pool.get().await?;
+    client.batch_execute(EDIT_SESSIONS_SQL).await?;
+    let id = Uuid::new_v4().simple().to_string();
+    client
+        .execute(
+            "INSERT INTO gridwalk.edit_sessions (id, table_schema, table_name, name)
+             VALUES ($1, $2, $3, $4)",
+            &[&id, &table.schema, &table.table, &name],
+        )
+        .await?;
+    let row = client
+        .query_one(SESSION_SQL, &[&id, &table.schema, &table.table])
+        .await?;
+    EditSession::from_row(&row).ok_or(EditError::NotFound("Edit session"))
+}
+
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn get(pool: &Pool, table: &LayerTable, id: &str) -> Result<EditSession, EditError> {
+    let client = pool.get().await?;
+    client.batch_execute(EDIT_SESSIONS_SQL).await?;
+    let row = client
+        .query_opt(SESSION_SQL, &[&id, &table.schema, &table.table])
+        .await?;
+    row.as_ref()
+        .and_then(EditSession::from_row)
+        .ok_or(EditError::NotFound("Edit session"))
+}
+
+/// Row-level access to one table within a session's transaction.
+struct Rows<'a> {
+    transaction: Transaction<'a>,
+    table: &'a LayerTable,
+    key: &'a str,
+    session: &'a str,
+}
+
+impl Rows<'_> {
+    /// Lock the session, which must be open.
+    async fn lock(&self) -> Result<(), EditError> {
+        let row = self
+            .transaction
+            .query_opt(
+                "SELECT status FROM gridwalk.edit_sessions
+                 WHERE id = $1 AND table_schema = $2 AND table_name = $3
+                 FOR UPDATE",
+                &[&self.session, &self.table.schema, &self.table.table],
+            )
+            .await?;
+        match row.as_ref().map(|row| row.get::<_, &str>(0)) {
+            None => Err(EditError::NotFound("Edit session")),
+            Some("open") => Ok(()),
+            Some(status) => Err(EditError::Conflict(format!("edit session is {status}"))),
+        }
+    }
+
+    /// The feature's whole row, locked, with its geometry as EWKB hex so
+    /// it reads back unchanged.
+    async fn snapshot(&self, id: &Value) -> Result<Option<Value>, EditError> {
+        let sql = format!(
+            "SELECT to_jsonb(t) || jsonb_build_object($2::text, t.{}::text)
+             FROM {} t WHERE {} FOR UPDATE",
+            quote_ident(&self.table.geometry_column),
+            self.table.qualified_name(),
+            self.table.key_match_sql(self.key, 1),
+        );
+        let row = self
+            .transaction
+            .query_opt(&sql, &[id, &self.table.geometry_column])
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Put the feature back to `target`, a snapshot or `None` for absent,
+    /// provided it is still as the session left it.
+    async fn restore(
+        &self,
+        id: &Value,
+        expected: Option<&Value>,
+        target: Option<&Value>,
+    ) -> Result<(), EditError> {
+        if self.snapshot(id).await?.as_ref() != expected {
+            return Err(EditError::Conflict(format!(
+                "feature {id} was changed outside the session"
+            )));
+        }
+        let table = self.table.qualified_name();
+        match (expected, target) {
+            (_, None) => {
+                let sql = self.table.delete_feature_sql(self.key);
+                self.transaction.execute(&sql, &[id]).await?;
+            }
+            (None, Some(row)) => {
+                let sql = format!(
+                    "INSERT INTO {table} OVERRIDING SYSTEM VALUE
+                     SELECT * FROM jsonb_populate_record(NULL::{table}, $1::jsonb)"
+                );
+                self.transaction.execute(&sql, &[row]).await?;
+            }
+            (Some(_), Some(row)) => {
+                let columns: Vec<String> = row
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|row| row.keys())
+                    .map(|column| quote_ident(column))
+                    .collect();
+                let values: Vec<String> = columns.iter().map(|c| format!("r.{c}")).collect();
+                let sql = format!(
+                    "UPDATE {table} t SET ({}) = (
+                         SELECT {} FROM jsonb_populate_record(NULL::{table}, $2::jsonb) r
+                     )
+                     WHERE {}",
+                    columns.join(", "),
+                    values.join(", "),
+                    self.table.key_match_sql(self.key, 1),
+                );
+                self.transaction.execute(&sql, &[id, row]).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The newest step still applied, or the oldest undone one.
+    async fn step(&self, undone: bool) -> Result<Option<Step>, EditError> {
+        let order = if undone { "ASC" } else { "DESC" };
+        let row = self
+            .transaction
+            .query_opt(
+                &format!(
+                    "SELECT seq, feature_id, old_row, new_row FROM gridwalk.edit_steps
+                     WHERE session_id = $1 AND undone = $2
+                     ORDER BY seq {order} LIMIT 1"
+                ),
+                &[&self.session, &undone],
+            )
+            .await?;
+        Ok(row.map(|row| Step {
+            seq: row.get(0),
+            feature_id: row.get(1),
+            old_row: row.get(2),
+            new_row: row.get(3),
+        }))
+    }
+
+    async fn mark(&self, seq: i32, undone: bool) -> Result<(), EditError> {
+        self.transaction
+            .execute(
+                "UPDATE gridwalk.edit_steps SET undone = $3 WHERE session_id = $1 AND seq = $2",
+                &[&self.session, &seq, &undone],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Close the session, dropping its stacks.
+    async fn close(self, status: EditSessionStatus) -> Result<(), EditError> {
+        self.transaction
+            .execute(
+                "DELETE FROM gridwalk.edit_steps WHERE session_id = $1",
+                &[&self.session],
+            )
+            .await?;
+        self.transaction
+            .execute(
+                "UPDATE gridwalk.edit_sessions SET status = $2 WHERE id = $1",
+                &[&self.session, &status.as_str()],
+            )
+            .await?;
+        self.transaction.commit().await?;
+        Ok(())
+    }
+}
+
+struct Step {
+    seq: i32,
+    feature_id: Value,
+    old_row: Option<Value>,
+    new_row: Option<Value>,
+}
+
+async fn session_rows<'a>(
+    client: &'a mut deadpool_postgres::Client,
+    table: &'a LayerTable,
+    key: &'a str,
+    session: &'a str,
+) -> Result<Rows<'a>, EditError> {
+    let rows = Rows {
+        transaction: client.transaction().await?,
+        table,
+        key,
+        session,
+    };
+    rows.lock().await?;
+    Ok(rows)
+}
+
+/// Write an edit to the table as a new step of the session, clearing its
+/// redo stack. Returns the feature's key.
+#[instrument(skip(pool, table, edit), fields(table = %table.table))]
+pub async fn apply(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    session: &str,
+    edit: &SessionEdit,
+) -> Result<Value, EditError> {
+    let mut client = pool.get().await?;
+    let rows = session_rows(&mut client, table, key, session).await?;
+    let columns = feature_columns(edit.feature.as_ref());
+    let (id, old_row) = match (edit.operation, &edit.id, &edit.feature) {
+        (ChangeOperation::Insert, _, Some(feature)) => {
+            let sql = table.insert_feature_sql(key, &columns);
+            let row = rows.transaction.query_one(&sql, &[feature]).await?;
+            (row.get(0), None)
+        }
+        (ChangeOperation::Update | ChangeOperation::Delete, Some(id), feature) => {
+            let old = rows
+                .snapshot(id)
+                .await?
+                .ok_or(EditError::NotFound("Feature"))?;
+            match feature {
+                Some(feature) if edit.operation == ChangeOperation::Update => {
+                    let sql = table.update_feature_sql(key, &columns);
+                    rows.transaction.execute(&sql, &[feature, id]).await?;
+                }
+                None if edit.operation == ChangeOperation::Update => {
+                    return Err(EditError::Invalid("Updates need a feature".to_string()))
+                }
+                _ => {
+                    let sql = table.delete_feature_sql(key);
+                    rows.transaction.execute(&sql, &[id]).await?;
+                }
+            }
+            (id.clone(), Some(old))
+        }
+        (ChangeOperation::Insert, _, None) => {
+            return Err(EditError::Invalid("Inserts need a feature".to_string()))
+        }
+        (_, None, _) => {
+            return Err(EditError::Invalid(
+                "Updates and deletes need the feature id".to_string(),
+            ))
+        }
+    };
+    let new_row = rows.snapshot(&id).await?;
+
+    rows.transaction
+        .execute(
+            "DELETE FROM gridwalk.edit_steps WHERE session_id = $1 AND undone",
+            &[&session],
+        )
+        .await?;
+    rows.transaction
+        .execute(
+            "INSERT INTO gridwalk.edit_steps (session_id, seq, feature_id, old_row, new_row)
+             SELECT $1, coalesce(max(seq), 0) + 1, $2, $3, $4
+             FROM gridwalk.edit_steps WHERE session_id = $1",
+            &[&session, &id, &old_row, &new_row],
+        )
+        .await?;
+    rows.transaction.commit().await?;
+    Ok(id)
+}
+
+/// Revert the session's newest applied edit.
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn undo(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    session: &str,
+) -> Result<EditSession, EditError> {
+    let mut client = pool.get().await?;
+    let rows = session_rows(&mut client, table, key, session).await?;
+    let step = rows
+        .step(false)
+        .await?
+        .ok_or_else(|| EditError::Conflict("nothing to undo".to_string()))?;
+    rows.restore(
+        &step.feature_id,
+        step.new_row.as_ref(),
+        step.old_row.as_ref(),
+    )
+    .await?;
+    rows.mark(step.seq, true).await?;
+    rows.transaction.commit().await?;
+    get(pool, table, session).await
+}
+
+/// Reapply the session's most recently undone edit.
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn redo(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    session: &str,
+) -> Result<EditSession, EditError> {
+    let mut client = pool.get().await?;
+    let rows = session_rows(&mut client, table, key, session).await?;
+    let step = rows
+        .step(true)
+        .await?
+        .ok_or_else(|| EditError::Conflict("nothing to redo".to_string()))?;
+    rows.restore(
+        &step.feature_id,
+        step.old_row.as_ref(),
+        step.new_row.as_ref(),
+    )
+    .await?;
+    rows.mark(step.seq, false).await?;
+    rows.transaction.commit().await?;
+    get(pool, table, session).await
+}
+
+/// Keep the session's edits and close it.
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn commit(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    session: &str,
+) -> Result<(), EditError> {
+    let mut client = pool.get().await?;
+    let rows = session_rows(&mut client, table, key, session).await?;
+    rows.close(EditSessionStatus::Committed).await
+}
+
+/// Revert all of the session's applied edits, newest first, and close it.
+#[instrument(skip(pool, table), fields(table = %table.table))]
+pub async fn discard(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    session: &str,
+) -> Result<(), EditError> {
+    let mut client = pool.get().await?;
+    let rows = session_rows(&mut client, table, key, session).await?;
+    while let Some(step) = rows.step(false).await? {
+        rows.restore(
+            &step.feature_id,
+            step.new_row.as_ref(),
+            step.old_row.as_ref(),
+        )
+        .await?;
+        rows.mark(step.seq, true).await?;
+    }
+    rows.close(EditSessionStatus::Discarded).await
+}
@@ -0,0 +1,339 @@
+//! Computed attribute expressions. They are pasted into tile queries, so
+//! rather than trusting whatever Postgres would accept, an expression may
+//! only be made of column names, literals, operators, casts and the
+//! functions listed here: nothing that reads other tables or changes the
+//! session, such as `set_config`.
+
+/// Functions an expression may call: pure arithmetic, text and geometry
+/// measures.
+const FUNCTIONS: &[&str] = &[
+    "abs",
+    "age",
+    "btrim",
+    "cbrt",
+    "ceil",
+    "ceiling",
+    "char_length",
+    "coalesce",
+    "concat",
+    "concat_ws",
+    "cos",
+    "date_part",
+    "date_trunc",
+    "degrees",
+    "exp",
+    "floor",
+    "greatest",
+    "initcap",
+    "least",
+    "left",
+    "length",
+    "ln",
+    "log",
+    "log10",
+    "lower",
+    "lpad",
+    "ltrim",
+    "mod",
+    "nullif",
+    "pi",
+    "power",
+    "radians",
+    "replace",
+    "reverse",
+    "right",
+    "round",
+    "rpad",
+    "rtrim",
+    "sign",
+    "sin",
+    "split_part",
+    "sqrt",
+    "st_area",
+    "st_centroid",
+    "st_dimension",
+    "st_geometrytype",
+    "st_isempty",
+    "st_isvalid",
+    "st_length",
+    "st_npoints",
+    "st_numgeometries",
+    "st_perimeter",
+    "st_pointonsurface",
+    "st_srid",
+    "st_transform",
+    "st_x",
+    "st_xmax",
+    "st_xmin",
+    "st_y",
+    "st_ymax",
+    "st_ymin",
+    "substr",
+    "tan",
+    "to_char",
+    "trim",
+    "trunc",
+    "upper",
+];
+
+const KEYWORDS: &[&str] = &[
+    "and",
+    "between",
+    "case",
+    "else",
+    "end",
+    "false",
+    "ilike",
+    "in",
+    "is",
+    "like",
+    "not",
+    "null",
+    "or",
+    "precision",
+    "then",
+    "true",
+    "when",
+];
+
+/// Types a value may be cast to with `::`.
+const TYPES: &[&str] = &[
+    "bigint",
+    "bool",
+    "boolean",
+    "date",
+    "decimal",
+    "double",
+    "float",
+    "float4",
+    "float8",
+    "geography",
+    "geometry",
+    "int",
+    "int2",
+    "int4",
+    "int8",
+    "integer",
+    "numeric",
+    "real",
+    "smallint",
+    "text",
+    "timestamp",
+    "timestamptz",
+    "varchar",
+];
+
+/// Words that would start a query or a clause of one rather than name a
+/// column.
+const RESERVED: &[&str] = &[
+    "array",
+    "delete",
+    "except",
+    "from",
+    "group",
+    "having",
+    "insert",
+    "intersect",
+    "into",
+    "join",
+    "lateral",
+    "limit",
+    "offset",
+    "only",
+    "order",
+    "over",
+    "returning",
+    "row",
+    "select",
+    "table",
+    "union",
+    "update",
+    "values",
+    "where",
+    "window",
+    "with",
+];
+
+const OPERATORS: &[&str] = &[
+    "::", "||", "<=", ">=", "<>", "!=", "+", "-", "*", "/", "%", "=", "<", ">", "(", ")", ",",
+];
+
+/// The columns `expression` refers to, or why it is not allowed. Columns
+/// are as Postgres would resolve them: unquoted names in lowercase.
+pub fn columns(expression: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut columns: Vec<String> = Vec::new();
+    let mut after_cast = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if rest == "--" || rest == "/*" {
+            return Err("must not contain comments".to_string());
+        }
+        let cast = after_cast;
+        after_cast = false;
+        if c == '\'' {
+            // A string literal, with '' for a quote. Backslashes are refused
+            // so that a literal reads the same whether or not Postgres treats
+            // it as an escape string.
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("has an unterminated string".to_string()),
+                    Some('\\') => return Err("must not contain \\ in a string".to_string()),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => i += 2,
+                    Some('\'') => break,
+                    Some(_) => i += 1,
+                }
+            }
+            i += 1;
+        } else if c == '"' {
+            let mut name = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("has an unterminated quoted name".to_string()),
+                    Some('"') if chars.get(i + 1) == Some(&'"') => {
+                        name.push('"');
+                        i += 2;
+                    }
+                    Some('"') => break,
+                    Some(&c) => {
+                        name.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            if cast {
+                return Err(format!("can't cast to {name}"));
+            }
+            if chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&'(') {
+                return Err(format!("can't call {name}"));
+            }
+            if !columns.contains(&name) {
+                columns.push(name);
+            }
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if matches!(chars.get(i), Some('e' | 'E')) {
+                i += 1;
+                if matches!(chars.get(i), Some('+' | '-')) {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+            if chars.get(i) == Some(&'\'') {
+                // E'...' and friends, where \' escapes the quote rather than ending the string
+                return Err(format!("must not use {}'...' strings", word.to_uppercase()));
+            }
+            let call = chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&'(');
+            if RESERVED.contains(&word.as_str()) {
+                return Err(format!("must not use {}", word.to_uppercase()));
+            } else if cast {
+                if !TYPES.contains(&word.as_str()) {
+                    return Err(format!("can't cast to {word}"));
+                }
+            } else if KEYWORDS.contains(&word.as_str()) {
+                // e.g. `IN (...)`, which is not a call
+            } else if call {
+                if !FUNCTIONS.contains(&word.as_str()) {
+                    return Err(format!("can't call {word}"));
+                }
+            } else if !columns.contains(&word) {
+                columns.push(word);
+            }
+        } else {
+            let Some(operator) = OPERATORS
+                .iter()
+                .find(|operator| chars[i..].starts_with(&operator.chars().collect::<Vec<_>>()))
+            else {
+                return Err(format!("must not contain {c}"));
+            };
+            after_cast = *operator == "::";
+            i += operator.len();
+        }
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_listed_once_in_lowercase() {
+        assert_eq!(
+            columns("Population / st_area(geom) + population").unwrap(),
+            vec!["population", "geom"]
+        );
+    }
+
+    #[test]
+    fn quoted_names_keep_their_case() {
+        assert_eq!(
+            columns("\"Name\" || ' ' || \"a\"\"b\"").unwrap(),
+            vec!["Name", "a\"b"]
+        );
+    }
+
+    #[test]
+    fn literals_are_not_columns() {
+        assert_eq!(columns("'it''s from' || name").unwrap(), vec!["name"]);
+        assert_eq!(columns("1.5e-3 * .5 + area").unwrap(), vec!["area"]);
+    }
+
+    #[test]
+    fn casts_and_keywords_are_not_columns() {
+        assert_eq!(
+            columns("CASE WHEN kind IN ('a', 'b') THEN pop::numeric ELSE NULL END").unwrap(),
+            vec!["kind", "pop"]
+        );
+    }
+
+    #[test]
+    fn unlisted_functions_are_rejected() {
+        assert!(columns("set_config('role', 'postgres', false)").is_err());
+        assert!(columns("\"lower\"(name)").is_err());
+    }
+
+    #[test]
+    fn queries_and_comments_are_rejected() {
+        assert!(columns("(select 1)").is_err());
+        assert!(columns("name -- comment").is_err());
+        assert!(columns("name /* comment */").is_err());
+        assert!(columns("name; drop table layers").is_err());
+        assert!(columns("name::regclass").is_err());
+    }
+
+    #[test]
+    fn escape_strings_are_rejected() {
+        // Postgres reads \' as a quote, so the string ends later than it seems
+        assert!(columns("E'\\'' || (select 1) || '").is_err());
+        assert!(columns("e'abc'").is_err());
+        assert!(columns("'\\' || (select 1) || '").is_err());
+    }
+
+    #[test]
+    fn unterminated_strings_are_rejected() {
+        assert!(columns("'abc").is_err());
+        assert!(columns("\"abc").is_err());
+    }
+}
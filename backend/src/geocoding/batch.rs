@@ -0,0 +1,353 @@
+use super::{Busy, Geocoder, Place};
+use crate::core::ProgressReporter;
+use crate::postgis::{create_derived_table, quote_ident, quote_literal, LayerTable};
+use crate::validation::{Validate, Validator};
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+
+/// The outcome of geocoding each row of a batch, kept so failures can be
+/// reviewed after the job.
+const RESULTS_SQL: &str = "
+CREATE SCHEMA IF NOT EXISTS gridwalk;
+CREATE TABLE IF NOT EXISTS gridwalk.geocode_results (
+    job_id text NOT NULL,
+    row_id jsonb NOT NULL,
+    address text,
+    status text NOT NULL,
+    confidence float8,
+    label text,
+    lon float8,
+    lat float8,
+    error text,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (job_id, row_id)
+);
+";
+
+/// Most rows one job geocodes.
+pub const MAX_GEOCODE_ROWS: i64 = 100_000;
+/// Days results are kept for review.
+const RETENTION_DAYS: i32 = 30;
+/// Rows recorded per statement, and between progress reports.
+const RESULT_BATCH: usize = 500;
+/// Provider errors in a row after which the job gives up.
+const MAX_CONSECUTIVE_ERRORS: usize = 20;
+
+/// Geocode the address column of a layer of rows without geometry, such as
+/// a spreadsheet imported as features with null geometries, into a point
+/// layer.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BatchGeocode {
+    /// Column holding the full address of each row.
+    pub address_column: String,
+    /// Matches the provider is less sure of are not placed, and reported as
+    /// `low_confidence`. Providers that give no confidence always pass.
+    pub min_confidence: Option<f64>,
+}
+
+impl Validate for BatchGeocode {
+    fn check(&self, v: &mut Validator) {
+        v.length("address_column", &self.address_column, 1, 63);
+        if let Some(min_confidence) = self.min_confidence {
+            v.check(
+                "min_confidence",
+                (0.0..=1.0).contains(&min_confidence),
+                "out_of_range",
+                "must be between 0 and 1",
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GeocodeStatus {
+    /// Placed in the output layer.
+    Matched,
+    /// Matched below the requested confidence.
+    LowConfidence,
+    /// The provider found nothing.
+    NotFound,
+    /// The row has no address.
+    Empty,
+    /// The provider failed on the address.
+    Failed,
+}
+
+impl GeocodeStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GeocodeStatus::Matched => "matched",
+            GeocodeStatus::LowConfidence => "low_confidence",
+            GeocodeStatus::NotFound => "not_found",
+            GeocodeStatus::Empty => "empty",
+            GeocodeStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "matched" => Some(GeocodeStatus::Matched),
+            "low_confidence" => Some(GeocodeStatus::LowConfidence),
+            "not_found" => Some(GeocodeStatus::NotFound),
+            "empty" => Some(GeocodeStatus::Empty),
+            "failed" => Some(GeocodeStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A row a batch geocoding job could not place.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GeocodeFailure {
+    /// Primary key of the row in the input layer.
+    pub row_id: Value,
+    pub address: Option<String>,
+    pub status: GeocodeStatus,
+    /// Of the best match, when there was one.
+    pub confidence: Option<f64>,
+    pub label: Option<String>,
+    pub error: Option<String>,
+}
+
+struct RowResult {
+    row_id: Value,
+    address: Option<String>,
+    status: GeocodeStatus,
+    confidence: Option<f64>,
+    label: Option<String>,
+    lon: Option<f64>,
+    lat: Option<f64>,
+    error: Option<String>,
+}
+
+impl RowResult {
+    fn new(row_id: Value, address: Option<String>, status: GeocodeStatus) -> Self {
+        RowResult {
+            row_id,
+            address,
+            status,
+            confidence: None,
+            label: None,
+            lon: None,
+            lat: None,
+            error: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "row_id": self.row_id,
+            "address": self.address,
+            "status": self.status.as_str(),
+            "confidence": self.confidence,
+            "label": self.label,
+            "lon": self.lon,
+            "lat": self.lat,
+            "error": self.error,
+        })
+    }
+}
+
+async fn record(pool: &Pool, job_id: &str, results: &[RowResult]) -> Result<()> {
+    let rows = Value::Array(results.iter().map(RowResult::to_json).collect());
+    pool.get()
+        .await?
+        .execute(
+            "INSERT INTO gridwalk.geocode_results
+                 (job_id, row_id, address, status, confidence, label, lon, lat, error)
+             SELECT $1, r.row_id, r.address, r.status, r.confidence, r.label, r.lon, r.lat,
+                    r.error
+             FROM jsonb_to_recordset($2::jsonb) AS r(
+                 row_id jsonb, address text, status text, confidence float8, label text,
+                 lon float8, lat float8, error text
+             )",
+            &[&job_id, &rows],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The best match for `query`, waiting out interactive traffic rather than
+/// failing the row while the provider's rate limit is taken.
+async fn search_when_free(geocoder: &Geocoder, query: &str) -> Result<Vec<Place>> {
+    loop {
+        match geocoder.search(query, 1).await {
+            Err(e) => match e.downcast_ref::<Busy>() {
+                Some(busy) => tokio::time::sleep(busy.retry_after).await,
+                None => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Geocode each row of the layer's `address_column` through `geocoder`,
+/// which spaces requests to the provider's rate limit and answers repeated
+/// addresses from its cache, and write the matched rows to `output` as WGS84
+/// points with `geocode_confidence` and `geocode_label`. Every row's outcome
+/// is recorded under the job for `failures`. `key` is the layer's primary
+/// key.
+#[instrument(skip(pool, geocoder, spec, progress))]
+pub async fn geocode_layer(
+    pool: &Pool,
+    geocoder: &Geocoder,
+    source_id: &str,
+    key: &str,
+    spec: &BatchGeocode,
+    output: &str,
+    progress: &ProgressReporter,
+) -> Result<()> {
+    let source = LayerTable::from_source_id(pool, source_id).await?;
+    let columns = source.attribute_columns(pool).await?;
+    if !columns.contains(&spec.address_column) {
+        return Err(anyhow!("unknown column {}", spec.address_column));
+    }
+    let table = source.qualified_name();
+    let key = quote_ident(key);
+    let job_id = progress.job_id();
+
+    let rows = {
+        let client = pool.get().await?;
+        client.batch_execute(RESULTS_SQL).await?;
+        client
+            .execute(
+                &format!(
+                    "DELETE FROM gridwalk.geocode_results
+                     WHERE created_at < now() - interval '{RETENTION_DAYS} days'"
+                ),
+                &[],
+            )
+            .await?;
+        let count: i64 = client
+            .query_one(&format!("SELECT count(*) FROM {table}"), &[])
+            .await?
+            .get(0);
+        if count > MAX_GEOCODE_ROWS {
+            return Err(anyhow!(
+                "layer has {count} rows; at most {MAX_GEOCODE_ROWS} can be geocoded at once"
+            ));
+        }
+        client
+            .query(
+                &format!(
+                    "SELECT to_jsonb(t.{key}), t.{}::text FROM {table} t ORDER BY t.{key}",
+                    quote_ident(&spec.address_column)
+                ),
+                &[],
+            )
+            .await?
+    };
+
+    let total = rows.len() as u64;
+    let mut pending = Vec::with_capacity(RESULT_BATCH);
+    let mut consecutive_errors = 0;
+    for (index, row) in rows.iter().enumerate() {
+        let row_id: Value = row.get(0);
+        let address = row
+            .get::<_, Option<String>>(1)
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty());
+        let result = match &address {
+            None => RowResult::new(row_id, None, GeocodeStatus::Empty),
+            Some(query) => match search_when_free(geocoder, query).await {
+                Ok(places) => {
+                    consecutive_errors = 0;
+                    match places.into_iter().next() {
+                        None => RowResult::new(row_id, address, GeocodeStatus::NotFound),
+                        Some(place) => {
+                            let confident = match (spec.min_confidence, place.confidence) {
+                                (Some(min), Some(confidence)) => confidence >= min,
+                                _ => true,
+                            };
+                            let status = if confident {
+                                GeocodeStatus::Matched
+                            } else {
+                                GeocodeStatus::LowConfidence
+                            };
+                            RowResult {
+                                confidence: place.confidence,
+                                label: Some(place.label),
+                                lon: Some(place.lon),
+                                lat: Some(place.lat),
+                                ..RowResult::new(row_id, address, status)
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    consecutive_errors += 1;
+                    if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                        return Err(e.context(format!(
+                            "geocoding provider failed {MAX_CONSECUTIVE_ERRORS} times in a row"
+                        )));
+                    }
+                    warn!("Geocoding row {row_id} of {source_id} failed: {e:#}");
+                    RowResult {
+                        error: Some(format!("{e:#}")),
+                        ..RowResult::new(row_id, address, GeocodeStatus::Failed)
+                    }
+                }
+            },
+        };
+        pending.push(result);
+        if pending.len() >= RESULT_BATCH {
+            record(pool, &job_id, &pending).await?;
+            pending.clear();
+            progress.report(index as u64 + 1, total).await;
+        }
+    }
+    if !pending.is_empty() {
+        record(pool, &job_id, &pending).await?;
+    }
+    progress.report(total, total).await;
+
+    let attributes: String = columns
+        .iter()
+        .map(|column| format!("t.{}, ", quote_ident(column)))
+        .collect();
+    let select_sql = format!(
+        "SELECT {attributes}r.confidence AS geocode_confidence, r.label AS geocode_label,
+                ST_SetSRID(ST_MakePoint(r.lon, r.lat), 4326) AS geom
+         FROM {table} t
+         JOIN gridwalk.geocode_results r
+           ON r.job_id = {} AND r.row_id = to_jsonb(t.{key})
+         WHERE r.status = 'matched'",
+        quote_literal(&job_id)
+    );
+    create_derived_table(pool, output, &select_sql, 4326).await
+}
+
+/// Rows the job could not place, in the input layer's key order.
+#[instrument(skip(pool))]
+pub async fn failures(pool: &Pool, job_id: &str) -> Result<Vec<GeocodeFailure>> {
+    let client = pool.get().await?;
+    client.batch_execute(RESULTS_SQL).await?;
+    let rows = client
+        .query(
+            "SELECT row_id, address, status, confidence, label, error
+             FROM gridwalk.geocode_results
+             WHERE job_id = $1 AND status <> 'matched'
+             ORDER BY row_id",
+            &[&job_id],
+        )
+        .await?;
+    rows.iter()
+        .map(|row| {
+            let status: String = row.get(2);
+            Ok(GeocodeFailure {
+                row_id: row.get(0),
+                address: row.get(1),
+                status: GeocodeStatus::parse(&status)
+                    .ok_or_else(|| anyhow!("unknown geocode status {status}"))?,
+                confidence: row.get(3),
+                label: row.get(4),
+                error: row.get(5),
+            })
+        })
+        .collect()
+}
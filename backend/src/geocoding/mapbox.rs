@@ -0,0 +1,84 @@
+use super::{fetch_json, GeocodingProvider, Place};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Deserialize;
+
+const MAPBOX_PLACES_URL: &str = "https://api.mapbox.com/geocoding/v5/mapbox.places";
+
+pub struct Mapbox {
+    access_token: String,
+}
+
+impl Mapbox {
+    pub fn new(access_token: String) -> Self {
+        Mapbox { access_token }
+    }
+}
+
+#[derive(Deserialize)]
+struct MapboxResponse {
+    features: Vec<MapboxFeature>,
+}
+
+#[derive(Deserialize)]
+struct MapboxFeature {
+    place_name: String,
+    center: [f64; 2],
+    #[serde(default)]
+    place_type: Vec<String>,
+    bbox: Option<[f64; 4]>,
+    /// How well the feature matches the query, from 0 to 1.
+    relevance: Option<f64>,
+}
+
+impl From<MapboxFeature> for Place {
+    fn from(feature: MapboxFeature) -> Self {
+        Place {
+            label: feature.place_name,
+            lon: feature.center[0],
+            lat: feature.center[1],
+            kind: feature.place_type.into_iter().next(),
+            bbox: feature.bbox,
+            confidence: feature.relevance,
+        }
+    }
+}
+
+#[async_trait]
+impl GeocodingProvider for Mapbox {
+    fn name(&self) -> &'static str {
+        "mapbox"
+    }
+
+    async fn search(
+        &self,
+        client: &reqwest::Client,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Place>> {
+        let limit = limit.to_string();
+        let mut url = Url::parse(MAPBOX_PLACES_URL)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid Mapbox URL"))?
+            .push(&format!("{query}.json"));
+        let request = client.get(url).query(&[
+            ("access_token", self.access_token.as_str()),
+            ("limit", limit.as_str()),
+        ]);
+        let response: MapboxResponse = fetch_json(request).await?;
+        Ok(response.features.into_iter().map(Place::from).collect())
+    }
+
+    async fn reverse(&self, client: &reqwest::Client, lon: f64, lat: f64) -> Result<Vec<Place>> {
+        let mut url = Url::parse(MAPBOX_PLACES_URL)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid Mapbox URL"))?
+            .push(&format!("{lon},{lat}.json"));
+        let request = client
+            .get(url)
+            .query(&[("access_token", self.access_token.as_str())]);
+        let response: MapboxResponse = fetch_json(request).await?;
+        Ok(response.features.into_iter().map(Place::from).collect())
+    }
+}
@@ -0,0 +1,219 @@
+mod batch;
+mod mapbox;
+mod nominatim;
+mod pelias;
+
+pub use batch::*;
+pub use mapbox::Mapbox;
+pub use nominatim::Nominatim;
+pub use pelias::Pelias;
+
+use crate::config::GeocodingConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+/// A geocoding result, normalised across providers.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Place {
+    pub label: String,
+    pub lon: f64,
+    pub lat: f64,
+    /// Provider-specific classification, e.g. `city` or `address`.
+    pub kind: Option<String>,
+    /// `[west, south, east, north]`
+    pub bbox: Option<[f64; 4]>,
+    /// How sure the provider is of the match, from 0 to 1, for providers
+    /// that say.
+    pub confidence: Option<f64>,
+}
+
+#[async_trait]
+pub trait GeocodingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn search(
+        &self,
+        client: &reqwest::Client,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Place>>;
+    async fn reverse(&self, client: &reqwest::Client, lon: f64, lat: f64) -> Result<Vec<Place>>;
+}
+
+/// The JSON body of a provider response. Errors leave out the URL, which
+/// carries the provider's key.
+async fn fetch_json<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await.map_err(reqwest::Error::without_url)?;
+    let response = response
+        .error_for_status()
+        .map_err(reqwest::Error::without_url)?;
+    Ok(response.json().await.map_err(reqwest::Error::without_url)?)
+}
+
+/// Fronts a provider with a response cache and an outbound request limiter.
+pub struct Geocoder {
+    provider: Box<dyn GeocodingProvider>,
+    client: reqwest::Client,
+    cache: Mutex<HashMap<String, (Instant, Vec<Place>)>>,
+    cache_ttl: Duration,
+    min_interval: Duration,
+    next_request: Mutex<Instant>,
+}
+
+const CACHE_CAPACITY: usize = 10_000;
+/// Longest a request waits for a slot under the provider's rate limit
+/// before it is turned away.
+const MAX_QUEUE_WAIT: Duration = Duration::from_secs(5);
+
+/// The provider's rate limit has no slot free within `MAX_QUEUE_WAIT`.
+#[derive(Debug)]
+pub struct Busy {
+    /// When a slot is likely to be free.
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "geocoding provider is busy; retry in {}s",
+            self.retry_after.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for Busy {}
+
+impl Geocoder {
+    pub fn new(
+        provider: Box<dyn GeocodingProvider>,
+        cache_ttl: Duration,
+        max_requests_per_second: f64,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("gridwalk/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Geocoder {
+            provider,
+            client,
+            cache: Mutex::new(HashMap::new()),
+            cache_ttl,
+            min_interval: Duration::from_secs_f64(1.0 / max_requests_per_second),
+            next_request: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Build a geocoder from configuration. Nominatim without a URL uses the
+    /// public instance.
+    pub fn from_config(config: &GeocodingConfig) -> Result<Self> {
+        let api_key = config.api_key.as_ref().map(|key| key.expose().clone());
+        let url = config.url.clone();
+        let provider: Box<dyn GeocodingProvider> = match config.provider.as_str() {
+            "nominatim" => Box::new(Nominatim::new(url)),
+            "mapbox" => {
+                Box::new(Mapbox::new(api_key.ok_or_else(|| {
+                    anyhow!("GEOCODING_API_KEY is required for mapbox")
+                })?))
+            }
+            "pelias" => Box::new(Pelias::new(
+                url.ok_or_else(|| anyhow!("GEOCODING_URL is required for pelias"))?,
+                api_key,
+            )),
+            other => return Err(anyhow!("Unknown geocoding provider: {other}")),
+        };
+        Geocoder::new(
+            provider,
+            Duration::from_secs(config.cache_ttl),
+            config.max_rps,
+        )
+    }
+
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<Place>> {
+        let key = format!("search:{limit}:{}", query.trim().to_lowercase());
+        if let Some(places) = self.cached(&key).await {
+            return Ok(places);
+        }
+
+        self.throttle().await?;
+        let places = self.provider.search(&self.client, query, limit).await?;
+        self.store(key, places.clone()).await;
+        Ok(places)
+    }
+
+    pub async fn reverse(&self, lon: f64, lat: f64) -> Result<Vec<Place>> {
+        // ~10cm precision is plenty to share cache entries between clicks
+        let key = format!("reverse:{lon:.6}:{lat:.6}");
+        if let Some(places) = self.cached(&key).await {
+            return Ok(places);
+        }
+
+        self.throttle().await?;
+        let places = self.provider.reverse(&self.client, lon, lat).await?;
+        self.store(key, places.clone()).await;
+        Ok(places)
+    }
+
+    async fn cached(&self, key: &str) -> Option<Vec<Place>> {
+        let cache = self.cache.lock().await;
+        let places = cache
+            .get(key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < self.cache_ttl)
+            .map(|(_, places)| places.clone());
+        let result = if places.is_some() { "hit" } else { "miss" };
+        counter!("geocoder_cache_requests_total", "result" => result).increment(1);
+        places
+    }
+
+    async fn store(&self, key: String, places: Vec<Place>) {
+        let mut cache = self.cache.lock().await;
+        if cache.len() >= CACHE_CAPACITY {
+            cache.retain(|_, (stored_at, _)| stored_at.elapsed() < self.cache_ttl);
+            if cache.len() >= CACHE_CAPACITY {
+                cache.clear();
+            }
+        }
+        cache.insert(key, (Instant::now(), places));
+    }
+
+    /// Drop expired responses, returning how many were removed.
+    pub async fn evict_expired(&self) -> usize {
+        let mut cache = self.cache.lock().await;
+        let before = cache.len();
+        cache.retain(|_, (stored_at, _)| stored_at.elapsed() < self.cache_ttl);
+        before - cache.len()
+    }
+
+    /// Space outbound requests so the provider's rate limit is respected,
+    /// failing with `Busy` rather than queueing past `MAX_QUEUE_WAIT`.
+    async fn throttle(&self) -> Result<()> {
+        let wait_until = {
+            let mut next_request = self.next_request.lock().await;
+            let now = Instant::now();
+            let slot = (*next_request).max(now);
+            let wait = slot - now;
+            if wait > MAX_QUEUE_WAIT {
+                counter!("geocoder_requests_rejected_total").increment(1);
+                return Err(Busy {
+                    retry_after: (wait - MAX_QUEUE_WAIT).max(Duration::from_secs(1)),
+                }
+                .into());
+            }
+            *next_request = slot + self.min_interval;
+            slot
+        };
+        tokio::time::sleep_until(wait_until.into()).await;
+        Ok(())
+    }
+}
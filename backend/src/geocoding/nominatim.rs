@@ -0,0 +1,95 @@
+use super::{GeocodingProvider, Place};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+pub struct Nominatim {
+    base_url: String,
+}
+
+impl Nominatim {
+    pub fn new(base_url: Option<String>) -> Self {
+        Nominatim {
+            base_url: base_url.unwrap_or_else(|| "https://nominatim.openstreetmap.org".to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    display_name: String,
+    lat: String,
+    lon: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// `[south, north, west, east]` as strings
+    boundingbox: Option<[String; 4]>,
+    /// Prominence of the place, from 0 to 1.
+    importance: Option<f64>,
+}
+
+impl NominatimPlace {
+    fn into_place(self) -> Option<Place> {
+        let bbox = self.boundingbox.and_then(|b| {
+            let [south, north, west, east] =
+                [&b[0], &b[1], &b[2], &b[3]].map(|v| v.parse::<f64>().ok());
+            Some([west?, south?, east?, north?])
+        });
+        Some(Place {
+            label: self.display_name,
+            lon: self.lon.parse().ok()?,
+            lat: self.lat.parse().ok()?,
+            kind: self.kind,
+            bbox,
+            confidence: self.importance,
+        })
+    }
+}
+
+#[async_trait]
+impl GeocodingProvider for Nominatim {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
+
+    async fn search(
+        &self,
+        client: &reqwest::Client,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Place>> {
+        let limit = limit.to_string();
+        let places: Vec<NominatimPlace> = client
+            .get(format!("{}/search", self.base_url))
+            .query(&[
+                ("q", query),
+                ("format", "jsonv2"),
+                ("limit", limit.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(places
+            .into_iter()
+            .filter_map(NominatimPlace::into_place)
+            .collect())
+    }
+
+    async fn reverse(&self, client: &reqwest::Client, lon: f64, lat: f64) -> Result<Vec<Place>> {
+        let place: NominatimPlace = client
+            .get(format!("{}/reverse", self.base_url))
+            .query(&[
+                ("lat", lat.to_string()),
+                ("lon", lon.to_string()),
+                ("format", "jsonv2".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(place.into_place().into_iter().collect())
+    }
+}
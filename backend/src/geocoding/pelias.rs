@@ -0,0 +1,87 @@
+use super::{fetch_json, GeocodingProvider, Place};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+pub struct Pelias {
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Pelias {
+    pub fn new(base_url: String, api_key: Option<String>) -> Self {
+        Pelias { base_url, api_key }
+    }
+}
+
+#[derive(Deserialize)]
+struct PeliasResponse {
+    features: Vec<PeliasFeature>,
+}
+
+#[derive(Deserialize)]
+struct PeliasFeature {
+    geometry: PeliasGeometry,
+    properties: PeliasProperties,
+    bbox: Option<[f64; 4]>,
+}
+
+#[derive(Deserialize)]
+struct PeliasGeometry {
+    coordinates: [f64; 2],
+}
+
+#[derive(Deserialize)]
+struct PeliasProperties {
+    label: String,
+    layer: Option<String>,
+    confidence: Option<f64>,
+}
+
+impl From<PeliasFeature> for Place {
+    fn from(feature: PeliasFeature) -> Self {
+        Place {
+            label: feature.properties.label,
+            lon: feature.geometry.coordinates[0],
+            lat: feature.geometry.coordinates[1],
+            kind: feature.properties.layer,
+            bbox: feature.bbox,
+            confidence: feature.properties.confidence,
+        }
+    }
+}
+
+#[async_trait]
+impl GeocodingProvider for Pelias {
+    fn name(&self) -> &'static str {
+        "pelias"
+    }
+
+    async fn search(
+        &self,
+        client: &reqwest::Client,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Place>> {
+        let limit = limit.to_string();
+        let mut request = client
+            .get(format!("{}/v1/search", self.base_url))
+            .query(&[("text", query), ("size", limit.as_str())]);
+        if let Some(api_key) = &self.api_key {
+            request = request.query(&[("api_key", api_key)]);
+        }
+        let response: PeliasResponse = fetch_json(request).await?;
+        Ok(response.features.into_iter().map(Place::from).collect())
+    }
+
+    async fn reverse(&self, client: &reqwest::Client, lon: f64, lat: f64) -> Result<Vec<Place>> {
+        let mut request = client
+            .get(format!("{}/v1/reverse", self.base_url))
+            .query(&[("point.lat", lat), ("point.lon", lon)]);
+        if let Some(api_key) = &self.api_key {
+            request = request.query(&[("api_key", api_key)]);
+        }
+        let response: PeliasResponse = fetch_json(request).await?;
+        Ok(response.features.into_iter().map(Place::from).collect())
+    }
+}
@@ -0,0 +1,231 @@
+use crate::app_state::AppState;
+use crate::core::{
+    Attribute, Catalog, Form, Freshness, Layer, LayerStyle, Map, MapLayer, Tiling, Viewport,
+};
+use crate::data::{DataError, Database};
+use crate::quality::QualityRules;
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Json, Object, Request, Result, Schema,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Deepest nesting a query may ask for.
+const MAX_DEPTH: usize = 8;
+/// Most fields, counted across every level, a query may ask for.
+const MAX_COMPLEXITY: usize = 2000;
+
+pub type GraphqlSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// A read-only view over maps and layers, for clients that want a map with
+/// the metadata of its layers in one request. Writes go through REST.
+pub fn schema() -> GraphqlSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// `request` with what its resolvers need. Loaders are per request, so
+/// nothing is cached across requests.
+pub fn prepare(request: Request, state: &AppState) -> Request {
+    let layers = DataLoader::new(
+        LayerLoader {
+            database: state.app_data.clone(),
+        },
+        tokio::spawn,
+    );
+    request.data(state.clone()).data(layers)
+}
+
+/// Batches the layer lookups of a request into one store call.
+pub struct LayerLoader {
+    database: Arc<dyn Database>,
+}
+
+impl Loader<String> for LayerLoader {
+    type Value = Layer;
+    type Error = Arc<DataError>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Layer>, Self::Error> {
+        let layers = Layer::get_many(&self.database, keys).await?;
+        Ok(layers
+            .into_iter()
+            .map(|layer| (layer.id.clone(), layer))
+            .collect())
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Maps not in the trash, oldest first.
+    async fn maps(&self, ctx: &Context<'_>) -> Result<Vec<MapObject>> {
+        let state = ctx.data::<AppState>()?;
+        let mut maps = Map::get_all(&state.app_data).await?;
+        maps.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(maps.into_iter().map(MapObject).collect())
+    }
+
+    async fn map(&self, ctx: &Context<'_>, id: String) -> Result<Option<MapObject>> {
+        let state = ctx.data::<AppState>()?;
+        match Map::from_id(&state.app_data, &id).await {
+            Ok(map) => Ok(Some(MapObject(map))),
+            Err(DataError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Published layers whose metadata has been computed.
+    async fn layers(&self, ctx: &Context<'_>) -> Result<Vec<LayerObject>> {
+        let state = ctx.data::<AppState>()?;
+        let loader = ctx.data::<DataLoader<LayerLoader>>()?;
+        let ids = state.sources.ids();
+        let mut layers = loader.load_many(ids.iter().cloned()).await?;
+        Ok(ids
+            .iter()
+            .filter_map(|id| layers.remove(id))
+            .map(LayerObject)
+            .collect())
+    }
+
+    /// The layer, when it is published and its metadata has been computed.
+    async fn layer(&self, ctx: &Context<'_>, id: String) -> Result<Option<LayerObject>> {
+        load_layer(ctx, id).await
+    }
+}
+
+async fn load_layer(ctx: &Context<'_>, id: String) -> Result<Option<LayerObject>> {
+    let state = ctx.data::<AppState>()?;
+    if !state.sources.contains(&id) {
+        return Ok(None);
+    }
+    let loader = ctx.data::<DataLoader<LayerLoader>>()?;
+    Ok(loader.load_one(id).await?.map(LayerObject))
+}
+
+pub struct MapObject(Map);
+
+#[Object(name = "Map")]
+impl MapObject {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// Layers in draw order, bottom first.
+    async fn layers(&self) -> Vec<MapLayerObject> {
+        self.0.layers.iter().cloned().map(MapLayerObject).collect()
+    }
+
+    async fn viewport(&self) -> Json<Viewport> {
+        Json(self.0.viewport.clone())
+    }
+
+    async fn basemap(&self) -> Option<&str> {
+        self.0.basemap.as_deref()
+    }
+
+    async fn created_at(&self) -> i64 {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> i64 {
+        self.0.updated_at
+    }
+
+    async fn version(&self) -> u64 {
+        self.0.version
+    }
+}
+
+pub struct MapLayerObject(MapLayer);
+
+#[Object(name = "MapLayer")]
+impl MapLayerObject {
+    async fn source_id(&self) -> &str {
+        &self.0.source_id
+    }
+
+    async fn visible(&self) -> bool {
+        self.0.visible
+    }
+
+    async fn style(&self) -> Json<LayerStyle> {
+        Json(self.0.style.clone())
+    }
+
+    /// The layer's metadata, loaded together with that of the other layers
+    /// in the request.
+    async fn layer(&self, ctx: &Context<'_>) -> Result<Option<LayerObject>> {
+        load_layer(ctx, self.0.source_id.clone()).await
+    }
+}
+
+pub struct LayerObject(Layer);
+
+#[Object(name = "Layer")]
+impl LayerObject {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    /// `[west, south, east, north]` in WGS84; null for an empty layer.
+    async fn bbox(&self) -> Option<Vec<f64>> {
+        self.0.bbox.map(Vec::from)
+    }
+
+    async fn feature_count(&self) -> i64 {
+        self.0.feature_count
+    }
+
+    async fn geometry_types(&self) -> Vec<String> {
+        self.0.geometry_types.clone()
+    }
+
+    async fn attributes(&self) -> Json<Vec<Attribute>> {
+        Json(self.0.attributes.clone())
+    }
+
+    async fn srid(&self) -> i32 {
+        self.0.srid
+    }
+
+    async fn catalog(&self) -> Json<Catalog> {
+        Json(self.0.catalog.clone())
+    }
+
+    async fn form(&self) -> Option<Json<Form>> {
+        self.0.form.clone().map(Json)
+    }
+
+    async fn tiling(&self) -> Json<Tiling> {
+        Json(self.0.tiling.clone())
+    }
+
+    /// Expected update frequency, and since when the data is overdue.
+    async fn freshness(&self) -> Option<Json<Freshness>> {
+        self.0.freshness.clone().map(Json)
+    }
+
+    async fn quality(&self) -> Option<Json<QualityRules>> {
+        self.0.quality.clone().map(Json)
+    }
+
+    async fn updated_at(&self) -> i64 {
+        self.0.updated_at
+    }
+
+    async fn version(&self) -> u64 {
+        self.0.version
+    }
+}
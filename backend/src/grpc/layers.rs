@@ -0,0 +1,137 @@
+use super::proto::{self, layer_service_server::LayerService};
+use crate::app_state::AppState;
+use crate::core::Layer;
+use crate::routes::PageParams;
+use std::collections::HashMap;
+use tonic::{Request, Response, Status};
+
+impl From<Layer> for proto::Layer {
+    fn from(layer: Layer) -> Self {
+        proto::Layer {
+            id: layer.id,
+            bbox: layer
+                .bbox
+                .map(|[west, south, east, north]| proto::BoundingBox {
+                    west,
+                    south,
+                    east,
+                    north,
+                }),
+            feature_count: layer.feature_count,
+            geometry_types: layer.geometry_types,
+            attributes: layer
+                .attributes
+                .into_iter()
+                .map(|attribute| proto::Attribute {
+                    name: attribute.name,
+                    data_type: attribute.data_type,
+                })
+                .collect(),
+            srid: layer.srid,
+            updated_at: layer.updated_at,
+            catalog: Some(proto::Catalog {
+                description: layer.catalog.description,
+                tags: layer.catalog.tags,
+                license: layer.catalog.license,
+                attribution: layer.catalog.attribution,
+            }),
+            version: layer.version,
+        }
+    }
+}
+
+/// The layer endpoints of the REST API, for internal services.
+pub struct LayerApi {
+    state: AppState,
+}
+
+impl LayerApi {
+    pub fn new(state: AppState) -> Self {
+        LayerApi { state }
+    }
+
+    fn check_published(&self, id: &str) -> Result<(), Status> {
+        if !self.state.sources.contains(id) {
+            return Err(Status::not_found("Layer not found"));
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl LayerService for LayerApi {
+    async fn get_layer(
+        &self,
+        request: Request<proto::GetLayerRequest>,
+    ) -> Result<Response<proto::Layer>, Status> {
+        let id = request.into_inner().id;
+        self.check_published(&id)?;
+        match Layer::from_id(
+            &self.state.app_data,
+            &self.state.pg_pool,
+            &self.state.read_pool,
+            &id,
+        )
+        .await
+        {
+            Ok(layer) => Ok(Response::new(layer.into())),
+            Err(_) => Err(Status::internal("Failed to read layer metadata")),
+        }
+    }
+
+    async fn list_layers(
+        &self,
+        request: Request<proto::ListLayersRequest>,
+    ) -> Result<Response<proto::ListLayersResponse>, Status> {
+        let request = request.into_inner();
+        let mut cached: HashMap<String, Layer> = match Layer::get_all(&self.state.app_data).await {
+            Ok(layers) => layers.into_iter().map(|l| (l.id.clone(), l)).collect(),
+            Err(_) => return Err(Status::internal("Failed to list layers")),
+        };
+        let layers: Vec<proto::Layer> = self
+            .state
+            .sources
+            .ids()
+            .into_iter()
+            .map(|id| match cached.remove(&id) {
+                Some(layer) => layer.into(),
+                None => proto::Layer {
+                    id,
+                    ..Default::default()
+                },
+            })
+            .collect();
+
+        let params = PageParams {
+            cursor: Some(request.page_token).filter(|token| !token.is_empty()),
+            limit: Some(request.page_size as usize).filter(|size| *size > 0),
+        };
+        let Some(page) = params.page(layers) else {
+            return Err(Status::invalid_argument("Invalid page_token"));
+        };
+        Ok(Response::new(proto::ListLayersResponse {
+            layers: page.items,
+            next_page_token: page.next_cursor.unwrap_or_default(),
+            total: page.total.unwrap_or_default() as u64,
+        }))
+    }
+
+    async fn refresh_layer(
+        &self,
+        request: Request<proto::RefreshLayerRequest>,
+    ) -> Result<Response<proto::Layer>, Status> {
+        let id = request.into_inner().id;
+        self.check_published(&id)?;
+        match Layer::refresh(
+            &self.state.app_data,
+            &self.state.pg_pool,
+            &self.state.read_pool,
+            &id,
+        )
+        .await
+        {
+            Ok(layer) => Ok(Response::new(layer.into())),
+            Err(_) => Err(Status::internal("Failed to refresh layer metadata")),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+#[cfg(feature = "grpc")]
+mod layers;
+
+use crate::app_state::AppState;
+use crate::config::GrpcConfig;
+use anyhow::Result;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Generated from `proto/gridwalk/v1`, which is the contract for clients.
+#[cfg(feature = "grpc")]
+pub mod proto {
+    tonic::include_proto!("gridwalk.v1");
+
+    /// The encoded proto definitions, served for reflection.
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("gridwalk_descriptor");
+}
+
+/// Serve the gRPC API on its own port until `stop`, when it is enabled.
+#[cfg(feature = "grpc")]
+pub fn spawn(
+    config: &GrpcConfig,
+    state: AppState,
+    stop: CancellationToken,
+) -> Result<Option<JoinHandle<()>>> {
+    use proto::layer_service_server::LayerServiceServer;
+    use tonic::transport::Server;
+    use tracing::{error, info};
+
+    let Some(bind) = config.bind else {
+        return Ok(None);
+    };
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let server = Server::builder()
+        .add_service(reflection)
+        .add_service(LayerServiceServer::new(layers::LayerApi::new(state)))
+        .serve_with_shutdown(bind, stop.cancelled_owned());
+    info!("gRPC listening on {bind}");
+    Ok(Some(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("gRPC server failed: {e}");
+        }
+    })))
+}
+
+#[cfg(not(feature = "grpc"))]
+pub fn spawn(
+    config: &GrpcConfig,
+    _state: AppState,
+    _stop: CancellationToken,
+) -> Result<Option<JoinHandle<()>>> {
+    match config.bind {
+        Some(_) => Err(anyhow::anyhow!(
+            "gRPC is not available in this build; enable the grpc feature"
+        )),
+        None => Ok(None),
+    }
+}
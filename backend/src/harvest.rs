@@ -0,0 +1,256 @@
+use crate::app_state::AppState;
+use crate::config::LimitsConfig;
+use crate::core::{Connector, ConnectorSource, Layer};
+use crate::postgis::{
+    check_complexity, create_feature_table, quote_ident, quote_literal, LayerTable,
+};
+use crate::remote::Fetcher;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use serde_json::{Map as JsonMap, Value};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+const PAGE_SIZE: usize = 1000;
+/// Stop runaway paging through services that ignore offsets.
+const MAX_PAGES: usize = 5000;
+
+/// Column type for a property, from the values on the first page. Numbers
+/// are always `double precision`, as a page of integers says nothing of the
+/// pages after it.
+fn column_type<'a>(values: impl Iterator<Item = &'a Value>) -> &'static str {
+    let values: Vec<&Value> = values.filter(|value| !value.is_null()).collect();
+    if values.is_empty() {
+        "text"
+    } else if values.iter().all(|v| v.is_number()) {
+        "double precision"
+    } else if values.iter().all(|v| v.is_boolean()) {
+        "boolean"
+    } else if values.iter().all(|v| v.is_object() || v.is_array()) {
+        "jsonb"
+    } else {
+        "text"
+    }
+}
+
+/// Comment marking a table as the one a connector harvests into. Harvests
+/// only ever replace a table carrying their connector's mark.
+fn table_mark(connector: &Connector) -> String {
+    format!("gridwalk connector {}", connector.id)
+}
+
+fn properties(feature: &Value) -> Option<&JsonMap<String, Value>> {
+    feature.get("properties")?.as_object()
+}
+
+/// Create the staging table with columns for the properties seen on the
+/// first page. Properties first seen on later pages are dropped.
+async fn create_table(
+    pool: &Pool,
+    name: &str,
+    features: &[Value],
+) -> Result<(LayerTable, Vec<String>)> {
+    let mut columns: Vec<String> = Vec::new();
+    for feature in features {
+        for key in properties(feature).into_iter().flat_map(|p| p.keys()) {
+            if key != "geom" && !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    let definitions: Vec<(String, &str)> = columns
+        .iter()
+        .map(|column| {
+            let values = features
+                .iter()
+                .filter_map(|feature| properties(feature)?.get(column));
+            (column.clone(), column_type(values))
+        })
+        .collect();
+    let table = create_feature_table(pool, name, &definitions).await?;
+    Ok((table, columns))
+}
+
+/// The features of a page, and whether the service has more after it.
+fn read_page(source: &ConnectorSource, body: &[u8]) -> Result<(Vec<Value>, bool)> {
+    let mut page: Value = serde_json::from_slice(body)?;
+    if let Some(error) = page.get("error") {
+        return Err(anyhow!("service error: {error}"));
+    }
+    let exceeded = page
+        .get("exceededTransferLimit")
+        .or_else(|| page.get("properties")?.get("exceededTransferLimit"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let Some(Value::Array(features)) = page.get_mut("features").map(Value::take) else {
+        return Err(anyhow!("response is not a GeoJSON FeatureCollection"));
+    };
+    let has_more = match source {
+        // ArcGIS servers cap pages at their own maximum and flag truncation
+        ConnectorSource::Arcgis { .. } => exceeded,
+        ConnectorSource::Wfs { .. } => features.len() >= PAGE_SIZE,
+    };
+    Ok((features, has_more))
+}
+
+/// Page through the connector's service into a new table, then swap it in
+/// for the layer's table. Returns the number of features written. Features
+/// without a geometry are skipped.
+#[instrument(skip_all, fields(connector = %connector.id, layer = %connector.layer_id))]
+pub async fn harvest(
+    pool: &Pool,
+    fetcher: &Fetcher,
+    connector: &Connector,
+    limits: &LimitsConfig,
+) -> Result<u64> {
+    let staging = format!("harvest_{}", Uuid::new_v4().simple());
+    let result = harvest_into(pool, fetcher, connector, limits, &staging).await;
+    if result.is_err() {
+        let client = pool.get().await?;
+        client
+            .batch_execute(&format!("DROP TABLE IF EXISTS public.{staging}"))
+            .await?;
+    }
+    result
+}
+
+async fn harvest_into(
+    pool: &Pool,
+    fetcher: &Fetcher,
+    connector: &Connector,
+    limits: &LimitsConfig,
+    staging: &str,
+) -> Result<u64> {
+    let mut staged: Option<(LayerTable, Vec<String>)> = None;
+    let mut offset = 0;
+    let mut written = 0;
+    let mut pages = 0;
+    loop {
+        if pages == MAX_PAGES {
+            return Err(anyhow!("service has more than {MAX_PAGES} pages"));
+        }
+        pages += 1;
+        let url = connector.source.page_url(offset, PAGE_SIZE)?;
+        let body = fetcher.get(url.as_str()).await?;
+        let (features, has_more) = read_page(&connector.source, &body)?;
+        offset += features.len();
+
+        let features: Vec<Value> = features
+            .into_iter()
+            .filter(|feature| feature.get("geometry").is_some_and(Value::is_object))
+            .collect();
+        if limits.max_features > 0 && written + features.len() as u64 > limits.max_features {
+            return Err(anyhow!(
+                "service has more than {} features",
+                limits.max_features
+            ));
+        }
+        for feature in &features {
+            check_complexity(feature, limits).map_err(|e| anyhow!(e))?;
+        }
+        let (table, columns) = match staged.take() {
+            Some(staged) => staged,
+            None => {
+                let staged = create_table(pool, staging, &features).await?;
+                pool.get()
+                    .await?
+                    .batch_execute(&format!(
+                        "COMMENT ON TABLE public.{staging} IS {}",
+                        quote_literal(&table_mark(connector))
+                    ))
+                    .await?;
+                staged
+            }
+        };
+        if !features.is_empty() {
+            written += table
+                .insert_features(pool, &columns, &Value::Array(features))
+                .await?;
+        }
+        staged = Some((table, columns));
+        if !has_more {
+            break;
+        }
+    }
+
+    let layer = quote_ident(&connector.layer_id);
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let existing = transaction
+        .query_opt(
+            "SELECT obj_description(c.oid, 'pg_class')
+             FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+             WHERE n.nspname = 'public' AND c.relname = $1",
+            &[&connector.layer_id],
+        )
+        .await?;
+    if let Some(row) = existing {
+        let mark: Option<String> = row.get(0);
+        if mark != Some(table_mark(connector)) {
+            return Err(anyhow!(
+                "public.{} was not created by this connector; rename or drop it first",
+                connector.layer_id
+            ));
+        }
+        transaction
+            .batch_execute(&format!("DROP TABLE public.{layer}"))
+            .await?;
+    }
+    transaction
+        .batch_execute(&format!("ALTER TABLE public.{staging} RENAME TO {layer}"))
+        .await?;
+    transaction.commit().await?;
+    Ok(written)
+}
+
+/// Harvest the connector, publish the result as its layer and record the
+/// outcome on the connector.
+pub async fn run(state: &AppState, connector: &mut Connector) -> Result<()> {
+    let result = async {
+        let written = harvest(&state.pg_pool, &state.fetcher, connector, &state.limits).await?;
+        state.sources.refresh().await?;
+        Layer::refresh(
+            &state.app_data,
+            &state.pg_pool,
+            &state.read_pool,
+            &connector.layer_id,
+        )
+        .await?;
+        Ok::<_, anyhow::Error>(written)
+    }
+    .await;
+    let error = match &result {
+        Ok(written) => {
+            info!("Harvested {written} features into {}", connector.layer_id);
+            None
+        }
+        Err(e) => Some(format!("{e:#}")),
+    };
+    if let Err(e) = connector.finish(&state.app_data, error).await {
+        warn!(
+            "Failed to record harvest of connector {}: {e}",
+            connector.id
+        );
+    }
+    result.map(|_| ())
+}
+
+/// Harvest every connector whose refresh is due, one at a time.
+pub async fn run_due(state: &AppState) -> Result<()> {
+    let now = Utc::now().timestamp();
+    let connectors = Connector::get_all(&state.app_data).await?;
+    let mut failed = 0;
+    let mut due = 0;
+    for mut connector in connectors.into_iter().filter(|c| c.is_due(now)) {
+        due += 1;
+        if let Err(e) = run(state, &mut connector).await {
+            warn!("Failed to harvest connector {}: {e:#}", connector.id);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {due} connectors failed to harvest"));
+    }
+    Ok(())
+}
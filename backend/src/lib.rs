@@ -1,4 +1,42 @@
+pub mod alerts;
+pub mod analysis;
 pub mod app_state;
+pub mod backup;
+pub mod branches;
+pub mod cdn;
+pub mod changes;
 pub mod config;
+pub mod core;
+pub mod cron;
+pub mod data;
+pub mod editing;
+pub mod expression;
+pub mod geocoding;
+pub mod graphql;
+pub mod grpc;
+pub mod harvest;
+pub mod openapi;
+pub mod osm;
+pub mod postgis;
+pub mod quality;
+pub mod query_cache;
+pub mod rate_limit;
+pub mod redact;
+pub mod remote;
 pub mod routes;
+pub mod scanning;
+pub mod scheduler;
+pub mod schedules;
+pub mod schema;
+pub mod security_headers;
+pub mod seeding;
 pub mod server;
+pub mod signing;
+pub mod sniff;
+pub mod sources;
+pub mod storage;
+pub mod sync;
+pub mod telemetry;
+pub mod tiling;
+pub mod topology;
+pub mod validation;
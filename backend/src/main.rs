@@ -1,35 +1,151 @@
 use anyhow::Result;
+use clap::Parser;
 use rustls;
-use std::collections::HashMap;
-use tracing::info;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
-use gridwalk_backend::{app_state::AppState, config, server};
+use gridwalk_backend::{
+    alerts::Alerts,
+    analysis::routing::RoutingService,
+    app_state::AppState,
+    cdn::Cdn,
+    changes,
+    config::{self, Cli, Config},
+    core::{Basemap, EventBus, JobRunner},
+    data::{Dynamodb, PublishingStore},
+    geocoding::Geocoder,
+    graphql, grpc,
+    query_cache::QueryCache,
+    rate_limit::RateLimiter,
+    redact,
+    remote::Fetcher,
+    scanning::Scanning,
+    scheduler,
+    security_headers::SecurityHeaders,
+    server,
+    signing::UrlSigner,
+    sources::SourceRegistry,
+    storage::Storage,
+    telemetry,
+    tiling::TilingRegistry,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let config = Config::load(&Cli::parse())?;
+
     // Initialize tracing
-    tracing_subscriber::fmt::init();
+    redact::set_enabled(config.redact_logs);
+    telemetry::init_tracing(config.otlp_endpoint.as_deref())?;
 
     rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .unwrap();
 
-    // Initialize PgConfig and sources
-    let tile_info_sources = config::initialize_pg_config().await?;
-    let mut sources: HashMap<String, Box<dyn martin::Source>> = HashMap::new();
-    for source in tile_info_sources {
-        let id = source.get_id().to_string();
-        sources.insert(id, source);
-    }
+    let metrics = telemetry::install_metrics()?;
+
+    // Connect to the application database, publishing an event for every
+    // write
+    let events = EventBus::from_config(&config.events).await?;
+    let app_data = PublishingStore::wrap(Dynamodb::new(&config.dynamodb).await?, events.clone());
+
+    let geocoder = Arc::new(Geocoder::from_config(&config.geocoding)?);
+    let routing = RoutingService::from_config(&config.routing)?.map(Arc::new);
+
+    // Initialize PgConfig and sources. Tiles are served through sandboxed
+    // connections
+    let sandboxed_url = config.postgres.sandboxed_url(config.database_url.expose());
+    let tile_info_sources = config::initialize_pg_config(&sandboxed_url).await?;
+    let pg_pool = config::initialize_pg_pool(config.database_url.expose())?;
+    let read_pool = config::initialize_pg_pool(&sandboxed_url)?;
+    changes::migrate(&pg_pool).await?;
+    let sources = Arc::new(SourceRegistry::new(tile_info_sources, &sandboxed_url));
+    sources.load_trash(&app_data).await?;
+    sources.clone().track_trash(&events, app_data.clone());
+    let tiling = Arc::new(TilingRegistry::load(&app_data).await?);
+    tiling.clone().track_changes(&events, app_data.clone());
+
+    let cdn = Arc::new(Cdn::from_config(&config.cdn).await?);
+    cdn.clone().purge_on_changes(&events);
+
+    let query_cache = Arc::new(QueryCache::from_config(&config.query_cache).await?);
+    query_cache.clone().invalidate_on_changes(&events);
 
-    let app_state = AppState { sources };
+    let storage = Arc::new(Storage::from_config(&config.storage).await?);
+
+    let jobs = JobRunner::default();
+    let app_state = AppState {
+        app_data: app_data.clone(),
+        geocoder,
+        pg_pool,
+        read_pool,
+        boundary_layers: config.boundary_layers.clone(),
+        routing,
+        public_url: config.server.public_url.clone(),
+        sources,
+        tiling,
+        rate_limiter: Arc::new(RateLimiter::from_config(&config.rate_limit)),
+        security_headers: Arc::new(SecurityHeaders::from_config(&config.security_headers)?),
+        metrics,
+        jobs: jobs.clone(),
+        events,
+        alerts: Arc::new(Alerts::from_config(&config.alerts)?),
+        signer: Arc::new(UrlSigner::from_config(&config.signing)),
+        cdn,
+        query_cache,
+        storage,
+        scanning: Arc::new(Scanning::from_config(&config.scanning)?),
+        backups: config.backups.clone(),
+        fetcher: Arc::new(Fetcher::from_config(&config.remote)),
+        basemaps: Arc::new(Basemap::from_config(&config.basemaps)),
+        labels: config.labels.clone(),
+        limits: config.limits.clone(),
+        seeding: config.seeding.clone(),
+        sql_layers: config.sql_layers.clone(),
+        graphql: graphql::schema(),
+    };
+    scheduler::maintenance(&config, &app_state).start();
+    let stop = CancellationToken::new();
+    let grpc = grpc::spawn(&config.grpc, app_state.clone(), stop.clone())?;
     let app = server::create_app(app_state);
 
     // Run our app with hyper
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3001").await?;
+    let listener = tokio::net::TcpListener::bind(config.server.bind).await?;
     info!("Server listening on {}", listener.local_addr()?);
-    axum::serve(listener, app).await?;
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(stop.clone().cancelled_owned())
+        .into_future(),
+    );
+
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        _ = server::shutdown_signal() => {}
+    }
+
+    // Stop accepting connections, then give in-flight requests and
+    // background jobs a chance to finish
+    let grace = Duration::from_secs(config.server.shutdown_timeout);
+    stop.cancel();
+    if timeout(grace, server).await.is_err() {
+        warn!("In-flight requests did not finish within {grace:?}");
+    }
+    if let Some(grpc) = grpc {
+        if timeout(grace, grpc).await.is_err() {
+            warn!("In-flight gRPC calls did not finish within {grace:?}");
+        }
+    }
+    jobs.drain(&app_data, grace).await;
 
+    telemetry::shutdown_tracing();
     Ok(())
 }
 
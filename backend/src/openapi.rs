@@ -0,0 +1,305 @@
+use crate::alerts::Alert;
+use crate::analysis::{
+    routing::{Contour, Profile, Route},
+    Aggregation, Dedupe, DedupeMode, Grid, JoinPredicate, Operation, SpatialJoin, Statistic,
+    StatisticOp,
+};
+use crate::backup::{BackupSummary, RestoreFilter};
+use crate::branches::{Branch, BranchDiff, BranchStatus, MergeReport};
+use crate::changes::{ChangeOperation, FeatureChange, FeatureDelta, LayerDiff, ValueDelta};
+use crate::core::{
+    Attachment, Attribute, AttributeStats, AttributeZoom, Basemap, Catalog, Comment, CommentAnchor,
+    CommentThread, ComputedAttribute, Condition, ConditionOp, Connector, ConnectorSource, Event,
+    FieldType, Fill, Form, FormField, Freshness, Job, JobProgress, JobStatus, LabelPlacement,
+    LabelStyle, Layer, LayerStyle, Legend, LegendEntry, Map, MapLayer, Paint, Ramp, RampKind,
+    SearchResult, Share, ShareResource, SqlView, Stop, Stroke, StyleRule, SwatchShape,
+    TileEncoding, Tiling, ToleranceStop, ValueCount, Viewport,
+};
+use crate::editing::{EditSession, EditSessionStatus, SessionEdit};
+use crate::geocoding::{BatchGeocode, GeocodeFailure, GeocodeStatus, Place};
+use crate::osm::OsmLayer;
+use crate::quality::{QualityReport, QualityRule, QualityRules, RuleResult};
+use crate::routes::{
+    AggregateRequest, AnalyzeRequest, AttachmentPage, BatchReport, BranchRequest, ChangeFeed,
+    CommentRequest, CommentThreadPage, ConnectorPage, ConnectorRequest, CopyLayerRequest,
+    DedupeRequest, EditSessionRequest, FreshnessRequest, GeocodeFailurePage, GeocodeLayerRequest,
+    ImportUrlRequest, IsochroneRequest, LayerPage, LegendFormat, LineError, MapPage, MapRequest,
+    MergeConflicts, OsmImportRequest, RouteRequest, ScheduleRequest, SessionEditResult, SharePage,
+    ShareRequest, SignedTileUrl, SpatialJoinRequest, SqlLayerRequest, SyncRequest, SyncResponse,
+    TopologyCheckRequest,
+};
+use crate::scanning::ScanResult;
+use crate::schedules::{ChangeSummary, LayerSchedule, RunTrigger, ScheduleRun, ScheduleSource};
+use crate::schema::SchemaChange;
+use crate::seeding::{SeedPlan, ZoomRange};
+use crate::sync::{AppliedEdit, ClientEdit, ConflictStrategy, RejectedEdit, SyncConflict};
+use crate::topology::{TopologyReport, TopologyRule, TopologyRules, TopologyViolation};
+use crate::validation::{FieldError, ValidationErrors};
+use utoipa::OpenApi;
+
+/// The HTTP API contract, served at `/openapi.json` and browsable at
+/// `/docs`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Gridwalk API"),
+    paths(
+        crate::routes::health_check,
+        crate::routes::healthz,
+        crate::routes::readyz,
+        crate::routes::get_metrics,
+        crate::routes::tiles,
+        crate::routes::source_tiles,
+        crate::routes::get_basemaps,
+        crate::routes::get_maps,
+        crate::routes::create_map,
+        crate::routes::get_map,
+        crate::routes::update_map,
+        crate::routes::delete_map,
+        crate::routes::restore_map,
+        crate::routes::get_map_style,
+        crate::routes::map_tiles,
+        crate::routes::get_comments,
+        crate::routes::create_comment,
+        crate::routes::resolve_comment,
+        crate::routes::reopen_comment,
+        crate::routes::get_layers,
+        crate::routes::get_layer,
+        crate::routes::delete_layer,
+        crate::routes::restore_layer,
+        crate::routes::refresh_layer,
+        crate::routes::copy_layer,
+        crate::routes::create_sql_layer,
+        crate::routes::update_layer_catalog,
+        crate::routes::update_layer_tiling,
+        crate::routes::update_layer_schema,
+        crate::routes::seed_layer,
+        crate::routes::get_layer_legend,
+        crate::routes::get_attribute_stats,
+        crate::routes::get_layer_form,
+        crate::routes::update_layer_form,
+        crate::routes::delete_layer_form,
+        crate::routes::update_layer_freshness,
+        crate::routes::delete_layer_freshness,
+        crate::routes::update_layer_quality,
+        crate::routes::delete_layer_quality,
+        crate::routes::get_layer_quality_report,
+        crate::routes::sign_layer_tiles,
+        crate::routes::insert_features,
+        crate::routes::import_url,
+        crate::routes::import_osm,
+        crate::routes::track_changes,
+        crate::routes::get_changes,
+        crate::routes::get_layer_diff,
+        crate::routes::sync_layer,
+        crate::routes::check_topology,
+        crate::routes::create_edit_session,
+        crate::routes::get_edit_session,
+        crate::routes::edit_in_session,
+        crate::routes::undo_edit,
+        crate::routes::redo_edit,
+        crate::routes::commit_edit_session,
+        crate::routes::discard_edit_session,
+        crate::routes::create_branch,
+        crate::routes::get_branches,
+        crate::routes::get_branch_diff,
+        crate::routes::merge_branch,
+        crate::routes::put_layer_schedule,
+        crate::routes::get_layer_schedule,
+        crate::routes::delete_layer_schedule,
+        crate::routes::run_layer_schedule,
+        crate::routes::get_layer_schedule_runs,
+        crate::routes::upload_attachment,
+        crate::routes::get_attachments,
+        crate::routes::download_attachment,
+        crate::routes::attachment_thumbnail,
+        crate::routes::delete_attachment,
+        crate::routes::create_connector,
+        crate::routes::get_connectors,
+        crate::routes::get_connector,
+        crate::routes::delete_connector,
+        crate::routes::harvest_connector,
+        crate::routes::analyze_layer,
+        crate::routes::aggregate_layer,
+        crate::routes::dedupe_layer,
+        crate::routes::aggregate_tiles,
+        crate::routes::spatial_join,
+        crate::routes::get_job,
+        crate::routes::route,
+        crate::routes::isochrone,
+        crate::routes::share_map,
+        crate::routes::share_layer,
+        crate::routes::get_map_shares,
+        crate::routes::get_layer_shares,
+        crate::routes::revoke_share,
+        crate::routes::shared_style,
+        crate::routes::shared_tiles,
+        crate::routes::embed_map,
+        crate::routes::embed_config,
+        crate::routes::search,
+        crate::routes::geocode,
+        crate::routes::reverse_geocode,
+        crate::routes::geocode_layer,
+        crate::routes::get_geocode_failures,
+        crate::routes::get_events,
+        crate::routes::create_backup,
+        crate::routes::restore_backup,
+    ),
+    components(schemas(
+        AggregateRequest,
+        Aggregation,
+        Alert,
+        AnalyzeRequest,
+        AppliedEdit,
+        Attachment,
+        AttachmentPage,
+        Attribute,
+        AttributeStats,
+        AttributeZoom,
+        BackupSummary,
+        Basemap,
+        BatchGeocode,
+        BatchReport,
+        Branch,
+        BranchDiff,
+        BranchRequest,
+        BranchStatus,
+        Catalog,
+        ChangeFeed,
+        ChangeOperation,
+        ChangeSummary,
+        ClientEdit,
+        Comment,
+        CommentAnchor,
+        CommentRequest,
+        CommentThread,
+        CommentThreadPage,
+        ComputedAttribute,
+        Condition,
+        ConditionOp,
+        ConflictStrategy,
+        Connector,
+        ConnectorPage,
+        ConnectorRequest,
+        ConnectorSource,
+        Contour,
+        CopyLayerRequest,
+        Dedupe,
+        DedupeMode,
+        DedupeRequest,
+        EditSession,
+        EditSessionRequest,
+        EditSessionStatus,
+        Event,
+        FeatureChange,
+        FeatureDelta,
+        FieldError,
+        FieldType,
+        Fill,
+        Form,
+        FormField,
+        Freshness,
+        FreshnessRequest,
+        GeocodeFailure,
+        GeocodeFailurePage,
+        GeocodeLayerRequest,
+        GeocodeStatus,
+        Grid,
+        ImportUrlRequest,
+        IsochroneRequest,
+        Job,
+        JobProgress,
+        JobStatus,
+        JoinPredicate,
+        LabelPlacement,
+        LabelStyle,
+        Layer,
+        LayerDiff,
+        LayerPage,
+        LayerSchedule,
+        LayerStyle,
+        Legend,
+        LegendEntry,
+        LegendFormat,
+        LineError,
+        Map,
+        MapLayer,
+        MapPage,
+        MapRequest,
+        MergeConflicts,
+        MergeReport,
+        Operation,
+        OsmImportRequest,
+        OsmLayer,
+        Paint,
+        Place,
+        Profile,
+        QualityReport,
+        QualityRule,
+        QualityRules,
+        Ramp,
+        RampKind,
+        RejectedEdit,
+        RestoreFilter,
+        Route,
+        RouteRequest,
+        RuleResult,
+        RunTrigger,
+        ScanResult,
+        ScheduleRequest,
+        ScheduleRun,
+        ScheduleSource,
+        SchemaChange,
+        SearchResult,
+        SeedPlan,
+        SessionEdit,
+        SessionEditResult,
+        Share,
+        SharePage,
+        ShareRequest,
+        ShareResource,
+        SignedTileUrl,
+        SpatialJoin,
+        SpatialJoinRequest,
+        SqlLayerRequest,
+        SqlView,
+        Statistic,
+        StatisticOp,
+        Stop,
+        Stroke,
+        StyleRule,
+        SwatchShape,
+        SyncConflict,
+        SyncRequest,
+        SyncResponse,
+        TileEncoding,
+        Tiling,
+        ToleranceStop,
+        TopologyCheckRequest,
+        TopologyReport,
+        TopologyRule,
+        TopologyRules,
+        TopologyViolation,
+        ValidationErrors,
+        ValueCount,
+        ValueDelta,
+        Viewport,
+        ZoomRange,
+    )),
+    tags(
+        (name = "health", description = "Liveness, readiness and metrics"),
+        (name = "tiles", description = "Vector tiles from PostGIS sources"),
+        (name = "maps", description = "Saved maps and their styles"),
+        (name = "comments", description = "Review threads pinned to maps and features"),
+        (name = "layers", description = "Layer metadata and catalog"),
+        (name = "attachments", description = "Files attached to features, such as inspection photos"),
+        (name = "connectors", description = "Layers harvested from external WFS and ArcGIS services"),
+        (name = "analysis", description = "Spatial analysis jobs producing new layers"),
+        (name = "routing", description = "Routes and isochrones"),
+        (name = "sharing", description = "Public share links and embeds"),
+        (name = "search", description = "Search across layers, maps and features"),
+        (name = "geocoding", description = "Forward and reverse geocoding"),
+        (name = "events", description = "Live updates over server-sent events"),
+        (name = "admin", description = "Backups of the metadata store, when enabled"),
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use osmpbf::{Element, ElementReader};
+use serde::Deserialize;
+use serde_json::{json, Map as JsonMap, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use utoipa::ToSchema;
+
+/// Selects OSM elements by tag: `key` or `key=*` for any value, `key=value`
+/// for one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl TagFilter {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let (key, value) = match expression.split_once('=') {
+            Some((key, "*")) => (key, None),
+            Some((key, value)) => (key, Some(value.trim().to_string())),
+            None => (expression, None),
+        };
+        let key = key.trim();
+        if key.is_empty() || value.as_deref() == Some("") {
+            return Err(anyhow!("invalid tag expression {expression}"));
+        }
+        Ok(TagFilter {
+            key: key.to_string(),
+            value,
+        })
+    }
+
+    fn matches(&self, tags: &[(String, String)]) -> bool {
+        tags.iter().any(|(key, value)| {
+            *key == self.key && self.value.as_ref().map_or(true, |v| v == value)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct OsmLayer {
+    /// Table name for the layer.
+    pub name: String,
+    /// Tag expression, e.g. `highway=*` or `amenity=school`.
+    pub filter: String,
+}
+
+/// Columns of every extracted layer, besides the filter's own key.
+pub const COLUMNS: [(&str, &str); 4] = [
+    ("osm_id", "bigint"),
+    ("osm_type", "text"),
+    ("name", "text"),
+    ("tags", "jsonb"),
+];
+
+/// Keys of ways that are lines even when closed, unless tagged `area=yes`.
+const LINEAR_KEYS: [&str; 5] = ["highway", "barrier", "railway", "waterway", "power"];
+
+struct MatchedWay {
+    id: i64,
+    refs: Vec<i64>,
+    tags: Vec<(String, String)>,
+    layers: Vec<usize>,
+}
+
+fn owned_tags<'a>(tags: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<(String, String)> {
+    tags.map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn matching_layers(filters: &[TagFilter], tags: &[(String, String)]) -> Vec<usize> {
+    filters
+        .iter()
+        .enumerate()
+        .filter(|(_, filter)| filter.matches(tags))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn feature(
+    osm_type: &str,
+    id: i64,
+    tags: &[(String, String)],
+    filter: &TagFilter,
+    geometry: Value,
+) -> Value {
+    let all: JsonMap<String, Value> = tags
+        .iter()
+        .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+        .collect();
+    let mut properties = JsonMap::new();
+    properties.insert(filter.key.clone(), all.get(&filter.key).cloned().into());
+    properties.insert("osm_id".to_string(), json!(id));
+    properties.insert("osm_type".to_string(), json!(osm_type));
+    properties.insert("name".to_string(), all.get("name").cloned().into());
+    properties.insert("tags".to_string(), Value::Object(all));
+    json!({ "type": "Feature", "properties": properties, "geometry": geometry })
+}
+
+fn is_area(tags: &[(String, String)]) -> bool {
+    let tag = |key: &str| tags.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    match tag("area") {
+        Some("yes") => true,
+        Some("no") => false,
+        _ => {
+            !tags.iter().any(|(k, _)| LINEAR_KEYS.contains(&k.as_str()))
+                && tag("natural") != Some("coastline")
+        }
+    }
+}
+
+/// WGS84 GeoJSON features per filter, from an `.osm.pbf` extract. Nodes
+/// become points and ways lines, or polygons when closed and not linear.
+/// Relations are skipped. Reads the extract twice, so only the coordinates
+/// of nodes on matching ways are kept in memory.
+pub fn extract(data: &[u8], filters: &[TagFilter]) -> Result<Vec<Vec<Value>>> {
+    let mut layers: Vec<Vec<Value>> = vec![Vec::new(); filters.len()];
+    let mut ways: Vec<MatchedWay> = Vec::new();
+    let mut point = |id: i64, lon: f64, lat: f64, tags: Vec<(String, String)>| {
+        for index in matching_layers(filters, &tags) {
+            let geometry = json!({ "type": "Point", "coordinates": [lon, lat] });
+            layers[index].push(feature("node", id, &tags, &filters[index], geometry));
+        }
+    };
+    ElementReader::new(Cursor::new(data)).for_each(|element| match element {
+        Element::Node(node) => point(node.id(), node.lon(), node.lat(), owned_tags(node.tags())),
+        Element::DenseNode(node) => {
+            point(node.id(), node.lon(), node.lat(), owned_tags(node.tags()))
+        }
+        Element::Way(way) => {
+            let tags = owned_tags(way.tags());
+            let matched = matching_layers(filters, &tags);
+            if !matched.is_empty() {
+                ways.push(MatchedWay {
+                    id: way.id(),
+                    refs: way.refs().collect(),
+                    tags,
+                    layers: matched,
+                });
+            }
+        }
+        Element::Relation(_) => {}
+    })?;
+
+    let needed: HashSet<i64> = ways
+        .iter()
+        .flat_map(|way| way.refs.iter().copied())
+        .collect();
+    let mut coordinates: HashMap<i64, [f64; 2]> = HashMap::new();
+    ElementReader::new(Cursor::new(data)).for_each(|element| {
+        let (id, lon, lat) = match element {
+            Element::Node(node) => (node.id(), node.lon(), node.lat()),
+            Element::DenseNode(node) => (node.id(), node.lon(), node.lat()),
+            _ => return,
+        };
+        if needed.contains(&id) {
+            coordinates.insert(id, [lon, lat]);
+        }
+    })?;
+
+    for way in ways {
+        // Extracts clipped to a boundary can miss nodes of ways crossing it
+        let Some(line) = way
+            .refs
+            .iter()
+            .map(|id| coordinates.get(id).copied())
+            .collect::<Option<Vec<[f64; 2]>>>()
+        else {
+            continue;
+        };
+        if line.len() < 2 {
+            continue;
+        }
+        let closed = line.len() >= 4 && line.first() == line.last();
+        let geometry = if closed && is_area(&way.tags) {
+            json!({ "type": "Polygon", "coordinates": [line] })
+        } else {
+            json!({ "type": "LineString", "coordinates": line })
+        };
+        for index in way.layers {
+            layers[index].push(feature(
+                "way",
+                way.id,
+                &way.tags,
+                &filters[index],
+                geometry.clone(),
+            ));
+        }
+    }
+    Ok(layers)
+}
@@ -0,0 +1,862 @@
+use crate::config::LimitsConfig;
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use tracing::instrument;
+
+/// The PostGIS table backing a tile source.
+#[derive(Debug, Clone)]
+pub struct LayerTable {
+    pub schema: String,
+    pub table: String,
+    pub geometry_column: String,
+    pub srid: i32,
+    /// A view, such as a SQL layer's, rather than a table.
+    pub is_view: bool,
+}
+
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl LayerTable {
+    /// Look up the table for a source id. Auto-published sources are named
+    /// after their table, optionally qualified with the schema.
+    #[instrument(skip(pool))]
+    pub async fn from_source_id(pool: &Pool, source_id: &str) -> Result<Self> {
+        let (schema, table) = match source_id.split_once('.') {
+            Some((schema, table)) => (Some(schema), table),
+            None => (None, source_id),
+        };
+        let client = pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT f_table_schema::text, f_table_name::text, f_geometry_column::text, srid,
+                        EXISTS (SELECT 1 FROM information_schema.views v
+                                WHERE v.table_schema = f_table_schema
+                                  AND v.table_name = f_table_name)
+                 FROM geometry_columns
+                 WHERE f_table_name = $1 AND ($2::text IS NULL OR f_table_schema = $2)
+                 ORDER BY f_table_schema = 'public' DESC
+                 LIMIT 1",
+                &[&table, &schema],
+            )
+            .await?
+            .ok_or_else(|| anyhow!("no geometry table for source {source_id}"))?;
+        Ok(LayerTable {
+            schema: row.get(0),
+            table: row.get(1),
+            geometry_column: row.get(2),
+            srid: row.get(3),
+            is_view: row.get(4),
+        })
+    }
+
+    /// Non-geometry columns, in table order.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn attribute_columns(&self, pool: &Pool) -> Result<Vec<String>> {
+        let client = pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT column_name::text FROM information_schema.columns
+                 WHERE table_schema = $1 AND table_name = $2 AND column_name <> $3
+                 ORDER BY ordinal_position",
+                &[&self.schema, &self.table, &self.geometry_column],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Columns holding text, used for attribute search.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn text_columns(&self, pool: &Pool) -> Result<Vec<String>> {
+        let client = pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT column_name::text FROM information_schema.columns
+                 WHERE table_schema = $1 AND table_name = $2
+                   AND data_type IN ('text', 'character varying', 'character')
+                 ORDER BY ordinal_position",
+                &[&self.schema, &self.table],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Columns holding numbers that may have fractions, which tiles can
+    /// round.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn fractional_columns(&self, pool: &Pool) -> Result<Vec<String>> {
+        let client = pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT column_name::text FROM information_schema.columns
+                 WHERE table_schema = $1 AND table_name = $2
+                   AND data_type IN ('real', 'double precision', 'numeric')
+                 ORDER BY ordinal_position",
+                &[&self.schema, &self.table],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Features whose text attributes contain `term`, case-insensitively.
+    /// Yields the attributes and a WGS84 `[lon, lat]` label point for each.
+    #[instrument(skip(self, pool), fields(table = %self.table))]
+    pub async fn search_features(
+        &self,
+        pool: &Pool,
+        term: &str,
+        limit: i64,
+    ) -> Result<Vec<(Value, [f64; 2])>> {
+        let columns = self.text_columns(pool).await?;
+        if columns.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conditions: Vec<String> = columns
+            .iter()
+            .map(|column| format!("t.{} ILIKE $1", quote_ident(column)))
+            .collect();
+        let point = transform_sql(
+            &format!(
+                "ST_PointOnSurface(t.{})",
+                quote_ident(&self.geometry_column)
+            ),
+            self.srid,
+            4326,
+        );
+        let sql = format!(
+            "SELECT to_jsonb(t) - $2::text, ST_X(p), ST_Y(p)
+             FROM {} t, LATERAL (SELECT {point} AS p) label
+             WHERE {}
+             LIMIT $3",
+            self.qualified_name(),
+            conditions.join(" OR "),
+        );
+        let escaped = term
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{escaped}%");
+        let client = pool.get().await?;
+        let rows = client
+            .query(&sql, &[&pattern, &self.geometry_column, &limit])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), [row.get(1), row.get(2)]))
+            .collect())
+    }
+
+    /// What the database finds wrong with `expression` over the table's
+    /// columns, if anything. The expression is planned, not run.
+    #[instrument(skip(self, pool), fields(table = %self.table))]
+    pub async fn expression_error(&self, pool: &Pool, expression: &str) -> Result<Option<String>> {
+        let client = pool.get().await?;
+        let sql = format!(
+            "SELECT ({expression}) FROM {} t LIMIT 0",
+            self.qualified_name()
+        );
+        match client.prepare(&sql).await {
+            Ok(_) => Ok(None),
+            Err(e) => match e.as_db_error() {
+                Some(db) => Ok(Some(db.message().to_string())),
+                None => Err(e.into()),
+            },
+        }
+    }
+
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", quote_ident(&self.schema), quote_ident(&self.table))
+    }
+
+    /// SQL for a WGS84 point (`$1` lon, `$2` lat) in the table's SRID.
+    fn point_param_sql(&self) -> String {
+        let point = "ST_SetSRID(ST_MakePoint($1, $2), 4326)";
+        match self.srid {
+            0 | 4326 => point.to_string(),
+            srid => format!("ST_Transform({point}, {srid})"),
+        }
+    }
+
+    /// Target columns and the matching expressions filling them from a
+    /// WGS84 GeoJSON feature `f`, whose properties are populated into `r`.
+    fn feature_assignments(&self, columns: &[String]) -> (Vec<String>, Vec<String>) {
+        let mut targets: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+        targets.push(quote_ident(&self.geometry_column));
+        let mut values: Vec<String> = columns
+            .iter()
+            .map(|c| format!("r.{}", quote_ident(c)))
+            .collect();
+        values.push(transform_sql(
+            "ST_SetSRID(ST_GeomFromGeoJSON((f->'geometry')::text), 4326)",
+            4326,
+            self.srid,
+        ));
+        (targets, values)
+    }
+
+    /// SQL matching `t.{key}` against the JSON value in `$param`, cast to the
+    /// key column's type.
+    pub(crate) fn key_match_sql(&self, key: &str, param: usize) -> String {
+        format!(
+            "t.{key} = (jsonb_populate_record(NULL::{}, jsonb_build_object({}, ${param}::jsonb))).{key}",
+            self.qualified_name(),
+            quote_literal(key),
+            key = quote_ident(key),
+        )
+    }
+
+    /// Insert GeoJSON features given in WGS84, as a JSON array, in one
+    /// statement. `columns` are filled from each feature's properties, with
+    /// Postgres casting values to the column types; missing properties are
+    /// left NULL.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn insert_features(
+        &self,
+        pool: &Pool,
+        columns: &[String],
+        features: &Value,
+    ) -> Result<u64> {
+        let client = pool.get().await?;
+        let sql = self.insert_features_sql(columns);
+        Ok(client.execute(&sql, &[features]).await?)
+    }
+
+    /// SQL inserting the array of WGS84 GeoJSON features in `$1`.
+    pub(crate) fn insert_features_sql(&self, columns: &[String]) -> String {
+        let (targets, values) = self.feature_assignments(columns);
+        format!(
+            "INSERT INTO {table} ({})
+             SELECT {}
+             FROM jsonb_array_elements($1::jsonb) f,
+                  LATERAL jsonb_populate_record(NULL::{table}, f->'properties') r",
+            targets.join(", "),
+            values.join(", "),
+            table = self.qualified_name(),
+        )
+    }
+
+    /// Rows in the table.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn feature_count(&self, pool: &Pool) -> Result<u64> {
+        let client = pool.get().await?;
+        let row = client
+            .query_one(
+                &format!("SELECT count(*) FROM {}", self.qualified_name()),
+                &[],
+            )
+            .await?;
+        Ok(row.get::<_, i64>(0) as u64)
+    }
+
+    /// Features that may still be added under `max_features`, or `None`
+    /// when there is no limit.
+    pub async fn room(&self, pool: &Pool, limits: &LimitsConfig) -> Result<Option<u64>> {
+        if limits.max_features == 0 {
+            return Ok(None);
+        }
+        let count = self.feature_count(pool).await?;
+        Ok(Some(limits.max_features.saturating_sub(count)))
+    }
+
+    /// The single-column primary key, if the table has one. Individual
+    /// features are addressed by it.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn primary_key(&self, pool: &Pool) -> Result<Option<String>> {
+        let client = pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT a.attname::text FROM pg_index i
+                 JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+                 WHERE i.indrelid = $1::text::regclass AND i.indisprimary",
+                &[&self.qualified_name()],
+            )
+            .await?;
+        Ok(match rows.as_slice() {
+            [row] => Some(row.get(0)),
+            _ => None,
+        })
+    }
+
+    /// Insert one feature, returning its primary key.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn insert_feature(
+        &self,
+        pool: &Pool,
+        key: &str,
+        columns: &[String],
+        feature: &Value,
+    ) -> Result<Value> {
+        let client = pool.get().await?;
+        let row = client
+            .query_one(&self.insert_feature_sql(key, columns), &[feature])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// SQL inserting the WGS84 GeoJSON feature in `$1`, returning its key.
+    pub(crate) fn insert_feature_sql(&self, key: &str, columns: &[String]) -> String {
+        let (targets, values) = self.feature_assignments(columns);
+        format!(
+            "INSERT INTO {table} ({})
+             SELECT {}
+             FROM (SELECT $1::jsonb AS f) s,
+                  LATERAL jsonb_populate_record(NULL::{table}, f->'properties') r
+             RETURNING to_jsonb({})",
+            targets.join(", "),
+            values.join(", "),
+            quote_ident(key),
+            table = self.qualified_name(),
+        )
+    }
+
+    /// Overwrite `columns` and the geometry of the feature with primary key
+    /// `id`. Returns whether it existed.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn update_feature(
+        &self,
+        pool: &Pool,
+        key: &str,
+        id: &Value,
+        columns: &[String],
+        feature: &Value,
+    ) -> Result<bool> {
+        let client = pool.get().await?;
+        let sql = self.update_feature_sql(key, columns);
+        Ok(client.execute(&sql, &[feature, id]).await? > 0)
+    }
+
+    /// SQL overwriting the feature keyed by `$2` with the WGS84 GeoJSON
+    /// feature in `$1`.
+    pub(crate) fn update_feature_sql(&self, key: &str, columns: &[String]) -> String {
+        let (targets, values) = self.feature_assignments(columns);
+        let assignments: Vec<String> = targets
+            .iter()
+            .zip(&values)
+            .map(|(target, value)| format!("{target} = {value}"))
+            .collect();
+        format!(
+            "UPDATE {table} t SET {}
+             FROM (SELECT $1::jsonb AS f) s,
+                  LATERAL jsonb_populate_record(NULL::{table}, f->'properties') r
+             WHERE {}",
+            assignments.join(", "),
+            self.key_match_sql(key, 2),
+            table = self.qualified_name(),
+        )
+    }
+
+    /// Delete the feature with primary key `id`. Returns whether it existed.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn delete_feature(&self, pool: &Pool, key: &str, id: &Value) -> Result<bool> {
+        let client = pool.get().await?;
+        Ok(client.execute(&self.delete_feature_sql(key), &[id]).await? > 0)
+    }
+
+    /// SQL deleting the feature keyed by `$1`.
+    pub(crate) fn delete_feature_sql(&self, key: &str) -> String {
+        format!(
+            "DELETE FROM {} t WHERE {}",
+            self.qualified_name(),
+            self.key_match_sql(key, 1),
+        )
+    }
+
+    /// The feature with primary key `id` as WGS84 GeoJSON.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn feature(&self, pool: &Pool, key: &str, id: &Value) -> Result<Option<Value>> {
+        let geometry = transform_sql(
+            &format!("t.{}", quote_ident(&self.geometry_column)),
+            self.srid,
+            4326,
+        );
+        let sql = format!(
+            "SELECT jsonb_build_object(
+                 'type', 'Feature',
+                 'properties', to_jsonb(t) - $2::text,
+                 'geometry', ST_AsGeoJSON({geometry})::jsonb
+             )
+             FROM {} t WHERE {}",
+            self.qualified_name(),
+            self.key_match_sql(key, 1),
+        );
+        let client = pool.get().await?;
+        let row = client.query_opt(&sql, &[id, &self.geometry_column]).await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// The features whose primary key is one of `ids`, as WGS84 GeoJSON.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn features(&self, pool: &Pool, key: &str, ids: &[Value]) -> Result<Vec<Value>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let geometry = transform_sql(
+            &format!("t.{}", quote_ident(&self.geometry_column)),
+            self.srid,
+            4326,
+        );
+        let sql = format!(
+            "SELECT jsonb_build_object(
+                 'type', 'Feature',
+                 'properties', to_jsonb(t) - $2::text,
+                 'geometry', ST_AsGeoJSON({geometry})::jsonb
+             )
+             FROM {} t WHERE to_jsonb(t.{}) = ANY($1)",
+            self.qualified_name(),
+            quote_ident(key),
+        );
+        let client = pool.get().await?;
+        let rows = client.query(&sql, &[&ids, &self.geometry_column]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Attributes of features containing a WGS84 point.
+    #[instrument(skip_all, fields(table = %self.table))]
+    pub async fn features_at(&self, pool: &Pool, lon: f64, lat: f64) -> Result<Vec<Value>> {
+        let sql = format!(
+            "SELECT to_jsonb(t) - $3::text FROM {} t WHERE ST_Intersects(t.{}, {}) LIMIT 10",
+            self.qualified_name(),
+            quote_ident(&self.geometry_column),
+            self.point_param_sql(),
+        );
+        let client = pool.get().await?;
+        let rows = client
+            .query(&sql, &[&lon, &lat, &self.geometry_column])
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+/// SQL reprojecting `expr` between SRIDs, leaving it alone when either is unknown.
+pub fn transform_sql(expr: &str, from_srid: i32, to_srid: i32) -> String {
+    if from_srid == to_srid || from_srid == 0 || to_srid == 0 {
+        expr.to_string()
+    } else {
+        format!("ST_Transform({expr}, {to_srid})")
+    }
+}
+
+/// Accept lower-case Postgres identifiers that need no quoting.
+pub fn valid_table_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && name.len() <= 63
+}
+
+/// Whether the public schema holds a relation of any kind named `name`,
+/// including tables that are no tile source, e.g. without geometry.
+pub async fn relation_exists(pool: &Pool, name: &str) -> Result<bool> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "SELECT EXISTS (
+                 SELECT 1 FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE n.nspname = 'public' AND c.relname = $1
+             )",
+            &[&name],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Positions in a GeoJSON geometry, counting ring closures.
+fn vertex_count(geometry: &Value) -> usize {
+    fn positions(coordinates: &Value) -> usize {
+        match coordinates.as_array() {
+            Some(items) if items.first().is_some_and(Value::is_number) => 1,
+            Some(items) => items.iter().map(positions).sum(),
+            None => 0,
+        }
+    }
+    match geometry.get("geometries").and_then(Value::as_array) {
+        Some(geometries) => geometries.iter().map(vertex_count).sum(),
+        None => geometry.get("coordinates").map_or(0, positions),
+    }
+}
+
+/// Check a GeoJSON feature's vertex and property counts against the limits.
+pub fn check_complexity(feature: &Value, limits: &LimitsConfig) -> Result<(), String> {
+    let attributes = feature
+        .get("properties")
+        .and_then(Value::as_object)
+        .map_or(0, |properties| properties.len());
+    if limits.max_attributes > 0 && attributes > limits.max_attributes {
+        return Err(format!(
+            "Feature has {attributes} properties; the limit is {}",
+            limits.max_attributes
+        ));
+    }
+    let vertices = feature.get("geometry").map_or(0, vertex_count);
+    if limits.max_vertices > 0 && vertices > limits.max_vertices {
+        return Err(format!(
+            "Feature has {vertices} vertices; the limit is {}",
+            limits.max_vertices
+        ));
+    }
+    Ok(())
+}
+
+/// Check a WGS84 GeoJSON feature before writing it to a table with the given
+/// attribute columns.
+pub fn validate_feature(
+    feature: &Value,
+    columns: &HashSet<String>,
+    limits: &LimitsConfig,
+) -> Result<(), String> {
+    if feature.get("type").and_then(Value::as_str) != Some("Feature") {
+        return Err("Not a GeoJSON Feature".to_string());
+    }
+    if !feature.get("geometry").is_some_and(Value::is_object) {
+        return Err("Feature has no geometry".to_string());
+    }
+    check_complexity(feature, limits)?;
+    match feature.get("properties") {
+        None | Some(Value::Null) => Ok(()),
+        Some(Value::Object(properties)) => {
+            let unknown: Vec<&str> = properties
+                .keys()
+                .filter(|key| !columns.contains(*key))
+                .map(String::as_str)
+                .collect();
+            if unknown.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("Unknown properties: {}", unknown.join(", ")))
+            }
+        }
+        Some(_) => Err("Feature properties must be an object".to_string()),
+    }
+}
+
+/// Property names used across features, sorted, for the column list of a
+/// write.
+pub fn feature_columns<'a>(features: impl IntoIterator<Item = &'a Value>) -> Vec<String> {
+    features
+        .into_iter()
+        .filter_map(|feature| feature.get("properties")?.as_object())
+        .flat_map(|properties| properties.keys().cloned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Materialise `select_sql` as a new table in the public schema. The select
+/// must produce its geometry as a `geom` column in `srid`.
+#[instrument(skip(pool, select_sql))]
+pub async fn create_derived_table(
+    pool: &Pool,
+    name: &str,
+    select_sql: &str,
+    srid: i32,
+) -> Result<()> {
+    if !valid_table_name(name) {
+        return Err(anyhow!("invalid table name {name}"));
+    }
+    let table = format!("public.{}", quote_ident(name));
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    transaction
+        .batch_execute(&format!("CREATE TABLE {table} AS {select_sql}"))
+        .await?;
+    if srid != 0 {
+        // Constrain the column so the SRID is discoverable by the tile server
+        transaction
+            .batch_execute(&format!(
+                "ALTER TABLE {table} ALTER COLUMN geom TYPE geometry(Geometry, {srid})
+                 USING ST_SetSRID(geom, {srid})"
+            ))
+            .await?;
+    }
+    transaction
+        .batch_execute(&format!("CREATE INDEX ON {table} USING gist (geom)"))
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Create an empty WGS84 table in the public schema with the given column
+/// names and types, ready for `LayerTable::insert_features`.
+#[instrument(skip(pool, columns))]
+pub async fn create_feature_table(
+    pool: &Pool,
+    name: &str,
+    columns: &[(String, &str)],
+) -> Result<LayerTable> {
+    if !valid_table_name(name) {
+        return Err(anyhow!("invalid table name {name}"));
+    }
+    let definitions: String = columns
+        .iter()
+        .map(|(column, column_type)| format!("{} {column_type}, ", quote_ident(column)))
+        .collect();
+    let table = format!("public.{}", quote_ident(name));
+    let client = pool.get().await?;
+    client
+        .batch_execute(&format!(
+            "CREATE TABLE {table} ({definitions}geom geometry(Geometry, 4326));
+             CREATE INDEX ON {table} USING gist (geom);"
+        ))
+        .await?;
+    Ok(LayerTable {
+        schema: "public".to_string(),
+        table: name.to_string(),
+        geometry_column: "geom".to_string(),
+        srid: 4326,
+        is_view: false,
+    })
+}
+
+/// Why a statement built from a request failed.
+#[derive(Debug)]
+pub enum StatementError {
+    /// The request is at fault, e.g. its query does not parse or values
+    /// don't cast to a new column type.
+    Invalid(String),
+    Failed(anyhow::Error),
+}
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementError::Invalid(message) => write!(f, "invalid: {message}"),
+            StatementError::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for StatementError {}
+
+impl From<tokio_postgres::Error> for StatementError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        match e.as_db_error() {
+            Some(db) => StatementError::Invalid(db.message().to_string()),
+            None => StatementError::Failed(e.into()),
+        }
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for StatementError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        StatementError::Failed(e.into())
+    }
+}
+
+/// Milliseconds a new view's query may take to plan and start while it is
+/// checked.
+const VIEW_CHECK_TIMEOUT: u32 = 10_000;
+
+/// Functions a layer's SQL must not call. `set_config` changes session
+/// settings, which outlive the query on pooled connections and could turn
+/// off their read-only sandbox; the rest reach outside the query.
+pub const FORBIDDEN_FUNCTIONS: [&str; 12] = [
+    "set_config",
+    "pg_sleep",
+    "pg_read_file",
+    "pg_read_binary_file",
+    "pg_ls_dir",
+    "pg_reload_conf",
+    "pg_cancel_backend",
+    "pg_terminate_backend",
+    "lo_import",
+    "lo_export",
+    "dblink",
+    "dblink_exec",
+];
+
+/// Publish `select_sql` as a view in the public schema, owned by `owner`
+/// when given. The query is first run in a read-only transaction on
+/// `read_pool`, as `owner`, so it must read nothing that role can't and
+/// write nothing. It must be one statement, and have a geometry column with
+/// an SRID, which the tile server needs.
+#[instrument(skip(pool, read_pool, select_sql))]
+pub async fn create_view(
+    pool: &Pool,
+    read_pool: &Pool,
+    name: &str,
+    select_sql: &str,
+    owner: Option<&str>,
+) -> Result<(), StatementError> {
+    if !valid_table_name(name) {
+        return Err(StatementError::Invalid(format!("invalid view name {name}")));
+    }
+    {
+        let mut client = read_pool.get().await?;
+        // Rolled back when dropped, along with any settings the query made
+        let transaction = client.build_transaction().read_only(true).start().await?;
+        transaction
+            .batch_execute(&format!(
+                "SET LOCAL statement_timeout = {VIEW_CHECK_TIMEOUT}"
+            ))
+            .await?;
+        if let Some(owner) = owner {
+            transaction
+                .batch_execute(&format!("SET LOCAL ROLE {}", quote_ident(owner)))
+                .await?;
+        }
+        // As a subquery, a second statement after the SELECT is an error
+        transaction
+            .query(&format!("SELECT * FROM ({select_sql}) q LIMIT 0"), &[])
+            .await?;
+    }
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let view = format!("public.{}", quote_ident(name));
+    transaction
+        .execute(&format!("CREATE VIEW {view} AS {select_sql}"), &[])
+        .await?;
+    if let Some(owner) = owner {
+        transaction
+            .batch_execute(&format!(
+                "ALTER VIEW {view} OWNER TO {}",
+                quote_ident(owner)
+            ))
+            .await?;
+    }
+    // Functions the view calls, however they are spelled in the query
+    let forbidden: Vec<String> = transaction
+        .query(
+            "SELECT DISTINCT p.proname::text
+             FROM pg_rewrite r
+             JOIN pg_depend d ON d.classid = 'pg_rewrite'::regclass AND d.objid = r.oid
+             JOIN pg_proc p ON d.refclassid = 'pg_proc'::regclass AND d.refobjid = p.oid
+             WHERE r.ev_class = $1::text::regclass AND p.proname = ANY($2)",
+            &[&view, &FORBIDDEN_FUNCTIONS.as_slice()],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if !forbidden.is_empty() {
+        return Err(StatementError::Invalid(format!(
+            "the query must not call {}",
+            forbidden.join(", ")
+        )));
+    }
+    let srids: Vec<i32> = transaction
+        .query(
+            "SELECT srid FROM geometry_columns
+             WHERE f_table_schema = 'public' AND f_table_name = $1",
+            &[&name],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    match srids.as_slice() {
+        [] => Err(StatementError::Invalid(
+            "the query has no geometry column".to_string(),
+        )),
+        [0, ..] => Err(StatementError::Invalid(
+            "cast the geometry to a type with an SRID, e.g. geom::geometry(Geometry, 4326)"
+                .to_string(),
+        )),
+        _ => {
+            transaction.commit().await?;
+            Ok(())
+        }
+    }
+}
+
+/// Copy the features of `source` into a new table in the public schema. A
+/// single-column primary key is kept, but not its default, so inserts into
+/// the copy must supply keys.
+#[instrument(skip(pool))]
+pub async fn copy_table(pool: &Pool, source: &LayerTable, name: &str) -> Result<()> {
+    let columns = source.attribute_columns(pool).await?;
+    let attributes: String = columns
+        .iter()
+        .map(|column| format!("t.{}, ", quote_ident(column)))
+        .collect();
+    let select_sql = format!(
+        "SELECT {attributes}t.{} AS geom FROM {} t",
+        quote_ident(&source.geometry_column),
+        source.qualified_name()
+    );
+    create_derived_table(pool, name, &select_sql, source.srid).await?;
+    if let Some(key) = source.primary_key(pool).await? {
+        let client = pool.get().await?;
+        client
+            .batch_execute(&format!(
+                "ALTER TABLE public.{} ADD PRIMARY KEY ({})",
+                quote_ident(name),
+                quote_ident(&key)
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, PostgresConfig};
+
+    /// Pools connect lazily, so these never reach a database.
+    fn unreachable_pool() -> Pool {
+        config::initialize_pg_pool("postgres://gridwalk@127.0.0.1:1/gridwalk").unwrap()
+    }
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(quote_ident("parks"), "\"parks\"");
+        assert_eq!(
+            quote_ident("a\"; DROP TABLE b; --"),
+            "\"a\"\"; DROP TABLE b; --\""
+        );
+        assert_eq!(quote_literal("it's"), "'it''s'");
+    }
+
+    #[test]
+    fn table_names_need_no_quoting() {
+        assert!(valid_table_name("parks_2024"));
+        assert!(valid_table_name("_staging"));
+        assert!(!valid_table_name("Parks"));
+        assert!(!valid_table_name("2024_parks"));
+        assert!(!valid_table_name("parks; drop"));
+        assert!(!valid_table_name(&"a".repeat(64)));
+    }
+
+    #[test]
+    fn sandboxed_connections_are_read_only_with_a_timeout() {
+        let postgres = PostgresConfig {
+            statement_timeout: 5000,
+            work_mem: String::new(),
+        };
+        let url = postgres.sandboxed_url("postgres://gridwalk@db/gridwalk");
+        assert_eq!(
+            url,
+            "postgres://gridwalk@db/gridwalk?options=-c%20default_transaction_read_only%3Don%20-c%20statement_timeout%3D5000"
+        );
+        let dsn = postgres.sandboxed_url("host=db dbname=gridwalk");
+        assert_eq!(
+            dsn,
+            "host=db dbname=gridwalk options='-c default_transaction_read_only=on -c statement_timeout=5000'"
+        );
+    }
+
+    #[tokio::test]
+    async fn views_need_a_valid_name() {
+        let pool = unreachable_pool();
+        let result = create_view(&pool, &pool, "Parks View", "SELECT 1", None).await;
+        assert!(matches!(result, Err(StatementError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn views_are_not_created_when_the_read_only_check_cannot_run() {
+        let read_pool = unreachable_pool();
+        let pool = unreachable_pool();
+        let result = create_view(&pool, &read_pool, "parks", "SELECT 1", None).await;
+        assert!(matches!(result, Err(StatementError::Failed(_))));
+        assert_eq!(pool.status().size, 0);
+    }
+}
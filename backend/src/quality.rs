@@ -0,0 +1,400 @@
+use crate::core::Attribute;
+use crate::postgis::{quote_ident, LayerTable};
+use crate::validation::{ValidationErrors, Validator};
+use anyhow::Result;
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use metrics::counter;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value};
+use tokio_postgres::types::ToSql;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+const MAX_RULES: usize = 100;
+const MAX_PATTERN_LENGTH: usize = 1000;
+/// Keys of failing features listed per rule in a report.
+const SAMPLE_SIZE: i64 = 20;
+
+const GEOMETRY_TYPES: [&str; 7] = [
+    "Point",
+    "MultiPoint",
+    "LineString",
+    "MultiLineString",
+    "Polygon",
+    "MultiPolygon",
+    "GeometryCollection",
+];
+
+const NUMERIC_TYPES: [&str; 6] = [
+    "smallint",
+    "integer",
+    "bigint",
+    "numeric",
+    "real",
+    "double precision",
+];
+
+/// A condition every feature of a layer should meet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QualityRule {
+    /// The attribute must have a value.
+    NotNull { attribute: String },
+    /// The numeric attribute must lie within `min` and `max`, inclusive.
+    /// Empty values pass; pair with `not_null` to require one.
+    Range {
+        attribute: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    /// The attribute, as text, must contain a match of the regular
+    /// expression. Anchor it with `^` and `$` to match the whole value.
+    /// Stick to syntax shared by Rust and PostgreSQL regular expressions:
+    /// classes, anchors, groups, alternation and quantifiers.
+    Pattern { attribute: String, pattern: String },
+    /// Geometries must be of one of these GeoJSON types.
+    GeometryType { types: Vec<String> },
+    /// Geometries must be stored in this coordinate reference system.
+    Crs { srid: i32 },
+}
+
+impl QualityRule {
+    fn attribute(&self) -> Option<&str> {
+        match self {
+            QualityRule::NotNull { attribute }
+            | QualityRule::Range { attribute, .. }
+            | QualityRule::Pattern { attribute, .. } => Some(attribute),
+            QualityRule::GeometryType { .. } | QualityRule::Crs { .. } => None,
+        }
+    }
+}
+
+/// Rules a layer's features are checked against as they are imported and
+/// edited, and that its quality report measures the whole layer by.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QualityRules {
+    pub rules: Vec<QualityRule>,
+    /// Reject features that break a rule. Otherwise they are written, and
+    /// counted in the quality report.
+    #[serde(default)]
+    pub block_on_failure: bool,
+}
+
+impl QualityRules {
+    /// Reject rules that do not fit the layer's attributes.
+    pub fn validate(&self, attributes: &[Attribute]) -> Result<(), ValidationErrors> {
+        let mut v = Validator::default();
+        v.count("rules", self.rules.len(), 1, MAX_RULES);
+        for (index, rule) in self.rules.iter().enumerate() {
+            v.within(&format!("rules[{index}]"), |v| {
+                let attribute = rule
+                    .attribute()
+                    .map(|name| (name, attributes.iter().find(|a| a.name == name)));
+                if let Some((name, found)) = attribute {
+                    v.check(
+                        "attribute",
+                        found.is_some(),
+                        "unknown_attribute",
+                        format!("{name} is not an attribute of the layer"),
+                    );
+                }
+                match rule {
+                    QualityRule::NotNull { .. } => {}
+                    QualityRule::Range { min, max, .. } => {
+                        if let Some((_, Some(attribute))) = attribute {
+                            v.check(
+                                "attribute",
+                                NUMERIC_TYPES.contains(&attribute.data_type.as_str()),
+                                "not_numeric",
+                                format!("{} is {}", attribute.name, attribute.data_type),
+                            );
+                        }
+                        v.check(
+                            "min",
+                            min.is_some() || max.is_some(),
+                            "required",
+                            "set min, max or both",
+                        );
+                        v.check(
+                            "max",
+                            !matches!((min, max), (Some(min), Some(max)) if min > max),
+                            "out_of_range",
+                            "must not be below min",
+                        );
+                    }
+                    QualityRule::Pattern { pattern, .. } => {
+                        v.length("pattern", pattern, 1, MAX_PATTERN_LENGTH);
+                        if let Err(e) = Regex::new(pattern) {
+                            v.error("pattern", "invalid_pattern", e.to_string());
+                        }
+                    }
+                    QualityRule::GeometryType { types } => {
+                        v.count("types", types.len(), 1, GEOMETRY_TYPES.len());
+                        for geometry_type in types {
+                            v.check(
+                                "types",
+                                GEOMETRY_TYPES.contains(&geometry_type.as_str()),
+                                "unknown_type",
+                                format!("{geometry_type} is not a GeoJSON geometry type"),
+                            );
+                        }
+                    }
+                    QualityRule::Crs { srid } => {
+                        v.range("srid", *srid, 1, 999_999);
+                    }
+                }
+            });
+        }
+        v.finish()
+    }
+
+    /// The rules with their patterns compiled, for checking many features.
+    pub fn checker(&self, srid: i32) -> QualityChecker {
+        QualityChecker {
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| {
+                    let pattern = match rule {
+                        QualityRule::Pattern { pattern, .. } => Regex::new(pattern).ok(),
+                        _ => None,
+                    };
+                    (rule.clone(), pattern)
+                })
+                .collect(),
+            block_on_failure: self.block_on_failure,
+            srid,
+        }
+    }
+}
+
+/// Checks submitted features against a layer's rules.
+pub struct QualityChecker {
+    rules: Vec<(QualityRule, Option<Regex>)>,
+    block_on_failure: bool,
+    /// Of the layer's table, which features are transformed into.
+    srid: i32,
+}
+
+impl QualityChecker {
+    /// Check a submitted GeoJSON feature. Partial submissions, such as
+    /// updates, only check the attributes they include. Breaking a rule is
+    /// an error when the rules block failures; otherwise returns whether
+    /// the feature broke one.
+    pub fn enforce(&self, feature: &Value, partial: bool) -> Result<bool, String> {
+        let failures = self.failures(feature, partial);
+        if failures.is_empty() {
+            return Ok(false);
+        }
+        let outcome = if self.block_on_failure {
+            "blocked"
+        } else {
+            "flagged"
+        };
+        counter!("quality_failures_total", "outcome" => outcome).increment(1);
+        if self.block_on_failure {
+            Err(failures.join("; "))
+        } else {
+            Ok(true)
+        }
+    }
+
+    fn failures(&self, feature: &Value, partial: bool) -> Vec<String> {
+        let empty = JsonMap::new();
+        let properties = feature
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let mut failures = Vec::new();
+        for (rule, regex) in &self.rules {
+            match rule {
+                QualityRule::NotNull { attribute } => {
+                    let missing = match properties.get(attribute) {
+                        None => !partial,
+                        Some(value) => value.is_null(),
+                    };
+                    if missing {
+                        failures.push(format!("{attribute} must have a value"));
+                    }
+                }
+                QualityRule::Range {
+                    attribute,
+                    min,
+                    max,
+                } => {
+                    let Some(number) = properties.get(attribute).and_then(Value::as_f64) else {
+                        continue;
+                    };
+                    if min.is_some_and(|min| number < min) || max.is_some_and(|max| number > max) {
+                        let bound =
+                            |b: &Option<f64>| b.map_or("any".to_string(), |b| b.to_string());
+                        failures.push(format!(
+                            "{attribute} must be between {} and {}",
+                            bound(min),
+                            bound(max)
+                        ));
+                    }
+                }
+                QualityRule::Pattern { attribute, pattern } => {
+                    let (Some(regex), Some(value)) = (regex, properties.get(attribute)) else {
+                        continue;
+                    };
+                    let matched = match value {
+                        Value::Null => continue,
+                        Value::String(text) => regex.is_match(text),
+                        other => regex.is_match(&other.to_string()),
+                    };
+                    if !matched {
+                        failures.push(format!("{attribute} must match {pattern}"));
+                    }
+                }
+                QualityRule::GeometryType { types } => {
+                    let Some(geometry_type) = feature["geometry"]["type"].as_str() else {
+                        continue;
+                    };
+                    if !types.iter().any(|t| t == geometry_type) {
+                        failures.push(format!(
+                            "geometry must be a {}, not a {geometry_type}",
+                            types.join(" or ")
+                        ));
+                    }
+                }
+                QualityRule::Crs { srid } => {
+                    if self.srid != *srid {
+                        failures.push(format!("layer is stored in SRID {}, not {srid}", self.srid));
+                    }
+                }
+            }
+        }
+        failures
+    }
+}
+
+/// How many of a layer's features break one rule.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RuleResult {
+    pub rule: QualityRule,
+    pub failed: i64,
+    /// Primary keys of the first 20 failing features; empty for layers
+    /// without a single-column primary key.
+    pub sample: Vec<Value>,
+}
+
+/// A layer's features measured against its quality rules.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QualityReport {
+    pub feature_count: i64,
+    /// Features that break at least one rule.
+    pub failed: i64,
+    pub rules: Vec<RuleResult>,
+    pub checked_at: i64,
+}
+
+/// SQL condition true for rows of the table that break `rule`, with its
+/// parameters, numbered from `$first`.
+fn failure_sql<'a>(
+    rule: &'a QualityRule,
+    geom: &str,
+    first: usize,
+) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    let next = first + 1;
+    match rule {
+        QualityRule::NotNull { attribute } => {
+            (format!("t.{} IS NULL", quote_ident(attribute)), vec![])
+        }
+        QualityRule::Range {
+            attribute,
+            min,
+            max,
+        } => {
+            let column = quote_ident(attribute);
+            (
+                format!("(t.{column} < ${first}::float8 OR t.{column} > ${next}::float8) IS TRUE"),
+                vec![min as &(dyn ToSql + Sync), max],
+            )
+        }
+        QualityRule::Pattern { attribute, pattern } => (
+            format!("t.{}::text !~ ${first}::text", quote_ident(attribute)),
+            vec![pattern as &(dyn ToSql + Sync)],
+        ),
+        QualityRule::GeometryType { types } => (
+            format!("GeometryType(t.{geom}) <> ALL(SELECT upper(unnest(${first}::text[])))"),
+            vec![types as &(dyn ToSql + Sync)],
+        ),
+        QualityRule::Crs { srid } => (
+            format!("ST_SRID(t.{geom}) <> ${first}::int4"),
+            vec![srid as &(dyn ToSql + Sync)],
+        ),
+    }
+}
+
+/// Measure every feature of the table against `rules`. Features with an
+/// empty geometry pass the geometry rules.
+#[instrument(skip(pool, table, rules), fields(table = %table.table))]
+pub async fn report(
+    pool: &Pool,
+    table: &LayerTable,
+    key: Option<&str>,
+    rules: &QualityRules,
+) -> Result<QualityReport> {
+    let geom = quote_ident(&table.geometry_column);
+    let table = table.qualified_name();
+    let id = match key {
+        Some(key) => format!("to_jsonb(t.{})", quote_ident(key)),
+        None => "NULL::jsonb".to_string(),
+    };
+    let client = pool.get().await?;
+    let mut results = Vec::with_capacity(rules.rules.len());
+    for rule in &rules.rules {
+        let (condition, params) = failure_sql(rule, &geom, 1);
+        let row = client
+            .query_one(
+                &format!(
+                    "SELECT (SELECT count(*) FROM {table} t WHERE {condition}),
+                            coalesce((
+                                SELECT jsonb_agg(id) FROM (
+                                    SELECT {id} AS id FROM {table} t
+                                    WHERE {condition} AND {id} IS NOT NULL
+                                    LIMIT {SAMPLE_SIZE}
+                                ) s
+                            ), '[]'::jsonb)"
+                ),
+                &params,
+            )
+            .await?;
+        let sample: Value = row.get(1);
+        results.push(RuleResult {
+            rule: rule.clone(),
+            failed: row.get(0),
+            sample: match sample {
+                Value::Array(ids) => ids,
+                _ => Vec::new(),
+            },
+        });
+    }
+
+    let mut conditions = vec!["false".to_string()];
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    for rule in &rules.rules {
+        let (condition, rule_params) = failure_sql(rule, &geom, params.len() + 1);
+        conditions.push(format!("({condition})"));
+        params.extend(rule_params);
+    }
+    let row = client
+        .query_one(
+            &format!(
+                "SELECT count(*), count(*) FILTER (WHERE {}) FROM {table} t",
+                conditions.join(" OR ")
+            ),
+            &params,
+        )
+        .await?;
+    Ok(QualityReport {
+        feature_count: row.get(0),
+        failed: row.get(1),
+        rules: results,
+        checked_at: Utc::now().timestamp(),
+    })
+}
@@ -0,0 +1,81 @@
+use super::CacheStore;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+struct Entry {
+    expires_at: Instant,
+    tags: Vec<String>,
+    value: Vec<u8>,
+}
+
+/// Entries held in this process, up to `capacity` of them.
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, Entry>>,
+    capacity: usize,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let entries = self.entries.lock().await;
+        Ok(entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.value.clone()))
+    }
+
+    async fn put(&self, key: &str, tags: &[String], value: Vec<u8>, ttl: Duration) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        if entries.len() >= self.capacity {
+            let now = Instant::now();
+            entries.retain(|_, entry| entry.expires_at > now);
+            if entries.len() >= self.capacity {
+                entries.clear();
+            }
+        }
+        entries.insert(
+            key.to_string(),
+            Entry {
+                expires_at: Instant::now() + ttl,
+                tags: tags.to_vec(),
+                value,
+            },
+        );
+        Ok(())
+    }
+
+    async fn invalidate(&self, tag: &str) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        entries.retain(|_, entry| !entry.tags.iter().any(|t| t == tag));
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.entries.lock().await.clear();
+        Ok(())
+    }
+
+    async fn evict_expired(&self) -> usize {
+        let mut entries = self.entries.lock().await;
+        let before = entries.len();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        before - entries.len()
+    }
+}
@@ -0,0 +1,154 @@
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+
+pub use memory::MemoryStore;
+
+use crate::cdn::CacheKey;
+use crate::config::QueryCacheConfig;
+use crate::core::{Event, EventBus};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Where cached query results are kept.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn put(&self, key: &str, tags: &[String], value: Vec<u8>, ttl: Duration) -> Result<()>;
+    /// Drop every entry carrying `tag`.
+    async fn invalidate(&self, tag: &str) -> Result<()>;
+    async fn clear(&self) -> Result<()>;
+    /// Drop expired entries, returning how many were removed.
+    async fn evict_expired(&self) -> usize;
+}
+
+/// Results of expensive PostGIS queries, such as attribute statistics and
+/// feature searches, keyed by the query and dropped when the layers or maps
+/// they were computed from change. Store failures are logged and the query
+/// is run as if nothing was cached.
+pub struct QueryCache {
+    store: Option<Box<dyn CacheStore>>,
+    ttl: Duration,
+}
+
+impl QueryCache {
+    pub async fn from_config(config: &QueryCacheConfig) -> Result<Self> {
+        let store: Option<Box<dyn CacheStore>> = match config.backend.as_str() {
+            "none" => None,
+            "memory" => Some(Box::new(MemoryStore::new(config.max_entries))),
+            #[cfg(feature = "redis")]
+            "redis" => Some(Box::new(redis::RedisStore::connect(config.url()?).await?)),
+            other => {
+                return Err(anyhow!(
+                    "Query cache backend {other} is not available in this build"
+                ))
+            }
+        };
+        Ok(QueryCache {
+            store,
+            ttl: Duration::from_secs(config.ttl),
+        })
+    }
+
+    /// The cached result of the `kind` query with `params`, or the result of
+    /// `compute`, cached against `keys`.
+    pub async fn get_or_compute<T, P, F, Fut>(
+        &self,
+        kind: &'static str,
+        params: &P,
+        keys: &[CacheKey],
+        compute: F,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        P: Serialize + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let Some(store) = &self.store else {
+            return compute().await;
+        };
+        let digest = Sha256::digest(serde_json::to_vec(params)?);
+        let key = format!("{kind}:{}", hex::encode(digest));
+
+        let cached = match store.get(&key).await {
+            Ok(cached) => cached.and_then(|value| serde_json::from_slice(&value).ok()),
+            Err(e) => {
+                warn!("Query cache read via {} failed: {e:#}", store.name());
+                None
+            }
+        };
+        let result = if cached.is_some() { "hit" } else { "miss" };
+        counter!("query_cache_requests_total", "kind" => kind, "result" => result).increment(1);
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+
+        let value = compute().await?;
+        let tags: Vec<String> = keys.iter().map(CacheKey::tag).collect();
+        let encoded = serde_json::to_vec(&value)?;
+        if let Err(e) = store.put(&key, &tags, encoded, self.ttl).await {
+            warn!("Query cache write via {} failed: {e:#}", store.name());
+        }
+        Ok(value)
+    }
+
+    /// Drop expired results, returning how many were removed.
+    pub async fn evict_expired(&self) -> usize {
+        match &self.store {
+            Some(store) => store.evict_expired().await,
+            None => 0,
+        }
+    }
+
+    /// Drop results whenever the layers or maps behind them change.
+    pub fn invalidate_on_changes(self: Arc<Self>, events: &EventBus) {
+        if self.store.is_none() {
+            return;
+        }
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            let Some(store) = &self.store else {
+                return;
+            };
+            loop {
+                let outcome = match receiver.recv().await {
+                    Ok(Event::LayerUpdated { layer }) => {
+                        store.invalidate(&CacheKey::Layer(layer.id).tag()).await
+                    }
+                    Ok(Event::LayerDeleted { layer_id }) => {
+                        store.invalidate(&CacheKey::Layer(layer_id).tag()).await
+                    }
+                    Ok(Event::MapUpdated { map }) => {
+                        store.invalidate(&CacheKey::Map(map.id).tag()).await
+                    }
+                    Ok(Event::MapDeleted { map_id }) => {
+                        store.invalidate(&CacheKey::Map(map_id).tag()).await
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Missed {missed} events; clearing the query cache");
+                        store.clear().await
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if let Err(e) = outcome {
+                    warn!(
+                        "Query cache invalidation via {} failed: {e:#}",
+                        store.name()
+                    );
+                }
+            }
+        });
+    }
+}
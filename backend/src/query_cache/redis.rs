@@ -0,0 +1,80 @@
+use super::CacheStore;
+use anyhow::Result;
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Client};
+use std::time::Duration;
+
+const PREFIX: &str = "gridwalk:cache";
+
+fn entry_key(key: &str) -> String {
+    format!("{PREFIX}:entry:{key}")
+}
+
+/// Set of the entry keys carrying a tag, so they can be invalidated together.
+fn tag_key(tag: &str) -> String {
+    format!("{PREFIX}:tag:{tag}")
+}
+
+/// Entries shared by every replica. Redis expires them, so only
+/// invalidation needs the tag sets.
+pub struct RedisStore {
+    connection: MultiplexedConnection,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(RedisStore { connection })
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.connection.clone().get(entry_key(key)).await?)
+    }
+
+    async fn put(&self, key: &str, tags: &[String], value: Vec<u8>, ttl: Duration) -> Result<()> {
+        let key = entry_key(key);
+        let seconds = ttl.as_secs().max(1);
+        let mut pipe = redis::pipe();
+        pipe.set_ex(&key, value, seconds).ignore();
+        for tag in tags {
+            // Tag sets outlive their newest entry, then expire
+            pipe.sadd(tag_key(tag), &key)
+                .ignore()
+                .expire(tag_key(tag), seconds as i64)
+                .ignore();
+        }
+        let () = pipe.query_async(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    async fn invalidate(&self, tag: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let keys: Vec<String> = connection.smembers(tag_key(tag)).await?;
+        let mut pipe = redis::pipe();
+        if !keys.is_empty() {
+            pipe.del(keys).ignore();
+        }
+        pipe.del(tag_key(tag)).ignore();
+        let () = pipe.query_async(&mut connection).await?;
+        Ok(())
+    }
+
+    /// Entries are left to expire, as Redis has no cheap way to drop
+    /// everything under a prefix.
+    async fn clear(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn evict_expired(&self) -> usize {
+        0
+    }
+}
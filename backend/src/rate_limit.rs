@@ -0,0 +1,172 @@
+use crate::app_state::AppState;
+use crate::config::RateLimitConfig;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Routes share a bucket per client within their class, so heavy tile
+/// traffic cannot starve API calls and vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    Tiles,
+    Api,
+    /// Requests that start analysis jobs or call out to geocoding or
+    /// routing services.
+    Expensive,
+}
+
+impl RouteClass {
+    fn of(method: &Method, path: &str) -> Option<Self> {
+        if matches!(path, "/health" | "/healthz" | "/readyz" | "/metrics") {
+            return None;
+        }
+        if path.contains("/tiles/") || path.contains("/aggregate/") {
+            return Some(RouteClass::Tiles);
+        }
+        let expensive = path.starts_with("/analysis/")
+            || path.starts_with("/imports/")
+            || path.starts_with("/routing/")
+            || path.starts_with("/geocode")
+            || path.starts_with("/reverse")
+            || path.starts_with("/search")
+            || path.starts_with("/admin/")
+            || (method == Method::POST
+                && (path.ends_with("/analyze")
+                    || path.ends_with("/aggregate")
+                    || path.ends_with("/copy")
+                    || path.ends_with("/import-url")
+                    || path.ends_with("/harvest")
+                    || path.ends_with("/seed")
+                    || path.ends_with("/features/batch")));
+        Some(if expensive {
+            RouteClass::Expensive
+        } else {
+            RouteClass::Api
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets per client IP and route class. Limits are requests per
+/// minute; a limit of zero disables limiting for that class.
+pub struct RateLimiter {
+    limits: HashMap<RouteClass, u32>,
+    trust_proxy: bool,
+    trusted_proxies: usize,
+    buckets: Mutex<HashMap<(IpAddr, RouteClass), Bucket>>,
+}
+
+const MAX_BUCKETS: usize = 100_000;
+
+impl RateLimiter {
+    pub fn new(
+        limits: HashMap<RouteClass, u32>,
+        trust_proxy: bool,
+        trusted_proxies: usize,
+    ) -> Self {
+        RateLimiter {
+            limits,
+            trust_proxy,
+            trusted_proxies,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Client IPs are taken from `X-Forwarded-For` only when `trust_proxy`
+    /// is set, counting `trusted_proxies` entries from the right.
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        let limits = HashMap::from([
+            (RouteClass::Tiles, config.tiles),
+            (RouteClass::Api, config.api),
+            (RouteClass::Expensive, config.expensive),
+        ]);
+        RateLimiter::new(limits, config.trust_proxy, config.trusted_proxies)
+    }
+
+    /// Take a token, or return the seconds until one is available.
+    fn check(&self, client: IpAddr, class: RouteClass) -> Result<(), u64> {
+        let limit = self.limits.get(&class).copied().unwrap_or(0);
+        if limit == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(limit);
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            // Buckets that have refilled carry no state worth keeping
+            buckets.retain(|&(_, class), bucket| {
+                let limit = f64::from(self.limits.get(&class).copied().unwrap_or(0));
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * limit / 60.0 < limit
+            });
+        }
+        let bucket = buckets.entry((client, class)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / per_second).ceil() as u64)
+        }
+    }
+
+    fn client_ip(&self, request: &Request, peer: SocketAddr) -> IpAddr {
+        if self.trust_proxy {
+            // Clients can send any entries they like, so only those
+            // appended by our own proxies are trusted
+            let forwarded = request
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .rev()
+                .nth(self.trusted_proxies.saturating_sub(1))
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+}
+
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(class) = RouteClass::of(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let client = state.rate_limiter.client_ip(&request, peer);
+    match state.rate_limiter.check(client, class) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            "Too many requests".to_string(),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,129 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Shown in place of redacted values. Plain letters, so it reads the same
+/// percent-encoded in a URL.
+const REDACTED: &str = "redacted";
+
+/// Query parameters whose values are credentials.
+const SECRET_PARAMS: [&str; 6] = [
+    "access_token",
+    "api_key",
+    "key",
+    "signature",
+    "token",
+    "password",
+];
+
+/// Path segments followed by a share token.
+const TOKEN_SEGMENTS: [&str; 3] = ["shared", "shares", "embed"];
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turn redaction off, for local development where full detail helps more
+/// than it risks.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A credential or other value to keep out of logs. Its `Debug` output is
+/// redacted, so it can sit in structs that are logged whole, like the
+/// config; use `expose` where the value itself is needed.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            f.write_str(REDACTED)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// A URL or request target to log, without its password, credential query
+/// parameters or share tokens.
+pub struct LogUrl<'a>(pub &'a str);
+
+impl LogUrl<'_> {
+    fn redacted(&self) -> String {
+        // Request targets are relative, so parse them against a placeholder
+        // and strip it again
+        let (mut url, relative) = match Url::parse(self.0) {
+            Ok(url) => (url, false),
+            Err(_) => match Url::parse("http://relative").and_then(|base| base.join(self.0)) {
+                Ok(url) => (url, true),
+                Err(_) => return REDACTED.to_string(),
+            },
+        };
+        if url.password().is_some() {
+            let _ = url.set_password(Some(REDACTED));
+        }
+
+        let mut follows_token_segment = false;
+        let path: Vec<&str> = url
+            .path()
+            .split('/')
+            .map(|segment| {
+                let shown = if follows_token_segment {
+                    REDACTED
+                } else {
+                    segment
+                };
+                follows_token_segment = TOKEN_SEGMENTS.contains(&segment);
+                shown
+            })
+            .collect();
+        let path = path.join("/");
+        url.set_path(&path);
+
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if SECRET_PARAMS.contains(&name.to_lowercase().as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    value.into_owned()
+                };
+                (name.into_owned(), value)
+            })
+            .collect();
+        if !pairs.is_empty() {
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+
+        match (relative, url.query()) {
+            (false, _) => url.to_string(),
+            (true, Some(query)) => format!("{}?{query}", url.path()),
+            (true, None) => url.path().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LogUrl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            f.write_str(&self.redacted())
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
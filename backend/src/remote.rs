@@ -0,0 +1,157 @@
+use crate::config::RemoteConfig;
+use crate::redact::LogUrl;
+use anyhow::{anyhow, Result};
+use reqwest::{header::LOCATION, redirect::Policy, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tracing::instrument;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Downloads data from user-supplied URLs. Hosts must resolve to public
+/// addresses, checked on every redirect and pinned for the request so a
+/// second lookup cannot point elsewhere.
+pub struct Fetcher {
+    max_size: u64,
+    timeout: Duration,
+    allow_private_networks: bool,
+}
+
+impl Fetcher {
+    pub fn from_config(config: &RemoteConfig) -> Self {
+        Fetcher {
+            max_size: config.max_download_size,
+            timeout: Duration::from_secs(config.timeout),
+            allow_private_networks: config.allow_private_networks,
+        }
+    }
+
+    /// Check the URL is one the server may fetch, before any work is queued.
+    pub fn check_url(&self, url: &str) -> Result<Url> {
+        let url = Url::parse(url)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("only http and https URLs can be fetched"));
+        }
+        if url.host_str().is_none() {
+            return Err(anyhow!("URL has no host"));
+        }
+        Ok(url)
+    }
+
+    /// The response body, up to the configured size.
+    #[instrument(skip_all, fields(url = %LogUrl(url)))]
+    pub async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let mut url = self.check_url(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let host = url.host_str().unwrap_or_default().to_string();
+            let addrs = self.resolve(&url).await?;
+            let client = reqwest::Client::builder()
+                .user_agent(concat!("gridwalk/", env!("CARGO_PKG_VERSION")))
+                .timeout(self.timeout)
+                .redirect(Policy::none())
+                .resolve_to_addrs(&host, &addrs)
+                .build()?;
+            // Errors would name the URL, which may carry credentials
+            let mut response = client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(reqwest::Error::without_url)?;
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| anyhow!("redirect without a location"))?;
+                url = self.check_url(url.join(location)?.as_str())?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(anyhow!(
+                    "{} answered {}",
+                    LogUrl(url.as_str()),
+                    response.status()
+                ));
+            }
+            if response
+                .content_length()
+                .is_some_and(|length| length > self.max_size)
+            {
+                return Err(self.too_large());
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                if (body.len() + chunk.len()) as u64 > self.max_size {
+                    return Err(self.too_large());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            return Ok(body);
+        }
+        Err(anyhow!("more than {MAX_REDIRECTS} redirects"))
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Vec<SocketAddr>> {
+        let host = url.host_str().ok_or_else(|| anyhow!("URL has no host"))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| anyhow!("URL has no port"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+        if addrs.is_empty() {
+            return Err(anyhow!("{host} did not resolve"));
+        }
+        if !self.allow_private_networks && !addrs.iter().all(|addr| is_public(addr.ip())) {
+            return Err(anyhow!("{host} resolves to a non-public address"));
+        }
+        Ok(addrs)
+    }
+
+    fn too_large(&self) -> anyhow::Error {
+        anyhow!("download exceeds {} bytes", self.max_size)
+    }
+}
+
+/// Whether the address is globally routable, rather than private, loopback,
+/// link-local or otherwise reserved.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local
+        || (first & 0xfe00) == 0xfc00
+        // Link-local
+        || (first & 0xffc0) == 0xfe80
+        // Documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
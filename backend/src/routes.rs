@@ -1,36 +1,305 @@
+mod analysis;
+mod attachments;
+mod backups;
+mod basemaps;
+mod branches;
+mod comments;
+mod connectors;
+mod editing;
+mod embed;
+mod error;
+mod events;
+mod features;
+mod geocoding;
+mod graphql;
+mod health;
+mod jobs;
+mod layers;
+mod maps;
+mod osm;
+mod pagination;
+mod routing;
+mod schedules;
+mod search;
+mod shares;
+mod validated;
+
+pub use analysis::*;
+pub use attachments::*;
+pub use backups::*;
+pub use basemaps::*;
+pub use branches::*;
+pub use comments::*;
+pub use connectors::*;
+pub use editing::*;
+pub use embed::*;
+pub use events::*;
+pub use features::*;
+pub use geocoding::*;
+pub use graphql::*;
+pub use health::*;
+pub use jobs::*;
+pub use layers::*;
+pub use maps::*;
+pub use osm::*;
+pub use pagination::*;
+pub use routing::*;
+pub use schedules::*;
+pub use search::*;
+pub use shares::*;
+pub use validated::*;
+
 use crate::app_state::AppState;
+use crate::cdn::CacheKey;
+use crate::signing::UrlSignature;
+use crate::sources::source_layer;
+use crate::tiling::{self, TileLayer};
 use axum::{
-    extract::{Path, State},
-    http::{header, StatusCode},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use martin_tile_utils::TileCoord;
+use metrics::histogram;
+use serde::Serialize;
+use std::time::Instant;
+use tracing::{error, instrument};
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "Service is up")),
+)]
 pub async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({ "status": "healthy" }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tiles/{z}/{x}/{y}",
+    tag = "tiles",
+    params(("z" = u8, Path), ("x" = u32, Path), ("y" = u32, Path), UrlSignature),
+    responses(
+        (status = 200, description = "Mapbox vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 403, description = "Missing or invalid signature"),
+        (status = 404),
+    ),
+)]
 pub async fn tiles(
     Path((z, y, x)): Path<(u32, u32, u32)>,
+    Query(signature): Query<UrlSignature>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Response {
-    if let Some(tile_info_source) = state.sources.get("pois") {
-        let xyz = TileCoord {
-            x,
-            y,
-            z: z.try_into().unwrap(),
-        };
-        match tile_info_source.get_tile(xyz, None).await {
-            Ok(tile_data) => (
-                StatusCode::OK,
-                [
-                    (header::CONTENT_TYPE, "application/vnd.mapbox-vector-tile"),
-                    (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
-                ],
-                tile_data,
+    if let Some(response) = check_signature(&state, "pois", &signature) {
+        return response;
+    }
+    serve_tile(&state, &headers, "pois", &[], z, x, y).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tiles/{source_id}/{z}/{x}/{y}",
+    tag = "tiles",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("z" = u8, Path),
+        ("x" = u32, Path),
+        ("y" = u32, Path),
+        UrlSignature,
+    ),
+    responses(
+        (status = 200, description = "Mapbox vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 403, description = "Missing or invalid signature"),
+        (status = 404),
+    ),
+)]
+pub async fn source_tiles(
+    Path((source_id, z, x, y)): Path<(String, u32, u32, u32)>,
+    Query(signature): Query<UrlSignature>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_signature(&state, &source_id, &signature) {
+        return response;
+    }
+    serve_tile(&state, &headers, &source_id, &[], z, x, y).await
+}
+
+/// Signatures cover every tile of a layer, including aggregate tiles.
+fn tile_scope(source_id: &str) -> String {
+    format!("tiles/{source_id}")
+}
+
+/// Signatures for a map's composite tiles cover every tile of the map.
+pub(crate) fn map_tile_scope(map_id: &str) -> String {
+    format!("maps/{map_id}/tiles")
+}
+
+fn signed(state: &AppState, template: String, scope: &str) -> String {
+    match state.signer.sign(scope) {
+        Some((_, query)) => format!("{template}?{query}"),
+        None => template,
+    }
+}
+
+/// Tile URL template for a source, signed when signing keys are configured.
+pub(crate) fn tile_template(state: &AppState, source_id: &str) -> String {
+    let template = format!("{}/tiles/{source_id}/{{z}}/{{x}}/{{y}}", state.public_url);
+    signed(state, template, &tile_scope(source_id))
+}
+
+/// URL template for a map's composite tiles, signed like `tile_template`.
+pub(crate) fn map_tile_template(state: &AppState, map_id: &str) -> String {
+    let template = format!(
+        "{}/maps/{map_id}/tiles/{{z}}/{{x}}/{{y}}.mvt",
+        state.public_url
+    );
+    signed(state, template, &map_tile_scope(map_id))
+}
+
+/// Reject the request when signatures are required and this one is
+/// missing, expired or wrong.
+pub(crate) fn check_signature(
+    state: &AppState,
+    source_id: &str,
+    signature: &UrlSignature,
+) -> Option<Response> {
+    check_scope(state, &tile_scope(source_id), signature)
+}
+
+pub(crate) fn check_scope(
+    state: &AppState,
+    scope: &str,
+    signature: &UrlSignature,
+) -> Option<Response> {
+    if !state.signer.required() || state.signer.verify(scope, signature) {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::FORBIDDEN,
+            "Missing or invalid signature".to_string(),
+        )
+            .into_response(),
+    )
+}
+
+/// Reject the request unless it carries one of the configured API keys as
+/// `Authorization: Bearer <key>`: 401 without a key, 403 for an unknown one.
+pub(crate) fn check_api_key(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match key {
+        Some(key) if state.signer.authorizes(key.trim()) => None,
+        Some(_) => Some((StatusCode::FORBIDDEN, "Unknown API key".to_string()).into_response()),
+        None => Some(
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "An API key is required".to_string(),
             )
                 .into_response(),
+        ),
+    }
+}
+
+/// The version an update is based on, from its `If-Match` header. Updates
+/// must send one, so they can't silently overwrite changes the client never
+/// saw; `*` explicitly accepts whatever version is stored.
+pub(crate) fn if_match(headers: &HeaderMap) -> Result<Option<u64>, Response> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Err((
+            StatusCode::PRECONDITION_REQUIRED,
+            "If-Match must name the version being updated".to_string(),
+        )
+            .into_response());
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    match value.trim_matches('"').parse() {
+        Ok(version) => Ok(Some(version)),
+        Err(_) => Err((
+            StatusCode::BAD_REQUEST,
+            "If-Match must be a quoted version, e.g. \"3\"".to_string(),
+        )
+            .into_response()),
+    }
+}
+
+/// `body` with its version as the ETag, to send back in `If-Match`.
+pub(crate) fn versioned(body: impl Serialize, version: u64) -> Response {
+    let mut response = Json(body).into_response();
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{version}\"")) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// Serve a tile with cache headers. `extra_keys` are surrogate keys beyond
+/// the layer's own, e.g. the share the tile was requested through.
+#[instrument(skip(state, headers, extra_keys))]
+async fn serve_tile(
+    state: &AppState,
+    headers: &HeaderMap,
+    source_id: &str,
+    extra_keys: &[CacheKey],
+    z: u32,
+    x: u32,
+    y: u32,
+) -> Response {
+    if let Some(tile_info_source) = state.sources.get(source_id) {
+        let Ok(z) = z.try_into() else {
+            return (StatusCode::BAD_REQUEST, "Invalid zoom level".to_string()).into_response();
+        };
+        let settings = state.tiling.get(source_id);
+        let snap_zoom = settings
+            .max_zoom
+            .or(tile_info_source.get_tilejson().maxzoom)
+            .filter(|&max_zoom| z > max_zoom);
+        if snap_zoom.is_some() && !settings.overzoom {
+            return (
+                StatusCode::NOT_FOUND,
+                "Zoom is beyond the layer's max zoom".to_string(),
+            )
+                .into_response();
+        }
+        let start = Instant::now();
+        let tile = if snap_zoom.is_some() || settings.has_rules() {
+            let layer = TileLayer {
+                source_id: source_id.to_string(),
+                layer_name: source_layer(tile_info_source.as_ref()),
+                snap_zoom,
+                tiling: settings,
+            };
+            match tiling::layer_tile(&state.read_pool, &layer, z, x, y).await {
+                Ok(tile) => Ok(tile),
+                Err(e) => {
+                    error!("Tile query failed for {source_id}: {e}");
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to render tile".to_string(),
+                    )
+                        .into_response();
+                }
+            }
+        } else {
+            tile_info_source.get_tile(TileCoord { x, y, z }, None).await
+        };
+        histogram!("tile_render_seconds", "source" => source_id.to_string())
+            .record(start.elapsed().as_secs_f64());
+        match tile {
+            Ok(tile_data) => {
+                let mut keys = vec![CacheKey::Layer(source_id.to_string())];
+                keys.extend_from_slice(extra_keys);
+                state.cdn.tile_response(headers, tile_data, &keys)
+            }
             Err(_) => (StatusCode::NOT_FOUND, "Tile not found".to_string()).into_response(),
         }
     } else {
@@ -0,0 +1,408 @@
+use super::features::layer_table;
+use super::{check_signature, ValidJson};
+use crate::analysis::{
+    self, Aggregation, Dedupe, DedupeMode, Grid, Operation, SpatialJoin, Statistic, StatisticOp,
+};
+use crate::app_state::AppState;
+use crate::cdn::CacheKey;
+use crate::core::{Job, Layer, ProgressReporter};
+use crate::signing::UrlSignature;
+use crate::validation::{Validate, ValidationErrors, Validator};
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::future::Future;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AnalyzeRequest {
+    #[serde(flatten)]
+    pub operation: Operation,
+    /// Table name for the derived layer. Generated when omitted.
+    pub output_name: Option<String>,
+}
+
+impl Validate for AnalyzeRequest {
+    fn check(&self, v: &mut Validator) {
+        v.result("distance", self.operation.validate());
+        check_output_name(v, &self.output_name);
+    }
+}
+
+/// The output name, generating one when it is not given.
+pub(super) fn resolve_output_name(
+    output_name: Option<String>,
+    source_id: &str,
+    suffix: &str,
+) -> String {
+    output_name.unwrap_or_else(|| analysis::output_name(source_id, suffix))
+}
+
+pub(super) fn check_output_name(v: &mut Validator, output_name: &Option<String>) {
+    if let Some(name) = output_name {
+        v.table_name("output_name", name);
+    }
+}
+
+/// Persist a job and hand back the response clients poll with.
+pub(super) async fn start_job(state: &AppState, job: &Job) -> Result<Response, Response> {
+    match job.create(&state.app_data).await {
+        Ok(_) => Ok((StatusCode::ACCEPTED, Json(job.clone())).into_response()),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create job".to_string(),
+        )
+            .into_response()),
+    }
+}
+
+/// Scan a file a job downloaded, recording the verdict on the job and
+/// failing it when the file is flagged.
+pub(super) async fn scan_download(
+    state: &AppState,
+    progress: &ProgressReporter,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    let id = format!("jobs/{}", progress.job_id());
+    let Some(scan) = state.scanning.check(&state.storage, &id, body).await? else {
+        return Ok(());
+    };
+    let threat = scan.threat.clone();
+    progress.record_scan(scan).await;
+    match threat {
+        Some(threat) => Err(anyhow!("Download flagged by the malware scanner: {threat}")),
+        None => Ok(()),
+    }
+}
+
+/// Run a job that writes a new table, then publish it as a layer and record
+/// its metadata.
+pub(super) fn spawn_layer_job<F>(state: &AppState, job: Job, task: F)
+where
+    F: Future<Output = anyhow::Result<String>> + Send + 'static,
+{
+    let database = state.app_data.clone();
+    let pool = state.pg_pool.clone();
+    let read_pool = state.read_pool.clone();
+    let sources = state.sources.clone();
+    state.jobs.spawn(job, state.app_data.clone(), async move {
+        let output = task.await?;
+        sources.refresh().await?;
+        Layer::refresh(&database, &pool, &read_pool, &output).await?;
+        Ok(output)
+    });
+}
+
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/analyze",
+    tag = "analysis",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = AnalyzeRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn analyze_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<AnalyzeRequest>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    if let Operation::Clip { layer } = &req.operation {
+        if !state.sources.contains(layer) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown clip layer: {layer}"),
+            )
+                .into_response();
+        }
+    }
+    let output = resolve_output_name(req.output_name, &source_id, req.operation.name());
+
+    let job = Job::new(&format!("analysis:{}", req.operation.name()));
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let pool = state.pg_pool.clone();
+    let operation = req.operation;
+    spawn_layer_job(&state, job, async move {
+        analysis::run(&pool, &source_id, &operation, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SpatialJoinRequest {
+    #[serde(flatten)]
+    pub join: SpatialJoin,
+    pub output_name: Option<String>,
+}
+
+impl Validate for SpatialJoinRequest {
+    fn check(&self, v: &mut Validator) {
+        check_output_name(v, &self.output_name);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/analysis/spatial-join",
+    tag = "analysis",
+    request_body = SpatialJoinRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn spatial_join(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<SpatialJoinRequest>,
+) -> Response {
+    for source_id in [&req.join.target, &req.join.join] {
+        if !state.sources.contains(source_id) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown layer: {source_id}"),
+            )
+                .into_response();
+        }
+    }
+    let output = resolve_output_name(req.output_name, &req.join.target, "join");
+
+    let job = Job::new("analysis:spatial_join");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let pool = state.pg_pool.clone();
+    let spec = req.join;
+    spawn_layer_job(&state, job, async move {
+        analysis::spatial_join(&pool, &spec, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DedupeRequest {
+    #[serde(flatten)]
+    pub dedupe: Dedupe,
+    pub output_name: Option<String>,
+}
+
+impl Validate for DedupeRequest {
+    fn check(&self, v: &mut Validator) {
+        self.dedupe.check(v);
+        check_output_name(v, &self.output_name);
+    }
+}
+
+/// Find duplicate and near-duplicate features, such as those left by
+/// repeated or overlapping imports, and write either the duplicates or the
+/// layer without them to a new layer, as a job. The layer itself is left
+/// as it is. Needs a single-column primary key.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/dedupe",
+    tag = "analysis",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = DedupeRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn dedupe_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<DedupeRequest>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to read primary key of {source_id}: {e:#}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read layer".to_string(),
+            )
+                .into_response();
+        }
+    };
+    let suffix = match req.dedupe.mode {
+        DedupeMode::Report => "duplicates",
+        DedupeMode::Merge => "deduped",
+    };
+    let output = resolve_output_name(req.output_name, &source_id, suffix);
+
+    let job = Job::new("analysis:dedupe");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let pool = state.pg_pool.clone();
+    let dedupe = req.dedupe;
+    spawn_layer_job(&state, job, async move {
+        analysis::dedupe(&pool, &source_id, &key, &dedupe, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AggregateRequest {
+    #[serde(flatten)]
+    pub aggregation: Aggregation,
+    pub output_name: Option<String>,
+}
+
+impl Validate for AggregateRequest {
+    fn check(&self, v: &mut Validator) {
+        v.result("resolution", self.aggregation.validate());
+        check_output_name(v, &self.output_name);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/aggregate",
+    tag = "analysis",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = AggregateRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn aggregate_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<AggregateRequest>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let output = resolve_output_name(req.output_name, &source_id, "aggregate");
+
+    let job = Job::new("analysis:aggregate");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let pool = state.pg_pool.clone();
+    let aggregation = req.aggregation;
+    spawn_layer_job(&state, job, async move {
+        analysis::aggregate_layer(&pool, &source_id, &aggregation, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+/// Query parameters for aggregate tiles; takes at most one statistic.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AggregateTileParams {
+    pub grid: Grid,
+    pub resolution: u8,
+    pub op: Option<StatisticOp>,
+    pub column: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/aggregate/{z}/{x}/{y}",
+    tag = "analysis",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("z" = u8, Path),
+        ("x" = u32, Path),
+        ("y" = u32, Path),
+        AggregateTileParams,
+        UrlSignature,
+    ),
+    responses(
+        (status = 200, description = "Mapbox vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 400),
+        (status = 403, description = "Missing or invalid signature"),
+        (status = 404),
+    ),
+)]
+pub async fn aggregate_tiles(
+    Path((source_id, z, x, y)): Path<(String, u8, u32, u32)>,
+    Query(params): Query<AggregateTileParams>,
+    Query(signature): Query<UrlSignature>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_signature(&state, &source_id, &signature) {
+        return response;
+    }
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let statistics = match (params.op, params.column) {
+        (Some(op), Some(column)) => vec![Statistic { op, column }],
+        (None, None) => Vec::new(),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "op and column must be given together".to_string(),
+            )
+                .into_response()
+        }
+    };
+    let aggregation = Aggregation {
+        grid: params.grid,
+        resolution: params.resolution,
+        statistics,
+    };
+    if let Err(e) = aggregation.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    match analysis::aggregate_tile(&state.pg_pool, &source_id, &aggregation, z, x, y).await {
+        Ok(tile) => state
+            .cdn
+            .tile_response(&headers, tile, &[CacheKey::Layer(source_id)]),
+        Err(e) => {
+            error!("Aggregate tile failed for {source_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to aggregate tile".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
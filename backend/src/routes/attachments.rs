@@ -0,0 +1,390 @@
+use super::{AttachmentPage, PageParams};
+use crate::app_state::AppState;
+use crate::core::Attachment;
+use crate::data::DataError;
+use crate::postgis::LayerTable;
+use crate::scanning::ScanResult;
+use crate::sniff;
+use crate::storage::BlobStore;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+use utoipa::IntoParams;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadParams {
+    /// Original file name, returned when the file is downloaded.
+    pub filename: Option<String>,
+}
+
+fn storage_disabled() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Attachment storage is not configured".to_string(),
+    )
+        .into_response()
+}
+
+/// Check the feature exists, addressing it by the table's primary key.
+async fn check_feature(state: &AppState, source_id: &str, feature_id: &str) -> Option<Response> {
+    if !state.sources.contains(source_id) {
+        return Some((StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response());
+    }
+    let lookup = async {
+        let table = LayerTable::from_source_id(&state.pg_pool, source_id).await?;
+        let Some(key) = table.primary_key(&state.pg_pool).await? else {
+            return Ok(None);
+        };
+        let id = Value::String(feature_id.to_string());
+        table.feature(&state.pg_pool, &key, &id).await
+    };
+    match lookup.await {
+        Ok(Some(_)) => None,
+        Ok(None) => Some((StatusCode::NOT_FOUND, "Feature not found".to_string()).into_response()),
+        Err(_) => Some(
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to look up feature".to_string(),
+            )
+                .into_response(),
+        ),
+    }
+}
+
+/// Remove stored objects, e.g. after the record could not be written.
+/// Failures only leave orphaned objects, so they are logged.
+async fn delete_objects(store: &dyn BlobStore, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = store.delete(key).await {
+            warn!("Failed to delete attachment object {key}: {e:#}");
+        }
+    }
+}
+
+/// Attach the request body to a feature. Its type is sniffed from its
+/// contents, the `Content-Type` only telling kinds of text apart, and must be
+/// in `storage.allowed_types`. Thumbnails are generated for JPEG, PNG and
+/// WebP images. With scanning enabled, files the scanner flags are
+/// quarantined instead.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/features/{feature_id}/attachments",
+    tag = "attachments",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("feature_id" = String, Path, description = "Primary key of the feature"),
+        UploadParams,
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 201, body = Attachment),
+        (status = 400),
+        (status = 404),
+        (status = 413),
+        (status = 415, description = "The file's type is not accepted"),
+        (status = 422, body = ScanResult, description = "Flagged by the malware scanner"),
+        (status = 503, description = "Attachment storage is not configured, or the scanner failed"),
+    ),
+)]
+pub async fn upload_attachment(
+    State(state): State<AppState>,
+    Path((source_id, feature_id)): Path<(String, String)>,
+    Query(params): Query<UploadParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(store) = state.storage.store() else {
+        return storage_disabled();
+    };
+    if body.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Empty upload".to_string()).into_response();
+    }
+    if let Some(response) = check_feature(&state, &source_id, &feature_id).await {
+        return response;
+    }
+
+    let declared = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    let content_type = &sniff::content_type(declared, &body);
+    if !state.storage.allows(content_type) {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Attachments of type {content_type} are not accepted"),
+        )
+            .into_response();
+    }
+    let mut attachment = Attachment::new(
+        &source_id,
+        &feature_id,
+        params.filename.as_deref().unwrap_or("attachment"),
+        content_type,
+        body.len() as i64,
+    );
+    let quarantine_id = format!("attachments/{}", attachment.object_key());
+    match state
+        .scanning
+        .check(&state.storage, &quarantine_id, &body)
+        .await
+    {
+        Ok(Some(scan)) if !scan.clean => {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(scan)).into_response()
+        }
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Failed to scan attachment: {e:#}");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Malware scanner is unavailable".to_string(),
+            )
+                .into_response();
+        }
+    }
+    let thumbnail = match state.storage.thumbnail(content_type, &body).await {
+        Ok(thumbnail) => thumbnail,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Unreadable image: {e:#}")).into_response()
+        }
+    };
+
+    let mut stored = vec![state.storage.key(&attachment.object_key())];
+    if let Err(e) = store.put(&stored[0], body.to_vec(), content_type).await {
+        warn!("Failed to store attachment via {}: {e:#}", store.name());
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store attachment".to_string(),
+        )
+            .into_response();
+    }
+    if let Some(thumbnail) = thumbnail {
+        let key = state.storage.key(&attachment.thumbnail_key());
+        match store.put(&key, thumbnail, "image/jpeg").await {
+            Ok(()) => {
+                attachment.has_thumbnail = true;
+                stored.push(key);
+            }
+            Err(e) => warn!("Failed to store thumbnail via {}: {e:#}", store.name()),
+        }
+    }
+
+    match attachment.create(&state.app_data).await {
+        Ok(()) => (StatusCode::CREATED, Json(attachment)).into_response(),
+        Err(_) => {
+            delete_objects(store, &stored).await;
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save attachment".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/features/{feature_id}/attachments",
+    tag = "attachments",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("feature_id" = String, Path, description = "Primary key of the feature"),
+        PageParams,
+    ),
+    responses(
+        (status = 200, body = AttachmentPage),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn get_attachments(
+    State(state): State<AppState>,
+    Path((source_id, feature_id)): Path<(String, String)>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match Attachment::for_feature(&state.app_data, &source_id, &feature_id).await {
+        Ok(attachments) => page.respond(attachments),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list attachments".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+async fn serve_object(
+    state: &AppState,
+    source_id: &str,
+    feature_id: &str,
+    attachment_id: &str,
+    thumbnail: bool,
+) -> Response {
+    let Some(store) = state.storage.store() else {
+        return storage_disabled();
+    };
+    let attachment =
+        match Attachment::from_id(&state.app_data, source_id, feature_id, attachment_id).await {
+            Ok(attachment) => attachment,
+            Err(DataError::NotFound(_)) => {
+                return (StatusCode::NOT_FOUND, "Attachment not found".to_string()).into_response()
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read attachment".to_string(),
+                )
+                    .into_response()
+            }
+        };
+    let (key, content_type) = if thumbnail {
+        if !attachment.has_thumbnail {
+            return (
+                StatusCode::NOT_FOUND,
+                "Attachment has no thumbnail".to_string(),
+            )
+                .into_response();
+        }
+        (attachment.thumbnail_key(), "image/jpeg")
+    } else {
+        (attachment.object_key(), attachment.content_type.as_str())
+    };
+
+    match store.get(&state.storage.key(&key)).await {
+        Ok(Some(body)) => {
+            let filename: String = attachment
+                .filename
+                .chars()
+                .filter(|c| !c.is_control() && *c != '"' && *c != '\\')
+                .collect();
+            (
+                [
+                    (header::CONTENT_TYPE, content_type.to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("inline; filename=\"{filename}\""),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            "Attachment file is missing".to_string(),
+        )
+            .into_response(),
+        Err(e) => {
+            warn!("Failed to read attachment via {}: {e:#}", store.name());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read attachment".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/features/{feature_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("feature_id" = String, Path, description = "Primary key of the feature"),
+        ("attachment_id" = String, Path, description = "Attachment id"),
+    ),
+    responses(
+        (status = 200, description = "The attached file"),
+        (status = 404),
+        (status = 503, description = "Attachment storage is not configured"),
+    ),
+)]
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    Path((source_id, feature_id, attachment_id)): Path<(String, String, String)>,
+) -> Response {
+    serve_object(&state, &source_id, &feature_id, &attachment_id, false).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/features/{feature_id}/attachments/{attachment_id}/thumbnail",
+    tag = "attachments",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("feature_id" = String, Path, description = "Primary key of the feature"),
+        ("attachment_id" = String, Path, description = "Attachment id"),
+    ),
+    responses(
+        (status = 200, description = "JPEG thumbnail of an image attachment"),
+        (status = 404),
+        (status = 503, description = "Attachment storage is not configured"),
+    ),
+)]
+pub async fn attachment_thumbnail(
+    State(state): State<AppState>,
+    Path((source_id, feature_id, attachment_id)): Path<(String, String, String)>,
+) -> Response {
+    serve_object(&state, &source_id, &feature_id, &attachment_id, true).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/features/{feature_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("feature_id" = String, Path, description = "Primary key of the feature"),
+        ("attachment_id" = String, Path, description = "Attachment id"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404),
+        (status = 503, description = "Attachment storage is not configured"),
+    ),
+)]
+pub async fn delete_attachment(
+    State(state): State<AppState>,
+    Path((source_id, feature_id, attachment_id)): Path<(String, String, String)>,
+) -> Response {
+    let Some(store) = state.storage.store() else {
+        return storage_disabled();
+    };
+    let attachment =
+        match Attachment::from_id(&state.app_data, &source_id, &feature_id, &attachment_id).await {
+            Ok(attachment) => attachment,
+            Err(DataError::NotFound(_)) => {
+                return (StatusCode::NOT_FOUND, "Attachment not found".to_string()).into_response()
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read attachment".to_string(),
+                )
+                    .into_response()
+            }
+        };
+    if attachment.delete(&state.app_data).await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete attachment".to_string(),
+        )
+            .into_response();
+    }
+    let mut keys = vec![state.storage.key(&attachment.object_key())];
+    if attachment.has_thumbnail {
+        keys.push(state.storage.key(&attachment.thumbnail_key()));
+    }
+    delete_objects(store, &keys).await;
+    StatusCode::NO_CONTENT.into_response()
+}
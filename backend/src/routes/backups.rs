@@ -0,0 +1,132 @@
+use super::{check_api_key, ValidJson};
+use crate::app_state::AppState;
+use crate::backup::{self, BackupSummary, RestoreFilter};
+use crate::storage::BlobStore;
+use crate::validation::ValidationErrors;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, info};
+
+/// The storage backups go to, unless the endpoints are disabled, the caller
+/// has no API key or there is no storage.
+fn backup_store<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> Result<&'a dyn BlobStore, Response> {
+    if !state.backups.http {
+        return Err((StatusCode::NOT_FOUND, "Not found".to_string()).into_response());
+    }
+    if let Some(response) = check_api_key(state, headers) {
+        return Err(response);
+    }
+    state.storage.store().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Storage is not configured".to_string(),
+        )
+            .into_response()
+    })
+}
+
+/// Export every item in the metadata store to object storage. Disabled
+/// unless `backups.http` is set; callers authenticate with one of the
+/// signing API keys.
+#[utoipa::path(
+    post,
+    path = "/admin/backups",
+    tag = "admin",
+    params(("Authorization" = String, Header, description = "`Bearer` and a signing API key")),
+    responses(
+        (status = 201, body = BackupSummary),
+        (status = 401, description = "Missing API key"),
+        (status = 403, description = "Unknown API key"),
+        (status = 404, description = "Backup endpoints are disabled"),
+        (status = 503, description = "Storage is not configured"),
+    ),
+)]
+pub async fn create_backup(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let store = match backup_store(&state, &headers) {
+        Ok(store) => store,
+        Err(response) => return response,
+    };
+    match backup::create(&state.app_data, store, &state.backups.prefix).await {
+        Ok(summary) => {
+            info!("Backed up {} items to {}", summary.items, summary.name);
+            (StatusCode::CREATED, Json(summary)).into_response()
+        }
+        Err(e) => {
+            error!("Backup failed: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create backup".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Write the matching items of a backup over what is stored now. Servers
+/// cache layer settings, so restart them afterwards.
+#[utoipa::path(
+    post,
+    path = "/admin/backups/{name}/restore",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Name returned when the backup was created"),
+        ("Authorization" = String, Header, description = "`Bearer` and a signing API key"),
+    ),
+    request_body = RestoreFilter,
+    responses(
+        (status = 200, body = BackupSummary, description = "Items restored"),
+        (status = 401, description = "Missing API key"),
+        (status = 403, description = "Unknown API key"),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+        (status = 503, description = "Storage is not configured"),
+    ),
+)]
+pub async fn restore_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    ValidJson(filter): ValidJson<RestoreFilter>,
+) -> Response {
+    let store = match backup_store(&state, &headers) {
+        Ok(store) => store,
+        Err(response) => return response,
+    };
+    let items = match backup::load(store, &state.backups.prefix, &name).await {
+        Ok(Some(items)) => items,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Backup not found".to_string()).into_response(),
+        Err(e) => {
+            error!("Failed to read backup {name}: {e:#}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read backup".to_string(),
+            )
+                .into_response();
+        }
+    };
+    match backup::restore(&state.app_data, &items, &filter).await {
+        Ok(restored) => {
+            info!("Restored {restored} items from {name}");
+            Json(BackupSummary {
+                name,
+                items: restored,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            error!("Failed to restore backup {name}: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to restore backup".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use crate::app_state::AppState;
+use crate::core::Basemap;
+use axum::{extract::State, Json};
+
+/// The basemaps configured for the deployment, in display order.
+#[utoipa::path(
+    get,
+    path = "/basemaps",
+    tag = "maps",
+    responses(
+        (status = 200, body = Vec<Basemap>),
+    ),
+)]
+pub async fn get_basemaps(State(state): State<AppState>) -> Json<Vec<Basemap>> {
+    Json(state.basemaps.as_ref().clone())
+}
@@ -0,0 +1,238 @@
+use super::features::layer_table;
+use super::{check_output_name, resolve_output_name, spawn_layer_job, start_job, ValidJson};
+use crate::app_state::AppState;
+use crate::branches::{self, Branch, BranchDiff, BranchError, MergeReport};
+use crate::core::{Job, Layer};
+use crate::postgis::LayerTable;
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BranchRequest {
+    /// Shown to reviewers, e.g. `2024 survey corrections`.
+    pub name: String,
+    /// Table name for the branch's layer. Generated when omitted.
+    pub output_name: Option<String>,
+}
+
+impl Validate for BranchRequest {
+    fn check(&self, v: &mut Validator) {
+        v.length("name", &self.name, 1, 200);
+        check_output_name(v, &self.output_name);
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MergeConflicts {
+    /// Primary keys of features changed on both the branch and its parent.
+    pub conflicts: Vec<Value>,
+}
+
+fn branch_error(branch_id: &str, e: BranchError) -> Response {
+    match e {
+        BranchError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+        BranchError::Merged => (StatusCode::CONFLICT, e.to_string()).into_response(),
+        BranchError::Conflicts(conflicts) => {
+            (StatusCode::CONFLICT, Json(MergeConflicts { conflicts })).into_response()
+        }
+        BranchError::Invalid(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+        BranchError::Failed(e) => {
+            warn!("Branch {branch_id} failed: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to process branch".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The branch, its table, its parent's table and the key they share.
+async fn branch_tables(
+    state: &AppState,
+    branch_id: &str,
+) -> Result<(Branch, LayerTable, LayerTable, String), Response> {
+    let failed = |e: anyhow::Error| branch_error(branch_id, BranchError::Failed(e));
+    let branch = match branches::get(&state.pg_pool, branch_id).await {
+        Ok(Some(branch)) => branch,
+        Ok(None) => return Err(branch_error(branch_id, BranchError::NotFound("Branch"))),
+        Err(e) => return Err(failed(e)),
+    };
+    let table = layer_table(state, branch_id).await?;
+    let parent = layer_table(state, &branch.parent_id).await?;
+    match parent.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => Ok((branch, table, parent, key)),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            "Parent layer has no single-column primary key".to_string(),
+        )
+            .into_response()),
+        Err(e) => Err(failed(e)),
+    }
+}
+
+/// Branch the layer: copy its features, catalog metadata and form into a
+/// new layer that can be edited on its own and merged back, as a job.
+/// Needs a single-column primary key.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/branches",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = BranchRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "A layer with the output name exists"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_branch(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<BranchRequest>,
+) -> Response {
+    let parent = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let key = match parent.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(e) => return branch_error(&source_id, BranchError::Failed(e)),
+    };
+    let output = resolve_output_name(req.output_name, &source_id, "branch");
+    if state.sources.contains(&output) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Layer {output} already exists"),
+        )
+            .into_response();
+    }
+
+    let job = Job::new("layer:branch");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let database = state.app_data.clone();
+    let pool = state.pg_pool.clone();
+    let read_pool = state.read_pool.clone();
+    spawn_layer_job(&state, job, async move {
+        branches::create(&pool, &parent, &source_id, &key, &output, &req.name).await?;
+        Layer::copy(&database, &pool, &read_pool, &source_id, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+/// Branches made from the layer, newest first.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/branches",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = Vec<Branch>),
+        (status = 404),
+    ),
+)]
+pub async fn get_branches(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match branches::list(&state.pg_pool, &source_id).await {
+        Ok(branches) => Json(branches).into_response(),
+        Err(e) => branch_error(&source_id, BranchError::Failed(e)),
+    }
+}
+
+/// Features the branch added, modified and deleted since it was made, and
+/// those of its changes that conflict with changes to its parent.
+#[utoipa::path(
+    get,
+    path = "/branches/{branch_id}/diff",
+    tag = "layers",
+    params(("branch_id" = String, Path, description = "Tile source id of the branch")),
+    responses(
+        (status = 200, body = BranchDiff),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn get_branch_diff(
+    State(state): State<AppState>,
+    Path(branch_id): Path<String>,
+) -> Response {
+    let (_, table, parent, key) = match branch_tables(&state, &branch_id).await {
+        Ok(tables) => tables,
+        Err(response) => return response,
+    };
+    match branches::diff(&state.pg_pool, &branch_id, &table, &parent, &key).await {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => branch_error(&branch_id, e),
+    }
+}
+
+/// Apply the branch's changes to its parent and mark it merged. Nothing is
+/// applied while any change conflicts; resolve them on the branch, e.g. by
+/// editing the feature to match the parent's, and merge again.
+#[utoipa::path(
+    post,
+    path = "/branches/{branch_id}/merge",
+    tag = "layers",
+    params(("branch_id" = String, Path, description = "Tile source id of the branch")),
+    responses(
+        (status = 200, body = MergeReport),
+        (status = 400),
+        (status = 404),
+        (status = 409, body = MergeConflicts, description = "Changes conflict, or the branch was merged already"),
+    ),
+)]
+pub async fn merge_branch(
+    State(state): State<AppState>,
+    Path(branch_id): Path<String>,
+) -> Response {
+    let (branch, table, parent, key) = match branch_tables(&state, &branch_id).await {
+        Ok(tables) => tables,
+        Err(response) => return response,
+    };
+    let report = match branches::merge(&state.pg_pool, &branch_id, &table, &parent, &key).await {
+        Ok(report) => report,
+        Err(e) => return branch_error(&branch_id, e),
+    };
+    if let Err(e) = Layer::refresh(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &branch.parent_id,
+    )
+    .await
+    {
+        warn!(
+            "Failed to refresh layer {} after merging {branch_id}: {e}",
+            branch.parent_id
+        );
+    }
+    Json(report).into_response()
+}
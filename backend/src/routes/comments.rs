@@ -0,0 +1,173 @@
+use super::{CommentThreadPage, PageParams};
+use crate::app_state::AppState;
+use crate::core::{Comment, CommentAnchor, Map};
+use crate::validation::ValidationErrors;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CommentRequest {
+    pub author: String,
+    /// Mention people as `@name`.
+    pub body: String,
+    /// Where to pin a new thread. Replies take their thread's anchor.
+    pub anchor: Option<CommentAnchor>,
+    /// The comment starting the thread being replied to.
+    pub parent_id: Option<String>,
+}
+
+/// The map's comment threads, oldest first.
+#[utoipa::path(
+    get,
+    path = "/maps/{map_id}/comments",
+    tag = "comments",
+    params(("map_id" = String, Path), PageParams),
+    responses(
+        (status = 200, body = CommentThreadPage),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn get_comments(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    if let Err(e) = Map::from_id(&state.app_data, &map_id).await {
+        return e.into_response();
+    }
+    match Comment::threads(&state.app_data, &map_id).await {
+        Ok(threads) => page.respond(threads),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get comments".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Start a thread, or reply to one with `parent_id`.
+#[utoipa::path(
+    post,
+    path = "/maps/{map_id}/comments",
+    tag = "comments",
+    params(("map_id" = String, Path)),
+    request_body = CommentRequest,
+    responses(
+        (status = 201, body = Comment),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_comment(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    Json(request): Json<CommentRequest>,
+) -> Response {
+    if let Err(e) = Map::from_id(&state.app_data, &map_id).await {
+        return e.into_response();
+    }
+    if let Some(parent_id) = &request.parent_id {
+        match Comment::from_id(&state.app_data, &map_id, parent_id).await {
+            Ok(parent) if parent.parent_id.is_none() => {}
+            Ok(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Replies must be to the comment starting a thread".to_string(),
+                )
+                    .into_response()
+            }
+            Err(e) => return e.into_response(),
+        }
+    }
+    if let Some(CommentAnchor::Feature { layer_id, .. }) = &request.anchor {
+        if !state.sources.contains(layer_id) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown layer source: {layer_id}"),
+            )
+                .into_response();
+        }
+    }
+
+    let comment = match Comment::new(
+        &map_id,
+        request.parent_id,
+        &request.author,
+        &request.body,
+        request.anchor,
+    ) {
+        Ok(comment) => comment,
+        Err(e) => return e.into_response(),
+    };
+    match comment.create(&state.app_data).await {
+        Ok(_) => (StatusCode::CREATED, Json(comment)).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create comment".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+async fn set_resolved(
+    state: &AppState,
+    map_id: &str,
+    comment_id: &str,
+    resolved: bool,
+) -> Response {
+    let mut comment = match Comment::from_id(&state.app_data, map_id, comment_id).await {
+        Ok(comment) => comment,
+        Err(e) => return e.into_response(),
+    };
+    if comment.parent_id.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Only threads can be resolved, not replies".to_string(),
+        )
+            .into_response();
+    }
+    match comment.set_resolved(&state.app_data, resolved).await {
+        Ok(_) => Json(comment).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update comment".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/maps/{map_id}/comments/{comment_id}/resolve",
+    tag = "comments",
+    params(("map_id" = String, Path), ("comment_id" = String, Path)),
+    responses((status = 200, body = Comment), (status = 400), (status = 404)),
+)]
+pub async fn resolve_comment(
+    State(state): State<AppState>,
+    Path((map_id, comment_id)): Path<(String, String)>,
+) -> Response {
+    set_resolved(&state, &map_id, &comment_id, true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/maps/{map_id}/comments/{comment_id}/reopen",
+    tag = "comments",
+    params(("map_id" = String, Path), ("comment_id" = String, Path)),
+    responses((status = 200, body = Comment), (status = 400), (status = 404)),
+)]
+pub async fn reopen_comment(
+    State(state): State<AppState>,
+    Path((map_id, comment_id)): Path<(String, String)>,
+) -> Response {
+    set_resolved(&state, &map_id, &comment_id, false).await
+}
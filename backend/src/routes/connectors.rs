@@ -0,0 +1,183 @@
+use super::{start_job, ConnectorPage, PageParams};
+use crate::app_state::AppState;
+use crate::core::{Connector, ConnectorSource, Job};
+use crate::harvest;
+use crate::postgis;
+use crate::validation::ValidationErrors;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConnectorRequest {
+    pub source: ConnectorSource,
+    /// Name of the new layer the features are written to.
+    pub layer_id: String,
+    /// Seconds between scheduled harvests, at least 300. Omit to only
+    /// harvest on request.
+    pub refresh_interval: Option<u64>,
+}
+
+/// Create a connector. Harvest it with `POST /connectors/{id}/harvest`;
+/// connectors with a refresh interval are also harvested by the scheduler,
+/// starting at its next check.
+#[utoipa::path(
+    post,
+    path = "/connectors",
+    tag = "connectors",
+    request_body = ConnectorRequest,
+    responses(
+        (status = 201, body = Connector),
+        (status = 400),
+        (status = 409, description = "The layer, or a table of its name, already exists"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_connector(
+    State(state): State<AppState>,
+    Json(req): Json<ConnectorRequest>,
+) -> Response {
+    let connector = match Connector::new(req.source, &req.layer_id, req.refresh_interval) {
+        Ok(connector) => connector,
+        Err(e) => return e.into_response(),
+    };
+    // Harvests replace the layer's table, so connectors only write tables
+    // of their own
+    let connectors = match Connector::get_all(&state.app_data).await {
+        Ok(connectors) => connectors,
+        Err(e) => return e.into_response(),
+    };
+    let exists = match postgis::relation_exists(&state.pg_pool, &connector.layer_id).await {
+        Ok(exists) => exists,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create connector".to_string(),
+            )
+                .into_response()
+        }
+    };
+    if exists
+        || state.sources.contains(&connector.layer_id)
+        || connectors.iter().any(|c| c.layer_id == connector.layer_id)
+    {
+        return (
+            StatusCode::CONFLICT,
+            format!("Layer {} already exists", connector.layer_id),
+        )
+            .into_response();
+    }
+    match connector.create(&state.app_data).await {
+        Ok(_) => (StatusCode::CREATED, Json(connector)).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create connector".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/connectors",
+    tag = "connectors",
+    params(PageParams),
+    responses((status = 200, body = ConnectorPage), (status = 400)),
+)]
+pub async fn get_connectors(
+    State(state): State<AppState>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    match Connector::get_all(&state.app_data).await {
+        Ok(mut connectors) => {
+            connectors.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+            page.respond(connectors)
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get connectors".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/connectors/{connector_id}",
+    tag = "connectors",
+    params(("connector_id" = String, Path)),
+    responses((status = 200, body = Connector), (status = 404)),
+)]
+pub async fn get_connector(
+    State(state): State<AppState>,
+    Path(connector_id): Path<String>,
+) -> Response {
+    match Connector::from_id(&state.app_data, &connector_id).await {
+        Ok(connector) => Json(connector).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Stop harvesting. The layer is kept as last harvested.
+#[utoipa::path(
+    delete,
+    path = "/connectors/{connector_id}",
+    tag = "connectors",
+    params(("connector_id" = String, Path)),
+    responses((status = 204), (status = 404)),
+)]
+pub async fn delete_connector(
+    State(state): State<AppState>,
+    Path(connector_id): Path<String>,
+) -> Response {
+    let connector = match Connector::from_id(&state.app_data, &connector_id).await {
+        Ok(connector) => connector,
+        Err(e) => return e.into_response(),
+    };
+    match connector.delete(&state.app_data).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete connector".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Harvest the connector now, as a job. The layer's table is replaced once
+/// every page has been fetched.
+#[utoipa::path(
+    post,
+    path = "/connectors/{connector_id}/harvest",
+    tag = "connectors",
+    params(("connector_id" = String, Path)),
+    responses((status = 202, body = Job), (status = 404)),
+)]
+pub async fn harvest_connector(
+    State(state): State<AppState>,
+    Path(connector_id): Path<String>,
+) -> Response {
+    let mut connector = match Connector::from_id(&state.app_data, &connector_id).await {
+        Ok(connector) => connector,
+        Err(e) => return e.into_response(),
+    };
+
+    let job = Job::new("harvest");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let jobs = state.jobs.clone();
+    let database = state.app_data.clone();
+    jobs.spawn(job, database, async move {
+        harvest::run(&state, &mut connector).await?;
+        Ok(connector.layer_id)
+    });
+    response
+}
@@ -0,0 +1,386 @@
+use super::features::layer_table;
+use super::{versioned, ValidJson};
+use crate::app_state::AppState;
+use crate::changes::ChangeOperation;
+use crate::core::Layer;
+use crate::editing::{self, EditError, EditSession, SessionEdit};
+use crate::postgis::{validate_feature, LayerTable};
+use crate::topology::{self, TopologyReport};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::warn;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EditSessionRequest {
+    /// Shown to the editor, e.g. `Fix parcel boundaries`.
+    pub name: String,
+}
+
+impl Validate for EditSessionRequest {
+    fn check(&self, v: &mut Validator) {
+        v.length("name", &self.name, 1, 200);
+    }
+}
+
+impl Validate for SessionEdit {
+    fn check(&self, v: &mut Validator) {
+        v.check(
+            "feature",
+            self.operation == ChangeOperation::Delete || self.feature.is_some(),
+            "required",
+            "inserts and updates need a feature",
+        );
+        v.check(
+            "id",
+            self.operation == ChangeOperation::Insert || self.id.is_some(),
+            "required",
+            "updates and deletes need the feature id",
+        );
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionEditResult {
+    /// Primary key of the feature, assigned by the server for inserts.
+    pub id: Value,
+    pub session: EditSession,
+}
+
+fn edit_error(source_id: &str, e: EditError) -> Response {
+    match e {
+        EditError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+        EditError::Conflict(message) => (StatusCode::CONFLICT, message).into_response(),
+        EditError::Invalid(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+        EditError::Failed(e) => {
+            warn!("Edit session on {source_id} failed: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to edit layer".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The layer's table and the primary key features are addressed by.
+async fn keyed_table(state: &AppState, source_id: &str) -> Result<(LayerTable, String), Response> {
+    let table = layer_table(state, source_id).await?;
+    match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => Ok((table, key)),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            "Layer has no single-column primary key".to_string(),
+        )
+            .into_response()),
+        Err(e) => Err(edit_error(source_id, EditError::Failed(e))),
+    }
+}
+
+/// Open an edit session on the layer. Its edits can be undone and redone
+/// one at a time until it is committed or discarded. Needs a single-column
+/// primary key.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/edit-sessions",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = EditSessionRequest,
+    responses(
+        (status = 201, body = EditSession),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_edit_session(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<EditSessionRequest>,
+) -> Response {
+    let (table, _) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match editing::start(&state.pg_pool, &table, &req.name).await {
+        Ok(session) => (StatusCode::CREATED, Json(session)).into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/edit-sessions/{session_id}",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    responses(
+        (status = 200, body = EditSession),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn get_edit_session(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match editing::get(&state.pg_pool, &table, &session_id).await {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
+
+/// Write an edit to the layer as the next step of the session, checked as
+/// sync checks edits. Undone steps can no longer be redone after it.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/edit-sessions/{session_id}/edits",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    request_body = SessionEdit,
+    responses(
+        (status = 200, body = SessionEditResult),
+        (status = 400),
+        (status = 404),
+        (status = 409, body = TopologyReport, description = "The session is closed, the layer is full, or the geometry breaks the layer's topology rules"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn edit_in_session(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+    ValidJson(mut edit): ValidJson<SessionEdit>,
+) -> Response {
+    let (table, key) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let failed = |e: anyhow::Error| edit_error(&source_id, EditError::Failed(e));
+    let layer = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => layer,
+        Err(e) => return failed(e),
+    };
+
+    if edit.operation == ChangeOperation::Insert {
+        match table.room(&state.pg_pool, &state.limits).await {
+            Ok(Some(0)) => {
+                return (
+                    StatusCode::CONFLICT,
+                    format!(
+                        "Layer is at its limit of {} features",
+                        state.limits.max_features
+                    ),
+                )
+                    .into_response()
+            }
+            Ok(_) => {}
+            Err(e) => return failed(e),
+        }
+    }
+    if let Some(feature) = edit.feature.as_mut() {
+        let columns: HashSet<String> = match table.attribute_columns(&state.pg_pool).await {
+            Ok(columns) => columns.into_iter().collect(),
+            Err(e) => return failed(e),
+        };
+        let form = layer.form.as_ref();
+        let partial = edit.operation == ChangeOperation::Update;
+        let quality = layer
+            .quality
+            .as_ref()
+            .map(|quality| quality.checker(table.srid));
+        let checked = validate_feature(feature, &columns, &state.limits)
+            .and_then(|()| match form {
+                Some(form) => form.check(feature, partial),
+                None => Ok(()),
+            })
+            .and_then(|()| match &quality {
+                Some(quality) => quality.enforce(feature, partial).map(|_| ()),
+                None => Ok(()),
+            });
+        if let Err(message) = checked {
+            return ValidationErrors::single("feature", "invalid_feature", message).into_response();
+        }
+        if let Some(rules) = form.and_then(|form| form.topology.as_ref()) {
+            let report = match topology::check(
+                &state.pg_pool,
+                &table,
+                &key,
+                edit.id.as_ref(),
+                &feature["geometry"],
+                rules,
+            )
+            .await
+            {
+                Ok(report) => report,
+                Err(e) => return failed(e),
+            };
+            if !report.violations.is_empty() {
+                return (StatusCode::CONFLICT, Json(report)).into_response();
+            }
+            feature["geometry"] = report.geometry;
+        }
+    }
+
+    let id = match editing::apply(&state.pg_pool, &table, &key, &session_id, &edit).await {
+        Ok(id) => id,
+        Err(e) => return edit_error(&source_id, e),
+    };
+    match editing::get(&state.pg_pool, &table, &session_id).await {
+        Ok(session) => Json(SessionEditResult { id, session }).into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
+
+/// Revert the session's newest edit.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/edit-sessions/{session_id}/undo",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    responses(
+        (status = 200, body = EditSession),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "Nothing to undo, the session is closed, or the feature was changed outside it"),
+    ),
+)]
+pub async fn undo_edit(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+) -> Response {
+    let (table, key) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match editing::undo(&state.pg_pool, &table, &key, &session_id).await {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
+
+/// Reapply the session's most recently undone edit.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/edit-sessions/{session_id}/redo",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    responses(
+        (status = 200, body = EditSession),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "Nothing to redo, the session is closed, or the feature was changed outside it"),
+    ),
+)]
+pub async fn redo_edit(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+) -> Response {
+    let (table, key) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match editing::redo(&state.pg_pool, &table, &key, &session_id).await {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
+
+/// Keep the session's edits and close it, bumping the layer's version.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/edit-sessions/{session_id}/commit",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    responses(
+        (status = 200, body = Layer),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The session is closed"),
+    ),
+)]
+pub async fn commit_edit_session(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+) -> Response {
+    let (table, key) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    if let Err(e) = editing::commit(&state.pg_pool, &table, &key, &session_id).await {
+        return edit_error(&source_id, e);
+    }
+    match Layer::refresh(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => edit_error(&source_id, EditError::Failed(e)),
+    }
+}
+
+/// Revert all of the session's edits and close it.
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/edit-sessions/{session_id}",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("session_id" = String, Path, description = "Edit session id"),
+    ),
+    responses(
+        (status = 204),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The session is closed, or a feature was changed outside it"),
+    ),
+)]
+pub async fn discard_edit_session(
+    State(state): State<AppState>,
+    Path((source_id, session_id)): Path<(String, String)>,
+) -> Response {
+    let (table, key) = match keyed_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match editing::discard(&state.pg_pool, &table, &key, &session_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => edit_error(&source_id, e),
+    }
+}
@@ -0,0 +1,121 @@
+use super::shares::active_share;
+use crate::app_state::AppState;
+use crate::core::{Basemap, Map, Share, ShareResource};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+const EMBED_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="stylesheet" href="https://unpkg.com/maplibre-gl@4/dist/maplibre-gl.css">
+<script src="https://unpkg.com/maplibre-gl@4/dist/maplibre-gl.js"></script>
+<style>html, body, #map { margin: 0; height: 100%; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+new maplibregl.Map({ container: "map", style: {style_url} });
+</script>
+</body>
+</html>
+"#;
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Resolve an embeddable (map) share and the map behind it.
+async fn embeddable(state: &AppState, token: &str) -> Result<(Share, Map), Response> {
+    let not_found = || (StatusCode::NOT_FOUND, "Share not found".to_string()).into_response();
+    let share = active_share(state, token).await.ok_or_else(not_found)?;
+    if share.resource != ShareResource::Map {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Only maps can be embedded".to_string(),
+        )
+            .into_response());
+    }
+    let map = Map::from_id(&state.app_data, &share.resource_id)
+        .await
+        .map_err(|_| not_found())?;
+    Ok((share, map))
+}
+
+fn style_url(state: &AppState, share: &Share) -> String {
+    format!("{}/shared/{}/style.json", state.public_url, share.token)
+}
+
+/// The embed policy, restricting which sites may frame the response to the
+/// share's allowlist.
+fn with_frame_ancestors(state: &AppState, share: &Share, mut response: Response) -> Response {
+    let policy = state
+        .security_headers
+        .embed_policy(&share.frame_ancestors());
+    if let Ok(value) = HeaderValue::from_str(&policy) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_SECURITY_POLICY, value);
+    }
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/embed/{token}",
+    tag = "sharing",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "Embeddable HTML map viewer", content_type = "text/html"),
+        (status = 404),
+    ),
+)]
+pub async fn embed_map(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    let (share, map) = match embeddable(&state, &token).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let style_url = serde_json::to_string(&style_url(&state, &share)).unwrap_or_default();
+    let page = EMBED_TEMPLATE
+        .replace("{title}", &escape_html(&map.name))
+        .replace("{style_url}", &style_url);
+    with_frame_ancestors(&state, &share, Html(page).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/embed/{token}/config.json",
+    tag = "sharing",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "Viewer configuration"),
+        (status = 404),
+    ),
+)]
+pub async fn embed_config(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    let (share, map) = match embeddable(&state, &token).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let config = json!({
+        "name": map.name,
+        "style_url": style_url(&state, &share),
+        "viewport": map.viewport,
+        "basemap": Basemap::for_map(&state.basemaps, map.basemap.as_deref()),
+        "allowed_origins": share.allowed_origins,
+    });
+    with_frame_ancestors(&state, &share, Json(config).into_response())
+}
@@ -0,0 +1,34 @@
+use crate::data::DataError;
+use crate::validation::ValidationErrors;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::error;
+
+impl IntoResponse for DataError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            DataError::NotFound(_) => StatusCode::NOT_FOUND,
+            DataError::Conflict(_) => StatusCode::CONFLICT,
+            DataError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            DataError::Backend(e) => {
+                // Backend details stay in the logs
+                error!("Data store error: {e:#}");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error".to_string(),
+                )
+                    .into_response();
+            }
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(self)).into_response()
+    }
+}
@@ -0,0 +1,35 @@
+use crate::app_state::AppState;
+use axum::{
+    extract::State,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+};
+use std::convert::Infallible;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Stream changes to layers, maps, shares and jobs as server-sent events.
+/// Each event is named after its `type` and carries the JSON event as data.
+/// A `lagged` event means some updates were dropped and clients should
+/// refetch.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    responses(
+        (status = 200, description = "Stream of `Event`s", content_type = "text/event-stream"),
+    ),
+)]
+pub async fn get_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(|event| match event {
+        Ok(event) => SseEvent::default()
+            .event(event.name())
+            .json_data(&event)
+            .ok(),
+        Err(BroadcastStreamRecvError::Lagged(_)) => {
+            Some(SseEvent::default().event("lagged").data(""))
+        }
+    });
+    Sse::new(stream.map(Ok)).keep_alive(KeepAlive::default())
+}
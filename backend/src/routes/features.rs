@@ -0,0 +1,878 @@
+use super::{scan_download, start_job, ValidJson};
+use crate::app_state::AppState;
+use crate::changes::{self, FeatureChange, LayerDiff};
+use crate::config::LimitsConfig;
+use crate::core::{Form, Job, Layer};
+use crate::postgis::{feature_columns, validate_feature, LayerTable};
+use crate::quality::QualityChecker;
+use crate::schedules::parse_download;
+use crate::sync::{
+    AppliedEdit, ClientEdit, ConflictStrategy, PushResult, RejectedEdit, SyncConflict, SyncSession,
+};
+use crate::topology::{self, TopologyReport};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use anyhow::anyhow;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use tokio_stream::StreamExt;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const MAX_BATCH_SIZE: usize = 10_000;
+const MAX_LINE_BYTES: usize = 16 * 1024 * 1024;
+const MAX_REPORTED_ERRORS: usize = 1000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchParams {
+    /// Features inserted per statement, up to 10000. Defaults to 500.
+    pub batch_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LineError {
+    /// 1-based line number, or position in a FeatureCollection.
+    pub line: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BatchReport {
+    pub inserted: u64,
+    pub failed: usize,
+    /// Features inserted though they break the layer's quality rules.
+    pub flagged: usize,
+    /// The first 1000 failures; `failed` counts them all.
+    pub errors: Vec<LineError>,
+}
+
+impl BatchReport {
+    fn fail(&mut self, line: usize, lines: usize, error: String) {
+        self.failed += lines;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(LineError { line, error });
+        }
+    }
+}
+
+/// Validates features line by line and inserts them a batch at a time. A
+/// batch the database rejects fails as a whole, reported against its first
+/// line.
+struct BatchWriter<'a> {
+    pool: &'a Pool,
+    table: LayerTable,
+    columns: HashSet<String>,
+    form: Option<Form>,
+    quality: Option<QualityChecker>,
+    batch_size: usize,
+    limits: &'a LimitsConfig,
+    /// Features the layer has room for, less those pending.
+    room: Option<u64>,
+    pending: Vec<(usize, Value)>,
+    report: BatchReport,
+}
+
+impl<'a> BatchWriter<'a> {
+    async fn new(
+        state: &'a AppState,
+        source_id: &str,
+        table: LayerTable,
+        batch_size: usize,
+    ) -> anyhow::Result<Self> {
+        let columns = table.attribute_columns(&state.pg_pool).await?;
+        let layer =
+            Layer::from_id(&state.app_data, &state.pg_pool, &state.read_pool, source_id).await?;
+        let room = table.room(&state.pg_pool, &state.limits).await?;
+        Ok(BatchWriter {
+            pool: &state.pg_pool,
+            quality: layer.quality.map(|quality| quality.checker(table.srid)),
+            table,
+            columns: columns.into_iter().collect(),
+            form: layer.form,
+            batch_size,
+            limits: &state.limits,
+            room,
+            pending: Vec::new(),
+            report: BatchReport::default(),
+        })
+    }
+
+    async fn push(&mut self, line: usize, bytes: &[u8]) {
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        match serde_json::from_slice(bytes) {
+            Ok(feature) => self.push_feature(line, feature).await,
+            Err(e) => self.report.fail(line, 1, format!("Invalid JSON: {e}")),
+        }
+    }
+
+    async fn push_feature(&mut self, line: usize, feature: Value) {
+        if self.room == Some(0) {
+            let error = format!(
+                "Layer is at its limit of {} features",
+                self.limits.max_features
+            );
+            self.report.fail(line, 1, error);
+            return;
+        }
+        match self.validate(&feature) {
+            Ok(flagged) => {
+                if flagged {
+                    self.report.flagged += 1;
+                }
+                self.pending.push((line, feature));
+                self.room = self.room.map(|room| room - 1);
+            }
+            Err(e) => self.report.fail(line, 1, e),
+        }
+        if self.pending.len() >= self.batch_size {
+            self.flush().await;
+        }
+    }
+
+    /// Whether the feature may be written and, if so, whether it breaks a
+    /// quality rule that does not block.
+    fn validate(&self, feature: &Value) -> Result<bool, String> {
+        validate_feature(feature, &self.columns, self.limits)?;
+        if let Some(form) = &self.form {
+            form.check(feature, false)?;
+        }
+        match &self.quality {
+            Some(quality) => quality.enforce(feature, false),
+            None => Ok(false),
+        }
+    }
+
+    async fn flush(&mut self) {
+        let Some(&(first_line, _)) = self.pending.first() else {
+            return;
+        };
+        let batch = std::mem::take(&mut self.pending);
+        let columns = feature_columns(batch.iter().map(|(_, feature)| feature));
+        let lines = batch.len();
+        let features = Value::Array(batch.into_iter().map(|(_, feature)| feature).collect());
+        match self
+            .table
+            .insert_features(self.pool, &columns, &features)
+            .await
+        {
+            Ok(inserted) => self.report.inserted += inserted,
+            Err(e) => {
+                self.room = self.room.map(|room| room + lines as u64);
+                self.report.fail(
+                    first_line,
+                    lines,
+                    format!("Batch of {lines} features starting here failed: {e:#}"),
+                )
+            }
+        }
+    }
+}
+
+/// Insert newline-delimited GeoJSON features into the layer's table. The
+/// body is streamed, so uploads are not bound by the usual request size
+/// limit. Features are validated per line, including against the configured
+/// vertex, property and per-layer feature limits, and inserted in batches;
+/// lines that fail are reported and the rest are kept.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/features/batch",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        BatchParams,
+    ),
+    request_body(content = String, content_type = "application/x-ndjson"),
+    responses(
+        (status = 200, body = BatchReport),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn insert_features(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(params): Query<BatchParams>,
+    body: Body,
+) -> Response {
+    let batch_size = params.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    if batch_size == 0 || batch_size > MAX_BATCH_SIZE {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("batch_size must be between 1 and {MAX_BATCH_SIZE}"),
+        )
+            .into_response();
+    }
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let mut writer = match BatchWriter::new(&state, &source_id, table, batch_size).await {
+        Ok(writer) => writer,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read layer metadata".to_string(),
+            )
+                .into_response()
+        }
+    };
+    let mut stream = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut line = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                writer
+                    .report
+                    .fail(line + 1, 0, format!("Failed to read request body: {e}"));
+                buffer.clear();
+                break;
+            }
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            line += 1;
+            writer.push(line, &buffer[..end]).await;
+            buffer.drain(..=end);
+        }
+        if buffer.len() > MAX_LINE_BYTES {
+            writer.report.fail(
+                line + 1,
+                0,
+                format!("Line exceeds {MAX_LINE_BYTES} bytes; stopped reading"),
+            );
+            buffer.clear();
+            break;
+        }
+    }
+    if !buffer.is_empty() {
+        line += 1;
+        writer.push(line, &buffer).await;
+    }
+    writer.flush().await;
+
+    if writer.report.inserted > 0 {
+        if let Err(e) = Layer::refresh(
+            &state.app_data,
+            &state.pg_pool,
+            &state.read_pool,
+            &source_id,
+        )
+        .await
+        {
+            warn!("Failed to refresh layer {source_id} after inserting features: {e}");
+        }
+    }
+    Json(writer.report).into_response()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportUrlRequest {
+    /// HTTP(S) URL of a GeoJSON FeatureCollection, Feature or
+    /// newline-delimited GeoJSON file.
+    pub url: String,
+}
+
+impl Validate for ImportUrlRequest {
+    fn check(&self, v: &mut Validator) {
+        v.http_url("url", &self.url);
+    }
+}
+
+/// Download GeoJSON from a URL and insert its features into the layer's
+/// table, as a job. The URL must resolve to a public address. Features are
+/// validated and inserted as by the batch endpoint; if any fail the job
+/// fails with a summary, keeping the features that were inserted.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/import-url",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = ImportUrlRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn import_url(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<ImportUrlRequest>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+
+    let job = Job::new("import:url");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let jobs = state.jobs.clone();
+    let database = state.app_data.clone();
+    jobs.spawn_with_progress(job, database, |progress| async move {
+        let body = state.fetcher.get(&req.url).await?;
+        scan_download(&state, &progress, &body).await?;
+        let mut writer = BatchWriter::new(&state, &source_id, table, DEFAULT_BATCH_SIZE).await?;
+        match parse_download(&body)? {
+            Some(features) => {
+                for (index, feature) in features.into_iter().enumerate() {
+                    writer.push_feature(index + 1, feature).await;
+                }
+            }
+            None => {
+                for (index, line) in body.split(|b| *b == b'\n').enumerate() {
+                    writer.push(index + 1, line).await;
+                }
+            }
+        }
+        writer.flush().await;
+
+        let report = writer.report;
+        if report.inserted > 0 {
+            Layer::refresh(
+                &state.app_data,
+                &state.pg_pool,
+                &state.read_pool,
+                &source_id,
+            )
+            .await?;
+        }
+        if let Some(first) = report.errors.first() {
+            return Err(anyhow!(
+                "{} features inserted, {} failed; line {}: {}",
+                report.inserted,
+                report.failed,
+                first.line,
+                first.error
+            ));
+        }
+        Ok(source_id)
+    });
+    response
+}
+
+const DEFAULT_CHANGE_LIMIT: i64 = 1000;
+const MAX_CHANGE_LIMIT: i64 = 10_000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangeParams {
+    /// Cursor from a previous page; omit to read from the start.
+    pub since: Option<i64>,
+    /// Changes per page, up to 10000. Defaults to 1000.
+    pub limit: Option<i64>,
+}
+
+impl Validate for SyncRequest {
+    fn check(&self, v: &mut Validator) {
+        if let Some(limit) = self.limit {
+            v.range("limit", limit, 1, MAX_CHANGE_LIMIT);
+        }
+        v.count("edits", self.edits.len(), 0, MAX_SYNC_EDITS);
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChangeFeed {
+    pub changes: Vec<FeatureChange>,
+    /// Pass as `since` to fetch the next page.
+    pub cursor: i64,
+    pub has_more: bool,
+}
+
+/// Look up the table behind a layer, answering with the error response when
+/// there is none.
+pub(super) async fn layer_table(state: &AppState, source_id: &str) -> Result<LayerTable, Response> {
+    if !state.sources.contains(source_id) {
+        return Err((StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response());
+    }
+    let table = LayerTable::from_source_id(&state.pg_pool, source_id)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Layer is not backed by a table".to_string(),
+            )
+                .into_response()
+        })?;
+    if table.is_view {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Layer is a SQL view; edit the tables it selects from".to_string(),
+        )
+            .into_response());
+    }
+    Ok(table)
+}
+
+/// Like `layer_table`, additionally requiring change tracking.
+async fn tracked_table(state: &AppState, source_id: &str) -> Result<LayerTable, Response> {
+    let table = layer_table(state, source_id).await?;
+    match changes::is_tracked(&state.pg_pool, &table).await {
+        Ok(true) => Ok(table),
+        Ok(false) => Err((
+            StatusCode::CONFLICT,
+            "Change tracking is not enabled for this layer".to_string(),
+        )
+            .into_response()),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read change tracking state".to_string(),
+        )
+            .into_response()),
+    }
+}
+
+/// Start recording feature-level changes to the layer, so they can be read
+/// from its change feed. Changes made before this are not available.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/changes/track",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 204),
+        (status = 400),
+        (status = 404),
+    ),
+)]
+pub async fn track_changes(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    match changes::track(&state.pg_pool, &table).await {
+        Ok(()) => {
+            // Diffs can start from the version tracking began at
+            let marked = async {
+                let layer = Layer::from_id(
+                    &state.app_data,
+                    &state.pg_pool,
+                    &state.read_pool,
+                    &source_id,
+                )
+                .await?;
+                changes::mark_version(&state.pg_pool, &source_id, layer.version).await
+            };
+            if let Err(e) = marked.await {
+                warn!("Failed to record the version of {source_id}: {e:#}");
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            warn!("Failed to track changes to {source_id}: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to enable change tracking".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Inserts, updates and deletes of the layer's features in the order they
+/// were made, paged by cursor.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/changes",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ChangeParams,
+    ),
+    responses(
+        (status = 200, body = ChangeFeed),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "Change tracking is not enabled for the layer"),
+    ),
+)]
+pub async fn get_changes(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(params): Query<ChangeParams>,
+) -> Response {
+    let limit = params.limit.unwrap_or(DEFAULT_CHANGE_LIMIT);
+    if !(1..=MAX_CHANGE_LIMIT).contains(&limit) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("limit must be between 1 and {MAX_CHANGE_LIMIT}"),
+        )
+            .into_response();
+    }
+    let table = match tracked_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+
+    let since = params.since.unwrap_or(0);
+    match changes::since(&state.pg_pool, &table, since, limit + 1).await {
+        Ok(mut changes) => {
+            let has_more = changes.len() as i64 > limit;
+            changes.truncate(limit as usize);
+            let cursor = changes.last().map_or(since, |change| change.id);
+            Json(ChangeFeed {
+                changes,
+                cursor,
+                has_more,
+            })
+            .into_response()
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read changes".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffParams {
+    /// The earlier layer version.
+    pub from: u64,
+    /// The later layer version; omit to compare with the latest changes.
+    pub to: Option<u64>,
+}
+
+/// Features added, removed and modified between two versions of the layer,
+/// with the attributes and geometry that changed. Versions from before
+/// change tracking was enabled can't be compared. At most 10000 features
+/// are returned.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/diff",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        DiffParams,
+    ),
+    responses(
+        (status = 200, body = LayerDiff),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "Change tracking is not enabled for the layer"),
+    ),
+)]
+pub async fn get_layer_diff(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(params): Query<DiffParams>,
+) -> Response {
+    if params.to.is_some_and(|to| to < params.from) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "from must not be after to".to_string(),
+        )
+            .into_response();
+    }
+    let table = match tracked_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let failed = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to compare versions".to_string(),
+        )
+            .into_response()
+    };
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(_) => return failed(),
+    };
+    let position = |version: u64| changes::version_position(&state.pg_pool, &table, version);
+    let from = match position(params.from).await {
+        Ok(Some(from)) => from,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Version {} predates change tracking", params.from),
+            )
+                .into_response()
+        }
+        Err(_) => return failed(),
+    };
+    let to = match params.to {
+        Some(version) => match position(version).await {
+            Ok(to) => to,
+            Err(_) => return failed(),
+        },
+        None => None,
+    };
+    match changes::diff(&state.pg_pool, &table, &key, from, to, MAX_CHANGE_LIMIT).await {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => {
+            warn!("Failed to diff {source_id}: {e:#}");
+            failed()
+        }
+    }
+}
+
+const MAX_SYNC_EDITS: usize = 1000;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SyncRequest {
+    /// Cursor from the client's last sync; 0 on first sync.
+    #[serde(default)]
+    pub since: i64,
+    #[serde(default)]
+    pub strategy: ConflictStrategy,
+    #[serde(default)]
+    pub edits: Vec<ClientEdit>,
+    /// Changes to return, up to 10000. Defaults to 1000.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SyncResponse {
+    pub applied: Vec<AppliedEdit>,
+    pub conflicts: Vec<SyncConflict>,
+    pub rejected: Vec<RejectedEdit>,
+    /// Changes since `since`, including the edits just applied.
+    pub changes: Vec<FeatureChange>,
+    pub cursor: i64,
+    /// Sync again from `cursor`, without edits, for the remaining changes.
+    pub has_more: bool,
+}
+
+/// Push offline edits and pull what changed since the last sync. Edits to
+/// features the server changed since `since` are conflicts, resolved by
+/// `strategy`. Needs change tracking and a single-column primary key.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/sync",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = SyncRequest,
+    responses(
+        (status = 200, body = SyncResponse),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "Change tracking is not enabled for the layer"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn sync_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<SyncRequest>,
+) -> Response {
+    let limit = req.limit.unwrap_or(DEFAULT_CHANGE_LIMIT);
+    let table = match tracked_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to sync layer".to_string(),
+            )
+                .into_response()
+        }
+    };
+
+    let pushed = if req.edits.is_empty() {
+        PushResult::default()
+    } else {
+        let session = async {
+            let layer = Layer::from_id(
+                &state.app_data,
+                &state.pg_pool,
+                &state.read_pool,
+                &source_id,
+            )
+            .await?;
+            SyncSession::new(
+                &state.pg_pool,
+                &table,
+                key,
+                layer,
+                &state.limits,
+                req.since,
+                req.strategy,
+            )
+            .await
+        };
+        match session.await {
+            Ok(session) => session.push(req.edits).await,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to sync layer".to_string(),
+                )
+                    .into_response()
+            }
+        }
+    };
+    if !pushed.applied.is_empty() {
+        if let Err(e) = Layer::refresh(
+            &state.app_data,
+            &state.pg_pool,
+            &state.read_pool,
+            &source_id,
+        )
+        .await
+        {
+            warn!("Failed to refresh layer {source_id} after sync: {e}");
+        }
+    }
+
+    match changes::since(&state.pg_pool, &table, req.since, limit + 1).await {
+        Ok(mut changes) => {
+            let has_more = changes.len() as i64 > limit;
+            changes.truncate(limit as usize);
+            let cursor = changes.last().map_or(req.since, |change| change.id);
+            Json(SyncResponse {
+                applied: pushed.applied,
+                conflicts: pushed.conflicts,
+                rejected: pushed.rejected,
+                changes,
+                cursor,
+                has_more,
+            })
+            .into_response()
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read changes".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TopologyCheckRequest {
+    /// WGS84 GeoJSON geometry.
+    #[schema(value_type = Object)]
+    pub geometry: Value,
+    /// Primary key of the feature being edited, left out of its neighbours.
+    pub id: Option<Value>,
+}
+
+impl Validate for TopologyCheckRequest {
+    fn check(&self, v: &mut Validator) {
+        v.check(
+            "geometry",
+            self.geometry.get("type").is_some_and(Value::is_string),
+            "invalid_geometry",
+            "must be a GeoJSON geometry",
+        );
+    }
+}
+
+/// Snap a geometry to its neighbours and check it against the layer's
+/// topology rules, as a sync would, without writing it. Lets editors show
+/// problems while drawing.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/topology/check",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = TopologyCheckRequest,
+    responses(
+        (status = 200, body = TopologyReport),
+        (status = 400),
+        (status = 404, description = "Layer not found, or it has no topology rules"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn check_topology(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<TopologyCheckRequest>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let failed = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check topology".to_string(),
+        )
+            .into_response()
+    };
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(_) => return failed(),
+    };
+    let rules = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => layer.form.and_then(|form| form.topology),
+        Err(_) => return failed(),
+    };
+    let Some(rules) = rules else {
+        return (
+            StatusCode::NOT_FOUND,
+            "Layer has no topology rules".to_string(),
+        )
+            .into_response();
+    };
+    match topology::check(
+        &state.read_pool,
+        &table,
+        &key,
+        req.id.as_ref(),
+        &req.geometry,
+        &rules,
+    )
+    .await
+    {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => {
+            warn!("Failed to check topology of {source_id}: {e:#}");
+            failed()
+        }
+    }
+}
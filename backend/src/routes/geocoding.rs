@@ -0,0 +1,314 @@
+use super::features::layer_table;
+use super::{
+    check_output_name, resolve_output_name, start_job, GeocodeFailurePage, PageParams, ValidJson,
+};
+use crate::app_state::AppState;
+use crate::core::{Job, Layer};
+use crate::data::DataError;
+use crate::geocoding::{self, BatchGeocode, Busy};
+use crate::postgis::LayerTable;
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, warn};
+use utoipa::{IntoParams, ToSchema};
+
+const MAX_RESULTS: usize = 20;
+/// Kind of batch geocoding jobs.
+const GEOCODE_JOB: &str = "geocode:layer";
+
+/// 503 with `Retry-After` when the provider's rate limit is taken, 502 for
+/// anything else.
+fn provider_failed(e: anyhow::Error, context: &str) -> Response {
+    if let Some(busy) = e.downcast_ref::<Busy>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, busy.retry_after.as_secs().to_string())],
+            "Geocoding provider is busy".to_string(),
+        )
+            .into_response();
+    }
+    error!("{context}: {e}");
+    (
+        StatusCode::BAD_GATEWAY,
+        "Geocoding provider unavailable".to_string(),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GeocodeParams {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/geocode",
+    tag = "geocoding",
+    params(GeocodeParams),
+    responses(
+        (status = 200, description = "The provider name and matching places, under `results`"),
+        (status = 400),
+        (status = 502),
+        (status = 503, description = "Too many requests are queued for the provider"),
+    ),
+)]
+pub async fn geocode(
+    State(state): State<AppState>,
+    Query(params): Query<GeocodeParams>,
+) -> Response {
+    let query = params.q.trim();
+    if query.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Query must not be empty".to_string(),
+        )
+            .into_response();
+    }
+    let limit = params.limit.unwrap_or(5).clamp(1, MAX_RESULTS);
+
+    match state.geocoder.search(query, limit).await {
+        Ok(results) => Json(json!({
+            "provider": state.geocoder.provider_name(),
+            "results": results,
+        }))
+        .into_response(),
+        Err(e) => provider_failed(e, "Geocoding failed"),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReverseParams {
+    pub lat: f64,
+    pub lon: f64,
+    /// Ask the geocoding provider for nearby places.
+    #[serde(default = "default_true")]
+    pub provider: bool,
+    /// Look the point up in the configured boundary layers.
+    #[serde(default = "default_true")]
+    pub boundaries: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/reverse",
+    tag = "geocoding",
+    params(ReverseParams),
+    responses(
+        (status = 200, description = "Places and containing boundaries at the point"),
+        (status = 400),
+        (status = 502),
+        (status = 503, description = "Too many requests are queued for the provider"),
+    ),
+)]
+pub async fn reverse_geocode(
+    State(state): State<AppState>,
+    Query(params): Query<ReverseParams>,
+) -> Response {
+    if !(-180.0..=180.0).contains(&params.lon) || !(-90.0..=90.0).contains(&params.lat) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Coordinates out of range".to_string(),
+        )
+            .into_response();
+    }
+
+    let mut results = Vec::new();
+    if params.provider {
+        match state.geocoder.reverse(params.lon, params.lat).await {
+            Ok(places) => results = places,
+            Err(e) => return provider_failed(e, "Reverse geocoding failed"),
+        }
+    }
+
+    let mut boundaries = Vec::new();
+    if params.boundaries {
+        for source_id in &state.boundary_layers {
+            let features = match LayerTable::from_source_id(&state.read_pool, source_id).await {
+                Ok(table) => {
+                    table
+                        .features_at(&state.read_pool, params.lon, params.lat)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match features {
+                Ok(features) => boundaries.extend(
+                    features
+                        .into_iter()
+                        .map(|properties| json!({ "layer": source_id, "properties": properties })),
+                ),
+                Err(e) => warn!("Boundary lookup on {source_id} failed: {e}"),
+            }
+        }
+    }
+
+    Json(json!({
+        "provider": state.geocoder.provider_name(),
+        "results": results,
+        "boundaries": boundaries,
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GeocodeLayerRequest {
+    #[serde(flatten)]
+    pub geocode: BatchGeocode,
+    /// Table name for the point layer. Generated when omitted.
+    pub output_name: Option<String>,
+}
+
+impl Validate for GeocodeLayerRequest {
+    fn check(&self, v: &mut Validator) {
+        self.geocode.check(v);
+        check_output_name(v, &self.output_name);
+    }
+}
+
+/// Geocode an address column of the layer, row by row, into a new point
+/// layer, as a job. Requests to the provider are spaced to its configured
+/// rate limit, so large tables take a while; the job reports progress.
+/// Rows that could not be placed are listed by
+/// `/jobs/{job_id}/geocode-failures`. Needs a single-column primary key.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/geocode",
+    tag = "geocoding",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = GeocodeLayerRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn geocode_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<GeocodeLayerRequest>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let failed = |e: anyhow::Error| {
+        error!("Failed to read layer {source_id}: {e:#}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read layer".to_string(),
+        )
+            .into_response()
+    };
+    match table.attribute_columns(&state.pg_pool).await {
+        Ok(columns) if columns.contains(&req.geocode.address_column) => {}
+        Ok(_) => {
+            return ValidationErrors::single(
+                "address_column",
+                "unknown_column",
+                format!(
+                    "{} is not a column of the layer",
+                    req.geocode.address_column
+                ),
+            )
+            .into_response()
+        }
+        Err(e) => return failed(e),
+    }
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer has no single-column primary key".to_string(),
+            )
+                .into_response()
+        }
+        Err(e) => return failed(e),
+    };
+    let output = resolve_output_name(req.output_name, &source_id, "geocoded");
+    if state.sources.contains(&output) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Layer {output} already exists"),
+        )
+            .into_response();
+    }
+
+    let job = Job::new(GEOCODE_JOB);
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let jobs = state.jobs.clone();
+    let database = state.app_data.clone();
+    let spec = req.geocode;
+    jobs.spawn_with_progress(job, database, |progress| async move {
+        geocoding::geocode_layer(
+            &state.pg_pool,
+            &state.geocoder,
+            &source_id,
+            &key,
+            &spec,
+            &output,
+            &progress,
+        )
+        .await?;
+        state.sources.refresh().await?;
+        Layer::refresh(&state.app_data, &state.pg_pool, &state.read_pool, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+/// Rows of a geocoding job that were not placed, with why: no address, no
+/// match, a match below the requested confidence, or a provider error.
+/// Kept for 30 days.
+#[utoipa::path(
+    get,
+    path = "/jobs/{job_id}/geocode-failures",
+    tag = "geocoding",
+    params(("job_id" = String, Path), PageParams),
+    responses(
+        (status = 200, body = GeocodeFailurePage),
+        (status = 400, description = "Invalid cursor"),
+        (status = 404),
+    ),
+)]
+pub async fn get_geocode_failures(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    match Job::from_id(&state.app_data, &job_id).await {
+        Ok(job) if job.kind == GEOCODE_JOB => {}
+        Ok(_) => return DataError::NotFound("Geocoding job").into_response(),
+        Err(e) => return e.into_response(),
+    }
+    match geocoding::failures(&state.pg_pool, &job_id).await {
+        Ok(failures) => page.respond(failures),
+        Err(e) => {
+            error!("Failed to read geocoding failures of job {job_id}: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read geocoding failures".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
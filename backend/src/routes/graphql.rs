@@ -0,0 +1,19 @@
+use crate::app_state::AppState;
+use crate::graphql;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{extract::State, response::Html};
+
+/// Answer a GraphQL query over maps and layers.
+pub async fn graphql_query(
+    State(state): State<AppState>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let request = graphql::prepare(request.into_inner(), &state);
+    state.graphql.execute(request).await.into()
+}
+
+/// An in-browser editor for the schema, posting to `/graphql`.
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
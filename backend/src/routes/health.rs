@@ -0,0 +1,87 @@
+use crate::app_state::AppState;
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness: the process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "Process is live")),
+)]
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn check<F>(future: F) -> Value
+where
+    F: Future<Output = Result<()>>,
+{
+    match timeout(CHECK_TIMEOUT, future).await {
+        Ok(Ok(())) => json!({ "status": "ok" }),
+        Ok(Err(e)) => json!({ "status": "error", "error": e.to_string() }),
+        Err(_) => json!({ "status": "error", "error": "timed out" }),
+    }
+}
+
+async fn ping_postgis(state: &AppState) -> Result<()> {
+    let client = state.pg_pool.get().await?;
+    client.simple_query("SELECT 1").await?;
+    Ok(())
+}
+
+/// Prometheus scrape endpoint.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Prometheus exposition format", body = String),
+    ),
+)]
+pub async fn get_metrics(State(state): State<AppState>) -> String {
+    state.metrics.render()
+}
+
+/// Readiness: every dependency needed to serve traffic is reachable.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "All dependencies reachable"),
+        (status = 503, description = "A dependency is unavailable"),
+    ),
+)]
+pub async fn readyz(State(state): State<AppState>) -> Response {
+    let (dynamodb, postgis) = tokio::join!(
+        check(async { Ok(state.app_data.ping().await?) }),
+        check(ping_postgis(&state)),
+    );
+    let mut checks = Map::new();
+    checks.insert("dynamodb".to_string(), dynamodb);
+    checks.insert("postgis".to_string(), postgis);
+
+    let ready = checks.values().all(|c| c["status"] == "ok");
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "unavailable" },
+        "checks": checks,
+    });
+    (status, Json(body)).into_response()
+}
@@ -0,0 +1,24 @@
+use crate::app_state::AppState;
+use crate::core::Job;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{job_id}",
+    tag = "analysis",
+    params(("job_id" = String, Path)),
+    responses(
+        (status = 200, body = Job),
+        (status = 404),
+    ),
+)]
+pub async fn get_job(State(state): State<AppState>, Path(job_id): Path<String>) -> Response {
+    match Job::from_id(&state.app_data, &job_id).await {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
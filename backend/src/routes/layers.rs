@@ -0,0 +1,1474 @@
+use super::features::layer_table;
+use super::{
+    check_output_name, if_match, resolve_output_name, spawn_layer_job, start_job, tile_scope,
+    tile_template, versioned, LayerPage, PageParams, ValidJson,
+};
+use crate::app_state::AppState;
+use crate::cdn::CacheKey;
+use crate::core::{
+    AttributeStats, Catalog, Form, Freshness, Job, Layer, LayerStyle, Legend, Map, SqlView,
+    SwatchShape, Tiling,
+};
+use crate::data::DataError;
+use crate::expression;
+use crate::postgis::{self, LayerTable, StatementError};
+use crate::quality::{self, QualityReport, QualityRules};
+use crate::schema::{self, SchemaChange};
+use crate::seeding::{self, SeedPlan, TileUrl};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LayerFilter {
+    pub tag: Option<String>,
+    pub license: Option<String>,
+    /// Case-insensitive match on the layer id and description.
+    pub q: Option<String>,
+    /// List layers in the trash instead.
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+impl LayerFilter {
+    fn is_empty(&self) -> bool {
+        self.tag.is_none() && self.license.is_none() && self.q.is_none()
+    }
+
+    fn matches(&self, layer: &Layer) -> bool {
+        let catalog = &layer.catalog;
+        if let Some(tag) = &self.tag {
+            if !catalog.has_tag(tag) {
+                return false;
+            }
+        }
+        if let Some(license) = &self.license {
+            if !catalog
+                .license
+                .as_deref()
+                .is_some_and(|l| l.eq_ignore_ascii_case(license))
+            {
+                return false;
+            }
+        }
+        if let Some(q) = &self.q {
+            let q = q.to_lowercase();
+            let in_description = catalog
+                .description
+                .as_deref()
+                .is_some_and(|d| d.to_lowercase().contains(&q));
+            if !layer.id.to_lowercase().contains(&q) && !in_description {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// All published layers, with metadata where it has been computed. When
+/// filtering, layers without catalog metadata never match.
+#[utoipa::path(
+    get,
+    path = "/layers",
+    tag = "layers",
+    params(LayerFilter, PageParams),
+    responses((status = 200, body = LayerPage), (status = 400)),
+)]
+pub async fn get_layers(
+    State(state): State<AppState>,
+    Query(filter): Query<LayerFilter>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    if filter.trashed {
+        return match Layer::get_trash(&state.app_data).await {
+            Ok(layers) => page.respond(
+                layers
+                    .iter()
+                    .filter(|layer| filter.matches(layer))
+                    .map(|layer| json!(layer))
+                    .collect(),
+            ),
+            Err(e) => e.into_response(),
+        };
+    }
+    let cached: HashMap<String, Layer> = match Layer::get_all(&state.app_data).await {
+        Ok(layers) => layers.into_iter().map(|l| (l.id.clone(), l)).collect(),
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list layers".to_string(),
+            )
+                .into_response()
+        }
+    };
+
+    let layers: Vec<_> = state
+        .sources
+        .ids()
+        .into_iter()
+        .filter_map(|id| match cached.get(&id) {
+            Some(layer) if filter.matches(layer) => Some(json!(layer)),
+            Some(_) => None,
+            None if filter.is_empty() => Some(json!({ "id": id })),
+            None => None,
+        })
+        .collect();
+    page.respond(layers)
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = Layer),
+        (status = 404),
+    ),
+)]
+pub async fn get_layer(State(state): State<AppState>, Path(source_id): Path<String>) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read layer metadata".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Move the layer to the trash, hiding it and its tiles. Its table is
+/// dropped once the retention window has passed, unless restored first.
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 204),
+        (status = 404),
+    ),
+)]
+pub async fn delete_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match Layer::trash(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(_) => {
+            state.sources.set_trashed(&source_id, true);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => update_failed(e, "Failed to move layer to the trash"),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/restore",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = Layer),
+        (status = 404, description = "No such layer in the trash"),
+    ),
+)]
+pub async fn restore_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    match Layer::restore(&state.app_data, &source_id).await {
+        Ok(layer) => {
+            state.sources.set_trashed(&source_id, false);
+            versioned(&layer, layer.version)
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Recompute metadata, e.g. after the table was edited outside Gridwalk.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/refresh",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = Layer),
+        (status = 404),
+    ),
+)]
+pub async fn refresh_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match Layer::refresh(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to refresh layer metadata".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CopyLayerRequest {
+    /// Table name for the copy. Generated when omitted.
+    pub output_name: Option<String>,
+}
+
+impl Validate for CopyLayerRequest {
+    fn check(&self, v: &mut Validator) {
+        check_output_name(v, &self.output_name);
+    }
+}
+
+/// Copy the layer's features, catalog metadata and form into a new layer,
+/// as a job.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/copy",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = CopyLayerRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn copy_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<CopyLayerRequest>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let output = resolve_output_name(req.output_name, &source_id, "copy");
+
+    let job = Job::new("layer:copy");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let database = state.app_data.clone();
+    let pool = state.pg_pool.clone();
+    let read_pool = state.read_pool.clone();
+    spawn_layer_job(&state, job, async move {
+        let source = LayerTable::from_source_id(&pool, &source_id).await?;
+        postgis::copy_table(&pool, &source, &output).await?;
+        Layer::copy(&database, &pool, &read_pool, &source_id, &output).await?;
+        Ok(output)
+    });
+    response
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SqlLayerRequest {
+    /// Name of the view, and so of the layer.
+    pub name: String,
+    #[serde(flatten)]
+    pub view: SqlView,
+}
+
+impl Validate for SqlLayerRequest {
+    fn check(&self, v: &mut Validator) {
+        v.table_name("name", &self.name);
+        self.view.check(v);
+    }
+}
+
+/// Publish a query as a layer, by creating a view of it that is tiled and
+/// searched like any table. Its metadata is computed as a job. Disabled
+/// unless `sql_layers.enabled` is set. The view is owned by
+/// `sql_layers.owner`, and only read through read-only connections;
+/// imports and edits are refused.
+#[utoipa::path(
+    post,
+    path = "/layers",
+    tag = "layers",
+    request_body = SqlLayerRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 404, description = "SQL layers are disabled"),
+        (status = 409, description = "A layer already exists"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_sql_layer(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<SqlLayerRequest>,
+) -> Response {
+    if !state.sql_layers.enabled {
+        return (StatusCode::NOT_FOUND, "Not found".to_string()).into_response();
+    }
+    if state.sources.contains(&req.name) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Layer {} already exists", req.name),
+        )
+            .into_response();
+    }
+    let created = postgis::create_view(
+        &state.pg_pool,
+        &state.read_pool,
+        &req.name,
+        &req.view.render(),
+        state.sql_layers.owner.as_deref(),
+    )
+    .await;
+    match created {
+        Ok(()) => {}
+        Err(StatementError::Invalid(message)) => {
+            return ValidationErrors::single("sql", "invalid_query", message).into_response()
+        }
+        Err(e) => {
+            warn!("Failed to create view {}: {e}", req.name);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create layer".to_string(),
+            )
+                .into_response();
+        }
+    }
+
+    let job = Job::new("layer:sql");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let database = state.app_data.clone();
+    // The view's query runs with the read-only connections' limits
+    let pool = state.read_pool.clone();
+    let sources = state.sources.clone();
+    state.jobs.spawn(job, state.app_data.clone(), async move {
+        sources.refresh().await?;
+        Layer::create_sql(&database, &pool, &req.name, req.view).await?;
+        Ok(req.name)
+    });
+    response
+}
+
+/// Replace the layer's catalog metadata.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/catalog",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = Catalog,
+    responses(
+        (status = 200, body = Layer),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_catalog(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    Json(catalog): Json<Catalog>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let catalog = match catalog.normalize() {
+        Ok(catalog) => catalog,
+        Err(e) => return e.into_response(),
+    };
+    match Layer::update_catalog(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        catalog,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => update_failed(e, "Failed to update layer catalog"),
+    }
+}
+
+/// Add, rename, retype or drop one of the layer's attribute columns, then
+/// recompute its metadata, moving it to a new version. Columns the layer's
+/// form uses, and its primary key, can't be renamed, retyped or dropped.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/schema",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = SchemaChange,
+    responses(
+        (status = 200, body = Layer),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_schema(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    ValidJson(change): ValidJson<SchemaChange>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let failed = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to change layer schema".to_string(),
+        )
+            .into_response()
+    };
+    let table = match LayerTable::from_source_id(&state.pg_pool, &source_id).await {
+        Ok(table) if !table.is_view => table,
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Layer is not backed by a table".to_string(),
+            )
+                .into_response()
+        }
+    };
+    let layer = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => layer,
+        Err(e) => return failed(e),
+    };
+    if let Some(version) = version.filter(|&version| version != layer.version) {
+        return DataError::Conflict(format!(
+            "layer is at version {}, not {version}",
+            layer.version
+        ))
+        .into_response();
+    }
+    let columns = match table.attribute_columns(&state.pg_pool).await {
+        Ok(columns) => columns,
+        Err(e) => return failed(e),
+    };
+    let key = match table.primary_key(&state.pg_pool).await {
+        Ok(key) => key,
+        Err(e) => return failed(e),
+    };
+    if let Some(column) = change.column() {
+        let problem = if !columns.iter().any(|c| c == column) {
+            Some(("unknown_column", "is not a column of the layer"))
+        } else if key.as_deref() == Some(column) {
+            Some(("primary_key", "is the layer's primary key"))
+        } else if layer
+            .form
+            .as_ref()
+            .is_some_and(|form| form.fields.iter().any(|field| field.name == column))
+        {
+            Some((
+                "used_by_form",
+                "is used by the layer's form; change the form first",
+            ))
+        } else {
+            None
+        };
+        if let Some((code, message)) = problem {
+            return ValidationErrors::single("name", code, message).into_response();
+        }
+    }
+    if let Some(new_column) = change.new_column() {
+        if new_column == table.geometry_column || columns.iter().any(|c| c == new_column) {
+            let field = if change.column().is_some() {
+                "new_name"
+            } else {
+                "name"
+            };
+            return ValidationErrors::single(
+                field,
+                "duplicate",
+                "is already a column of the layer",
+            )
+            .into_response();
+        }
+    }
+
+    match schema::apply(&state.pg_pool, &table, &change, &layer.tiling.computed).await {
+        Ok(()) => {}
+        Err(StatementError::Invalid(message)) => {
+            return ValidationErrors::single("", "invalid_change", message).into_response()
+        }
+        Err(e) => {
+            warn!("Failed to change the schema of {source_id}: {e}");
+            return failed(e.into());
+        }
+    }
+    // The tile server reads a table's columns once, when it is published
+    if let Err(e) = state.sources.refresh().await {
+        warn!("Failed to refresh tile sources after a schema change: {e}");
+    }
+    match Layer::refresh(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => failed(e),
+    }
+}
+
+/// Check tiling settings against the layer's table: computed attribute
+/// names must not shadow its columns, their expressions may only refer to
+/// its columns and must plan, and the
+/// feature id field must be one of its columns.
+async fn check_tiling_columns(
+    state: &AppState,
+    source_id: &str,
+    tiling: &Tiling,
+) -> Result<(), Response> {
+    let failed = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check tiling settings".to_string(),
+        )
+            .into_response()
+    };
+    let table = LayerTable::from_source_id(&state.read_pool, source_id)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Layer is not backed by a table".to_string(),
+            )
+                .into_response()
+        })?;
+    let columns = table
+        .attribute_columns(&state.read_pool)
+        .await
+        .map_err(failed)?;
+    if let Some(id_field) = &tiling.encoding.id_field {
+        if !columns.contains(id_field) {
+            return Err(ValidationErrors::single(
+                "encoding.id_field",
+                "unknown_column",
+                "is not a column of the layer",
+            )
+            .into_response());
+        }
+    }
+    for (index, attribute) in tiling.computed.iter().enumerate() {
+        if attribute.name == table.geometry_column || columns.contains(&attribute.name) {
+            return Err(ValidationErrors::single(
+                &format!("computed[{index}].name"),
+                "shadows_column",
+                "is the name of a column of the layer",
+            )
+            .into_response());
+        }
+        // Validation has already vetted the expression itself
+        let refs = expression::columns(&attribute.expression).unwrap_or_default();
+        if let Some(unknown) = refs
+            .iter()
+            .find(|name| **name != table.geometry_column && !columns.contains(name))
+        {
+            return Err(ValidationErrors::single(
+                &format!("computed[{index}].expression"),
+                "unknown_column",
+                format!("{unknown} is not a column of the layer"),
+            )
+            .into_response());
+        }
+        let error = table
+            .expression_error(&state.read_pool, &attribute.expression)
+            .await
+            .map_err(failed)?;
+        if let Some(message) = error {
+            return Err(ValidationErrors::single(
+                &format!("computed[{index}].expression"),
+                "invalid_expression",
+                message,
+            )
+            .into_response());
+        }
+    }
+    Ok(())
+}
+
+/// Replace the layer's tiling settings, e.g. its max zoom.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/tiling",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = Tiling,
+    responses(
+        (status = 200, body = Layer),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_tiling(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    ValidJson(tiling): ValidJson<Tiling>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    if !tiling.computed.is_empty() || tiling.encoding.id_field.is_some() {
+        if let Err(response) = check_tiling_columns(&state, &source_id, &tiling).await {
+            return response;
+        }
+    }
+    match Layer::update_tiling(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        tiling,
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => update_failed(e, "Failed to update layer tiling"),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SeedParams {
+    #[serde(default)]
+    pub minzoom: u8,
+    pub maxzoom: u8,
+    /// `west,south,east,north` in WGS84; the layer's extent when omitted.
+    pub bbox: Option<String>,
+    /// Only return the tiles the job would request.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Request the layer's tiles over a region and range of zooms through the
+/// public URL, as a job, so the caches in front of it are warm before
+/// traffic arrives. The plan gives the number of tiles, which is what the
+/// job costs.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/seed",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        SeedParams,
+    ),
+    responses(
+        (status = 200, body = SeedPlan, description = "The plan, for dry runs"),
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn seed_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(params): Query<SeedParams>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let bbox = match params.bbox.as_deref().map(seeding::parse_bbox) {
+        Some(Ok(bbox)) => bbox,
+        Some(Err(e)) => {
+            return ValidationErrors::single("bbox", "invalid_bbox", e.to_string()).into_response()
+        }
+        None => match Layer::from_id(
+            &state.app_data,
+            &state.pg_pool,
+            &state.read_pool,
+            &source_id,
+        )
+        .await
+        {
+            Ok(Layer {
+                bbox: Some(bbox), ..
+            }) => bbox,
+            Ok(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Layer has no features; pass a bbox".to_string(),
+                )
+                    .into_response()
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read layer metadata".to_string(),
+                )
+                    .into_response()
+            }
+        },
+    };
+    let plan = match SeedPlan::new(bbox, params.minzoom, params.maxzoom) {
+        Ok(plan) => plan,
+        Err(e) => return e.into_response(),
+    };
+    if params.dry_run {
+        return Json(plan).into_response();
+    }
+    if plan.tiles > state.seeding.max_tiles {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "{} tiles exceeds the limit of {}; narrow the bbox or zooms",
+                plan.tiles, state.seeding.max_tiles
+            ),
+        )
+            .into_response();
+    }
+
+    let job = Job::new("tiles:seed");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let concurrency = state.seeding.concurrency;
+    let signing_state = state.clone();
+    let layer_id = source_id.clone();
+    // Signed per tile, as a long job can outlive a signature
+    let tile_url: TileUrl = Arc::new(move |z, x, y| {
+        tile_template(&signing_state, &layer_id)
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+    });
+    state
+        .jobs
+        .spawn_with_progress(job, state.app_data.clone(), |progress| async move {
+            seeding::seed(&plan, tile_url, concurrency, progress).await?;
+            Ok(source_id)
+        });
+    response
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LegendFormat {
+    #[default]
+    Json,
+    Png,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LegendParams {
+    /// Use the layer's style on this map; the default style otherwise.
+    pub map_id: Option<String>,
+    /// `json` for entries and labels, `png` for their swatches stacked in
+    /// the same order.
+    #[serde(default)]
+    pub format: LegendFormat,
+}
+
+/// A legend for the layer's symbology, with an entry per ramp class.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/legend",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        LegendParams,
+    ),
+    responses(
+        (status = 200, body = Legend, description = "The legend, or its swatches as PNG"),
+        (status = 404, description = "Unknown layer or map, or the layer is not on the map"),
+    ),
+)]
+pub async fn get_layer_legend(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(params): Query<LegendParams>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let style = match &params.map_id {
+        Some(map_id) => {
+            let map = match Map::from_id(&state.app_data, map_id).await {
+                Ok(map) => map,
+                Err(e) => return e.into_response(),
+            };
+            match map
+                .layers
+                .into_iter()
+                .find(|layer| layer.source_id == source_id)
+            {
+                Some(layer) => layer.style,
+                None => {
+                    return (StatusCode::NOT_FOUND, "Layer is not on the map".to_string())
+                        .into_response()
+                }
+            }
+        }
+        None => LayerStyle::default(),
+    };
+    let legend = state
+        .query_cache
+        .get_or_compute(
+            "legend",
+            &(&source_id, &style),
+            &[CacheKey::Layer(source_id.clone())],
+            || async {
+                let layer = Layer::from_id(
+                    &state.app_data,
+                    &state.pg_pool,
+                    &state.read_pool,
+                    &source_id,
+                )
+                .await?;
+                let shape = SwatchShape::for_geometry_types(&layer.geometry_types);
+                Ok(Legend::new(&source_id, shape, &style))
+            },
+        )
+        .await;
+    let legend = match legend {
+        Ok(legend) => legend,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read layer metadata".to_string(),
+            )
+                .into_response()
+        }
+    };
+
+    match params.format {
+        LegendFormat::Json => Json(legend).into_response(),
+        LegendFormat::Png => match legend.to_png() {
+            Ok(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to render legend: {e}"),
+            )
+                .into_response(),
+        },
+    }
+}
+
+/// Counts, range and most common values of one of the layer's attributes.
+/// Cached until the layer changes.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/attributes/{name}/stats",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("name" = String, Path, description = "Attribute name"),
+    ),
+    responses(
+        (status = 200, body = AttributeStats),
+        (status = 404),
+    ),
+)]
+pub async fn get_attribute_stats(
+    State(state): State<AppState>,
+    Path((source_id, name)): Path<(String, String)>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let stats = state
+        .query_cache
+        .get_or_compute(
+            "attribute_stats",
+            &(&source_id, &name),
+            &[CacheKey::Layer(source_id.clone())],
+            || AttributeStats::compute(&state.read_pool, &source_id, &name),
+        )
+        .await;
+    match stats {
+        Ok(Some(stats)) => Json(stats).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Attribute not found".to_string()).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to compute attribute statistics".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// The layer's data-collection form.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/form",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = Form),
+        (status = 404),
+    ),
+)]
+pub async fn get_layer_form(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(Layer {
+            form: Some(form), ..
+        }) => Json(form).into_response(),
+        Ok(_) => (StatusCode::NOT_FOUND, "Layer has no form".to_string()).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read layer metadata".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Set the form features of the layer are collected with. Fields must name
+/// attributes of the layer.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/form",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = Form,
+    responses(
+        (status = 200, body = Layer),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_form(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    Json(form): Json<Form>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let layer = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => layer,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read layer metadata".to_string(),
+            )
+                .into_response()
+        }
+    };
+    if let Err(e) = form.validate(&layer.attributes) {
+        return e.into_response();
+    }
+    match Layer::update_form(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        Some(form),
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => update_failed(e, "Failed to update layer form"),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/form",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn delete_layer_form(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    match Layer::update_form(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        None,
+    )
+    .await
+    {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => update_failed(e, "Failed to update layer form"),
+    }
+}
+
+/// Shortest and longest expected update frequency, five minutes to a year.
+const MIN_MAX_AGE: u64 = 300;
+const MAX_MAX_AGE: u64 = 366 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FreshnessRequest {
+    /// Seconds the layer's data may go without an update before it is
+    /// flagged as stale, from 300 to a year.
+    pub max_age: u64,
+}
+
+impl Validate for FreshnessRequest {
+    fn check(&self, v: &mut Validator) {
+        v.range("max_age", self.max_age, MIN_MAX_AGE, MAX_MAX_AGE);
+    }
+}
+
+/// Declare how often the layer's data is expected to be updated. Once it
+/// goes longer without one, the scheduler flags the layer's `freshness` as
+/// stale and sends an alert; the flag clears when the data is next updated.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/freshness",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = FreshnessRequest,
+    responses(
+        (status = 200, body = Layer),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_freshness(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<FreshnessRequest>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let freshness = Freshness::new(req.max_age);
+    match Layer::update_freshness(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        Some(freshness),
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => update_failed(e, "Failed to update layer freshness"),
+    }
+}
+
+/// Stop expecting updates to the layer's data.
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/freshness",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn delete_layer_freshness(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    match Layer::update_freshness(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        None,
+    )
+    .await
+    {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => update_failed(e, "Failed to update layer freshness"),
+    }
+}
+
+/// Set the rules the layer's features are checked against as they are
+/// imported, edited, synced or refreshed. Features that break a rule are
+/// rejected with `block_on_failure`, and otherwise written and counted in
+/// the quality report. Rules must name attributes of the layer.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/quality",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = QualityRules,
+    responses(
+        (status = 200, body = Layer),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_layer_quality(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+    Json(quality): Json<QualityRules>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    let layer = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(layer) => layer,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read layer metadata".to_string(),
+            )
+                .into_response()
+        }
+    };
+    if let Err(e) = quality.validate(&layer.attributes) {
+        return e.into_response();
+    }
+    match Layer::update_quality(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        Some(quality),
+    )
+    .await
+    {
+        Ok(layer) => versioned(&layer, layer.version),
+        Err(e) => update_failed(e, "Failed to update layer quality rules"),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/quality",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404),
+        (status = 409, description = "The layer has changed since that version"),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn delete_layer_quality(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    match Layer::update_quality(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+        version,
+        None,
+    )
+    .await
+    {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => update_failed(e, "Failed to update layer quality rules"),
+    }
+}
+
+/// Measure every feature of the layer against its quality rules, with a
+/// sample of the features breaking each. Features written before the rules
+/// were set, or while they did not block, show up here.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/quality/report",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = QualityReport),
+        (status = 404, description = "The layer has no quality rules"),
+    ),
+)]
+pub async fn get_layer_quality_report(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    let table = match layer_table(&state, &source_id).await {
+        Ok(table) => table,
+        Err(response) => return response,
+    };
+    let failed = |e: anyhow::Error| {
+        warn!("Quality report of layer {source_id} failed: {e:#}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check layer quality".to_string(),
+        )
+            .into_response()
+    };
+    let rules = match Layer::from_id(
+        &state.app_data,
+        &state.pg_pool,
+        &state.read_pool,
+        &source_id,
+    )
+    .await
+    {
+        Ok(Layer {
+            quality: Some(rules),
+            ..
+        }) => rules,
+        Ok(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                "Layer has no quality rules".to_string(),
+            )
+                .into_response()
+        }
+        Err(e) => return failed(e),
+    };
+    let key = match table.primary_key(&state.read_pool).await {
+        Ok(key) => key,
+        Err(e) => return failed(e),
+    };
+    match quality::report(&state.read_pool, &table, key.as_deref(), &rules).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => failed(e),
+    }
+}
+
+/// A write that lost to a concurrent one as 409, anything else as a 500.
+fn update_failed(e: anyhow::Error, message: &str) -> Response {
+    match e.downcast::<DataError>() {
+        Ok(e @ DataError::Conflict(_)) => e.into_response(),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, message.to_string()).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SignedTileUrl {
+    /// Tile URL template with `{z}`, `{x}` and `{y}` placeholders.
+    pub tiles: String,
+    pub expires_at: i64,
+}
+
+/// Mint a signed tile URL template for the layer, for CDNs and clients
+/// that cannot attach credentials to tile requests. Callers authenticate
+/// with one of the configured signing API keys.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/signed-url",
+    tag = "layers",
+    params(
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("Authorization" = String, Header, description = "`Bearer` and a signing API key"),
+    ),
+    responses(
+        (status = 200, body = SignedTileUrl),
+        (status = 401, description = "Missing or unknown API key"),
+        (status = 404),
+        (status = 503, description = "No signing keys are configured"),
+    ),
+)]
+pub async fn sign_layer_tiles(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !key.is_some_and(|key| state.signer.authorizes(key.trim())) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "A signing API key is required".to_string(),
+        )
+            .into_response();
+    }
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let Some((expires_at, query)) = state.signer.sign(&tile_scope(&source_id)) else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "URL signing is not configured".to_string(),
+        )
+            .into_response();
+    };
+    Json(SignedTileUrl {
+        tiles: format!(
+            "{}/tiles/{source_id}/{{z}}/{{x}}/{{y}}?{query}",
+            state.public_url
+        ),
+        expires_at,
+    })
+    .into_response()
+}
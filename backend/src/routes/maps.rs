@@ -0,0 +1,392 @@
+use super::{
+    check_scope, if_match, map_tile_scope, map_tile_template, tile_template, versioned, MapPage,
+    PageParams, ValidJson,
+};
+use crate::app_state::AppState;
+use crate::cdn::CacheKey;
+use crate::core::{style_document, use_composite_source, Map, MapLayer, Viewport};
+use crate::signing::UrlSignature;
+use crate::sources::source_layer;
+use crate::tiling::{self, TileLayer};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MapRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub layers: Vec<MapLayer>,
+    #[serde(default)]
+    pub viewport: Viewport,
+    /// Id of a basemap from `GET /basemaps`; omit for the default.
+    pub basemap: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MapFilter {
+    /// List maps in the trash instead.
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+
+impl Validate for MapRequest {
+    fn check(&self, v: &mut Validator) {
+        v.length("name", &self.name, 1, MAX_NAME_LENGTH);
+        if let Some(description) = &self.description {
+            v.length("description", description, 0, MAX_DESCRIPTION_LENGTH);
+        }
+        v.each("layers", &self.layers);
+        v.nested("viewport", &self.viewport);
+    }
+}
+
+/// Check the parts of a map that depend on the deployment: its basemaps,
+/// sources and label support.
+fn check_available(state: &AppState, req: &MapRequest) -> Result<(), ValidationErrors> {
+    let mut v = Validator::default();
+    if let Some(basemap) = &req.basemap {
+        v.check(
+            "basemap",
+            state.basemaps.iter().any(|b| b.id == *basemap),
+            "unknown_basemap",
+            format!("unknown basemap {basemap}"),
+        );
+    }
+    for (index, layer) in req.layers.iter().enumerate() {
+        v.within(&format!("layers[{index}]"), |v| {
+            v.check(
+                "source_id",
+                state.sources.contains(&layer.source_id),
+                "unknown_layer",
+                format!("unknown layer source {}", layer.source_id),
+            );
+            v.check(
+                "style.label",
+                layer.style.label.is_none() || state.labels.glyphs_url.is_some(),
+                "labels_unavailable",
+                "labels need labels.glyphs_url to be configured",
+            );
+        });
+    }
+    v.finish()
+}
+
+#[utoipa::path(
+    post,
+    path = "/maps",
+    tag = "maps",
+    request_body = MapRequest,
+    responses(
+        (status = 201, body = Map),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn create_map(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<MapRequest>,
+) -> Response {
+    if let Err(e) = check_available(&state, &req) {
+        return e.into_response();
+    }
+
+    let map = Map::new(
+        req.name,
+        req.description,
+        req.layers,
+        req.viewport,
+        req.basemap,
+    );
+    match map.create(&state.app_data).await {
+        Ok(_) => (StatusCode::CREATED, versioned(&map, map.version)).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create map".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/maps",
+    tag = "maps",
+    params(MapFilter, PageParams),
+    responses((status = 200, body = MapPage), (status = 400)),
+)]
+pub async fn get_maps(
+    State(state): State<AppState>,
+    Query(filter): Query<MapFilter>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    let maps = if filter.trashed {
+        Map::get_trash(&state.app_data).await
+    } else {
+        Map::get_all(&state.app_data).await
+    };
+    match maps {
+        Ok(mut maps) => {
+            maps.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+            page.respond(maps)
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list maps".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/maps/{map_id}",
+    tag = "maps",
+    params(("map_id" = String, Path)),
+    responses(
+        (status = 200, body = Map),
+        (status = 404),
+    ),
+)]
+pub async fn get_map(State(state): State<AppState>, Path(map_id): Path<String>) -> Response {
+    match Map::from_id(&state.app_data, &map_id).await {
+        Ok(map) => versioned(&map, map.version),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/maps/{map_id}",
+    tag = "maps",
+    params(
+        ("map_id" = String, Path),
+        ("If-Match" = String, Header, description = "Version being updated, from the ETag, or `*`"),
+    ),
+    request_body = MapRequest,
+    responses(
+        (status = 200, body = Map),
+        (status = 400),
+        (status = 404),
+        (status = 409, description = "The map has changed since that version"),
+        (status = 422, body = ValidationErrors),
+        (status = 428, description = "If-Match is missing"),
+    ),
+)]
+pub async fn update_map(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<MapRequest>,
+) -> Response {
+    let version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+    if let Err(e) = check_available(&state, &req) {
+        return e.into_response();
+    }
+
+    let mut map = match Map::from_id(&state.app_data, &map_id).await {
+        Ok(map) => map,
+        Err(e) => return e.into_response(),
+    };
+    map.name = req.name;
+    map.description = req.description;
+    map.layers = req.layers;
+    map.viewport = req.viewport;
+    map.basemap = req.basemap;
+    if let Some(version) = version {
+        map.version = version;
+    }
+
+    match map.update(&state.app_data).await {
+        Ok(_) => versioned(&map, map.version),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Move the map to the trash. It is purged once the retention window has
+/// passed, unless restored first.
+#[utoipa::path(
+    delete,
+    path = "/maps/{map_id}",
+    tag = "maps",
+    params(("map_id" = String, Path)),
+    responses(
+        (status = 204),
+        (status = 404),
+    ),
+)]
+pub async fn delete_map(State(state): State<AppState>, Path(map_id): Path<String>) -> Response {
+    let mut map = match Map::from_id(&state.app_data, &map_id).await {
+        Ok(map) => map,
+        Err(e) => return e.into_response(),
+    };
+
+    match map.trash(&state.app_data).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/maps/{map_id}/restore",
+    tag = "maps",
+    params(("map_id" = String, Path)),
+    responses(
+        (status = 200, body = Map),
+        (status = 404, description = "No such map in the trash"),
+    ),
+)]
+pub async fn restore_map(State(state): State<AppState>, Path(map_id): Path<String>) -> Response {
+    let mut map = match Map::from_trash(&state.app_data, &map_id).await {
+        Ok(map) => map,
+        Err(e) => return e.into_response(),
+    };
+
+    match map.restore(&state.app_data).await {
+        Ok(_) => versioned(&map, map.version),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StyleParams {
+    /// Draw every layer from the map's composite tiles, one request per
+    /// tile, instead of a source per layer.
+    #[serde(default)]
+    pub composite: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/maps/{map_id}/style.json",
+    tag = "maps",
+    params(("map_id" = String, Path), StyleParams),
+    responses(
+        (status = 200, description = "MapLibre style document"),
+        (status = 404),
+    ),
+)]
+pub async fn get_map_style(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    Query(params): Query<StyleParams>,
+    headers: HeaderMap,
+) -> Response {
+    match Map::from_id(&state.app_data, &map_id).await {
+        Ok(map) => {
+            let tile_url = |source_id: &str| tile_template(&state, source_id);
+            let mut style = style_document(&map, &state.sources, &state.labels, tile_url);
+            if params.composite {
+                use_composite_source(&mut style, map_tile_template(&state, &map.id));
+            }
+            let mut keys = vec![CacheKey::Map(map.id.clone())];
+            keys.extend(
+                map.layers
+                    .iter()
+                    .map(|layer| CacheKey::Layer(layer.source_id.clone())),
+            );
+            state.cdn.style_response(&headers, &style, &keys)
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/maps/{map_id}/tiles/{z}/{x}/{y}",
+    tag = "tiles",
+    params(
+        ("map_id" = String, Path),
+        ("z" = u8, Path),
+        ("x" = u32, Path),
+        ("y" = String, Path, description = "Tile row, optionally suffixed `.mvt`"),
+        UrlSignature,
+    ),
+    responses(
+        (status = 200, description = "Mapbox vector tile with a layer per map layer", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 400, description = "Invalid tile coordinates"),
+        (status = 403, description = "Missing or invalid signature"),
+        (status = 404),
+    ),
+)]
+pub async fn map_tiles(
+    Path((map_id, z, x, y)): Path<(String, u8, u32, String)>,
+    Query(signature): Query<UrlSignature>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_scope(&state, &map_tile_scope(&map_id), &signature) {
+        return response;
+    }
+    let Ok(y) = y.trim_end_matches(".mvt").parse::<u32>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid tile row".to_string()).into_response();
+    };
+    let map = match Map::from_id(&state.app_data, &map_id).await {
+        Ok(map) => map,
+        Err(e) => return e.into_response(),
+    };
+
+    // A layer added twice to a map is drawn twice from the same tile data
+    let mut layers: Vec<TileLayer> = Vec::new();
+    for layer in &map.layers {
+        if layers.iter().any(|l| l.source_id == layer.source_id) {
+            continue;
+        }
+        let Some(source) = state.sources.get(&layer.source_id) else {
+            continue;
+        };
+        let tilejson = source.get_tilejson();
+        if tilejson.minzoom.is_some_and(|min_zoom| z < min_zoom) {
+            continue;
+        }
+        let settings = state.tiling.get(&layer.source_id);
+        let snap_zoom = settings
+            .max_zoom
+            .or(tilejson.maxzoom)
+            .filter(|&max_zoom| z > max_zoom);
+        if snap_zoom.is_some() && !settings.overzoom {
+            continue;
+        }
+        layers.push(TileLayer {
+            source_id: layer.source_id.clone(),
+            layer_name: source_layer(source.as_ref()),
+            snap_zoom,
+            tiling: settings,
+        });
+    }
+
+    match tiling::composite_tile(&state.read_pool, &layers, z, x, y).await {
+        Ok(tile) => {
+            let mut keys = vec![CacheKey::Map(map.id.clone())];
+            keys.extend(
+                layers
+                    .iter()
+                    .map(|layer| CacheKey::Layer(layer.source_id.clone())),
+            );
+            state.cdn.tile_response(&headers, tile, &keys)
+        }
+        Err(e) => {
+            error!("Composite tile failed for map {map_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render tile".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
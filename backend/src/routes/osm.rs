@@ -0,0 +1,192 @@
+use super::{scan_download, start_job, ValidJson};
+use crate::app_state::AppState;
+use crate::config::LimitsConfig;
+use crate::core::{Job, Layer};
+use crate::osm::{self, OsmLayer, TagFilter, COLUMNS};
+use crate::postgis::{check_complexity, create_feature_table};
+use crate::sniff::{sniff, FileType};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::warn;
+use utoipa::ToSchema;
+
+const MAX_LAYERS: usize = 20;
+const INSERT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OsmImportRequest {
+    /// HTTP(S) URL of an `.osm.pbf` extract, e.g. from Geofabrik.
+    pub url: String,
+    /// Layers to create, each from the elements matching its filter.
+    pub layers: Vec<OsmLayer>,
+}
+
+impl Validate for OsmImportRequest {
+    fn check(&self, v: &mut Validator) {
+        v.http_url("url", &self.url);
+        v.count("layers", self.layers.len(), 1, MAX_LAYERS);
+        let mut names = HashSet::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            v.within(&format!("layers[{index}]"), |v| {
+                v.table_name("name", &layer.name);
+                v.check(
+                    "name",
+                    names.insert(&layer.name),
+                    "duplicate",
+                    "is used by another layer",
+                );
+                v.result("filter", TagFilter::parse(&layer.filter).map(|_| ()));
+            });
+        }
+    }
+}
+
+/// Refuse the import before any table is created if a layer would break the
+/// configured limits.
+fn check_limits(layers: &[OsmLayer], features: &[Vec<Value>], limits: &LimitsConfig) -> Result<()> {
+    for (layer, features) in layers.iter().zip(features) {
+        if limits.max_features > 0 && features.len() as u64 > limits.max_features {
+            return Err(anyhow!(
+                "layer {} has {} features; the limit is {}",
+                layer.name,
+                features.len(),
+                limits.max_features
+            ));
+        }
+        for feature in features {
+            check_complexity(feature, limits).map_err(|e| anyhow!("layer {}: {e}", layer.name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write each layer's features to a new table.
+async fn write_layers(
+    pool: &Pool,
+    layers: &[OsmLayer],
+    filters: &[TagFilter],
+    features: Vec<Vec<Value>>,
+    created: &mut Vec<String>,
+) -> Result<()> {
+    for ((layer, filter), features) in layers.iter().zip(filters).zip(features) {
+        let mut columns: Vec<(String, &str)> = COLUMNS
+            .iter()
+            .map(|(name, column_type)| (name.to_string(), *column_type))
+            .collect();
+        if !columns.iter().any(|(name, _)| *name == filter.key) {
+            columns.push((filter.key.clone(), "text"));
+        }
+        let table = create_feature_table(pool, &layer.name, &columns).await?;
+        created.push(layer.name.clone());
+        let names: Vec<String> = columns.into_iter().map(|(name, _)| name).collect();
+        for batch in features.chunks(INSERT_BATCH_SIZE) {
+            table
+                .insert_features(pool, &names, &Value::Array(batch.to_vec()))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Import an OpenStreetMap extract as new layers, one per tag filter, as a
+/// job. Nodes become points and ways lines or polygons; relations, such as
+/// multipolygons, are skipped. The job's output layer is the first layer.
+#[utoipa::path(
+    post,
+    path = "/imports/osm",
+    tag = "layers",
+    request_body = OsmImportRequest,
+    responses(
+        (status = 202, body = Job),
+        (status = 400),
+        (status = 409, description = "A layer already exists"),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn import_osm(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<OsmImportRequest>,
+) -> Response {
+    if let Some(layer) = req.layers.iter().find(|l| state.sources.contains(&l.name)) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Layer {} already exists", layer.name),
+        )
+            .into_response();
+    }
+    let filters = match req
+        .layers
+        .iter()
+        .map(|layer| TagFilter::parse(&layer.filter))
+        .collect::<Result<Vec<_>>>()
+    {
+        Ok(filters) => filters,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let job = Job::new("import:osm");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let jobs = state.jobs.clone();
+    let database = state.app_data.clone();
+    jobs.spawn_with_progress(job, database, |progress| async move {
+        let data = state.fetcher.get(&req.url).await?;
+        scan_download(&state, &progress, &data).await?;
+        if sniff(&data) != Some(FileType::OsmPbf) {
+            return Err(anyhow!("not an OSM PBF extract"));
+        }
+        let extract_filters = filters.clone();
+        let features =
+            tokio::task::spawn_blocking(move || osm::extract(&data, &extract_filters)).await??;
+        check_limits(&req.layers, &features, &state.limits)?;
+
+        let mut created = Vec::new();
+        let written = write_layers(
+            &state.pg_pool,
+            &req.layers,
+            &filters,
+            features,
+            &mut created,
+        )
+        .await;
+        if let Err(e) = written {
+            // Leave nothing half-imported behind
+            if let Ok(client) = state.pg_pool.get().await {
+                for name in &created {
+                    if let Err(e) = client
+                        .batch_execute(&format!("DROP TABLE IF EXISTS public.{name}"))
+                        .await
+                    {
+                        warn!("Failed to drop partial OSM layer {name}: {e}");
+                    }
+                }
+            }
+            return Err(e);
+        }
+
+        state.sources.refresh().await?;
+        for layer in &req.layers {
+            Layer::refresh(
+                &state.app_data,
+                &state.pg_pool,
+                &state.read_pool,
+                &layer.name,
+            )
+            .await?;
+        }
+        Ok(req.layers[0].name.clone())
+    });
+    response
+}
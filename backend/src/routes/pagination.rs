@@ -0,0 +1,73 @@
+use crate::core::{Attachment, CommentThread, Connector, Layer, Map, Share};
+use crate::geocoding::GeocodeFailure;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// One page of a list. Pass `next_cursor` back as `cursor` for the next
+/// page; it is absent on the last.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    AttachmentPage = Page<Attachment>,
+    CommentThreadPage = Page<CommentThread>,
+    ConnectorPage = Page<Connector>,
+    GeocodeFailurePage = Page<GeocodeFailure>,
+    LayerPage = Page<Layer>,
+    MapPage = Page<Map>,
+    SharePage = Page<Share>,
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    /// Items across every page.
+    pub total: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+    /// Items per page: 100 by default, at most 1000.
+    pub limit: Option<usize>,
+}
+
+impl PageParams {
+    /// Cursors are positions in the full list, so items added or removed
+    /// between requests can shift later pages by that many.
+    fn offset(&self) -> Option<usize> {
+        let Some(cursor) = &self.cursor else {
+            return Some(0);
+        };
+        let bytes: [u8; 8] = hex::decode(cursor).ok()?.try_into().ok()?;
+        usize::try_from(u64::from_be_bytes(bytes)).ok()
+    }
+
+    /// The requested page of `items`, which must be in a stable order.
+    pub fn page<T>(&self, items: Vec<T>) -> Option<Page<T>> {
+        let offset = self.offset()?;
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let total = items.len();
+        let end = offset.saturating_add(limit);
+        let next_cursor = (end < total).then(|| hex::encode((end as u64).to_be_bytes()));
+        Some(Page {
+            items: items.into_iter().skip(offset).take(limit).collect(),
+            next_cursor,
+            total: Some(total),
+        })
+    }
+
+    pub fn respond<T: Serialize>(&self, items: Vec<T>) -> Response {
+        match self.page(items) {
+            Some(page) => Json(page).into_response(),
+            None => (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()).into_response(),
+        }
+    }
+}
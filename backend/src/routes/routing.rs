@@ -0,0 +1,205 @@
+use super::ValidJson;
+use crate::analysis::routing::{self, Profile, RoutingService};
+use crate::app_state::AppState;
+use crate::core::Layer;
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RouteRequest {
+    /// `[longitude, latitude]` pairs, in visiting order.
+    pub locations: Vec<[f64; 2]>,
+    #[serde(default)]
+    pub profile: Profile,
+    /// Save the result as a layer with this table name.
+    pub save_as: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct IsochroneRequest {
+    pub location: [f64; 2],
+    pub minutes: Vec<u32>,
+    #[serde(default)]
+    pub profile: Profile,
+    pub save_as: Option<String>,
+}
+
+impl Validate for RouteRequest {
+    fn check(&self, v: &mut Validator) {
+        v.count("locations", self.locations.len(), 2, usize::MAX);
+        for (index, location) in self.locations.iter().enumerate() {
+            v.lon_lat(&format!("locations[{index}]"), *location);
+        }
+        check_save_as(v, &self.save_as);
+    }
+}
+
+impl Validate for IsochroneRequest {
+    fn check(&self, v: &mut Validator) {
+        v.lon_lat("location", self.location);
+        v.count("minutes", self.minutes.len(), 1, usize::MAX);
+        for (index, minutes) in self.minutes.iter().enumerate() {
+            v.range(&format!("minutes[{index}]"), *minutes, 1, 120);
+        }
+        check_save_as(v, &self.save_as);
+    }
+}
+
+fn routing_service(state: &AppState) -> Result<Arc<RoutingService>, Response> {
+    state.routing.clone().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Routing is not configured".to_string(),
+        )
+            .into_response()
+    })
+}
+
+fn check_save_as(v: &mut Validator, save_as: &Option<String>) {
+    if let Some(name) = save_as {
+        v.table_name("save_as", name);
+    }
+}
+
+/// The provider's error is logged, not returned, as it may carry its URL
+/// or internal details.
+fn provider_error(e: anyhow::Error) -> Response {
+    error!("Routing request failed: {e:#}");
+    (
+        StatusCode::BAD_GATEWAY,
+        "Routing provider unavailable".to_string(),
+    )
+        .into_response()
+}
+
+/// Respond with the features, saving them first when asked to.
+async fn respond(
+    state: &AppState,
+    features: Vec<Value>,
+    save_as: Option<String>,
+    save: impl std::future::Future<Output = anyhow::Result<()>>,
+) -> Response {
+    if let Some(name) = &save_as {
+        let saved = match save.await {
+            Ok(_) => match state.sources.refresh().await {
+                Ok(_) => Layer::refresh(&state.app_data, &state.pg_pool, &state.read_pool, name)
+                    .await
+                    .map(|_| ()),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            error!("Failed to save routing layer {name}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save layer".to_string(),
+            )
+                .into_response();
+        }
+    }
+    Json(json!({
+        "type": "FeatureCollection",
+        "features": features,
+        "layer": save_as,
+    }))
+    .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/routing/route",
+    tag = "routing",
+    request_body = RouteRequest,
+    responses(
+        (status = 200, description = "Route as a GeoJSON feature"),
+        (status = 422, body = ValidationErrors),
+        (status = 502),
+        (status = 503, description = "No routing engine configured"),
+    ),
+)]
+pub async fn route(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<RouteRequest>,
+) -> Response {
+    let service = match routing_service(&state) {
+        Ok(service) => service,
+        Err(response) => return response,
+    };
+
+    let route = match service.route(req.profile, &req.locations).await {
+        Ok(route) => route,
+        Err(e) => return provider_error(e),
+    };
+    let feature = json!({
+        "type": "Feature",
+        "geometry": route.geometry,
+        "properties": { "distance": route.distance, "duration": route.duration },
+    });
+    let name = req.save_as.clone().unwrap_or_default();
+    respond(
+        &state,
+        vec![feature],
+        req.save_as,
+        routing::save_route(&state.pg_pool, &name, &route),
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/routing/isochrone",
+    tag = "routing",
+    request_body = IsochroneRequest,
+    responses(
+        (status = 200, description = "Isochrones as a GeoJSON feature collection"),
+        (status = 422, body = ValidationErrors),
+        (status = 502),
+        (status = 503, description = "No routing engine configured"),
+    ),
+)]
+pub async fn isochrone(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<IsochroneRequest>,
+) -> Response {
+    let service = match routing_service(&state) {
+        Ok(service) => service,
+        Err(response) => return response,
+    };
+
+    let contours = match service
+        .isochrone(req.profile, req.location, &req.minutes)
+        .await
+    {
+        Ok(contours) => contours,
+        Err(e) => return provider_error(e),
+    };
+    let features = contours
+        .iter()
+        .map(|contour| {
+            json!({
+                "type": "Feature",
+                "geometry": contour.geometry,
+                "properties": { "minutes": contour.minutes },
+            })
+        })
+        .collect();
+    let name = req.save_as.clone().unwrap_or_default();
+    respond(
+        &state,
+        features,
+        req.save_as,
+        routing::save_isochrones(&state.pg_pool, &name, &contours),
+    )
+    .await
+}
@@ -0,0 +1,213 @@
+use super::features::layer_table;
+use super::{start_job, ValidJson};
+use crate::app_state::AppState;
+use crate::core::{Connector, Job};
+use crate::cron::CronSchedule;
+use crate::schedules::{self, LayerSchedule, RunTrigger, ScheduleRun, ScheduleSource};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Runs listed per layer.
+const LISTED_RUNS: i64 = 50;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ScheduleRequest {
+    /// Five-field cron expression, evaluated in UTC, e.g. `0 6 * * 1-5`,
+    /// or a shorthand such as `@daily`.
+    pub cron: String,
+    pub source: ScheduleSource,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Validate for ScheduleRequest {
+    fn check(&self, v: &mut Validator) {
+        if let Err(message) = self.cron.parse::<CronSchedule>() {
+            v.check("cron", false, "invalid_cron", message);
+        }
+        match &self.source {
+            ScheduleSource::Url { url } => v.http_url("source.url", url),
+            ScheduleSource::Connector { connector_id } => {
+                v.length("source.connector_id", connector_id, 1, 200)
+            }
+        }
+    }
+}
+
+fn schedule_failed(source_id: &str, e: anyhow::Error) -> Response {
+    warn!("Schedule of layer {source_id} failed: {e:#}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to process schedule".to_string(),
+    )
+        .into_response()
+}
+
+/// Refresh the layer from a URL or connector on a cron schedule. Each run
+/// is recorded with counts of the features it added and removed, and runs
+/// that change the layer publish a `layer_data_changed` event. Replaces
+/// any schedule the layer had.
+#[utoipa::path(
+    put,
+    path = "/layers/{source_id}/schedule",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body = ScheduleRequest,
+    responses(
+        (status = 200, body = LayerSchedule),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn put_layer_schedule(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    ValidJson(req): ValidJson<ScheduleRequest>,
+) -> Response {
+    match &req.source {
+        ScheduleSource::Url { .. } => {
+            if let Err(response) = layer_table(&state, &source_id).await {
+                return response;
+            }
+        }
+        // A connector's layer only exists once it has been harvested
+        ScheduleSource::Connector { connector_id } => {
+            let connector = match Connector::from_id(&state.app_data, connector_id).await {
+                Ok(connector) => connector,
+                Err(e) => return e.into_response(),
+            };
+            if connector.layer_id != source_id {
+                return ValidationErrors::single(
+                    "source.connector_id",
+                    "wrong_layer",
+                    format!("connector publishes layer {}", connector.layer_id),
+                )
+                .into_response();
+            }
+        }
+    }
+    let cron = match req.cron.parse::<CronSchedule>() {
+        Ok(cron) => cron,
+        Err(message) => {
+            return ValidationErrors::single("cron", "invalid_cron", message).into_response()
+        }
+    };
+    match schedules::put(&state.pg_pool, &source_id, &cron, &req.source, req.enabled).await {
+        Ok(schedule) => Json(schedule).into_response(),
+        Err(e) => schedule_failed(&source_id, e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/schedule",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 200, body = LayerSchedule),
+        (status = 404, description = "The layer has no schedule"),
+    ),
+)]
+pub async fn get_layer_schedule(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    match schedules::get(&state.pg_pool, &source_id).await {
+        Ok(Some(schedule)) => Json(schedule).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Schedule not found".to_string()).into_response(),
+        Err(e) => schedule_failed(&source_id, e),
+    }
+}
+
+/// Stop refreshing the layer. The layer and its past runs are kept.
+#[utoipa::path(
+    delete,
+    path = "/layers/{source_id}/schedule",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 204),
+        (status = 404, description = "The layer has no schedule"),
+    ),
+)]
+pub async fn delete_layer_schedule(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    match schedules::delete(&state.pg_pool, &source_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Schedule not found".to_string()).into_response(),
+        Err(e) => schedule_failed(&source_id, e),
+    }
+}
+
+/// Refresh the layer from its schedule's source now, as a job. The next
+/// scheduled run is left as it was.
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/schedule/run",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses(
+        (status = 202, body = Job),
+        (status = 404, description = "The layer has no schedule"),
+    ),
+)]
+pub async fn run_layer_schedule(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    let schedule = match schedules::get(&state.pg_pool, &source_id).await {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, "Schedule not found".to_string()).into_response()
+        }
+        Err(e) => return schedule_failed(&source_id, e),
+    };
+
+    let job = Job::new("layer:refresh");
+    let response = match start_job(&state, &job).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let jobs = state.jobs.clone();
+    let database = state.app_data.clone();
+    jobs.spawn(job, database, async move {
+        schedules::run(&state, &schedule, RunTrigger::Manual).await?;
+        Ok(source_id)
+    });
+    response
+}
+
+/// The layer's most recent refreshes, newest first, with what each changed
+/// or why it failed.
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/schedule/runs",
+    tag = "layers",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    responses((status = 200, body = Vec<ScheduleRun>)),
+)]
+pub async fn get_layer_schedule_runs(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+) -> Response {
+    match schedules::runs(&state.pg_pool, &source_id, LISTED_RUNS).await {
+        Ok(runs) => Json(runs).into_response(),
+        Err(e) => schedule_failed(&source_id, e),
+    }
+}
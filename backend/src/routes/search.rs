@@ -0,0 +1,75 @@
+use crate::app_state::AppState;
+use crate::core;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+
+const MAX_FEATURE_LAYERS: usize = 10;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    pub q: String,
+    /// Comma-separated layers whose feature attributes are searched too.
+    pub layers: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matching layers, maps and features, under `results`"),
+        (status = 400),
+    ),
+)]
+pub async fn search(State(state): State<AppState>, Query(params): Query<SearchParams>) -> Response {
+    if params.q.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Query must not be empty".to_string(),
+        )
+            .into_response();
+    }
+    let feature_layers: Vec<String> = params
+        .layers
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| state.sources.contains(id))
+        .map(str::to_string)
+        .collect();
+    if feature_layers.len() > MAX_FEATURE_LAYERS {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("At most {MAX_FEATURE_LAYERS} layers can be searched at once"),
+        )
+            .into_response();
+    }
+
+    match core::search(
+        &state.app_data,
+        &state.sources,
+        &state.read_pool,
+        &state.query_cache,
+        &params.q,
+        &feature_layers,
+    )
+    .await
+    {
+        Ok(results) => Json(json!({ "results": results })).into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Search failed".to_string(),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,371 @@
+use super::{serve_tile, PageParams, SharePage};
+use crate::app_state::AppState;
+use crate::cdn::CacheKey;
+use crate::core::{
+    style_document, valid_origin, Map, Share, ShareResource, MAX_ALLOWED_ORIGINS, MAX_EXPIRES_IN,
+};
+use crate::validation::{Validate, ValidationErrors, Validator};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ShareRequest {
+    /// Lifetime of the link in seconds, at most a year. Omit for a link that
+    /// never expires.
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Validate for ShareRequest {
+    fn check(&self, v: &mut Validator) {
+        if let Some(seconds) = self.expires_in {
+            v.range("expires_in", seconds, 1, MAX_EXPIRES_IN);
+        }
+        v.count(
+            "allowed_origins",
+            self.allowed_origins.len(),
+            0,
+            MAX_ALLOWED_ORIGINS,
+        );
+        for (index, origin) in self.allowed_origins.iter().enumerate() {
+            v.check(
+                &format!("allowed_origins[{index}]"),
+                valid_origin(origin),
+                "invalid_origin",
+                "must be a scheme and host, e.g. https://example.com",
+            );
+        }
+    }
+}
+
+fn share_response(state: &AppState, share: &Share) -> Response {
+    let base = format!("{}/shared/{}", state.public_url, share.token);
+    let url = match share.resource {
+        ShareResource::Map => format!("{base}/style.json"),
+        ShareResource::Layer => format!("{base}/tiles/{}/{{z}}/{{x}}/{{y}}", share.resource_id),
+    };
+    (
+        StatusCode::CREATED,
+        Json(json!({ "share": share, "url": url })),
+    )
+        .into_response()
+}
+
+async fn create_share(
+    state: &AppState,
+    resource: ShareResource,
+    resource_id: &str,
+    req: ShareRequest,
+) -> Response {
+    if let Err(e) = req.validate() {
+        return e.into_response();
+    }
+
+    let share = match Share::new(resource, resource_id, req.expires_in, req.allowed_origins) {
+        Ok(share) => share,
+        Err(e) => return e.into_response(),
+    };
+    match share.create(&state.app_data).await {
+        Ok(_) => share_response(state, &share),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create share".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+async fn list_shares(
+    state: &AppState,
+    resource: ShareResource,
+    resource_id: &str,
+    page: PageParams,
+) -> Response {
+    match Share::get_active(&state.app_data, resource, resource_id).await {
+        Ok(shares) => page.respond(shares),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list shares".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/maps/{map_id}/share",
+    tag = "sharing",
+    params(("map_id" = String, Path)),
+    request_body(content = ShareRequest, description = "Optional; omit for a share that never expires and allows any origin"),
+    responses(
+        (status = 201, description = "The share and its public URL"),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn share_map(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    req: Option<Json<ShareRequest>>,
+) -> Response {
+    if let Err(e) = Map::from_id(&state.app_data, &map_id).await {
+        return e.into_response();
+    }
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+    create_share(&state, ShareResource::Map, &map_id, req).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/layers/{source_id}/share",
+    tag = "sharing",
+    params(("source_id" = String, Path, description = "Tile source id")),
+    request_body(content = ShareRequest, description = "Optional; omit for a share that never expires and allows any origin"),
+    responses(
+        (status = 201, description = "The share and its public URL"),
+        (status = 400),
+        (status = 404),
+        (status = 422, body = ValidationErrors),
+    ),
+)]
+pub async fn share_layer(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    req: Option<Json<ShareRequest>>,
+) -> Response {
+    if !state.sources.contains(&source_id) {
+        return (StatusCode::NOT_FOUND, "Layer not found".to_string()).into_response();
+    }
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+    create_share(&state, ShareResource::Layer, &source_id, req).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/maps/{map_id}/shares",
+    tag = "sharing",
+    params(("map_id" = String, Path), PageParams),
+    responses((status = 200, body = SharePage), (status = 400)),
+)]
+pub async fn get_map_shares(
+    State(state): State<AppState>,
+    Path(map_id): Path<String>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    list_shares(&state, ShareResource::Map, &map_id, page).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/layers/{source_id}/shares",
+    tag = "sharing",
+    params(("source_id" = String, Path, description = "Tile source id"), PageParams),
+    responses((status = 200, body = SharePage), (status = 400)),
+)]
+pub async fn get_layer_shares(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(page): Query<PageParams>,
+) -> Response {
+    list_shares(&state, ShareResource::Layer, &source_id, page).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/shares/{token}",
+    tag = "sharing",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 204),
+        (status = 404),
+    ),
+)]
+pub async fn revoke_share(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    let share = match Share::from_token(&state.app_data, &token).await {
+        Ok(share) => share,
+        Err(e) => return e.into_response(),
+    };
+
+    match share.revoke(&state.app_data).await {
+        Ok(_) => {
+            state.cdn.purge(&[CacheKey::Share(share.token)]).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to revoke share".to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Resolve a share token, treating expired links as missing.
+pub(super) async fn active_share(state: &AppState, token: &str) -> Option<Share> {
+    Share::from_token(&state.app_data, token)
+        .await
+        .ok()
+        .filter(Share::is_active)
+}
+
+/// Reject cross-origin requests from origins outside the share's allowlist,
+/// otherwise echo the origin back so browsers accept the response. Shares
+/// open to any origin say so on every response, as caches may hand one
+/// fetched without an `Origin` to any page.
+fn apply_cors(share: &Share, headers: &HeaderMap, mut response: Response) -> Response {
+    if share.allowed_origins.is_empty() {
+        response.headers_mut().insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+        return response;
+    }
+    // Tile responses allow any origin until told otherwise
+    let response_headers = response.headers_mut();
+    response_headers.remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    response_headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|o| o.to_str().ok()) else {
+        return response;
+    };
+    if !share.allows_origin(origin) {
+        return (StatusCode::FORBIDDEN, "Origin not allowed".to_string()).into_response();
+    }
+    if let Ok(value) = HeaderValue::from_str(origin) {
+        response
+            .headers_mut()
+            .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    }
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared/{token}/style.json",
+    tag = "sharing",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "MapLibre style document"),
+        (status = 403, description = "Origin not allowed"),
+        (status = 404),
+    ),
+)]
+pub async fn shared_style(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let not_found = (StatusCode::NOT_FOUND, "Share not found".to_string()).into_response();
+    let Some(share) = active_share(&state, &token).await else {
+        return not_found;
+    };
+    if share.resource != ShareResource::Map {
+        return not_found;
+    }
+
+    let response = match Map::from_id(&state.app_data, &share.resource_id).await {
+        Ok(map) => {
+            let tile_url = |source_id: &str| {
+                format!(
+                    "{}/shared/{}/tiles/{source_id}/{{z}}/{{x}}/{{y}}",
+                    state.public_url, share.token
+                )
+            };
+            let style = style_document(&map, &state.sources, &state.labels, tile_url);
+            let mut keys = vec![
+                CacheKey::Map(map.id.clone()),
+                CacheKey::Share(share.token.clone()),
+            ];
+            keys.extend(
+                map.layers
+                    .iter()
+                    .map(|layer| CacheKey::Layer(layer.source_id.clone())),
+            );
+            state.cdn.style_response(&headers, &style, &keys)
+        }
+        Err(_) => return not_found,
+    };
+    apply_cors(&share, &headers, response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared/{token}/tiles/{source_id}/{z}/{x}/{y}",
+    tag = "sharing",
+    params(
+        ("token" = String, Path, description = "Share token"),
+        ("source_id" = String, Path, description = "Tile source id"),
+        ("z" = u8, Path),
+        ("x" = u32, Path),
+        ("y" = u32, Path),
+    ),
+    responses(
+        (status = 200, description = "Mapbox vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 403, description = "Origin not allowed"),
+        (status = 404),
+    ),
+)]
+pub async fn shared_tiles(
+    Path((token, source_id, z, x, y)): Path<(String, String, u32, u32, u32)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let not_found = (StatusCode::NOT_FOUND, "Share not found".to_string()).into_response();
+    let Some(share) = active_share(&state, &token).await else {
+        return not_found;
+    };
+
+    let allowed = match share.resource {
+        ShareResource::Layer => share.resource_id == source_id,
+        ShareResource::Map => Map::from_id(&state.app_data, &share.resource_id)
+            .await
+            .map(|map| map.layers.iter().any(|layer| layer.source_id == source_id))
+            .unwrap_or(false),
+    };
+    if !allowed {
+        return not_found;
+    }
+
+    let share_key = [CacheKey::Share(share.token.clone())];
+    let response = serve_tile(&state, &headers, &source_id, &share_key, z, x, y).await;
+    apply_cors(&share, &headers, response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_is_capped_at_a_year() {
+        let within = ShareRequest {
+            expires_in: Some(MAX_EXPIRES_IN),
+            ..Default::default()
+        };
+        assert!(within.validate().is_ok());
+        let beyond = ShareRequest {
+            expires_in: Some(MAX_EXPIRES_IN + 1),
+            ..Default::default()
+        };
+        assert!(beyond.validate().is_err());
+    }
+
+    #[test]
+    fn allowed_origins_are_capped() {
+        let request = ShareRequest {
+            expires_in: None,
+            allowed_origins: (0..=MAX_ALLOWED_ORIGINS)
+                .map(|i| format!("https://{i}.example"))
+                .collect(),
+        };
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "allowed_origins");
+        assert_eq!(errors.errors[0].code, "too_many");
+    }
+}
@@ -0,0 +1,38 @@
+use crate::validation::{Validate, ValidationErrors};
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+
+/// A JSON body that has passed its [`Validate`] rules. Bodies that fail are
+/// answered with 422 and every failing field; unreadable bodies keep axum's
+/// status but report the problem in the same shape.
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let value = match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => value,
+            Err(rejection) => return Err(unreadable(rejection)),
+        };
+        match value.validate() {
+            Ok(()) => Ok(ValidJson(value)),
+            Err(errors) => Err(errors.into_response()),
+        }
+    }
+}
+
+fn unreadable(rejection: JsonRejection) -> Response {
+    let errors = ValidationErrors::single("", "invalid_body", rejection.body_text());
+    (rejection.status(), errors).into_response()
+}
@@ -0,0 +1,67 @@
+use super::Scanner;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Bytes sent per INSTREAM chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// clamd, streamed each file with its INSTREAM command.
+pub struct ClamAv {
+    /// `host:port`, or the path of a Unix socket.
+    address: String,
+}
+
+impl ClamAv {
+    pub fn new(address: String) -> Self {
+        ClamAv { address }
+    }
+}
+
+async fn instream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, body: &[u8]) -> Result<String> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in body.chunks(CHUNK_SIZE) {
+        stream
+            .write_all(&(chunk.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    Ok(String::from_utf8_lossy(&reply)
+        .trim_end_matches(['\0', '\n'])
+        .to_string())
+}
+
+/// `stream: OK`, `stream: <signature> FOUND` or `<reason> ERROR`.
+fn verdict(reply: &str) -> Result<Option<String>> {
+    let verdict = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if verdict == "OK" {
+        Ok(None)
+    } else if let Some(threat) = verdict.strip_suffix(" FOUND") {
+        Ok(Some(threat.to_string()))
+    } else {
+        Err(anyhow!("clamd answered {reply}"))
+    }
+}
+
+#[async_trait]
+impl Scanner for ClamAv {
+    fn name(&self) -> &'static str {
+        "clamav"
+    }
+
+    async fn scan(&self, body: &[u8]) -> Result<Option<String>> {
+        #[cfg(unix)]
+        if self.address.starts_with('/') {
+            let stream = tokio::net::UnixStream::connect(&self.address).await?;
+            return verdict(&instream(stream, body).await?);
+        }
+        let stream = TcpStream::connect(&self.address).await?;
+        verdict(&instream(stream, body).await?)
+    }
+}
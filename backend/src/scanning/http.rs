@@ -0,0 +1,55 @@
+use super::Scanner;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// An external scanning service, POSTed each file.
+pub struct HttpScanner {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct Verdict {
+    clean: bool,
+    threat: Option<String>,
+}
+
+impl HttpScanner {
+    pub fn new(url: String, timeout: Duration) -> Result<Self> {
+        Ok(HttpScanner {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url,
+        })
+    }
+}
+
+#[async_trait]
+impl Scanner for HttpScanner {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn scan(&self, body: &[u8]) -> Result<Option<String>> {
+        let verdict: Verdict = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(body.to_vec())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if verdict.clean {
+            Ok(None)
+        } else {
+            Ok(Some(
+                verdict
+                    .threat
+                    .unwrap_or_else(|| "unnamed threat".to_string()),
+            ))
+        }
+    }
+}
@@ -0,0 +1,122 @@
+mod clamav;
+mod http;
+
+pub use clamav::ClamAv;
+pub use http::HttpScanner;
+
+use crate::config::ScanningConfig;
+use crate::storage::Storage;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+#[async_trait]
+pub trait Scanner: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// The threat found in `body`, or `None` when it is clean.
+    async fn scan(&self, body: &[u8]) -> Result<Option<String>>;
+}
+
+/// The verdict on a scanned file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ScanResult {
+    /// Scanner that checked the file, e.g. `clamav`.
+    pub scanner: String,
+    pub clean: bool,
+    /// What the scanner found, when it flagged the file.
+    pub threat: Option<String>,
+    /// Storage key the flagged file was kept under, for review.
+    pub quarantine_key: Option<String>,
+    pub scanned_at: i64,
+}
+
+/// Scans attachments and downloaded imports before they are used, when a
+/// scanner is configured.
+pub struct Scanning {
+    scanner: Option<Box<dyn Scanner>>,
+    timeout: Duration,
+    fail_open: bool,
+    quarantine_prefix: String,
+}
+
+impl Scanning {
+    pub fn from_config(config: &ScanningConfig) -> Result<Self> {
+        let timeout = Duration::from_secs(config.timeout);
+        let scanner: Option<Box<dyn Scanner>> = match config.provider.as_str() {
+            "none" => None,
+            "clamav" => Some(Box::new(ClamAv::new(config.address()?))),
+            "http" => Some(Box::new(HttpScanner::new(config.url()?, timeout)?)),
+            other => return Err(anyhow!("Unknown scanning provider: {other}")),
+        };
+        Ok(Scanning {
+            scanner,
+            timeout,
+            fail_open: config.fail_open,
+            quarantine_prefix: config.quarantine_prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Scan a file, keeping it in quarantine under `id` when it is flagged.
+    /// `None` when scanning is disabled, or when the scanner failed and
+    /// `fail_open` is set.
+    pub async fn check(
+        &self,
+        storage: &Storage,
+        id: &str,
+        body: &[u8],
+    ) -> Result<Option<ScanResult>> {
+        let Some(scanner) = &self.scanner else {
+            return Ok(None);
+        };
+        let verdict = match tokio::time::timeout(self.timeout, scanner.scan(body)).await {
+            Ok(verdict) => verdict,
+            Err(_) => Err(anyhow!("no verdict within {:?}", self.timeout)),
+        };
+        let threat = match verdict {
+            Ok(threat) => threat,
+            Err(e) if self.fail_open => {
+                warn!(
+                    "Scanning via {} failed, accepting the file: {e:#}",
+                    scanner.name()
+                );
+                counter!("scans_total", "scanner" => scanner.name(), "result" => "error")
+                    .increment(1);
+                return Ok(None);
+            }
+            Err(e) => {
+                counter!("scans_total", "scanner" => scanner.name(), "result" => "error")
+                    .increment(1);
+                return Err(e.context(format!("scanning via {} failed", scanner.name())));
+            }
+        };
+        let result = if threat.is_some() { "flagged" } else { "clean" };
+        counter!("scans_total", "scanner" => scanner.name(), "result" => result).increment(1);
+
+        let mut quarantine_key = None;
+        if let Some(threat) = &threat {
+            warn!("{} flagged {id} as {threat}", scanner.name());
+            if let Some(store) = storage.store() {
+                let key = format!("{}/{id}", self.quarantine_prefix);
+                match store
+                    .put(&key, body.to_vec(), "application/octet-stream")
+                    .await
+                {
+                    Ok(()) => quarantine_key = Some(key),
+                    Err(e) => error!("Failed to quarantine {id}: {e:#}"),
+                }
+            }
+        }
+        Ok(Some(ScanResult {
+            scanner: scanner.name().to_string(),
+            clean: threat.is_none(),
+            threat,
+            quarantine_key,
+            scanned_at: Utc::now().timestamp(),
+        }))
+    }
+}
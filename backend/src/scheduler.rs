@@ -0,0 +1,242 @@
+use crate::alerts::Alert;
+use crate::app_state::AppState;
+use crate::config::{Config, TaskConfig};
+use crate::core::{Event, Layer, Map};
+use crate::harvest;
+use crate::schedules;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use metrics::{counter, gauge, histogram};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+type TaskFn = Box<dyn Fn() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+struct ScheduledTask {
+    name: &'static str,
+    period: Duration,
+    run: TaskFn,
+}
+
+/// Runs maintenance tasks at fixed intervals. Runs of the same task never
+/// overlap; a run that overruns its interval delays the next one.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    /// Run `task` every `config.interval` seconds, starting one interval
+    /// after startup. Disabled tasks are skipped.
+    pub fn add<F, Fut>(&mut self, name: &'static str, config: &TaskConfig, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        if !config.enabled {
+            info!("Scheduled task {name} is disabled");
+            return;
+        }
+        self.tasks.push(ScheduledTask {
+            name,
+            period: Duration::from_secs(config.interval),
+            run: Box::new(move || Box::pin(task())),
+        });
+    }
+
+    pub fn start(self) {
+        for task in self.tasks {
+            tokio::spawn(task.run_forever());
+        }
+    }
+}
+
+impl ScheduledTask {
+    async fn run_forever(self) {
+        let mut ticks = interval_at(Instant::now() + self.period, self.period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let start = Instant::now();
+            let result = (self.run)().await;
+            histogram!("scheduled_task_duration_seconds", "task" => self.name)
+                .record(start.elapsed().as_secs_f64());
+            let outcome = match result {
+                Ok(()) => {
+                    gauge!("scheduled_task_last_success_timestamp", "task" => self.name)
+                        .set(Utc::now().timestamp() as f64);
+                    "ok"
+                }
+                Err(e) => {
+                    warn!("Scheduled task {} failed: {e:#}", self.name);
+                    "error"
+                }
+            };
+            counter!("scheduled_task_runs_total", "task" => self.name, "outcome" => outcome)
+                .increment(1);
+        }
+    }
+}
+
+/// The backend's built-in maintenance tasks.
+pub fn maintenance(config: &Config, state: &AppState) -> Scheduler {
+    let mut scheduler = Scheduler::default();
+
+    let database = state.app_data.clone();
+    let pool = state.pg_pool.clone();
+    let retention = config.trash_retention_days * 24 * 60 * 60;
+    scheduler.add("purge_trash", &config.scheduler.purge_trash, move || {
+        let database = database.clone();
+        let pool = pool.clone();
+        async move {
+            let purged = Map::purge_trash(&database, retention).await?;
+            if purged > 0 {
+                info!("Purged {purged} maps from the trash");
+            }
+            let purged = Layer::purge_trash(&database, &pool, retention).await?;
+            if purged > 0 {
+                info!("Purged {purged} layers from the trash");
+            }
+            Ok(())
+        }
+    });
+
+    let refresh_state = state.clone();
+    scheduler.add(
+        "refresh_layers",
+        &config.scheduler.refresh_layers,
+        move || {
+            let state = refresh_state.clone();
+            async move { refresh_layers(&state).await }
+        },
+    );
+
+    let geocoder = state.geocoder.clone();
+    let query_cache = state.query_cache.clone();
+    scheduler.add(
+        "vacuum_caches",
+        &config.scheduler.vacuum_caches,
+        move || {
+            let geocoder = geocoder.clone();
+            let query_cache = query_cache.clone();
+            async move {
+                let evicted = geocoder.evict_expired().await;
+                if evicted > 0 {
+                    info!("Evicted {evicted} expired geocoder responses");
+                }
+                let evicted = query_cache.evict_expired().await;
+                if evicted > 0 {
+                    info!("Evicted {evicted} expired query results");
+                }
+                Ok(())
+            }
+        },
+    );
+
+    let harvest_state = state.clone();
+    scheduler.add(
+        "harvest_connectors",
+        &config.scheduler.harvest_connectors,
+        move || {
+            let state = harvest_state.clone();
+            async move { harvest::run_due(&state).await }
+        },
+    );
+
+    let schedules_state = state.clone();
+    scheduler.add(
+        "layer_schedules",
+        &config.scheduler.layer_schedules,
+        move || {
+            let state = schedules_state.clone();
+            async move { schedules::run_due(&state).await }
+        },
+    );
+
+    let freshness_state = state.clone();
+    scheduler.add(
+        "check_freshness",
+        &config.scheduler.check_freshness,
+        move || {
+            let state = freshness_state.clone();
+            async move { check_freshness(&state).await }
+        },
+    );
+
+    scheduler
+}
+
+/// Pick up tables added or dropped outside Gridwalk and recompute every
+/// layer's metadata, so statistics track edits made directly in PostGIS.
+async fn refresh_layers(state: &AppState) -> Result<()> {
+    state.sources.refresh().await?;
+    let source_ids = state.sources.ids();
+    let mut failed = 0;
+    for source_id in &source_ids {
+        if let Err(e) =
+            Layer::reindex(&state.app_data, &state.pg_pool, &state.read_pool, source_id).await
+        {
+            warn!("Failed to refresh layer {source_id}: {e:#}");
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} layers failed to refresh",
+            source_ids.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Flag layers whose data is overdue for an update and alert about each
+/// once, when it first becomes overdue.
+async fn check_freshness(state: &AppState) -> Result<()> {
+    let now = Utc::now().timestamp();
+    let layers = Layer::get_all(&state.app_data).await?;
+    let mut failed = 0;
+    for layer in layers {
+        let Some(freshness) = &layer.freshness else {
+            continue;
+        };
+        if freshness.stale_since.is_some() || !freshness.is_overdue(layer.data_updated_at, now) {
+            continue;
+        }
+        let alert = Alert::LayerStale {
+            layer_id: layer.id.clone(),
+            updated_at: layer.data_updated_at,
+            max_age: freshness.max_age,
+        };
+        match Layer::mark_stale(
+            &state.app_data,
+            &state.pg_pool,
+            &state.read_pool,
+            &layer.id,
+            layer.version,
+            now,
+        )
+        .await
+        {
+            Ok(Some(_)) => {
+                warn!("Layer {} is overdue for an update", layer.id);
+                state.events.publish(Event::Alert {
+                    alert: alert.clone(),
+                });
+                state.alerts.send(&alert).await;
+            }
+            // Updated meanwhile; checked again next run
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Failed to flag layer {} as stale: {e:#}", layer.id);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{failed} stale layers could not be flagged"));
+    }
+    Ok(())
+}
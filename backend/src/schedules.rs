@@ -0,0 +1,643 @@
+use crate::app_state::AppState;
+use crate::core::{Connector, Event, Layer};
+use crate::cron::CronSchedule;
+use crate::harvest;
+use crate::postgis::{feature_columns, quote_ident, quote_literal, validate_feature, LayerTable};
+use crate::sniff::{sniff, FileType};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::Row;
+use tracing::{info, instrument, warn};
+use utoipa::ToSchema;
+
+/// Refresh schedules of layers, and the outcome of each refresh with counts
+/// of the features it changed.
+const SCHEDULES_SQL: &str = "
+CREATE SCHEMA IF NOT EXISTS gridwalk;
+CREATE TABLE IF NOT EXISTS gridwalk.layer_schedules (
+    source_id text PRIMARY KEY,
+    cron text NOT NULL,
+    source jsonb NOT NULL,
+    enabled boolean NOT NULL DEFAULT true,
+    next_run_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS gridwalk.schedule_runs (
+    id bigserial PRIMARY KEY,
+    source_id text NOT NULL,
+    trigger text NOT NULL,
+    started_at timestamptz NOT NULL,
+    finished_at timestamptz NOT NULL DEFAULT now(),
+    added bigint,
+    removed bigint,
+    unchanged bigint,
+    error text
+);
+CREATE INDEX IF NOT EXISTS schedule_runs_source_idx ON gridwalk.schedule_runs (source_id, id);
+";
+
+const SCHEDULE_COLUMNS: &str = "source_id, cron, source, enabled,
+    extract(epoch FROM next_run_at)::bigint, extract(epoch FROM created_at)::bigint";
+
+const RUN_COLUMNS: &str = "id, source_id, trigger,
+    extract(epoch FROM started_at)::bigint, extract(epoch FROM finished_at)::bigint,
+    added, removed, unchanged, error";
+
+/// Runs kept per layer; older ones are dropped as new ones are recorded.
+const KEPT_RUNS: i64 = 100;
+
+/// Features written to the staging table per statement.
+const BATCH_SIZE: usize = 1000;
+
+/// Where a scheduled refresh reads the layer's data from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleSource {
+    /// A GeoJSON FeatureCollection, Feature or newline-delimited GeoJSON
+    /// file the layer mirrors. Features that no longer appear in it are
+    /// deleted and new ones inserted; unchanged features keep their keys.
+    Url { url: String },
+    /// Harvest the connector publishing the layer.
+    Connector { connector_id: String },
+}
+
+/// Re-reads a layer's data from its source on a cron schedule.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LayerSchedule {
+    pub source_id: String,
+    /// Cron expression, evaluated in UTC, e.g. `0 6 * * 1-5`.
+    pub cron: String,
+    pub source: ScheduleSource,
+    pub enabled: bool,
+    /// When the scheduler next refreshes the layer, unless disabled.
+    pub next_run_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl LayerSchedule {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(LayerSchedule {
+            source_id: row.get(0),
+            cron: row.get(1),
+            source: serde_json::from_value(row.get(2))?,
+            enabled: row.get(3),
+            next_run_at: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Scheduled,
+    Manual,
+}
+
+impl RunTrigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            RunTrigger::Scheduled => "scheduled",
+            RunTrigger::Manual => "manual",
+        }
+    }
+}
+
+/// Features a refresh changed. A feature whose attributes or geometry
+/// changed counts as one removed and one added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChangeSummary {
+    pub added: u64,
+    pub removed: u64,
+    pub unchanged: u64,
+}
+
+impl ChangeSummary {
+    pub fn changed(&self) -> bool {
+        self.added > 0 || self.removed > 0
+    }
+
+    /// Compare the rows of a table before and after a change, given as
+    /// counts of rows by hash.
+    fn between(before: &HashMap<String, i64>, after: &HashMap<String, i64>) -> Self {
+        let mut summary = ChangeSummary::default();
+        for (hash, &count) in after {
+            let was = before.get(hash).copied().unwrap_or(0);
+            summary.unchanged += count.min(was) as u64;
+            summary.added += (count - was).max(0) as u64;
+        }
+        for (hash, &count) in before {
+            let now = after.get(hash).copied().unwrap_or(0);
+            summary.removed += (count - now).max(0) as u64;
+        }
+        summary
+    }
+}
+
+/// One refresh of a layer.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScheduleRun {
+    pub id: i64,
+    pub source_id: String,
+    pub trigger: RunTrigger,
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// What changed, when the refresh succeeded.
+    pub changes: Option<ChangeSummary>,
+    /// Why the refresh failed, if it did.
+    pub error: Option<String>,
+}
+
+impl ScheduleRun {
+    fn from_row(row: &Row) -> Self {
+        let counts: (Option<i64>, Option<i64>, Option<i64>) = (row.get(5), row.get(6), row.get(7));
+        ScheduleRun {
+            id: row.get(0),
+            source_id: row.get(1),
+            trigger: if row.get::<_, &str>(2) == "manual" {
+                RunTrigger::Manual
+            } else {
+                RunTrigger::Scheduled
+            },
+            started_at: row.get(3),
+            finished_at: row.get(4),
+            changes: match counts {
+                (Some(added), Some(removed), Some(unchanged)) => Some(ChangeSummary {
+                    added: added as u64,
+                    removed: removed as u64,
+                    unchanged: unchanged as u64,
+                }),
+                _ => None,
+            },
+            error: row.get(8),
+        }
+    }
+}
+
+/// Create or replace the layer's schedule. The next run is the cron
+/// expression's next match from now.
+#[instrument(skip(pool, source))]
+pub async fn put(
+    pool: &Pool,
+    source_id: &str,
+    cron: &CronSchedule,
+    source: &ScheduleSource,
+    enabled: bool,
+) -> Result<LayerSchedule> {
+    let next_run_at = enabled
+        .then(|| cron.next_after(Utc::now()))
+        .flatten()
+        .map(|next| next.timestamp());
+    let client = pool.get().await?;
+    client.batch_execute(SCHEDULES_SQL).await?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO gridwalk.layer_schedules (source_id, cron, source, enabled, next_run_at)
+                 VALUES ($1, $2, $3, $4, to_timestamp($5::bigint))
+                 ON CONFLICT (source_id) DO UPDATE SET cron = EXCLUDED.cron,
+                     source = EXCLUDED.source, enabled = EXCLUDED.enabled,
+                     next_run_at = EXCLUDED.next_run_at
+                 RETURNING {SCHEDULE_COLUMNS}"
+            ),
+            &[
+                &source_id,
+                &cron.to_string(),
+                &serde_json::to_value(source)?,
+                &enabled,
+                &next_run_at,
+            ],
+        )
+        .await?;
+    LayerSchedule::from_row(&row)
+}
+
+#[instrument(skip(pool))]
+pub async fn get(pool: &Pool, source_id: &str) -> Result<Option<LayerSchedule>> {
+    let client = pool.get().await?;
+    client.batch_execute(SCHEDULES_SQL).await?;
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT {SCHEDULE_COLUMNS} FROM gridwalk.layer_schedules WHERE source_id = $1"
+            ),
+            &[&source_id],
+        )
+        .await?;
+    row.as_ref().map(LayerSchedule::from_row).transpose()
+}
+
+/// Stop refreshing the layer. Its past runs are kept. Returns whether it
+/// had a schedule.
+#[instrument(skip(pool))]
+pub async fn delete(pool: &Pool, source_id: &str) -> Result<bool> {
+    let client = pool.get().await?;
+    client.batch_execute(SCHEDULES_SQL).await?;
+    let deleted = client
+        .execute(
+            "DELETE FROM gridwalk.layer_schedules WHERE source_id = $1",
+            &[&source_id],
+        )
+        .await?;
+    Ok(deleted > 0)
+}
+
+/// The layer's most recent refreshes, newest first.
+#[instrument(skip(pool))]
+pub async fn runs(pool: &Pool, source_id: &str, limit: i64) -> Result<Vec<ScheduleRun>> {
+    let client = pool.get().await?;
+    client.batch_execute(SCHEDULES_SQL).await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {RUN_COLUMNS} FROM gridwalk.schedule_runs
+                 WHERE source_id = $1 ORDER BY id DESC LIMIT $2"
+            ),
+            &[&source_id, &limit],
+        )
+        .await?;
+    Ok(rows.iter().map(ScheduleRun::from_row).collect())
+}
+
+async fn record(
+    pool: &Pool,
+    source_id: &str,
+    trigger: RunTrigger,
+    started_at: i64,
+    changes: Option<ChangeSummary>,
+    error: Option<&str>,
+) -> Result<ScheduleRun> {
+    let count = |field: fn(&ChangeSummary) -> u64| changes.as_ref().map(|c| field(c) as i64);
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO gridwalk.schedule_runs
+                     (source_id, trigger, started_at, added, removed, unchanged, error)
+                 VALUES ($1, $2, to_timestamp($3::bigint), $4, $5, $6, $7)
+                 RETURNING {RUN_COLUMNS}"
+            ),
+            &[
+                &source_id,
+                &trigger.as_str(),
+                &started_at,
+                &count(|c| c.added),
+                &count(|c| c.removed),
+                &count(|c| c.unchanged),
+                &error,
+            ],
+        )
+        .await?;
+    client
+        .execute(
+            "DELETE FROM gridwalk.schedule_runs WHERE source_id = $1 AND id <= (
+                 SELECT id FROM gridwalk.schedule_runs WHERE source_id = $1
+                 ORDER BY id DESC OFFSET $2 LIMIT 1)",
+            &[&source_id, &KEPT_RUNS],
+        )
+        .await?;
+    Ok(ScheduleRun::from_row(&row))
+}
+
+/// Refresh the layer from its schedule's source and record the run. When
+/// any feature changed, a `layer_data_changed` event is published.
+#[instrument(skip_all, fields(layer = %schedule.source_id))]
+pub async fn run(
+    state: &AppState,
+    schedule: &LayerSchedule,
+    trigger: RunTrigger,
+) -> Result<ScheduleRun> {
+    let started_at = Utc::now().timestamp();
+    let result = match &schedule.source {
+        ScheduleSource::Url { url } => refresh_from_url(state, &schedule.source_id, url).await,
+        ScheduleSource::Connector { connector_id } => {
+            refresh_from_connector(state, &schedule.source_id, connector_id).await
+        }
+    };
+    let (changes, error) = match &result {
+        Ok(changes) => (Some(*changes), None),
+        Err(e) => (None, Some(format!("{e:#}"))),
+    };
+    let run = record(
+        &state.pg_pool,
+        &schedule.source_id,
+        trigger,
+        started_at,
+        changes,
+        error.as_deref(),
+    )
+    .await?;
+    let changes = result?;
+    if changes.changed() {
+        info!(
+            "Refresh of {} added {} and removed {} features",
+            schedule.source_id, changes.added, changes.removed
+        );
+        state
+            .events
+            .publish(Event::LayerDataChanged { run: run.clone() });
+    }
+    Ok(run)
+}
+
+/// Refresh every layer whose schedule is due, one at a time. Each due
+/// schedule is moved on to its next run before refreshing, so replicas
+/// don't pick up the same one.
+pub async fn run_due(state: &AppState) -> Result<()> {
+    let due = claim_due(&state.pg_pool).await?;
+    let mut failed = 0;
+    for schedule in &due {
+        if let Err(e) = run(state, schedule, RunTrigger::Scheduled).await {
+            warn!("Failed to refresh layer {}: {e:#}", schedule.source_id);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} scheduled refreshes failed",
+            due.len()
+        ));
+    }
+    Ok(())
+}
+
+async fn claim_due(pool: &Pool) -> Result<Vec<LayerSchedule>> {
+    let mut client = pool.get().await?;
+    client.batch_execute(SCHEDULES_SQL).await?;
+    let transaction = client.transaction().await?;
+    let rows = transaction
+        .query(
+            &format!(
+                "SELECT {SCHEDULE_COLUMNS} FROM gridwalk.layer_schedules
+                 WHERE enabled AND next_run_at <= now()
+                 ORDER BY next_run_at
+                 FOR UPDATE SKIP LOCKED"
+            ),
+            &[],
+        )
+        .await?;
+    let mut due = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let schedule = LayerSchedule::from_row(row)?;
+        let next_run_at = match schedule.cron.parse::<CronSchedule>() {
+            Ok(cron) => cron.next_after(Utc::now()).map(|next| next.timestamp()),
+            Err(e) => {
+                warn!("Schedule of {} is invalid: {e}", schedule.source_id);
+                None
+            }
+        };
+        transaction
+            .execute(
+                "UPDATE gridwalk.layer_schedules SET next_run_at = to_timestamp($2::bigint)
+                 WHERE source_id = $1",
+                &[&schedule.source_id, &next_run_at],
+            )
+            .await?;
+        due.push(schedule);
+    }
+    transaction.commit().await?;
+    Ok(due)
+}
+
+/// The features of a downloaded GeoJSON document, or `None` when it holds
+/// one feature per line.
+pub(crate) fn parse_download(body: &[u8]) -> Result<Option<Vec<Value>>> {
+    // Archives are refused whole rather than extracted
+    match sniff(body) {
+        Some(FileType::Json | FileType::Text) => {}
+        Some(file_type) => {
+            return Err(anyhow!(
+                "{} files are not supported; send GeoJSON",
+                file_type.name()
+            ))
+        }
+        None => return Err(anyhow!("unrecognised binary file; send GeoJSON")),
+    }
+    let Ok(mut document) = serde_json::from_slice::<Value>(body) else {
+        return Ok(None);
+    };
+    match document.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => match document.get_mut("features").map(Value::take) {
+            Some(Value::Array(features)) => Ok(Some(features)),
+            _ => Err(anyhow!("FeatureCollection has no features array")),
+        },
+        Some("Feature") => Ok(Some(vec![document])),
+        _ => Err(anyhow!("not a GeoJSON Feature or FeatureCollection")),
+    }
+}
+
+/// Every feature of a download, whole document or one per line.
+fn read_features(body: &[u8]) -> Result<Vec<Value>> {
+    if let Some(features) = parse_download(body)? {
+        return Ok(features);
+    }
+    body.split(|b| *b == b'\n')
+        .enumerate()
+        .map(|(index, line)| (index, line.strip_suffix(b"\r").unwrap_or(line)))
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(index, line)| {
+            serde_json::from_slice(line)
+                .map_err(|e| anyhow!("line {}: invalid JSON: {e}", index + 1))
+        })
+        .collect()
+}
+
+/// Download the URL and bring the layer's features in line with it. The
+/// download is checked as a URL import's is, but must pass whole.
+async fn refresh_from_url(state: &AppState, source_id: &str, url: &str) -> Result<ChangeSummary> {
+    let pool = &state.pg_pool;
+    let table = LayerTable::from_source_id(pool, source_id).await?;
+    if table.is_view {
+        return Err(anyhow!("layer is a SQL view and cannot be refreshed"));
+    }
+    let body = state.fetcher.get(url).await?;
+    let scan_id = format!("schedules/{source_id}");
+    if let Some(scan) = state
+        .scanning
+        .check(&state.storage, &scan_id, &body)
+        .await?
+    {
+        if let Some(threat) = scan.threat {
+            return Err(anyhow!("Download flagged by the malware scanner: {threat}"));
+        }
+    }
+    let features = read_features(&body)?;
+    if state.limits.max_features > 0 && features.len() as u64 > state.limits.max_features {
+        return Err(anyhow!(
+            "download has more than {} features",
+            state.limits.max_features
+        ));
+    }
+    let columns: HashSet<String> = table.attribute_columns(pool).await?.into_iter().collect();
+    let layer = Layer::from_id(&state.app_data, pool, &state.read_pool, source_id).await?;
+    let quality = layer
+        .quality
+        .as_ref()
+        .map(|quality| quality.checker(table.srid));
+    for (index, feature) in features.iter().enumerate() {
+        validate_feature(feature, &columns, &state.limits)
+            .and_then(|()| match &layer.form {
+                Some(form) => form.check(feature, false),
+                None => Ok(()),
+            })
+            .and_then(|()| match &quality {
+                Some(quality) => quality.enforce(feature, false).map(|_| ()),
+                None => Ok(()),
+            })
+            .map_err(|e| anyhow!("feature {}: {e}", index + 1))?;
+    }
+
+    let key = table.primary_key(pool).await?;
+    let changes = replace_features(pool, &table, key.as_deref(), &features).await?;
+    if changes.changed() {
+        Layer::refresh(&state.app_data, pool, &state.read_pool, source_id).await?;
+    }
+    Ok(changes)
+}
+
+/// SQL hashing a row of `table`, aliased `alias`, without the `ignored`
+/// columns. The geometry is hashed as EWKB.
+fn row_hash_sql(table: &LayerTable, alias: &str, ignored: &[String]) -> String {
+    let ignored: Vec<String> = ignored
+        .iter()
+        .chain([&table.geometry_column])
+        .map(|column| quote_literal(column))
+        .collect();
+    format!(
+        "md5(((to_jsonb({alias}) - ARRAY[{}]::text[]) || jsonb_build_object('', {alias}.{}::text))::text)",
+        ignored.join(", "),
+        quote_ident(&table.geometry_column),
+    )
+}
+
+/// Replace the features of `table` with `features`, touching only rows
+/// that differ: rows without an identical feature are deleted and features
+/// without an identical row inserted. Rows are compared on the columns the
+/// features set, so columns the download doesn't have, such as the primary
+/// key, don't count as changes.
+async fn replace_features(
+    pool: &Pool,
+    table: &LayerTable,
+    key: Option<&str>,
+    features: &[Value],
+) -> Result<ChangeSummary> {
+    let columns: Vec<String> = feature_columns(features)
+        .into_iter()
+        .filter(|column| Some(column.as_str()) != key)
+        .collect();
+    let ignored: Vec<String> = table
+        .attribute_columns(pool)
+        .await?
+        .into_iter()
+        .filter(|column| !columns.contains(column))
+        .collect();
+    let staging = LayerTable {
+        schema: "pg_temp".to_string(),
+        table: "gridwalk_refresh".to_string(),
+        ..table.clone()
+    };
+    let mut targets: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+    targets.push(quote_ident(&table.geometry_column));
+    let targets = targets.join(", ");
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    transaction
+        .batch_execute(&format!(
+            "CREATE TEMP TABLE gridwalk_refresh ON COMMIT DROP AS
+             SELECT * FROM {} WITH NO DATA",
+            table.qualified_name()
+        ))
+        .await?;
+    let insert = staging.insert_features_sql(&columns);
+    for batch in features.chunks(BATCH_SIZE) {
+        transaction
+            .execute(&insert, &[&Value::Array(batch.to_vec())])
+            .await?;
+    }
+    // Identical rows are paired off by their position among rows with the
+    // same hash, so duplicates are counted rather than collapsed
+    let row = transaction
+        .query_one(
+            &format!(
+                "WITH existing AS (
+                     SELECT row_id, hash, row_number() OVER (PARTITION BY hash) AS n
+                     FROM (SELECT t.ctid AS row_id, {existing_hash} AS hash FROM {table} t) s
+                 ), incoming AS (
+                     SELECT row_id, hash, row_number() OVER (PARTITION BY hash) AS n
+                     FROM (SELECT t.ctid AS row_id, {incoming_hash} AS hash FROM {staging} t) s
+                 ), removed AS (
+                     DELETE FROM {table} WHERE ctid IN (
+                         SELECT e.row_id FROM existing e WHERE NOT EXISTS (
+                             SELECT 1 FROM incoming i WHERE i.hash = e.hash AND i.n = e.n))
+                     RETURNING 1
+                 ), added AS (
+                     INSERT INTO {table} ({targets})
+                     SELECT {targets} FROM {staging} WHERE ctid IN (
+                         SELECT i.row_id FROM incoming i WHERE NOT EXISTS (
+                             SELECT 1 FROM existing e WHERE e.hash = i.hash AND e.n = i.n))
+                     RETURNING 1
+                 )
+                 SELECT (SELECT count(*) FROM added), (SELECT count(*) FROM removed),
+                        (SELECT count(*) FROM incoming)",
+                existing_hash = row_hash_sql(table, "t", &ignored),
+                incoming_hash = row_hash_sql(&staging, "t", &ignored),
+                table = table.qualified_name(),
+                staging = staging.qualified_name(),
+            ),
+            &[],
+        )
+        .await?;
+    transaction.commit().await?;
+    let (added, removed, total): (i64, i64, i64) = (row.get(0), row.get(1), row.get(2));
+    Ok(ChangeSummary {
+        added: added as u64,
+        removed: removed as u64,
+        unchanged: (total - added) as u64,
+    })
+}
+
+/// Rows of the layer's table counted by hash, empty when the layer doesn't
+/// exist yet.
+async fn row_counts(state: &AppState, source_id: &str) -> Result<HashMap<String, i64>> {
+    if !state.sources.contains(source_id) {
+        return Ok(HashMap::new());
+    }
+    let table = LayerTable::from_source_id(&state.pg_pool, source_id).await?;
+    let client = state.pg_pool.get().await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {}, count(*) FROM {} t GROUP BY 1",
+                row_hash_sql(&table, "t", &[]),
+                table.qualified_name()
+            ),
+            &[],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+/// Harvest the layer's connector, comparing the layer's rows before and
+/// after since a harvest replaces the table whole.
+async fn refresh_from_connector(
+    state: &AppState,
+    source_id: &str,
+    connector_id: &str,
+) -> Result<ChangeSummary> {
+    let mut connector = Connector::from_id(&state.app_data, connector_id).await?;
+    if connector.layer_id != source_id {
+        return Err(anyhow!(
+            "connector {connector_id} publishes {}, not {source_id}",
+            connector.layer_id
+        ));
+    }
+    let before = row_counts(state, source_id).await?;
+    harvest::run(state, &mut connector).await?;
+    let after = row_counts(state, source_id).await?;
+    Ok(ChangeSummary::between(&before, &after))
+}
@@ -0,0 +1,175 @@
+use crate::core::ComputedAttribute;
+use crate::postgis::{quote_ident, quote_literal, LayerTable, StatementError};
+use crate::validation::{Validate, Validator};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Types a column can be added as or changed to, named as layer attributes
+/// report them.
+pub const COLUMN_TYPES: [&str; 9] = [
+    "text",
+    "integer",
+    "bigint",
+    "double precision",
+    "numeric",
+    "boolean",
+    "date",
+    "timestamp with time zone",
+    "jsonb",
+];
+
+/// A change to the attribute columns of a layer's table.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SchemaChange {
+    /// Add a column, filled with `default` or left null.
+    Add {
+        name: String,
+        data_type: String,
+        /// A value of the new type, e.g. `0` or `"unknown"`.
+        default: Option<Value>,
+    },
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Change a column's type, casting its values. Fails, changing nothing,
+    /// if any value does not cast.
+    ChangeType {
+        name: String,
+        data_type: String,
+    },
+    Drop {
+        name: String,
+    },
+}
+
+fn check_type(v: &mut Validator, data_type: &str) {
+    v.check(
+        "data_type",
+        COLUMN_TYPES.contains(&data_type),
+        "unsupported_type",
+        format!("must be one of {}", COLUMN_TYPES.join(", ")),
+    );
+}
+
+impl Validate for SchemaChange {
+    fn check(&self, v: &mut Validator) {
+        match self {
+            SchemaChange::Add {
+                name, data_type, ..
+            } => {
+                v.table_name("name", name);
+                check_type(v, data_type);
+            }
+            SchemaChange::Rename { name, new_name } => {
+                v.length("name", name, 1, 63);
+                v.table_name("new_name", new_name);
+            }
+            SchemaChange::ChangeType { name, data_type } => {
+                v.length("name", name, 1, 63);
+                check_type(v, data_type);
+            }
+            SchemaChange::Drop { name } => v.length("name", name, 1, 63),
+        }
+    }
+}
+
+impl SchemaChange {
+    /// The existing column the change is to.
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            SchemaChange::Add { .. } => None,
+            SchemaChange::Rename { name, .. }
+            | SchemaChange::ChangeType { name, .. }
+            | SchemaChange::Drop { name } => Some(name),
+        }
+    }
+
+    /// The column the change creates, under a new name.
+    pub fn new_column(&self) -> Option<&str> {
+        match self {
+            SchemaChange::Add { name, .. } => Some(name),
+            SchemaChange::Rename { new_name, .. } => Some(new_name),
+            _ => None,
+        }
+    }
+
+    fn sql(&self, table: &LayerTable) -> String {
+        let alter = format!("ALTER TABLE {}", table.qualified_name());
+        match self {
+            SchemaChange::Add {
+                name,
+                data_type,
+                default,
+            } => {
+                let default = match default {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(text)) => {
+                        format!(" DEFAULT {}::{data_type}", quote_literal(text))
+                    }
+                    Some(value @ (Value::Array(_) | Value::Object(_))) => {
+                        format!(
+                            " DEFAULT {}::{data_type}",
+                            quote_literal(&value.to_string())
+                        )
+                    }
+                    Some(value) => format!(" DEFAULT ({value})::{data_type}"),
+                };
+                format!(
+                    "{alter} ADD COLUMN {} {data_type}{default}",
+                    quote_ident(name)
+                )
+            }
+            SchemaChange::Rename { name, new_name } => format!(
+                "{alter} RENAME COLUMN {} TO {}",
+                quote_ident(name),
+                quote_ident(new_name)
+            ),
+            SchemaChange::ChangeType { name, data_type } => {
+                let column = quote_ident(name);
+                format!(
+                    "{alter} ALTER COLUMN {column} TYPE {data_type} USING {column}::{data_type}"
+                )
+            }
+            SchemaChange::Drop { name } => {
+                format!("{alter} DROP COLUMN {}", quote_ident(name))
+            }
+        }
+    }
+}
+
+/// Apply `change` to the table. It is undone if a value fails to cast, or if
+/// one of `computed` no longer plans against the changed columns.
+#[instrument(skip(pool, table, computed), fields(table = %table.table))]
+pub async fn apply(
+    pool: &Pool,
+    table: &LayerTable,
+    change: &SchemaChange,
+    computed: &[ComputedAttribute],
+) -> Result<(), StatementError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    transaction.batch_execute(&change.sql(table)).await?;
+    for attribute in computed {
+        let sql = format!(
+            "SELECT ({}) FROM {} t LIMIT 0",
+            attribute.expression,
+            table.qualified_name()
+        );
+        if let Err(e) = transaction.prepare(&sql).await {
+            let message = e
+                .as_db_error()
+                .map_or_else(|| e.to_string(), |db| db.message().to_string());
+            return Err(StatementError::Invalid(format!(
+                "computed attribute {} would break: {message}",
+                attribute.name
+            )));
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::app_state::AppState;
+use crate::config::SecurityHeadersConfig;
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// Headers from `[security_headers]`, parsed once.
+pub struct SecurityHeaders {
+    content_security_policy: Option<HeaderValue>,
+    embed_content_security_policy: String,
+    strict_transport_security: Option<HeaderValue>,
+    referrer_policy: Option<HeaderValue>,
+}
+
+fn header_value(name: &str, value: &str) -> Result<Option<HeaderValue>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(value)
+        .map(Some)
+        .map_err(|_| anyhow!("security_headers.{name} is not a valid header value"))
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &SecurityHeadersConfig) -> Result<Self> {
+        let hsts = match (config.hsts_max_age, config.hsts_include_subdomains) {
+            (0, _) => String::new(),
+            (max_age, false) => format!("max-age={max_age}"),
+            (max_age, true) => format!("max-age={max_age}; includeSubDomains"),
+        };
+        // Checked here, as embed policies are only built per request
+        header_value(
+            "embed_content_security_policy",
+            &config.embed_content_security_policy,
+        )?;
+        Ok(SecurityHeaders {
+            content_security_policy: header_value(
+                "content_security_policy",
+                &config.content_security_policy,
+            )?,
+            embed_content_security_policy: config.embed_content_security_policy.clone(),
+            strict_transport_security: header_value("hsts_max_age", &hsts)?,
+            referrer_policy: header_value("referrer_policy", &config.referrer_policy)?,
+        })
+    }
+
+    /// The policy for an embed page that sites in `frame_ancestors` may
+    /// frame.
+    pub fn embed_policy(&self, frame_ancestors: &str) -> String {
+        if self.embed_content_security_policy.is_empty() {
+            format!("frame-ancestors {frame_ancestors}")
+        } else {
+            format!(
+                "{}; frame-ancestors {frame_ancestors}",
+                self.embed_content_security_policy
+            )
+        }
+    }
+
+    fn apply(&self, response: &mut Response) {
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+        let headers = response.headers_mut();
+        let mut set = |name: HeaderName, value: &Option<HeaderValue>| {
+            // Handlers that set their own, like the embed viewer, win
+            if let Some(value) = value {
+                headers.entry(name).or_insert_with(|| value.clone());
+            }
+        };
+        if !is_html {
+            set(
+                header::CONTENT_SECURITY_POLICY,
+                &self.content_security_policy,
+            );
+        }
+        set(
+            header::STRICT_TRANSPORT_SECURITY,
+            &self.strict_transport_security,
+        );
+        set(header::REFERRER_POLICY, &self.referrer_policy);
+        set(
+            header::X_CONTENT_TYPE_OPTIONS,
+            &Some(HeaderValue::from_static("nosniff")),
+        );
+    }
+}
+
+pub async fn security_headers(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    state.security_headers.apply(&mut response);
+    response
+}
@@ -0,0 +1,166 @@
+use crate::core::{ProgressReporter, MAX_TILE_ZOOM};
+use crate::validation::{ValidationErrors, Validator};
+use anyhow::{anyhow, Result};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::{info, instrument, warn};
+use utoipa::ToSchema;
+
+/// Latitude where Web Mercator tiles end.
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Progress is recorded about this many times over a job.
+const PROGRESS_UPDATES: u64 = 100;
+
+/// Tile URL for (z, x, y).
+pub type TileUrl = Arc<dyn Fn(u8, u32, u32) -> String + Send + Sync>;
+
+/// The tiles of one zoom level covering a bounding box, inclusive.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct ZoomRange {
+    pub zoom: u8,
+    pub min_x: u32,
+    pub max_x: u32,
+    pub min_y: u32,
+    pub max_y: u32,
+    pub tiles: u64,
+}
+
+impl ZoomRange {
+    /// Tiles at `zoom` touching `bbox`, given as WGS84 `[west, south, east,
+    /// north]`.
+    pub fn covering(bbox: [f64; 4], zoom: u8) -> Self {
+        let n = 2f64.powi(zoom.into());
+        let last = (1u64 << zoom) - 1;
+        let column = |lon: f64| {
+            let x = ((lon + 180.0) / 360.0 * n).floor();
+            (x.max(0.0) as u64).min(last) as u32
+        };
+        let row = |lat: f64| {
+            let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+            let y = ((1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n).floor();
+            (y.max(0.0) as u64).min(last) as u32
+        };
+        let (min_x, max_x) = (column(bbox[0]), column(bbox[2]));
+        let (min_y, max_y) = (row(bbox[3]), row(bbox[1]));
+        ZoomRange {
+            zoom,
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+            tiles: u64::from(max_x - min_x + 1) * u64::from(max_y - min_y + 1),
+        }
+    }
+
+    fn coords(self) -> impl Iterator<Item = (u8, u32, u32)> {
+        (self.min_x..=self.max_x)
+            .flat_map(move |x| (self.min_y..=self.max_y).map(move |y| (self.zoom, x, y)))
+    }
+}
+
+/// The tiles a seeding job requests, which is also its cost: each is one
+/// tile render and one request to the CDN.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SeedPlan {
+    pub bbox: [f64; 4],
+    pub zooms: Vec<ZoomRange>,
+    pub tiles: u64,
+}
+
+impl SeedPlan {
+    pub fn new(bbox: [f64; 4], min_zoom: u8, max_zoom: u8) -> Result<Self, ValidationErrors> {
+        let mut v = Validator::default();
+        v.bbox("bbox", bbox);
+        v.range("maxzoom", max_zoom, 0, MAX_TILE_ZOOM);
+        v.check(
+            "minzoom",
+            min_zoom <= max_zoom,
+            "out_of_range",
+            "must not exceed maxzoom",
+        );
+        v.finish()?;
+        let zooms: Vec<ZoomRange> = (min_zoom..=max_zoom)
+            .map(|zoom| ZoomRange::covering(bbox, zoom))
+            .collect();
+        let tiles = zooms.iter().map(|range| range.tiles).sum();
+        Ok(SeedPlan { bbox, zooms, tiles })
+    }
+}
+
+/// A `west,south,east,north` query parameter.
+pub fn parse_bbox(value: &str) -> Result<[f64; 4]> {
+    let values = value
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()?;
+    values
+        .try_into()
+        .map_err(|_| anyhow!("bbox needs four values"))
+}
+
+async fn request(client: &Client, url: &str) -> Result<()> {
+    let response = client.get(url).send().await?;
+    // Tiles outside a layer's zoom range are 404s and still cache
+    match response.status() {
+        status if status.is_success() || status == StatusCode::NOT_FOUND => {
+            response.bytes().await?;
+            Ok(())
+        }
+        status => Err(anyhow!("{status}")),
+    }
+}
+
+/// Request every tile of the plan, `concurrency` at a time, so caches in
+/// front of the tile URLs hold them. Fails if any tile could not be fetched,
+/// after trying them all.
+#[instrument(skip_all, fields(tiles = plan.tiles))]
+pub async fn seed(
+    plan: &SeedPlan,
+    tile_url: TileUrl,
+    concurrency: usize,
+    progress: ProgressReporter,
+) -> Result<()> {
+    let client = Client::builder()
+        .user_agent(concat!("gridwalk-seeder/", env!("CARGO_PKG_VERSION")))
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let step = (plan.tiles / PROGRESS_UPDATES).max(1);
+    let mut coords = plan.zooms.iter().flat_map(|range| range.coords());
+    let mut in_flight = JoinSet::new();
+    let (mut completed, mut failed) = (0, 0);
+    progress.report(0, plan.tiles).await;
+    loop {
+        while in_flight.len() < concurrency {
+            let Some((z, x, y)) = coords.next() else {
+                break;
+            };
+            let (client, url) = (client.clone(), tile_url(z, x, y));
+            in_flight.spawn(async move {
+                let result = request(&client, &url).await;
+                (z, x, y, result)
+            });
+        }
+        let Some(outcome) = in_flight.join_next().await else {
+            break;
+        };
+        if let (z, x, y, Err(e)) = outcome? {
+            warn!("Failed to seed tile {z}/{x}/{y}: {e}");
+            failed += 1;
+        }
+        completed += 1;
+        if completed % step == 0 {
+            progress.report(completed, plan.tiles).await;
+        }
+    }
+    progress.report(completed, plan.tiles).await;
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {completed} tiles failed to seed"));
+    }
+    info!("Seeded {completed} tiles");
+    Ok(())
+}
@@ -1,17 +1,250 @@
 use crate::app_state::AppState;
-use crate::routes::{health_check, tiles};
-use axum::{routing::get, Router};
+use crate::openapi::ApiDoc;
+use crate::rate_limit::rate_limit;
+use crate::routes::{
+    aggregate_layer, aggregate_tiles, analyze_layer, attachment_thumbnail, check_topology,
+    commit_edit_session, copy_layer, create_backup, create_branch, create_comment,
+    create_connector, create_edit_session, create_map, create_sql_layer, dedupe_layer,
+    delete_attachment, delete_connector, delete_layer, delete_layer_form, delete_layer_freshness,
+    delete_layer_quality, delete_layer_schedule, delete_map, discard_edit_session,
+    download_attachment, edit_in_session, embed_config, embed_map, geocode, geocode_layer,
+    get_attachments, get_attribute_stats, get_basemaps, get_branch_diff, get_branches, get_changes,
+    get_comments, get_connector, get_connectors, get_edit_session, get_events,
+    get_geocode_failures, get_job, get_layer, get_layer_diff, get_layer_form, get_layer_legend,
+    get_layer_quality_report, get_layer_schedule, get_layer_schedule_runs, get_layer_shares,
+    get_layers, get_map, get_map_shares, get_map_style, get_maps, get_metrics, graphiql,
+    graphql_query, harvest_connector, health_check, healthz, import_osm, import_url,
+    insert_features, isochrone, map_tiles, merge_branch, put_layer_schedule, readyz, redo_edit,
+    refresh_layer, reopen_comment, resolve_comment, restore_backup, restore_layer, restore_map,
+    reverse_geocode, revoke_share, route, run_layer_schedule, search, seed_layer, share_layer,
+    share_map, shared_style, shared_tiles, sign_layer_tiles, source_tiles, spatial_join,
+    sync_layer, tiles, track_changes, undo_edit, update_layer_catalog, update_layer_form,
+    update_layer_freshness, update_layer_quality, update_layer_schema, update_layer_tiling,
+    update_map, upload_attachment,
+};
+use crate::security_headers::security_headers;
+use crate::telemetry::{request_span, track_requests};
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
+    Router,
+};
+use std::future::pending;
+use tokio::signal;
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::{self, TraceLayer};
-use tracing::Level;
+use tracing::{error, info, Level};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 pub fn create_app(app_state: AppState) -> Router {
+    let limiter = middleware::from_fn_with_state(app_state.clone(), rate_limit);
+    let headers = middleware::from_fn_with_state(app_state.clone(), security_headers);
     Router::new()
         .route("/health", get(health_check))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(get_metrics))
         .route("/tiles/:z/:x/:y", get(tiles))
+        .route("/tiles/:source_id/:z/:x/:y", get(source_tiles))
+        .route("/basemaps", get(get_basemaps))
+        .route("/maps", get(get_maps).post(create_map))
+        .route(
+            "/maps/:map_id",
+            get(get_map).put(update_map).delete(delete_map),
+        )
+        .route("/maps/:map_id/restore", post(restore_map))
+        .route("/maps/:map_id/style.json", get(get_map_style))
+        .route("/maps/:map_id/tiles/:z/:x/:y", get(map_tiles))
+        .route("/maps/:map_id/share", post(share_map))
+        .route("/maps/:map_id/shares", get(get_map_shares))
+        .route(
+            "/maps/:map_id/comments",
+            get(get_comments).post(create_comment),
+        )
+        .route(
+            "/maps/:map_id/comments/:comment_id/resolve",
+            post(resolve_comment),
+        )
+        .route(
+            "/maps/:map_id/comments/:comment_id/reopen",
+            post(reopen_comment),
+        )
+        .route("/layers", get(get_layers).post(create_sql_layer))
+        .route("/layers/:source_id", get(get_layer).delete(delete_layer))
+        .route("/layers/:source_id/restore", post(restore_layer))
+        .route("/layers/:source_id/refresh", post(refresh_layer))
+        .route("/layers/:source_id/copy", post(copy_layer))
+        .route("/layers/:source_id/catalog", put(update_layer_catalog))
+        .route("/layers/:source_id/legend", get(get_layer_legend))
+        .route(
+            "/layers/:source_id/attributes/:name/stats",
+            get(get_attribute_stats),
+        )
+        .route("/layers/:source_id/tiling", put(update_layer_tiling))
+        .route(
+            "/layers/:source_id/freshness",
+            put(update_layer_freshness).delete(delete_layer_freshness),
+        )
+        .route(
+            "/layers/:source_id/quality",
+            put(update_layer_quality).delete(delete_layer_quality),
+        )
+        .route(
+            "/layers/:source_id/quality/report",
+            get(get_layer_quality_report),
+        )
+        .route("/layers/:source_id/schema", post(update_layer_schema))
+        .route("/layers/:source_id/seed", post(seed_layer))
+        .route(
+            "/layers/:source_id/form",
+            get(get_layer_form)
+                .put(update_layer_form)
+                .delete(delete_layer_form),
+        )
+        .route("/layers/:source_id/share", post(share_layer))
+        .route("/layers/:source_id/signed-url", post(sign_layer_tiles))
+        .route("/layers/:source_id/features/batch", post(insert_features))
+        .route("/layers/:source_id/import-url", post(import_url))
+        .route("/layers/:source_id/changes", get(get_changes))
+        .route("/layers/:source_id/changes/track", post(track_changes))
+        .route("/layers/:source_id/diff", get(get_layer_diff))
+        .route("/layers/:source_id/sync", post(sync_layer))
+        .route("/layers/:source_id/topology/check", post(check_topology))
+        .route(
+            "/layers/:source_id/edit-sessions",
+            post(create_edit_session),
+        )
+        .route(
+            "/layers/:source_id/branches",
+            get(get_branches).post(create_branch),
+        )
+        .route("/branches/:branch_id/diff", get(get_branch_diff))
+        .route("/branches/:branch_id/merge", post(merge_branch))
+        .route(
+            "/layers/:source_id/schedule",
+            get(get_layer_schedule)
+                .put(put_layer_schedule)
+                .delete(delete_layer_schedule),
+        )
+        .route("/layers/:source_id/schedule/run", post(run_layer_schedule))
+        .route(
+            "/layers/:source_id/schedule/runs",
+            get(get_layer_schedule_runs),
+        )
+        .route(
+            "/layers/:source_id/edit-sessions/:session_id",
+            get(get_edit_session).delete(discard_edit_session),
+        )
+        .route(
+            "/layers/:source_id/edit-sessions/:session_id/edits",
+            post(edit_in_session),
+        )
+        .route(
+            "/layers/:source_id/edit-sessions/:session_id/undo",
+            post(undo_edit),
+        )
+        .route(
+            "/layers/:source_id/edit-sessions/:session_id/redo",
+            post(redo_edit),
+        )
+        .route(
+            "/layers/:source_id/edit-sessions/:session_id/commit",
+            post(commit_edit_session),
+        )
+        .route(
+            "/layers/:source_id/features/:feature_id/attachments",
+            get(get_attachments)
+                .post(upload_attachment)
+                .layer(DefaultBodyLimit::max(app_state.storage.max_upload_size())),
+        )
+        .route(
+            "/layers/:source_id/features/:feature_id/attachments/:attachment_id",
+            get(download_attachment).delete(delete_attachment),
+        )
+        .route(
+            "/layers/:source_id/features/:feature_id/attachments/:attachment_id/thumbnail",
+            get(attachment_thumbnail),
+        )
+        .route("/layers/:source_id/analyze", post(analyze_layer))
+        .route("/layers/:source_id/aggregate", post(aggregate_layer))
+        .route("/layers/:source_id/dedupe", post(dedupe_layer))
+        .route(
+            "/layers/:source_id/aggregate/:z/:x/:y",
+            get(aggregate_tiles),
+        )
+        .route("/imports/osm", post(import_osm))
+        .route("/connectors", get(get_connectors).post(create_connector))
+        .route(
+            "/connectors/:connector_id",
+            get(get_connector).delete(delete_connector),
+        )
+        .route("/connectors/:connector_id/harvest", post(harvest_connector))
+        .route("/analysis/spatial-join", post(spatial_join))
+        .route("/routing/route", post(route))
+        .route("/routing/isochrone", post(isochrone))
+        .route("/jobs/:job_id", get(get_job))
+        .route("/layers/:source_id/shares", get(get_layer_shares))
+        .route("/shares/:token", delete(revoke_share))
+        .route("/shared/:token/style.json", get(shared_style))
+        .route(
+            "/shared/:token/tiles/:source_id/:z/:x/:y",
+            get(shared_tiles),
+        )
+        .route("/embed/:token", get(embed_map))
+        .route("/embed/:token/config.json", get(embed_config))
+        .route("/search", get(search))
+        .route("/events", get(get_events))
+        .route("/graphql", get(graphiql).post(graphql_query))
+        .route("/geocode", get(geocode))
+        .route("/reverse", get(reverse_geocode))
+        .route("/layers/:source_id/geocode", post(geocode_layer))
+        .route("/jobs/:job_id/geocode-failures", get(get_geocode_failures))
+        .route("/admin/backups", post(create_backup))
+        .route("/admin/backups/:name/restore", post(restore_backup))
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+        .layer(limiter)
+        .layer(headers)
+        .route_layer(middleware::from_fn(track_requests))
         .with_state(app_state)
+        // Compresses JSON and tiles for clients that accept it; event
+        // streams and already encoded responses pass through untouched
+        .layer(CompressionLayer::new())
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+                .make_span_with(request_span)
                 .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
         )
 }
+
+/// Resolves on Ctrl-C or SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {e}");
+            pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received");
+}
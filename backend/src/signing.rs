@@ -0,0 +1,99 @@
+use crate::config::SigningConfig;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::IntoParams;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Query parameters carried by a signed URL.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UrlSignature {
+    /// Unix time the signature stops being valid.
+    pub expires: Option<i64>,
+    /// Hex HMAC-SHA256 over the signed scope and `expires`.
+    pub signature: Option<String>,
+}
+
+/// Signs and checks expiring URLs. A signature covers a scope, such as every
+/// tile of one layer, rather than a single URL, so tile templates can be
+/// signed once.
+pub struct UrlSigner {
+    /// Newest first. URLs are signed with the first key and accepted with
+    /// any of them, so keys rotate by prepending a new one and dropping the
+    /// oldest once its URLs have expired.
+    keys: Vec<Vec<u8>>,
+    ttl: i64,
+    required: bool,
+    /// SHA-256 of each key allowed to mint signed URLs, so comparing them
+    /// leaks nothing about the keys through timing.
+    api_keys: Vec<Vec<u8>>,
+}
+
+impl UrlSigner {
+    pub fn from_config(config: &SigningConfig) -> Self {
+        UrlSigner {
+            keys: config
+                .keys
+                .iter()
+                .map(|key| key.expose().as_bytes().to_vec())
+                .collect(),
+            ttl: config.ttl as i64,
+            required: config.require_for_tiles,
+            api_keys: config
+                .api_keys
+                .iter()
+                .map(|key| Sha256::digest(key.expose().as_bytes()).to_vec())
+                .collect(),
+        }
+    }
+
+    /// Whether tile requests must carry a valid signature.
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Whether `key` may mint signed URLs.
+    pub fn authorizes(&self, key: &str) -> bool {
+        let digest = Sha256::digest(key.as_bytes());
+        self.api_keys
+            .iter()
+            .any(|k| k.as_slice() == digest.as_slice())
+    }
+
+    /// Sign `scope`, returning the expiry and the query string to append to
+    /// URLs within it, or `None` when no keys are configured. Expiry is
+    /// rounded up to a whole number of TTL windows so URLs handed out within
+    /// a window are identical and cache well.
+    pub fn sign(&self, scope: &str) -> Option<(i64, String)> {
+        let key = self.keys.first()?;
+        let expires = (Utc::now().timestamp() / self.ttl + 2) * self.ttl;
+        let signature = hex::encode(mac(key, scope, expires).finalize().into_bytes());
+        Some((expires, format!("expires={expires}&signature={signature}")))
+    }
+
+    pub fn verify(&self, scope: &str, params: &UrlSignature) -> bool {
+        let (Some(expires), Some(signature)) = (params.expires, &params.signature) else {
+            return false;
+        };
+        if expires < Utc::now().timestamp() {
+            return false;
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.keys
+            .iter()
+            .any(|key| mac(key, scope, expires).verify_slice(&signature).is_ok())
+    }
+}
+
+fn mac(key: &[u8], scope: &str, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(scope.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
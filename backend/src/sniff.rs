@@ -0,0 +1,153 @@
+/// Bytes looked at to tell text from binary.
+const TEXT_PREFIX: usize = 8192;
+
+/// File types told apart by their leading bytes, whatever their name or
+/// declared `Content-Type` says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Jpeg,
+    Png,
+    WebP,
+    Gif,
+    Tiff,
+    Pdf,
+    Zip,
+    Gzip,
+    /// SQLite, which GeoPackages are.
+    Sqlite,
+    OsmPbf,
+    /// Windows, Linux or macOS executables.
+    Executable,
+    Json,
+    /// UTF-8 text other than JSON, e.g. CSV or line-delimited GeoJSON.
+    Text,
+}
+
+impl FileType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Jpeg => "JPEG",
+            FileType::Png => "PNG",
+            FileType::WebP => "WebP",
+            FileType::Gif => "GIF",
+            FileType::Tiff => "TIFF",
+            FileType::Pdf => "PDF",
+            FileType::Zip => "zip",
+            FileType::Gzip => "gzip",
+            FileType::Sqlite => "SQLite",
+            FileType::OsmPbf => "OSM PBF",
+            FileType::Executable => "executable",
+            FileType::Json => "JSON",
+            FileType::Text => "text",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            FileType::Jpeg => "image/jpeg",
+            FileType::Png => "image/png",
+            FileType::WebP => "image/webp",
+            FileType::Gif => "image/gif",
+            FileType::Tiff => "image/tiff",
+            FileType::Pdf => "application/pdf",
+            FileType::Zip => "application/zip",
+            FileType::Gzip => "application/gzip",
+            FileType::Sqlite => "application/vnd.sqlite3",
+            FileType::OsmPbf => "application/x-protobuf",
+            FileType::Executable => "application/x-executable",
+            FileType::Json => "application/json",
+            FileType::Text => "text/plain",
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self, FileType::Json | FileType::Text)
+    }
+}
+
+/// UTF-8 up to the end of the prefix, where a character may be cut short.
+fn is_text(body: &[u8]) -> bool {
+    let prefix = &body[..body.len().min(TEXT_PREFIX)];
+    match std::str::from_utf8(prefix) {
+        Ok(text) => !text.contains('\0'),
+        Err(e) => e.error_len().is_none() && !prefix[..e.valid_up_to()].contains(&0),
+    }
+}
+
+/// The type of `body`, or `None` for binary data of a type not listed.
+pub fn sniff(body: &[u8]) -> Option<FileType> {
+    let starts = |signature: &[u8]| body.starts_with(signature);
+    let file_type = if starts(b"\xff\xd8\xff") {
+        FileType::Jpeg
+    } else if starts(b"\x89PNG\r\n\x1a\n") {
+        FileType::Png
+    } else if starts(b"RIFF") && body.get(8..12) == Some(b"WEBP") {
+        FileType::WebP
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        FileType::Gif
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        FileType::Tiff
+    } else if starts(b"%PDF-") {
+        FileType::Pdf
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        FileType::Zip
+    } else if starts(b"\x1f\x8b") {
+        FileType::Gzip
+    } else if starts(b"SQLite format 3\0") {
+        FileType::Sqlite
+    } else if starts(b"MZ")
+        || starts(b"\x7fELF")
+        || [
+            b"\xfe\xed\xfa\xce",
+            b"\xfe\xed\xfa\xcf",
+            b"\xce\xfa\xed\xfe",
+            b"\xcf\xfa\xed\xfe",
+            b"\xca\xfe\xba\xbe",
+        ]
+        .iter()
+        .any(|signature| starts(*signature))
+    {
+        FileType::Executable
+    } else if body
+        .get(4..32.min(body.len()))
+        .is_some_and(|header| header.windows(9).any(|w| w == b"OSMHeader"))
+    {
+        // A length-prefixed BlobHeader whose type is OSMHeader
+        FileType::OsmPbf
+    } else if is_text(body) {
+        let first = body.iter().find(|b| !b.is_ascii_whitespace());
+        if matches!(first, Some(b'{') | Some(b'[')) {
+            FileType::Json
+        } else {
+            FileType::Text
+        }
+    } else {
+        return None;
+    };
+    Some(file_type)
+}
+
+/// The type to store an upload as: the one its bytes show, or for text the
+/// declared one when that is a text type.
+pub fn content_type(declared: &str, body: &[u8]) -> String {
+    match sniff(body) {
+        Some(file_type) if !file_type.is_text() => file_type.mime().to_string(),
+        Some(file_type) => {
+            let declared = declared
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase();
+            let declared_text = declared.starts_with("text/")
+                || declared == "application/json"
+                || declared == "application/geo+json";
+            if declared_text {
+                declared
+            } else {
+                file_type.mime().to_string()
+            }
+        }
+        None => "application/octet-stream".to_string(),
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config;
+use crate::core::{Event, EventBus, Layer};
+use crate::data::Database;
+use anyhow::Result;
+use martin::Source;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Name of the layer inside the source's vector tiles.
+pub fn source_layer(source: &dyn Source) -> String {
+    source
+        .get_tilejson()
+        .vector_layers
+        .as_ref()
+        .and_then(|layers| layers.first())
+        .map(|vector_layer| vector_layer.id.clone())
+        .unwrap_or_else(|| source.get_id().to_string())
+}
+
+/// Tile sources keyed by id. Sources are resolved from PostGIS at startup and
+/// re-resolved whenever tables are added, e.g. by analysis jobs. Sources of
+/// layers in the trash are hidden.
+pub struct SourceRegistry {
+    sources: RwLock<HashMap<String, Arc<dyn Source>>>,
+    trashed: RwLock<HashSet<String>>,
+    database_url: String,
+}
+
+fn index(sources: Vec<Box<dyn Source>>) -> HashMap<String, Arc<dyn Source>> {
+    sources
+        .into_iter()
+        .map(|source| (source.get_id().to_string(), Arc::from(source)))
+        .collect()
+}
+
+impl SourceRegistry {
+    pub fn new(sources: Vec<Box<dyn Source>>, database_url: &str) -> Self {
+        SourceRegistry {
+            sources: RwLock::new(index(sources)),
+            trashed: RwLock::new(HashSet::new()),
+            database_url: database_url.to_string(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn Source>> {
+        if self.is_trashed(id) {
+            return None;
+        }
+        self.sources.read().unwrap().get(id).cloned()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        !self.is_trashed(id) && self.sources.read().unwrap().contains_key(id)
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        let trashed = self.trashed.read().unwrap();
+        let mut ids: Vec<String> = self
+            .sources
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|id| !trashed.contains(*id))
+            .cloned()
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn is_trashed(&self, id: &str) -> bool {
+        self.trashed.read().unwrap().contains(id)
+    }
+
+    /// Hide the layer's source while it is in the trash, or show it again.
+    pub fn set_trashed(&self, id: &str, trashed: bool) {
+        let mut ids = self.trashed.write().unwrap();
+        if trashed {
+            ids.insert(id.to_string());
+        } else {
+            ids.remove(id);
+        }
+    }
+
+    /// Hide the sources of every layer in the trash.
+    pub async fn load_trash(&self, database: &Arc<dyn Database>) -> Result<()> {
+        let trashed = Layer::get_trash(database)
+            .await?
+            .into_iter()
+            .map(|layer| layer.id)
+            .collect();
+        *self.trashed.write().unwrap() = trashed;
+        Ok(())
+    }
+
+    /// Follow layers into and out of the trash, including on other
+    /// replicas, and drop sources whose layers were purged.
+    pub fn track_trash(self: Arc<Self>, events: &EventBus, database: Arc<dyn Database>) {
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(Event::LayerUpdated { layer }) => {
+                        self.set_trashed(&layer.id, layer.deleted_at.is_some());
+                    }
+                    Ok(Event::LayerDeleted { layer_id }) => {
+                        if let Err(e) = self.refresh().await {
+                            warn!("Failed to refresh tile sources: {e}");
+                        }
+                        self.set_trashed(&layer_id, false);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Missed {missed} events; reloading the trash");
+                        if let Err(e) = self.load_trash(&database).await {
+                            warn!("Failed to reload the trash: {e}");
+                        }
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// Re-resolve sources from PostGIS, picking up new and dropped tables.
+    pub async fn refresh(&self) -> Result<()> {
+        let sources = index(config::initialize_pg_config(&self.database_url).await?);
+        info!("Refreshed tile sources: {} available", sources.len());
+        *self.sources.write().unwrap() = sources;
+        Ok(())
+    }
+}
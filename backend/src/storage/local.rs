@@ -0,0 +1,49 @@
+use super::BlobStore;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Stores objects as files under a directory, for development and single
+/// node deployments.
+pub struct Local {
+    root: PathBuf,
+}
+
+impl Local {
+    pub fn new(root: PathBuf) -> Self {
+        Local { root }
+    }
+}
+
+#[async_trait]
+impl BlobStore for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, body).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.root.join(key)).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+mod local;
+mod s3;
+
+pub use local::Local;
+pub use s3::S3;
+
+use crate::config::StorageConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageFormat;
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()>;
+    /// The object's bytes, or `None` if there is no such key.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Deleting a missing key succeeds.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Object storage for feature attachments, when configured.
+pub struct Storage {
+    store: Option<Box<dyn BlobStore>>,
+    prefix: String,
+    max_upload_size: usize,
+    thumbnail_size: u32,
+    allowed_types: Vec<String>,
+}
+
+impl Storage {
+    pub async fn from_config(config: &StorageConfig) -> Result<Self> {
+        let store: Option<Box<dyn BlobStore>> = match config.provider.as_str() {
+            "none" => None,
+            "s3" => Some(Box::new(S3::new(config.bucket()?).await)),
+            "local" => Some(Box::new(Local::new(config.path()?))),
+            other => return Err(anyhow!("Unknown storage provider: {other}")),
+        };
+        Ok(Storage {
+            store,
+            prefix: config.prefix.trim_end_matches('/').to_string(),
+            max_upload_size: config.max_upload_size,
+            thumbnail_size: config.thumbnail_size,
+            allowed_types: config.allowed_types.clone(),
+        })
+    }
+
+    /// The backing store, or `None` when attachments are disabled.
+    pub fn store(&self) -> Option<&dyn BlobStore> {
+        self.store.as_deref()
+    }
+
+    /// The backing store, for callers that need nothing else.
+    pub fn into_store(self) -> Option<Box<dyn BlobStore>> {
+        self.store
+    }
+
+    pub fn max_upload_size(&self) -> usize {
+        self.max_upload_size
+    }
+
+    /// Whether attachments may be of `content_type`.
+    pub fn allows(&self, content_type: &str) -> bool {
+        self.allowed_types.is_empty()
+            || self
+                .allowed_types
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(content_type))
+    }
+
+    /// Full object key for a key relative to the configured prefix.
+    pub fn key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{key}", self.prefix)
+        }
+    }
+
+    /// A JPEG thumbnail of an image, or `None` for formats that are not
+    /// thumbnailed. Decoding is CPU bound, so it runs off the async runtime.
+    pub async fn thumbnail(&self, content_type: &str, body: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(format) = ImageFormat::from_mime_type(content_type) else {
+            return Ok(None);
+        };
+        if !matches!(
+            format,
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP
+        ) {
+            return Ok(None);
+        }
+        let body = body.to_vec();
+        let size = self.thumbnail_size;
+        tokio::task::spawn_blocking(move || -> Result<Option<Vec<u8>>> {
+            let image = image::load_from_memory_with_format(&body, format)?;
+            let thumbnail = image.thumbnail(size, size).into_rgb8();
+            let mut encoded = Vec::new();
+            thumbnail.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, 80))?;
+            Ok(Some(encoded))
+        })
+        .await?
+    }
+}
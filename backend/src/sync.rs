@@ -0,0 +1,301 @@
+use crate::changes::{self, ChangeOperation};
+use crate::config::LimitsConfig;
+use crate::core::{Form, Layer};
+use crate::postgis::{feature_columns, validate_feature, LayerTable};
+use crate::quality::QualityChecker;
+use crate::topology::{self, TopologyViolation};
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+
+/// How an edit to a feature the server changed since the client last
+/// pulled is resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Drop the client's edit.
+    #[default]
+    ServerWins,
+    /// Apply the client's edit over the server's.
+    ClientWins,
+    /// Drop the client's edit and return both versions to resolve.
+    Manual,
+}
+
+/// An edit made on a client while offline.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ClientEdit {
+    /// Unique per edit; a retried edit with the same id is applied once.
+    pub client_id: String,
+    pub operation: ChangeOperation,
+    /// Primary key of the feature, for updates and deletes.
+    pub id: Option<Value>,
+    /// WGS84 GeoJSON feature, for inserts and updates. Properties not given
+    /// are left unchanged by updates.
+    pub feature: Option<Value>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AppliedEdit {
+    pub client_id: String,
+    /// Primary key of the feature, assigned by the server for inserts.
+    pub id: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SyncConflict {
+    pub client_id: String,
+    pub id: Value,
+    pub resolution: ConflictStrategy,
+    /// The client's version of the feature.
+    pub client: Option<Value>,
+    /// The server's current version, absent if it was deleted.
+    pub server: Option<Value>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RejectedEdit {
+    pub client_id: String,
+    pub error: String,
+    /// Topology rules the edit's geometry broke.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<TopologyViolation>,
+}
+
+/// Why an edit was not applied.
+struct Rejection {
+    error: String,
+    violations: Vec<TopologyViolation>,
+}
+
+impl From<String> for Rejection {
+    fn from(error: String) -> Self {
+        Rejection {
+            error,
+            violations: Vec::new(),
+        }
+    }
+}
+
+impl From<&str> for Rejection {
+    fn from(error: &str) -> Self {
+        error.to_string().into()
+    }
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct PushResult {
+    pub applied: Vec<AppliedEdit>,
+    pub conflicts: Vec<SyncConflict>,
+    pub rejected: Vec<RejectedEdit>,
+}
+
+/// Writes client edits to a layer table whose changes are tracked, checking
+/// them against server changes since the client's cursor.
+pub struct SyncSession<'a> {
+    pool: &'a Pool,
+    table: &'a LayerTable,
+    /// Primary key column features are addressed by.
+    key: String,
+    columns: HashSet<String>,
+    form: Option<Form>,
+    quality: Option<QualityChecker>,
+    limits: &'a LimitsConfig,
+    /// Inserts the layer has room for under its feature limit.
+    room: Option<u64>,
+    strategy: ConflictStrategy,
+    /// Features changed on the server since the client last pulled.
+    touched: HashSet<String>,
+}
+
+impl<'a> SyncSession<'a> {
+    pub async fn new(
+        pool: &'a Pool,
+        table: &'a LayerTable,
+        key: String,
+        layer: Layer,
+        limits: &'a LimitsConfig,
+        since: i64,
+        strategy: ConflictStrategy,
+    ) -> Result<Self> {
+        let columns = table.attribute_columns(pool).await?.into_iter().collect();
+        let touched = changes::touched_since(pool, table, &key, since).await?;
+        let room = table.room(pool, limits).await?;
+        Ok(SyncSession {
+            pool,
+            table,
+            key,
+            columns,
+            form: layer.form,
+            quality: layer.quality.map(|quality| quality.checker(table.srid)),
+            limits,
+            room,
+            strategy,
+            touched,
+        })
+    }
+
+    #[instrument(skip_all, fields(table = %self.table.table, edits = edits.len()))]
+    pub async fn push(&self, edits: Vec<ClientEdit>) -> PushResult {
+        let mut result = PushResult::default();
+        let mut room = self.room;
+        for edit in edits {
+            let client_id = edit.client_id.clone();
+            let insert = edit.operation == ChangeOperation::Insert;
+            if insert && room == Some(0) {
+                let error = format!(
+                    "Layer is at its limit of {} features",
+                    self.limits.max_features
+                );
+                result.rejected.push(RejectedEdit {
+                    client_id,
+                    error,
+                    violations: Vec::new(),
+                });
+                continue;
+            }
+            match self.apply(edit, &mut result).await {
+                Ok(Some(id)) => {
+                    if insert {
+                        room = room.map(|room| room - 1);
+                    }
+                    if let Err(e) = self.remember(&client_id, &id).await {
+                        warn!("Failed to record sync edit {client_id}: {e}");
+                    }
+                    result.applied.push(AppliedEdit { client_id, id });
+                }
+                Ok(None) => {}
+                Err(Rejection { error, violations }) => result.rejected.push(RejectedEdit {
+                    client_id,
+                    error,
+                    violations,
+                }),
+            }
+        }
+        result
+    }
+
+    /// Apply one edit, returning the feature's key if it was written.
+    /// Conflicts are added to `result` whether or not the edit wins.
+    async fn apply(
+        &self,
+        edit: ClientEdit,
+        result: &mut PushResult,
+    ) -> Result<Option<Value>, Rejection> {
+        let failed = |e: anyhow::Error| format!("Failed to apply edit: {e:#}");
+        if let Some(id) = self.applied_before(&edit.client_id).await.map_err(failed)? {
+            return Ok(Some(id));
+        }
+        if let Some(feature) = &edit.feature {
+            validate_feature(feature, &self.columns, self.limits)?;
+            let partial = edit.operation == ChangeOperation::Update;
+            if let Some(form) = &self.form {
+                form.check(feature, partial)?;
+            }
+            if let Some(quality) = &self.quality {
+                quality.enforce(feature, partial)?;
+            }
+        }
+
+        let ClientEdit {
+            client_id,
+            operation,
+            id,
+            mut feature,
+        } = edit;
+        let rules = self.form.as_ref().and_then(|form| form.topology.as_ref());
+        if let (Some(rules), Some(feature)) = (rules, feature.as_mut()) {
+            let report = topology::check(
+                self.pool,
+                self.table,
+                &self.key,
+                id.as_ref(),
+                &feature["geometry"],
+                rules,
+            )
+            .await
+            .map_err(failed)?;
+            if !report.violations.is_empty() {
+                return Err(Rejection {
+                    error: "Geometry breaks the layer's topology rules".to_string(),
+                    violations: report.violations,
+                });
+            }
+            feature["geometry"] = report.geometry;
+        }
+        if operation == ChangeOperation::Insert {
+            let feature = feature.ok_or("Inserts need a feature")?;
+            let columns = feature_columns([&feature]);
+            let id = self
+                .table
+                .insert_feature(self.pool, &self.key, &columns, &feature)
+                .await
+                .map_err(failed)?;
+            return Ok(Some(id));
+        }
+
+        let id = id.ok_or("Updates and deletes need the feature id")?;
+        if self.touched.contains(&id.to_string()) {
+            let server = self
+                .table
+                .feature(self.pool, &self.key, &id)
+                .await
+                .map_err(failed)?;
+            result.conflicts.push(SyncConflict {
+                client_id,
+                id: id.clone(),
+                resolution: self.strategy,
+                client: feature.clone(),
+                server,
+            });
+            if self.strategy != ConflictStrategy::ClientWins {
+                return Ok(None);
+            }
+        }
+
+        let found = match (operation, feature) {
+            (ChangeOperation::Update, Some(feature)) => {
+                let columns = feature_columns([&feature]);
+                self.table
+                    .update_feature(self.pool, &self.key, &id, &columns, &feature)
+                    .await
+            }
+            (ChangeOperation::Update, None) => return Err("Updates need a feature".into()),
+            _ => self.table.delete_feature(self.pool, &self.key, &id).await,
+        }
+        .map_err(failed)?;
+        if !found {
+            return Err("Feature does not exist".into());
+        }
+        Ok(Some(id))
+    }
+
+    async fn applied_before(&self, client_id: &str) -> Result<Option<Value>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT feature_id FROM gridwalk.sync_edits
+                 WHERE table_schema = $1 AND table_name = $2 AND client_id = $3",
+                &[&self.table.schema, &self.table.table, &client_id],
+            )
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn remember(&self, client_id: &str, id: &Value) -> Result<()> {
+        let client = self.pool.get().await?;
+        client
+            .execute(
+                "INSERT INTO gridwalk.sync_edits (table_schema, table_name, client_id, feature_id)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT DO NOTHING",
+                &[&self.table.schema, &self.table.table, &client_id, id],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::redact::LogUrl;
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    http::{self, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use std::time::Instant;
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Install the global Prometheus recorder. The handle renders the scrape
+/// output for `/metrics`.
+pub fn install_metrics() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)?
+        .install_recorder()?;
+    Ok(handle)
+}
+
+/// Record request counts and latency, labelled by route template rather
+/// than raw path so tile coordinates do not explode label cardinality.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    )
+    .increment(1);
+    histogram!(
+        "http_request_duration_seconds",
+        "method" => method,
+        "route" => route
+    )
+    .record(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Set up logging, plus OTLP trace export when an endpoint is configured.
+/// Incoming `traceparent` headers are honoured so spans join the caller's
+/// trace.
+pub fn init_tracing(otlp_endpoint: Option<&str>) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let otel = match otlp_endpoint {
+        Some(endpoint) => {
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            let provider = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::Config::default().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                    KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                ])))
+                .install_batch(runtime::Tokio)?;
+            let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+            global::set_tracer_provider(provider);
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+    Ok(())
+}
+
+/// Flush spans still buffered for export.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Request span for the trace layer, parented to the caller's trace context.
+pub fn request_span(request: &http::Request<Body>) -> Span {
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        uri = %LogUrl(&request.uri().to_string()),
+        version = ?request.version(),
+    );
+    let parent =
+        global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(request.headers())));
+    span.set_parent(parent);
+    span
+}
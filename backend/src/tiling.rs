@@ -0,0 +1,268 @@
+use crate::core::{Event, EventBus, Tiling};
+use crate::data::Database;
+use crate::expression;
+use crate::postgis::{quote_ident, quote_literal, transform_sql, LayerTable};
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{instrument, warn};
+
+/// Web Mercator's circumference at the equator, in metres.
+const EARTH_CIRCUMFERENCE: f64 = 40_075_016.685_578_49;
+const EXTENT: u32 = 4096;
+
+/// Every layer's tiling settings, held in memory so serving a tile needs no
+/// lookup, and kept current from layer events.
+#[derive(Default)]
+pub struct TilingRegistry {
+    settings: RwLock<HashMap<String, Tiling>>,
+}
+
+impl TilingRegistry {
+    pub async fn load(database: &Arc<dyn Database>) -> Result<Self> {
+        let registry = TilingRegistry::default();
+        registry.reload(database).await?;
+        Ok(registry)
+    }
+
+    async fn reload(&self, database: &Arc<dyn Database>) -> Result<()> {
+        let settings = database
+            .get_layers()
+            .await?
+            .into_iter()
+            .map(|layer| (layer.id, layer.tiling))
+            .collect();
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// The layer's settings, or the defaults for layers without metadata.
+    pub fn get(&self, source_id: &str) -> Tiling {
+        self.settings
+            .read()
+            .unwrap()
+            .get(source_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Apply settings from layer events, reloading everything if events
+    /// were missed.
+    pub fn track_changes(self: Arc<Self>, events: &EventBus, database: Arc<dyn Database>) {
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(Event::LayerUpdated { layer }) => {
+                        self.settings
+                            .write()
+                            .unwrap()
+                            .insert(layer.id, layer.tiling);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Missed {missed} events; reloading tiling settings");
+                        if let Err(e) = self.reload(&database).await {
+                            warn!("Failed to reload tiling settings: {e}");
+                        }
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+/// A layer rendered by Gridwalk's own tile query rather than the source's,
+/// for tiles the source can't produce: overzoomed, composite or shaped by
+/// tiling rules.
+#[derive(Debug)]
+pub struct TileLayer {
+    pub source_id: String,
+    /// MVT layer name, matching the source's own tiles.
+    pub layer_name: String,
+    /// Zoom to snap geometry to when the tile is beyond the layer's max zoom.
+    pub snap_zoom: Option<u8>,
+    pub tiling: Tiling,
+}
+
+/// Size of a tile unit at `zoom`, in Web Mercator metres.
+fn tile_unit(zoom: u8) -> f64 {
+    EARTH_CIRCUMFERENCE / 2f64.powi(zoom.into()) / f64::from(EXTENT)
+}
+
+/// The layer's columns as tiles encode them.
+struct TileColumns {
+    attributes: Vec<String>,
+    /// Columns rounded to the encoding's precision.
+    fractional: Vec<String>,
+    /// Column written as the feature id.
+    id: Option<String>,
+}
+
+/// Name the MVT feature id is selected as, out of the way of attributes.
+const FEATURE_ID: &str = "__gridwalk_id";
+
+/// A non-negative bigint id for the column's value: integers, or text of
+/// digits, as they are, and anything else hashed to 60 bits.
+fn feature_id_sql(column: &str) -> String {
+    let value = format!("t.{}::text", quote_ident(column));
+    format!(
+        "CASE WHEN {value} ~ '^[0-9]{{1,18}}$' THEN {value}::bigint
+              ELSE ('x' || substr(md5({value}), 1, 15))::bit(60)::bigint END"
+    )
+}
+
+/// SQL producing one layer's MVT for tile (z, x, y). With a snap zoom,
+/// geometry is first snapped to that zoom's grid, so an overzoomed tile
+/// carries no more detail than its ancestor there; tiling rules then apply
+/// as at that zoom.
+fn layer_tile_sql(
+    table: &LayerTable,
+    columns: &TileColumns,
+    layer: &TileLayer,
+    z: u8,
+    x: u32,
+    y: u32,
+) -> String {
+    let tiling = &layer.tiling;
+    let zoom = layer.snap_zoom.unwrap_or(z);
+    let unit = tile_unit(zoom);
+    let geom = quote_ident(&table.geometry_column);
+    let projected = transform_sql(&format!("t.{geom}"), table.srid, 3857);
+    let mut mercator = projected.clone();
+    if let Some(tolerance) = tiling.tolerance(zoom).filter(|&tolerance| tolerance > 0.0) {
+        mercator = format!(
+            "ST_SimplifyPreserveTopology({mercator}, {})",
+            tolerance * unit
+        );
+    }
+    if layer.snap_zoom.is_some() {
+        mercator = format!("ST_SnapToGrid({mercator}, {unit})");
+    }
+    let encoding = &tiling.encoding;
+    let mut attributes: String = columns
+        .attributes
+        .iter()
+        .filter(|column| encoding.includes(column) && tiling.keeps_attribute(column, zoom))
+        .map(|column| {
+            let quoted = quote_ident(column);
+            match encoding.precision {
+                Some(precision) if columns.fractional.contains(column) => {
+                    format!(", round(t.{quoted}::numeric, {precision})::float8 AS {quoted}")
+                }
+                _ => format!(", t.{quoted}"),
+            }
+        })
+        .chain(
+            tiling
+                .computed
+                .iter()
+                .filter(|attribute| tiling.keeps_attribute(&attribute.name, zoom))
+                // Settings saved before expressions were restricted may not pass
+                .filter(|attribute| expression::columns(&attribute.expression).is_ok())
+                .map(|attribute| {
+                    format!(
+                        ", ({}) AS {}",
+                        attribute.expression,
+                        quote_ident(&attribute.name)
+                    )
+                }),
+        )
+        .collect();
+    let mut feature_id = String::new();
+    if let Some(id) = &columns.id {
+        attributes.push_str(&format!(", {} AS {FEATURE_ID}", feature_id_sql(id)));
+        feature_id = format!(", {}", quote_literal(FEATURE_ID));
+    }
+    let mut conditions = String::new();
+    if let Some(min_area) = tiling.min_area {
+        conditions.push_str(&format!(
+            " AND (ST_Dimension(t.{geom}) <> 2 OR ST_Area({projected}) >= {})",
+            min_area * unit * unit
+        ));
+    }
+    if let Some(min_length) = tiling.min_length {
+        conditions.push_str(&format!(
+            " AND (ST_Dimension(t.{geom}) <> 1 OR ST_Length({projected}) >= {})",
+            min_length * unit
+        ));
+    }
+    format!(
+        "SELECT ST_AsMVT(tile, {layer}, {EXTENT}, 'geom'{feature_id}) FROM (
+             SELECT ST_AsMVTGeom({mercator}, ST_TileEnvelope({z}, {x}, {y}), {EXTENT}, 64) AS geom
+                    {attributes}
+             FROM {table} t
+             WHERE t.{geom} && {envelope}{conditions}
+         ) tile
+         WHERE geom IS NOT NULL",
+        layer = quote_literal(&layer.layer_name),
+        table = table.qualified_name(),
+        envelope = transform_sql(
+            &format!("ST_TileEnvelope({z}, {x}, {y}, margin => 0.02)"),
+            3857,
+            table.srid
+        ),
+    )
+}
+
+async fn layer_sql(pool: &Pool, layer: &TileLayer, z: u8, x: u32, y: u32) -> Result<String> {
+    let table = LayerTable::from_source_id(pool, &layer.source_id).await?;
+    let encoding = &layer.tiling.encoding;
+    let attributes = table.attribute_columns(pool).await?;
+    let fractional = match encoding.precision {
+        Some(_) => table.fractional_columns(pool).await?,
+        None => Vec::new(),
+    };
+    let id = match &encoding.id_field {
+        Some(field) => Some(field.clone()),
+        None if encoding.stable_ids => table.primary_key(pool).await?,
+        None => None,
+    };
+    // A column since dropped leaves features without ids rather than
+    // failing the tile
+    let id = id.filter(|id| attributes.contains(id));
+    let columns = TileColumns {
+        attributes,
+        fractional,
+        id,
+    };
+    Ok(layer_tile_sql(&table, &columns, layer, z, x, y))
+}
+
+/// One layer's tile. Beyond its max zoom, the tile is cut from its ancestor
+/// at the max zoom: clipped and scaled up to the requested tile.
+#[instrument(skip(pool))]
+pub async fn layer_tile(pool: &Pool, layer: &TileLayer, z: u8, x: u32, y: u32) -> Result<Vec<u8>> {
+    let sql = layer_sql(pool, layer, z, x, y).await?;
+    let client = pool.get().await?;
+    let row = client.query_one(&sql, &[]).await?;
+    Ok(row.get::<_, Option<Vec<u8>>>(0).unwrap_or_default())
+}
+
+/// One tile holding every layer, each as its own MVT layer. The layers are
+/// rendered by a single query; MVT layers concatenate into a valid tile.
+#[instrument(skip(pool))]
+pub async fn composite_tile(
+    pool: &Pool,
+    layers: &[TileLayer],
+    z: u8,
+    x: u32,
+    y: u32,
+) -> Result<Vec<u8>> {
+    let mut parts = Vec::new();
+    for layer in layers {
+        let sql = layer_sql(pool, layer, z, x, y).await?;
+        parts.push(format!("coalesce(({sql}), ''::bytea)"));
+    }
+    if parts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = pool.get().await?;
+    let row = client
+        .query_one(&format!("SELECT {}", parts.join(" || ")), &[])
+        .await?;
+    Ok(row.get::<_, Option<Vec<u8>>>(0).unwrap_or_default())
+}
@@ -0,0 +1,161 @@
+use crate::postgis::{quote_ident, transform_sql, LayerTable};
+use crate::validation::{Validate, Validator};
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Largest tolerance a rule may have, in metres.
+const MAX_TOLERANCE: f64 = 1000.0;
+
+/// Geometry rules for the features of an editable layer, checked against
+/// their neighbours as edits are written. Distances are in metres.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TopologyRules {
+    /// Polygons may not overlap the area of another feature.
+    #[serde(default)]
+    pub no_overlaps: bool,
+    /// Features must touch a neighbour or stay at least this far from it,
+    /// so no sliver gaps are left between them.
+    pub gap_tolerance: Option<f64>,
+    /// Vertices this close to a neighbour are moved onto it before the
+    /// other rules are checked.
+    pub snap_tolerance: Option<f64>,
+}
+
+fn check_tolerance(v: &mut Validator, name: &str, tolerance: Option<f64>) {
+    if let Some(tolerance) = tolerance {
+        v.check(
+            name,
+            tolerance.is_finite() && tolerance > 0.0 && tolerance <= MAX_TOLERANCE,
+            "out_of_range",
+            format!("must be above 0 and at most {MAX_TOLERANCE} metres"),
+        );
+    }
+}
+
+impl Validate for TopologyRules {
+    fn check(&self, v: &mut Validator) {
+        check_tolerance(v, "gap_tolerance", self.gap_tolerance);
+        check_tolerance(v, "snap_tolerance", self.snap_tolerance);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyRule {
+    Overlap,
+    Gap,
+}
+
+/// A rule a geometry breaks against one neighbour.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopologyViolation {
+    pub rule: TopologyRule,
+    /// Primary key of the neighbour.
+    pub feature_id: Value,
+    /// WGS84 GeoJSON point where the rule is broken, to show the editor.
+    #[schema(value_type = Object)]
+    pub location: Value,
+}
+
+/// A geometry as it would be written, after snapping, and the rules it
+/// breaks.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TopologyReport {
+    /// WGS84 GeoJSON geometry.
+    #[schema(value_type = Object)]
+    pub geometry: Value,
+    pub violations: Vec<TopologyViolation>,
+}
+
+/// Snap a WGS84 GeoJSON geometry to its neighbours in the table and check
+/// it against `rules`. `id` is the key of the feature being updated, which
+/// is not its own neighbour. Distances are measured in Web Mercator,
+/// corrected for latitude.
+#[instrument(skip(pool, table, geometry), fields(table = %table.table))]
+pub async fn check(
+    pool: &Pool,
+    table: &LayerTable,
+    key: &str,
+    id: Option<&Value>,
+    geometry: &Value,
+    rules: &TopologyRules,
+) -> Result<TopologyReport> {
+    let geom = quote_ident(&table.geometry_column);
+    let key = quote_ident(key);
+    let snap = rules.snap_tolerance.unwrap_or(0.0);
+    let gap = rules.gap_tolerance.unwrap_or(0.0);
+    let mercator = transform_sql(&format!("t.{geom}"), table.srid, 3857);
+    let window = transform_sql(
+        "ST_Expand(input.g, $6::float8 * input.stretch)",
+        3857,
+        table.srid,
+    );
+    let sql = format!(
+        "WITH input AS (
+             SELECT ST_Transform(ST_SetSRID(ST_GeomFromGeoJSON($1::text), 4326), 3857) AS g,
+                    1 / cos(radians(ST_Y(ST_Centroid(
+                        ST_SetSRID(ST_GeomFromGeoJSON($1::text), 4326)
+                    )))) AS stretch
+         ),
+         neighbours AS (
+             SELECT to_jsonb(t.{key}) AS id, {mercator} AS g
+             FROM {table} t, input
+             WHERE t.{geom} && {window}
+               AND ($2::jsonb IS NULL OR to_jsonb(t.{key}) <> $2::jsonb)
+         ),
+         snapped AS (
+             SELECT CASE
+                        WHEN $3::float8 > 0 AND EXISTS (SELECT 1 FROM neighbours)
+                        THEN ST_Snap(input.g, (SELECT ST_Collect(g) FROM neighbours),
+                                     $3::float8 * input.stretch)
+                        ELSE input.g
+                    END AS g,
+                    input.stretch
+             FROM input
+         )
+         SELECT ST_AsGeoJSON(ST_Transform(s.g, 4326))::jsonb,
+                coalesce((
+                    SELECT jsonb_agg(jsonb_build_object(
+                        'rule', v.rule,
+                        'feature_id', v.id,
+                        'location', ST_AsGeoJSON(ST_Transform(v.at, 4326))::jsonb
+                    ))
+                    FROM (
+                        SELECT 'overlap' AS rule, n.id,
+                               ST_PointOnSurface(ST_Intersection(s.g, n.g)) AS at
+                        FROM neighbours n
+                        WHERE $4::boolean AND ST_Relate(s.g, n.g, '2********')
+                        UNION ALL
+                        SELECT 'gap', n.id, ST_ClosestPoint(s.g, n.g)
+                        FROM neighbours n
+                        WHERE $5::float8 > 0
+                          AND NOT ST_Intersects(s.g, n.g)
+                          AND ST_DWithin(s.g, n.g, $5::float8 * s.stretch)
+                    ) v
+                ), '[]'::jsonb)
+         FROM snapped s",
+        table = table.qualified_name(),
+    );
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            &sql,
+            &[
+                &geometry.to_string(),
+                &id,
+                &snap,
+                &rules.no_overlaps,
+                &gap,
+                &snap.max(gap),
+            ],
+        )
+        .await?;
+    Ok(TopologyReport {
+        geometry: row.get(0),
+        violations: serde_json::from_value(row.get(1))?,
+    })
+}
@@ -0,0 +1,219 @@
+use crate::postgis::valid_table_name;
+use reqwest::Url;
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+/// One problem with one field of a request.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Path to the field, e.g. `layers[2].source_id`; empty when the
+    /// problem is with the request as a whole.
+    pub field: String,
+    /// Stable identifier of the rule that failed, e.g. `too_long`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Every problem found in a request, so clients can point at all of them at
+/// once.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// A single problem, for checks made outside a [`Validator`].
+    pub fn single(field: &str, code: &'static str, message: impl Into<String>) -> Self {
+        ValidationErrors {
+            errors: vec![FieldError {
+                field: field.to_string(),
+                code,
+                message: message.into(),
+            }],
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self
+            .errors
+            .iter()
+            .map(|e| match e.field.as_str() {
+                "" => e.message.clone(),
+                field => format!("{field}: {}", e.message),
+            })
+            .collect();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Collects problems while walking a value, recording each against the path
+/// of the field it was found in.
+#[derive(Debug, Default)]
+pub struct Validator {
+    path: Vec<String>,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    fn path_to(&self, name: &str) -> String {
+        let mut path = String::new();
+        for segment in self.path.iter().map(String::as_str).chain([name]) {
+            if !path.is_empty() && !segment.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+
+    /// Record a problem with the field `name` of the value being checked.
+    pub fn error(&mut self, name: &str, code: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: self.path_to(name),
+            code,
+            message: message.into(),
+        });
+    }
+
+    /// Record a problem unless `ok`.
+    pub fn check(&mut self, name: &str, ok: bool, code: &'static str, message: impl Into<String>) {
+        if !ok {
+            self.error(name, code, message);
+        }
+    }
+
+    /// Record the error from a validator that predates field paths, as a
+    /// problem with the field `name`.
+    pub fn result<E: fmt::Display>(&mut self, name: &str, result: Result<(), E>) {
+        if let Err(e) = result {
+            self.error(name, "invalid", e.to_string());
+        }
+    }
+
+    /// Check a nested value, recording its problems under `name`.
+    pub fn nested(&mut self, name: &str, value: &impl Validate) {
+        self.path.push(name.to_string());
+        value.check(self);
+        self.path.pop();
+    }
+
+    /// Check each item of a list, recording problems as `name[index]`.
+    pub fn each<T: Validate>(&mut self, name: &str, items: &[T]) {
+        for (index, item) in items.iter().enumerate() {
+            self.nested(&format!("{name}[{index}]"), item);
+        }
+    }
+
+    /// Run `check` with problems recorded under `name`, for fields checked
+    /// by hand rather than by a [`Validate`] impl.
+    pub fn within(&mut self, name: &str, check: impl FnOnce(&mut Validator)) {
+        self.path.push(name.to_string());
+        check(self);
+        self.path.pop();
+    }
+
+    /// `value` must be `min..=max` characters long.
+    pub fn length(&mut self, name: &str, value: &str, min: usize, max: usize) {
+        let length = value.chars().count();
+        if length < min {
+            let message = match min {
+                1 => "must not be empty".to_string(),
+                min => format!("must be at least {min} characters"),
+            };
+            self.error(name, "too_short", message);
+        } else if length > max {
+            self.error(
+                name,
+                "too_long",
+                format!("must be at most {max} characters"),
+            );
+        }
+    }
+
+    /// `count` items, which must be `min..=max`.
+    pub fn count(&mut self, name: &str, count: usize, min: usize, max: usize) {
+        if count < min {
+            self.error(name, "too_few", format!("needs at least {min} items"));
+        } else if count > max {
+            self.error(name, "too_many", format!("takes at most {max} items"));
+        }
+    }
+
+    pub fn range<T: PartialOrd + fmt::Display>(&mut self, name: &str, value: T, min: T, max: T) {
+        if !(min <= value && value <= max) {
+            self.error(
+                name,
+                "out_of_range",
+                format!("must be between {min} and {max}"),
+            );
+        }
+    }
+
+    /// A lower-case Postgres identifier, as layer tables are named.
+    pub fn table_name(&mut self, name: &str, value: &str) {
+        self.check(
+            name,
+            valid_table_name(value),
+            "invalid_name",
+            "must be lower-case letters, digits and underscores, starting with a letter, at most 63 characters",
+        );
+    }
+
+    pub fn http_url(&mut self, name: &str, value: &str) {
+        let ok = Url::parse(value).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        self.check(name, ok, "invalid_url", "must be an http(s) URL");
+    }
+
+    /// A `[longitude, latitude]` pair in WGS84.
+    pub fn lon_lat(&mut self, name: &str, [lon, lat]: [f64; 2]) {
+        let ok = (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat);
+        self.check(
+            name,
+            ok,
+            "out_of_bounds",
+            "must be [longitude, latitude] within WGS84 bounds",
+        );
+    }
+
+    /// A WGS84 `[west, south, east, north]` box.
+    pub fn bbox(&mut self, name: &str, [west, south, east, north]: [f64; 4]) {
+        let in_bounds = [west, east]
+            .iter()
+            .all(|lon| (-180.0..=180.0).contains(lon))
+            && [south, north]
+                .iter()
+                .all(|lat| (-90.0..=90.0).contains(lat));
+        if !in_bounds {
+            self.error(name, "out_of_bounds", "must be within WGS84 bounds");
+        } else if west > east || south > north {
+            self.error(name, "invalid_bbox", "must be [west, south, east, north]");
+        }
+    }
+
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors {
+                errors: self.errors,
+            })
+        }
+    }
+}
+
+/// Rules for a request body or a value nested in one.
+pub trait Validate {
+    /// Record every problem with `self` in `v`.
+    fn check(&self, v: &mut Validator);
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut v = Validator::default();
+        self.check(&mut v);
+        v.finish()
+    }
+}